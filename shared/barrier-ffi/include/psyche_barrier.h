@@ -0,0 +1,75 @@
+/*
+ * C bindings for psyche-core's CancellableBarrier, from the psyche-barrier-ffi crate.
+ *
+ * A barrier is created once with psyche_barrier_new, waited on by every participating
+ * thread, C or Rust, and freed once with psyche_barrier_free after all of them are done
+ * with it. Waits return PSYCHE_BARRIER_LEADER or PSYCHE_BARRIER_OK on release, and one of
+ * the negative PSYCHE_BARRIER_* codes otherwise. Panics never unwind into C.
+ *
+ * Link against libpsyche_barrier_ffi, and with a static build also the system libraries
+ * Rust's std needs, such as -lpthread -ldl -lm on Linux.
+ */
+
+#ifndef PSYCHE_BARRIER_H
+#define PSYCHE_BARRIER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Released, and this thread was the leader of its generation */
+#define PSYCHE_BARRIER_LEADER 1
+/* Released, or the call succeeded */
+#define PSYCHE_BARRIER_OK 0
+/* The barrier was cancelled */
+#define PSYCHE_BARRIER_CANCELLED -1
+/* The timeout elapsed before the barrier released */
+#define PSYCHE_BARRIER_TIMED_OUT -2
+/* The wait was interrupted */
+#define PSYCHE_BARRIER_INTERRUPTED -3
+/* This thread had already arrived in the current generation */
+#define PSYCHE_BARRIER_ALREADY_ARRIVED -4
+/* More threads are using the barrier than it has parties */
+#define PSYCHE_BARRIER_TOO_MANY_PARTIES -5
+/* The wait would block forever on a target without threads */
+#define PSYCHE_BARRIER_WOULD_BLOCK -6
+/* The barrier was aborted */
+#define PSYCHE_BARRIER_ABORTED -7
+/* The barrier is being drained, and the thread is asked to leave */
+#define PSYCHE_BARRIER_DRAINING -8
+/* The call panicked, for instance in a release listener, and the panic was caught */
+#define PSYCHE_BARRIER_PANICKED -9
+/* The barrier pointer was null */
+#define PSYCHE_BARRIER_INVALID -100
+
+/* Opaque handle to a barrier */
+typedef struct PsycheBarrier PsycheBarrier;
+
+/* Creates a barrier for n threads, or returns NULL if n is 0 or creating it failed */
+PsycheBarrier *psyche_barrier_new(size_t n);
+
+/* Waits until all threads have reached the barrier or the barrier is cancelled */
+int psyche_barrier_wait(PsycheBarrier *barrier);
+
+/* Waits until all threads have reached the barrier, the barrier is cancelled, or
+ * timeout_ms milliseconds elapse */
+int psyche_barrier_wait_timeout_ms(PsycheBarrier *barrier, uint64_t timeout_ms);
+
+/* Cancels the barrier, causing all waiting threads to return PSYCHE_BARRIER_CANCELLED */
+int psyche_barrier_cancel(PsycheBarrier *barrier);
+
+/* Resets the barrier, starting a new generation and uncancelling it */
+int psyche_barrier_reset(PsycheBarrier *barrier);
+
+/* Frees a barrier. Does nothing if barrier is NULL. No thread may use it during or
+ * after this call. */
+void psyche_barrier_free(PsycheBarrier *barrier);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* PSYCHE_BARRIER_H */
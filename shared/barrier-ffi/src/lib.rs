@@ -0,0 +1,7 @@
+//! The C bindings of `psyche-core`'s barrier, built into a static and a shared library
+//! for C code to link against.
+//!
+//! The functions and return codes are declared for C in `include/psyche_barrier.h`,
+//! which is kept by hand and checked against this crate by its tests.
+
+pub use psyche_core::ffi::*;
@@ -0,0 +1,75 @@
+/* Drives the barrier from C threads; exits non-zero with a message on the first failure. */
+
+#include <pthread.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "psyche_barrier.h"
+
+#define THREADS 4
+#define ROUNDS 100
+
+#define CHECK(cond)                                                                  \
+    do {                                                                             \
+        if (!(cond)) {                                                               \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+            exit(1);                                                                 \
+        }                                                                            \
+    } while (0)
+
+static PsycheBarrier *barrier;
+static int leaders[ROUNDS];
+static pthread_mutex_t leaders_lock = PTHREAD_MUTEX_INITIALIZER;
+
+static void *participant(void *arg) {
+    (void)arg;
+    for (int round = 0; round < ROUNDS; round++) {
+        int code = psyche_barrier_wait(barrier);
+        CHECK(code == PSYCHE_BARRIER_OK || code == PSYCHE_BARRIER_LEADER);
+        if (code == PSYCHE_BARRIER_LEADER) {
+            pthread_mutex_lock(&leaders_lock);
+            leaders[round]++;
+            pthread_mutex_unlock(&leaders_lock);
+        }
+    }
+    return NULL;
+}
+
+static void *cancelled_waiter(void *arg) {
+    (void)arg;
+    CHECK(psyche_barrier_wait(barrier) == PSYCHE_BARRIER_CANCELLED);
+    return NULL;
+}
+
+int main(void) {
+    CHECK(psyche_barrier_new(0) == NULL);
+    CHECK(psyche_barrier_wait(NULL) == PSYCHE_BARRIER_INVALID);
+    psyche_barrier_free(NULL);
+
+    barrier = psyche_barrier_new(THREADS);
+    CHECK(barrier != NULL);
+    pthread_t threads[THREADS];
+    for (int i = 0; i < THREADS; i++) {
+        CHECK(pthread_create(&threads[i], NULL, participant, NULL) == 0);
+    }
+    for (int i = 0; i < THREADS; i++) {
+        CHECK(pthread_join(threads[i], NULL) == 0);
+    }
+    for (int round = 0; round < ROUNDS; round++) {
+        CHECK(leaders[round] == 1);
+    }
+
+    /* a lone waiter gives up, then cancelling fails the one left waiting */
+    CHECK(psyche_barrier_wait_timeout_ms(barrier, 10) == PSYCHE_BARRIER_TIMED_OUT);
+    pthread_t waiter;
+    CHECK(pthread_create(&waiter, NULL, cancelled_waiter, NULL) == 0);
+    CHECK(psyche_barrier_wait_timeout_ms(barrier, 50) == PSYCHE_BARRIER_TIMED_OUT);
+    CHECK(psyche_barrier_cancel(barrier) == PSYCHE_BARRIER_OK);
+    CHECK(pthread_join(waiter, NULL) == 0);
+    CHECK(psyche_barrier_wait(barrier) == PSYCHE_BARRIER_CANCELLED);
+
+    CHECK(psyche_barrier_reset(barrier) == PSYCHE_BARRIER_OK);
+    CHECK(psyche_barrier_wait_timeout_ms(barrier, 10) == PSYCHE_BARRIER_TIMED_OUT);
+    psyche_barrier_free(barrier);
+    return 0;
+}
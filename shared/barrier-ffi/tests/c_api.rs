@@ -0,0 +1,119 @@
+use std::ffi::c_int;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use psyche_barrier_ffi::*;
+
+fn header() -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("include/psyche_barrier.h");
+    std::fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn test_header_matches_bindings() {
+    let codes = [
+        ("PSYCHE_BARRIER_LEADER", PSYCHE_BARRIER_LEADER),
+        ("PSYCHE_BARRIER_OK", PSYCHE_BARRIER_OK),
+        ("PSYCHE_BARRIER_CANCELLED", PSYCHE_BARRIER_CANCELLED),
+        ("PSYCHE_BARRIER_TIMED_OUT", PSYCHE_BARRIER_TIMED_OUT),
+        ("PSYCHE_BARRIER_INTERRUPTED", PSYCHE_BARRIER_INTERRUPTED),
+        (
+            "PSYCHE_BARRIER_ALREADY_ARRIVED",
+            PSYCHE_BARRIER_ALREADY_ARRIVED,
+        ),
+        (
+            "PSYCHE_BARRIER_TOO_MANY_PARTIES",
+            PSYCHE_BARRIER_TOO_MANY_PARTIES,
+        ),
+        ("PSYCHE_BARRIER_WOULD_BLOCK", PSYCHE_BARRIER_WOULD_BLOCK),
+        ("PSYCHE_BARRIER_ABORTED", PSYCHE_BARRIER_ABORTED),
+        ("PSYCHE_BARRIER_DRAINING", PSYCHE_BARRIER_DRAINING),
+        ("PSYCHE_BARRIER_PANICKED", PSYCHE_BARRIER_PANICKED),
+        ("PSYCHE_BARRIER_INVALID", PSYCHE_BARRIER_INVALID),
+    ];
+    let header = header();
+    let defines: Vec<(&str, c_int)> = header
+        .lines()
+        .filter_map(|line| line.strip_prefix("#define PSYCHE_BARRIER_"))
+        .filter_map(|define| define.split_once(' '))
+        .map(|(name, value)| (name, value.parse().unwrap()))
+        .collect();
+    assert_eq!(defines.len(), codes.len(), "header defines {defines:?}");
+    for (name, code) in codes {
+        let name = name.strip_prefix("PSYCHE_BARRIER_").unwrap();
+        assert!(
+            defines.contains(&(name, code)),
+            "{name} isn't {code} in the header"
+        );
+    }
+
+    for function in [
+        "PsycheBarrier *psyche_barrier_new(size_t n);",
+        "int psyche_barrier_wait(PsycheBarrier *barrier);",
+        "int psyche_barrier_wait_timeout_ms(PsycheBarrier *barrier, uint64_t timeout_ms);",
+        "int psyche_barrier_cancel(PsycheBarrier *barrier);",
+        "int psyche_barrier_reset(PsycheBarrier *barrier);",
+        "void psyche_barrier_free(PsycheBarrier *barrier);",
+    ] {
+        assert!(
+            header.contains(function),
+            "header doesn't declare {function}"
+        );
+    }
+    // keep the declarations above in step with the signatures they stand for
+    let _: [extern "C" fn(usize) -> *mut PsycheBarrier; 1] = [psyche_barrier_new];
+    let _: [unsafe extern "C" fn(*mut PsycheBarrier) -> c_int; 3] = [
+        psyche_barrier_wait,
+        psyche_barrier_cancel,
+        psyche_barrier_reset,
+    ];
+    let _: unsafe extern "C" fn(*mut PsycheBarrier, u64) -> c_int = psyche_barrier_wait_timeout_ms;
+    let _: unsafe extern "C" fn(*mut PsycheBarrier) = psyche_barrier_free;
+}
+
+/// Finds the static library cargo built next to this test, under `deps` or uplifted
+/// out of it
+fn staticlib() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    let deps = exe.parent().unwrap();
+    let is_staticlib = |path: &Path| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("libpsyche_barrier_ffi") && name.ends_with(".a"))
+    };
+    for dir in [deps, deps.parent().unwrap()] {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if is_staticlib(&path) {
+                return path;
+            }
+        }
+    }
+    panic!("the static library is built along with the tests");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_c_program() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let exe = Path::new(env!("CARGO_TARGET_TMPDIR")).join("barrier_test");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(cc)
+        .args(["-std=c11", "-Wall", "-Wextra", "-Werror", "-pthread"])
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg(root.join("tests/c/barrier_test.c"))
+        .arg(staticlib())
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&exe)
+        .status()
+        .unwrap();
+    assert!(status.success(), "compiling the C test failed");
+
+    let output = Command::new(&exe).output().unwrap();
+    assert!(
+        output.status.success(),
+        "the C test failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
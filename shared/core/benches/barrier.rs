@@ -0,0 +1,132 @@
+//! Compares the flat [`CancellableBarrier`], parking straight away or spinning first, with
+//! a [`TreeBarrier`] at high thread counts.
+//!
+//! Threads that aren't the last to arrive are counted without the barrier's lock, so with
+//! a little spinning most of them never take it at all: they see the release while
+//! spinning and return.
+//!
+//! A barrier with a single party is measured on its own, with and without tracking the
+//! threads that arrive, counting the allocations its waits make: there must be none once
+//! the first release has sized what the barrier keeps.
+//!
+//! Run with `cargo bench -p psyche-core --bench barrier`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use psyche_core::{BackoffConfig, CancellableBarrier, TreeBarrier};
+
+const ROUNDS: usize = 200;
+
+/// Waits on a one-party barrier, which never park, so there are many more of them
+const SINGLE_ROUNDS: u32 = 1_000_000;
+
+/// The system allocator, counting every allocation it makes
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Runs `ROUNDS` generations of `wait` on `threads` threads and returns the mean time per
+/// generation
+fn run<B: Send + Sync + 'static>(threads: usize, barrier: Arc<B>, wait: fn(&B)) -> Duration {
+    let start = Arc::new(CancellableBarrier::new(threads + 1));
+    let handles: Vec<_> = (0..threads)
+        .map(|_| {
+            let barrier = barrier.clone();
+            let start = start.clone();
+            thread::spawn(move || {
+                start.wait().unwrap();
+                for _ in 0..ROUNDS {
+                    wait(black_box(&barrier));
+                }
+            })
+        })
+        .collect();
+
+    // leave thread startup out of the measurement
+    start.wait().unwrap();
+    let began = Instant::now();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    began.elapsed() / ROUNDS as u32
+}
+
+/// Returns the mean time per wait on a one-party barrier, panicking if any of them
+/// allocated
+fn run_single(track_threads: bool) -> Duration {
+    let barrier = CancellableBarrier::builder()
+        .parties(1)
+        .track_threads(track_threads)
+        .build()
+        .unwrap();
+    // the first releases size what the barrier keeps
+    barrier.wait().unwrap();
+    barrier.wait().unwrap();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let began = Instant::now();
+    for _ in 0..SINGLE_ROUNDS {
+        black_box(barrier.wait().unwrap());
+    }
+    let elapsed = began.elapsed() / SINGLE_ROUNDS;
+    let allocated = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    assert_eq!(allocated, 0, "waits on a one-party barrier allocated");
+    elapsed
+}
+
+fn main() {
+    let single = run_single(false);
+    println!("   1 thread   {:<16} {single:>10.1?}/wait", "single party");
+    let tracked = run_single(true);
+    println!("   1 thread   {:<16} {tracked:>10.1?}/wait", "single (tracked)");
+
+    for threads in [16, 32, 64, 128] {
+        let flat = run(threads, CancellableBarrier::new(threads), |b| {
+            b.wait().unwrap();
+        });
+        println!(
+            "{threads:>4} threads  {:<16} {flat:>10.1?}/generation",
+            "flat"
+        );
+        let spinning = CancellableBarrier::builder()
+            .parties(threads)
+            .backoff(BackoffConfig {
+                spins: 10,
+                yields: 10,
+            })
+            .build()
+            .unwrap();
+        let spinning = run(threads, spinning, |b| {
+            b.wait().unwrap();
+        });
+        println!(
+            "{threads:>4} threads  {:<16} {spinning:>10.1?}/generation",
+            "flat (spinning)"
+        );
+        for fan_in in [4, 8, 16] {
+            let tree = run(threads, TreeBarrier::new(threads, fan_in), |b| {
+                b.wait().unwrap();
+            });
+            let label = format!("tree (fan-in {fan_in})");
+            println!("{threads:>4} threads  {label:<16} {tree:>10.1?}/generation");
+        }
+    }
+}
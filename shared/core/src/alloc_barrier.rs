@@ -0,0 +1,366 @@
+//! The barrier as built without the `std` feature, for targets that have an allocator but
+//! no OS to park threads on.
+//!
+//! It keeps the core of the std barrier's API under the same names: waiting, cancelling
+//! with a reason and resetting, so that code sticking to those builds either way. A wait
+//! spins until its generation is released or the barrier is cancelled, as there is
+//! nothing to block on, and nothing times out, as there is no clock. Everything else the
+//! std barrier offers needs std.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::fmt;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::sync::Mutex;
+
+/// A synchronization primitive that allows multiple threads to wait at a point until
+/// enough threads have arrived or the barrier is cancelled
+pub struct CancellableBarrier {
+    state: Mutex<BarrierState>,
+    /// Bumped under the lock on every release, cancel and reset, so spinning waiters can
+    /// notice a change without taking the lock
+    epoch: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct BarrierState {
+    count: usize,
+    total: usize,
+    generation: usize,
+    cancelled: Option<CancelReason>,
+    /// How many had arrived in the generation the barrier was cancelled in
+    cancelled_arrived: usize,
+}
+
+impl BarrierState {
+    fn check_cancelled(&self) -> Result<(), BarrierWaitError> {
+        match &self.cancelled {
+            Some(reason) => Err(BarrierWaitError::Cancelled(CancelledBarrier {
+                reason: reason.clone(),
+                arrived: Some(self.cancelled_arrived),
+            })),
+            None => Ok(()),
+        }
+    }
+}
+
+impl CancellableBarrier {
+    /// Creates a new barrier that can be used by `n` threads
+    #[must_use]
+    pub fn new(n: usize) -> Arc<Self> {
+        assert!(n > 0, "Barrier size must be greater than 0");
+        Arc::new(CancellableBarrier {
+            state: Mutex::new(BarrierState {
+                count: 0,
+                total: n,
+                generation: 0,
+                cancelled: None,
+                cancelled_arrived: 0,
+            }),
+            epoch: AtomicUsize::new(0),
+        })
+    }
+
+    /// Waits until all threads have reached the barrier or the barrier is cancelled,
+    /// spinning all the while.
+    pub fn wait(&self) -> Result<BarrierWaitResult, BarrierWaitError> {
+        let mut state = self.state.acquire();
+        state.check_cancelled()?;
+        let generation = state.generation;
+        state.count += 1;
+        if state.count == state.total {
+            self.release(&mut state);
+            return Ok(BarrierWaitResult {
+                generation,
+                is_leader: true,
+            });
+        }
+        loop {
+            // read under the lock, so a change right after it is released isn't missed
+            let epoch = self.epoch.load(Ordering::Acquire);
+            drop(state);
+            while self.epoch.load(Ordering::Acquire) == epoch {
+                spin_loop();
+            }
+            state = self.state.acquire();
+            // a generation that was released stays released, even if the barrier was
+            // cancelled before we got to look
+            if state.generation != generation {
+                return Ok(BarrierWaitResult {
+                    generation,
+                    is_leader: false,
+                });
+            }
+            state.check_cancelled()?;
+        }
+    }
+
+    /// Arrives at the barrier without blocking.
+    ///
+    /// If this arrival completes the barrier, the other threads are released and the
+    /// generation is returned. Otherwise the arrival is not kept and `None` is returned.
+    pub fn try_wait(&self) -> Result<Option<usize>, BarrierWaitError> {
+        let mut state = self.state.acquire();
+        state.check_cancelled()?;
+        if state.count + 1 < state.total {
+            return Ok(None);
+        }
+        let generation = state.generation;
+        self.release(&mut state);
+        Ok(Some(generation))
+    }
+
+    /// Cancels the barrier, causing all waiting threads to return with an error.
+    ///
+    /// Returns how many threads had arrived at the current generation when it was
+    /// cancelled.
+    pub fn cancel(&self) -> usize {
+        self.cancel_with(CancelReason::Unspecified)
+    }
+
+    /// Cancels the barrier with a `reason` that waiting threads receive in their error,
+    /// returning how many threads had arrived as [`Self::cancel`] does. Cancelling an
+    /// already cancelled barrier replaces the stored reason.
+    pub fn cancel_with(&self, reason: CancelReason) -> usize {
+        let mut state = self.state.acquire();
+        state.cancelled = Some(reason);
+        state.cancelled_arrived = state.count;
+        self.epoch.fetch_add(1, Ordering::Release);
+        state.count
+    }
+
+    /// Resets the barrier to its initial state.
+    ///
+    /// Resetting starts a new generation and uncancels the barrier. Threads still waiting
+    /// on the generation in progress are let go as if it had been released: their wait
+    /// returns `Ok` with that generation, and none of them is the leader.
+    pub fn reset(&self) {
+        let mut state = self.state.acquire();
+        state.cancelled = None;
+        state.cancelled_arrived = 0;
+        self.release(&mut state);
+    }
+
+    /// Returns true if the barrier has been cancelled and not reset since
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.state.acquire().cancelled.is_some()
+    }
+
+    /// Returns the reason the barrier was last cancelled with, or `None` if it isn't
+    /// cancelled
+    #[must_use]
+    pub fn cancel_reason(&self) -> Option<CancelReason> {
+        self.state.acquire().cancelled.clone()
+    }
+
+    /// Returns the number of parties the barrier needs
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.state.acquire().total
+    }
+
+    /// Starts the next generation, letting go everyone waiting on this one
+    fn release(&self, state: &mut BarrierState) {
+        state.count = 0;
+        state.generation = state.generation.wrapping_add(1);
+        self.epoch.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl fmt::Debug for CancellableBarrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellableBarrier")
+            .field("state", &*self.state.acquire())
+            .finish_non_exhaustive()
+    }
+}
+
+/// What a thread gets back from a released [`CancellableBarrier::wait`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult {
+    generation: usize,
+    is_leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// Returns the generation that was released
+    #[must_use]
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Returns true for exactly one thread per generation: the one whose arrival
+    /// released the barrier
+    #[must_use]
+    pub fn is_leader(&self) -> bool {
+        self.is_leader
+    }
+}
+
+/// Why a barrier was cancelled, as passed to [`CancellableBarrier::cancel_with`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CancelReason {
+    /// Cancelled through [`CancellableBarrier::cancel`] without a reason
+    Unspecified,
+    /// The process or component is shutting down
+    Shutdown,
+    /// A participant hit an error
+    Error,
+    /// A participant or supervisor gave up waiting
+    Timeout,
+    /// A participant panicked before arriving
+    Panicked,
+    /// Any other reason
+    Custom(String),
+}
+
+impl fmt::Display for CancelReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CancelReason::Unspecified => write!(f, "unspecified"),
+            CancelReason::Shutdown => write!(f, "shutdown"),
+            CancelReason::Error => write!(f, "error"),
+            CancelReason::Timeout => write!(f, "timeout"),
+            CancelReason::Panicked => write!(f, "participant panicked"),
+            CancelReason::Custom(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+/// Error for a barrier that has been cancelled, carrying the reason it was cancelled with.
+///
+/// Two errors are equal if their reasons are, whatever else was recorded alongside.
+#[derive(Debug, Clone)]
+pub struct CancelledBarrier {
+    reason: CancelReason,
+    arrived: Option<usize>,
+}
+
+impl CancelledBarrier {
+    /// Creates the error for a barrier cancelled with `reason`
+    #[must_use]
+    pub fn new(reason: CancelReason) -> Self {
+        CancelledBarrier {
+            reason,
+            arrived: None,
+        }
+    }
+
+    /// Returns why the barrier was cancelled
+    #[must_use]
+    pub fn reason(&self) -> &CancelReason {
+        &self.reason
+    }
+
+    /// Returns how many parties had arrived in the generation the barrier was cancelled
+    /// in
+    #[must_use]
+    pub fn arrived_count(&self) -> Option<usize> {
+        self.arrived
+    }
+}
+
+impl PartialEq for CancelledBarrier {
+    fn eq(&self, other: &Self) -> bool {
+        self.reason == other.reason
+    }
+}
+
+impl Eq for CancelledBarrier {}
+
+impl fmt::Display for CancelledBarrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            CancelReason::Unspecified => write!(f, "barrier cancelled"),
+            reason => write!(f, "barrier cancelled: {reason}"),
+        }
+    }
+}
+
+impl core::error::Error for CancelledBarrier {}
+
+/// Error returned when a wait on the barrier does not complete
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BarrierWaitError {
+    /// The barrier was cancelled before it released
+    Cancelled(CancelledBarrier),
+}
+
+impl fmt::Display for BarrierWaitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BarrierWaitError::Cancelled(cancelled) => cancelled.fmt(f),
+        }
+    }
+}
+
+impl core::error::Error for BarrierWaitError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_wait_releases_every_generation() {
+        let barrier = CancellableBarrier::new(3);
+        for generation in 0..3 {
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    let barrier = barrier.clone();
+                    thread::spawn(move || barrier.wait())
+                })
+                .collect();
+            let leader = barrier.wait().unwrap();
+            assert_eq!(leader.generation(), generation);
+            let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+            let leaders = results
+                .iter()
+                .chain([&Ok(leader)])
+                .filter(|result| result.as_ref().unwrap().is_leader())
+                .count();
+            assert_eq!(leaders, 1);
+        }
+        assert_eq!(barrier.try_wait(), Ok(None));
+    }
+
+    #[test]
+    fn test_cancel_and_reset() {
+        let barrier = CancellableBarrier::new(3);
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait())
+        };
+        while barrier.state.acquire().count == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(barrier.cancel_with(CancelReason::Shutdown), 1);
+        let err = waiter.join().unwrap().unwrap_err();
+        assert_eq!(
+            err,
+            BarrierWaitError::Cancelled(CancelledBarrier::new(CancelReason::Shutdown))
+        );
+        assert_eq!(err.to_string(), "barrier cancelled: shutdown");
+        assert!(barrier.wait().is_err());
+
+        // a reset uncancels it, and lets go whoever still waits
+        barrier.reset();
+        assert_eq!(barrier.cancel_reason(), None);
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait())
+        };
+        while barrier.state.acquire().count == 0 {
+            thread::yield_now();
+        }
+        barrier.reset();
+        let result = waiter.join().unwrap().unwrap();
+        assert!(!result.is_leader());
+        assert_eq!(barrier.total(), 3);
+    }
+}
@@ -0,0 +1,169 @@
+//! Lock-free arrivals for threads that aren't the last to arrive.
+//!
+//! While a thread is parked on the barrier, it leaves a window open through which more
+//! arrivals of the same generation can be counted with a compare-and-swap instead of the
+//! mutex. Anyone who takes the lock closes the window first and folds the arrivals it let
+//! in into the locked count, so code holding the lock always sees the full count and never
+//! races with the window. The window is only ever opened by a thread about to park, which
+//! gives up the lock right after, so nothing can change the count or the size behind an
+//! open window's back.
+//!
+//! The window never lets in the arrival that would complete the generation, since
+//! releasing needs the lock anyway, so it can't release a generation on its own.
+
+use std::sync::atomic::Ordering;
+
+use crate::sync::{AtomicBool, AtomicUsize};
+
+/// Bits of the word given to the count, half of it whatever the width of `usize`
+const COUNT_BITS: u32 = usize::BITS / 2;
+/// Arrivals counted since the window opened, in the low bits of the word. A window that
+/// fills up lets nobody else in, and they take the lock instead.
+const COUNT_MASK: usize = (1 << COUNT_BITS) - 1;
+/// Set while the window is open
+const OPEN: usize = 1 << COUNT_BITS;
+/// Bumped every time the window opens, in the bits above [`OPEN`], so that an arrival
+/// can't slip into a later window than the one it read the generation of
+const SEQUENCE_SHIFT: u32 = COUNT_BITS + 1;
+
+#[derive(Debug)]
+pub(super) struct ArrivalWindow {
+    word: AtomicUsize,
+    /// Generation the window is open for
+    generation: AtomicUsize,
+    /// Arrivals counted under the lock when the window opened
+    base: AtomicUsize,
+    /// Size of the barrier when the window opened
+    total: AtomicUsize,
+    /// Mirrors the barrier's generation, so a thread counted through the window can see
+    /// it released without taking the lock
+    current: AtomicUsize,
+    /// Mirrors whether the barrier is paused, which holds released threads back
+    paused: AtomicBool,
+}
+
+impl ArrivalWindow {
+    pub(super) fn new() -> Self {
+        ArrivalWindow {
+            word: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+            base: AtomicUsize::new(0),
+            total: AtomicUsize::new(0),
+            current: AtomicUsize::new(0),
+            paused: AtomicBool::new(false),
+        }
+    }
+
+    /// Opens the window for `generation`, with `base` of `total` arrivals already counted.
+    /// Must be called with the lock held and the window closed.
+    pub(super) fn open(&self, generation: usize, base: usize, total: usize) {
+        let word = self.word.load(Ordering::SeqCst);
+        debug_assert_eq!(word & OPEN, 0, "arrival window opened twice");
+        self.generation.store(generation, Ordering::SeqCst);
+        self.current.store(generation, Ordering::SeqCst);
+        self.base.store(base, Ordering::SeqCst);
+        self.total.store(total, Ordering::SeqCst);
+        let sequence = (word >> SEQUENCE_SHIFT).wrapping_add(1);
+        self.word
+            .store((sequence << SEQUENCE_SHIFT) | OPEN, Ordering::SeqCst);
+    }
+
+    /// Closes the window and returns the arrivals it let in. Must be called with the lock
+    /// held.
+    pub(super) fn close(&self) -> usize {
+        if self.word.load(Ordering::SeqCst) & OPEN == 0 {
+            return 0;
+        }
+        // keep the sequence, so the next window gets a new one
+        self.word.fetch_and(!(OPEN | COUNT_MASK), Ordering::SeqCst) & COUNT_MASK
+    }
+
+    /// Counts an arrival without the lock and returns the generation it arrived in, or
+    /// `None` if the window is closed or this would be the last arrival
+    pub(super) fn arrive(&self) -> Option<usize> {
+        let mut word = self.word.load(Ordering::SeqCst);
+        loop {
+            if word & OPEN == 0 {
+                return None;
+            }
+            // only trusted if the word is still the same when we swap it in below
+            let generation = self.generation.load(Ordering::SeqCst);
+            let base = self.base.load(Ordering::SeqCst);
+            let total = self.total.load(Ordering::SeqCst);
+            let count = word & COUNT_MASK;
+            if count == COUNT_MASK || base + count + 1 >= total {
+                return None;
+            }
+            match self.word.compare_exchange_weak(
+                word,
+                word + 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return Some(generation),
+                Err(current) => word = current,
+            }
+        }
+    }
+
+    /// Returns the arrivals the window has let in since it opened, without closing it
+    #[cfg(test)]
+    pub(super) fn counted(&self) -> usize {
+        self.word.load(Ordering::SeqCst) & COUNT_MASK
+    }
+
+    /// Records that the barrier moved on to `generation`. Must be called with the lock
+    /// held.
+    pub(super) fn set_current(&self, generation: usize) {
+        self.current.store(generation, Ordering::SeqCst);
+    }
+
+    /// Records whether the barrier is paused. Must be called with the lock held.
+    pub(super) fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+    }
+
+    /// Returns true if `generation` has been released, or reset, and its threads may
+    /// return without taking the lock. False negatives are fine, since the caller then
+    /// takes the lock to find out.
+    pub(super) fn released(&self, generation: usize) -> bool {
+        // the generation moves on before a later pause could be seen
+        self.current.load(Ordering::SeqCst) != generation && !self.paused.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_stops_short_of_the_last_arrival() {
+        let window = ArrivalWindow::new();
+        assert_eq!(window.arrive(), None);
+
+        window.open(7, 1, 4);
+        assert_eq!(window.arrive(), Some(7));
+        assert_eq!(window.arrive(), Some(7));
+        // the fourth arrival releases, which takes the lock
+        assert_eq!(window.arrive(), None);
+        assert_eq!(window.close(), 2);
+        assert_eq!(window.close(), 0);
+        assert_eq!(window.arrive(), None);
+
+        window.open(7, 3, 5);
+        assert_eq!(window.arrive(), Some(7));
+        assert_eq!(window.close(), 1);
+    }
+
+    #[test]
+    fn test_window_release_check() {
+        let window = ArrivalWindow::new();
+        window.open(3, 1, 3);
+        assert!(!window.released(3));
+        window.set_paused(true);
+        window.set_current(4);
+        assert!(!window.released(3));
+        window.set_paused(false);
+        assert!(window.released(3));
+    }
+}
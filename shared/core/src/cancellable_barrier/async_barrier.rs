@@ -0,0 +1,71 @@
+use super::{
+    BarrierCancelled, BarrierWait, CancelReason, CancellableBarrier, DefaultSync, RawSync,
+};
+use crate::sync::Arc;
+
+/// An async view of a [`CancellableBarrier`], returned by
+/// [`CancellableBarrier::into_async`].
+///
+/// It shares the barrier it was made from rather than copying it, so threads waiting on
+/// the barrier and tasks awaiting this handle are parties of the same generations, and a
+/// cancellation or reset through either is seen by both. Only the async waits are offered,
+/// so a task holding the handle can't block its executor by mistake; [`Self::barrier`]
+/// gives the barrier back for everything else. Cloning the handle shares the barrier
+/// again.
+#[derive(Debug)]
+pub struct AsyncBarrier<S: RawSync = DefaultSync> {
+    barrier: Arc<CancellableBarrier<S>>,
+}
+
+impl<S: RawSync> AsyncBarrier<S> {
+    pub(super) fn new(barrier: Arc<CancellableBarrier<S>>) -> Self {
+        AsyncBarrier { barrier }
+    }
+
+    /// Waits until all parties have reached the barrier or the barrier is cancelled, as
+    /// [`CancellableBarrier::wait_async`]
+    pub fn wait(&self) -> BarrierWait<'_, S> {
+        self.barrier.wait_async()
+    }
+
+    /// Returns a future that resolves once the barrier is cancelled, as
+    /// [`CancellableBarrier::cancelled`]
+    pub fn cancelled(&self) -> BarrierCancelled<'_, S> {
+        self.barrier.cancelled()
+    }
+
+    /// Cancels the barrier, failing the waits of threads and tasks alike, and returns how
+    /// many had arrived in the generation it cancelled
+    pub fn cancel(&self) -> usize {
+        self.barrier.cancel()
+    }
+
+    /// Cancels the barrier with a `reason`, as [`Self::cancel`]
+    pub fn cancel_with(&self, reason: CancelReason) -> usize {
+        self.barrier.cancel_with(reason)
+    }
+
+    /// Returns true if the barrier is cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.barrier.is_cancelled()
+    }
+
+    /// Returns the barrier this handle awaits
+    #[must_use]
+    pub fn barrier(&self) -> &Arc<CancellableBarrier<S>> {
+        &self.barrier
+    }
+
+    /// Gives back the barrier, for blocking waits again
+    #[must_use]
+    pub fn into_sync(self) -> Arc<CancellableBarrier<S>> {
+        self.barrier
+    }
+}
+
+impl<S: RawSync> Clone for AsyncBarrier<S> {
+    fn clone(&self) -> Self {
+        AsyncBarrier::new(self.barrier.clone())
+    }
+}
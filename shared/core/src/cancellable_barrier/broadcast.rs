@@ -0,0 +1,191 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::PoisonError;
+
+use super::{BarrierWaitError, CancelReason, CancelledBarrier};
+use crate::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// A barrier where the last thread to arrive produces a value that every thread of the
+/// generation receives on release.
+///
+/// Only the leader's closure runs; the others are dropped unused. The value is shared
+/// behind an `Arc`, so it doesn't need to be `Clone`.
+#[derive(Debug)]
+pub struct BarrierBroadcast<T> {
+    mutex: Mutex<BroadcastState<T>>,
+    condvar: Condvar,
+}
+
+#[derive(Debug)]
+struct BroadcastState<T> {
+    total: usize,
+    count: usize,
+    generation: usize,
+    /// The value published by the last release, with the generation it belongs to
+    released: Option<(usize, Arc<T>)>,
+    cancelled: Option<CancelReason>,
+}
+
+impl<T> BarrierBroadcast<T> {
+    /// Creates a new broadcast between `n` threads
+    #[must_use]
+    pub fn new(n: usize) -> Arc<Self> {
+        assert!(n > 0, "Barrier size must be greater than 0");
+        Arc::new(BarrierBroadcast {
+            mutex: Mutex::new(BroadcastState {
+                total: n,
+                count: 0,
+                generation: 0,
+                released: None,
+                cancelled: None,
+            }),
+            condvar: Condvar::new(),
+        })
+    }
+
+    /// Waits until all threads have arrived or the broadcast is cancelled, and returns the
+    /// value produced by the last thread to arrive.
+    ///
+    /// `produce` runs on the leader only, while the broadcast is locked, so it must not
+    /// call back into it. If it panics, the broadcast is cancelled with
+    /// [`CancelReason::Panicked`] so the others don't wait forever, and the panic then
+    /// continues on the leader.
+    pub fn wait_broadcast(&self, produce: impl FnOnce() -> T) -> Result<Arc<T>, BarrierWaitError> {
+        let mut state = self.lock();
+        check_cancelled(&state)?;
+
+        let generation = state.generation;
+        state.count += 1;
+
+        if state.count >= state.total {
+            // Last thread to arrive
+            let value = match panic::catch_unwind(AssertUnwindSafe(produce)) {
+                Ok(value) => Arc::new(value),
+                Err(payload) => {
+                    state.cancelled = Some(CancelReason::Panicked);
+                    self.condvar.notify_all();
+                    drop(state);
+                    panic::resume_unwind(payload);
+                }
+            };
+            state.released = Some((generation, value.clone()));
+            state.count = 0;
+            state.generation = state.generation.wrapping_add(1);
+            self.condvar.notify_all();
+            return Ok(value);
+        }
+
+        while state.generation == generation && state.cancelled.is_none() {
+            state = self
+                .condvar
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+
+        // a generation that was released stays released, even if the broadcast was
+        // cancelled before we got to run again
+        match &state.released {
+            Some((released, value)) if *released == generation => Ok(value.clone()),
+            _ => {
+                check_cancelled(&state)?;
+                // our generation was thrown away by a reset
+                Err(CancelledBarrier::new(CancelReason::Unspecified).into())
+            }
+        }
+    }
+
+    /// Cancels the broadcast, causing all waiting threads to return with an error
+    pub fn cancel(&self) {
+        self.cancel_with(CancelReason::Unspecified);
+    }
+
+    /// Cancels the broadcast with a `reason` that waiting threads receive in their error
+    pub fn cancel_with(&self, reason: CancelReason) {
+        let mut state = self.lock();
+        state.cancelled = Some(reason);
+        self.condvar.notify_all();
+    }
+
+    /// Resets the broadcast to its initial state.
+    ///
+    /// Threads still waiting on the unfinished generation are woken with a cancelled
+    /// error.
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.cancelled = None;
+        state.count = 0;
+        state.released = None;
+        state.generation = state.generation.wrapping_add(1);
+        self.condvar.notify_all();
+    }
+
+    /// Returns true if the broadcast is currently cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled.is_some()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BroadcastState<T>> {
+        self.mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn check_cancelled<T>(state: &BroadcastState<T>) -> Result<(), BarrierWaitError> {
+    match &state.cancelled {
+        Some(reason) => Err(CancelledBarrier::new(reason.clone()).into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_broadcast_runs_leader_only() {
+        let broadcast = BarrierBroadcast::new(4);
+        let produced = std::sync::Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let broadcast = broadcast.clone();
+                let produced = produced.clone();
+                thread::spawn(move || {
+                    broadcast
+                        .wait_broadcast(|| {
+                            produced.fetch_add(1, Ordering::SeqCst);
+                            format!("config from {i}")
+                        })
+                        .unwrap()
+                })
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(produced.load(Ordering::SeqCst), 1);
+        // everyone shares the leader's value
+        assert!(results.iter().all(|r| Arc::ptr_eq(r, &results[0])));
+    }
+
+    #[test]
+    fn test_broadcast_panicking_leader_cancels() {
+        let broadcast = BarrierBroadcast::<()>::new(2);
+
+        let broadcast_clone = broadcast.clone();
+        let waiter = thread::spawn(move || broadcast_clone.wait_broadcast(|| ()));
+        thread::sleep(Duration::from_millis(100));
+        let leader = {
+            let broadcast = broadcast.clone();
+            thread::spawn(move || broadcast.wait_broadcast(|| panic!("no config")))
+        };
+        assert!(leader.join().is_err());
+        assert_eq!(
+            waiter.join().unwrap().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Panicked)
+        );
+
+        broadcast.reset();
+        assert!(!broadcast.is_cancelled());
+    }
+}
@@ -0,0 +1,435 @@
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+use std::fmt;
+
+use super::{
+    BarrierAction, BarrierBuildError, BarrierObserver, CancellableBarrier, Clock, RawSync,
+};
+use crate::sync::Arc;
+
+/// How long a waiting thread keeps checking for a release before it parks.
+///
+/// A waiter first spins for `spins` rounds, each twice as long as the one before up to a
+/// cap, then calls [`std::thread::yield_now`] up to `yields` times, and only then parks.
+/// The default does neither, so waiters park straight away.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackoffConfig {
+    /// Rounds of busy-spinning
+    pub spins: u32,
+    /// Yields to the scheduler once done spinning
+    pub yields: u32,
+}
+
+/// What a barrier does when its action or one of its release listeners panics, as set
+/// with [`CancellableBarrierBuilder::on_callback_panic`].
+///
+/// The panic is caught either way, and the callbacks after the one that panicked still
+/// run. The default releases the generation regardless, so a panicking callback never
+/// leaves the waiters stuck.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CallbackPanicPolicy {
+    /// Releases the generation, then lets the panic continue on the releasing thread
+    #[default]
+    Resume,
+    /// Releases the generation and drops the panic, so the releasing thread returns as
+    /// the leader
+    Ignore,
+    /// Cancels the barrier with
+    /// [`CancelReason::CallbackPanicked`](super::CancelReason::CallbackPanicked) instead
+    /// of releasing, failing the generation's waiters, and lets the panic continue on the
+    /// releasing thread
+    Cancel,
+    /// Aborts the barrier instead of releasing, as [`CancellableBarrier::abort`], and lets
+    /// the panic continue on the releasing thread
+    Abort,
+}
+
+/// Which party of each generation a barrier reports as its leader, through
+/// [`BarrierWaitResult::is_leader`](super::BarrierWaitResult::is_leader), as set with
+/// [`CancellableBarrierBuilder::leader_policy`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LeaderPolicy {
+    /// The party whose arrival released the generation
+    #[default]
+    LastArriver,
+    /// Each [`BarrierHandle`](super::BarrierHandle) in turn, in the order of their
+    /// [`index`](super::BarrierHandle::index), whenever they arrived. Parties that wait
+    /// without a handle never lead while the barrier has handles, and a barrier without
+    /// any falls back to the last arriver.
+    RoundRobin,
+}
+
+/// Configures and constructs a [`CancellableBarrier`]
+#[derive(Clone)]
+pub struct CancellableBarrierBuilder {
+    pub(super) parties: usize,
+    pub(super) quorum: Option<usize>,
+    pub(super) default_timeout: Option<Duration>,
+    pub(super) overall_timeout: Option<Duration>,
+    pub(super) clock: Option<std::sync::Arc<dyn Clock>>,
+    pub(super) backoff: BackoffConfig,
+    pub(super) fair: bool,
+    pub(super) track_threads: bool,
+    pub(super) strict: bool,
+    pub(super) release_on_timeout: bool,
+    pub(super) break_on_give_up: bool,
+    pub(super) remove_lost_parties: bool,
+    pub(super) backpressure: bool,
+    pub(super) on_callback_panic: CallbackPanicPolicy,
+    pub(super) leader_policy: LeaderPolicy,
+    pub(super) max_generations: Option<usize>,
+    pub(super) linked: Option<std::sync::Arc<AtomicBool>>,
+    #[cfg(feature = "metrics")]
+    pub(super) cycle_history: usize,
+    #[cfg(feature = "event-log")]
+    pub(super) event_log_capacity: usize,
+    pub(super) name: Option<std::sync::Arc<str>>,
+    pub(super) action: Option<std::sync::Arc<BarrierAction>>,
+    pub(super) observer: Option<std::sync::Arc<dyn BarrierObserver>>,
+}
+
+impl fmt::Debug for CancellableBarrierBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("CancellableBarrierBuilder");
+        debug
+            .field("parties", &self.parties)
+            .field("quorum", &self.quorum)
+            .field("default_timeout", &self.default_timeout)
+            .field("overall_timeout", &self.overall_timeout)
+            .field("backoff", &self.backoff)
+            .field("fair", &self.fair)
+            .field("track_threads", &self.track_threads)
+            .field("strict", &self.strict)
+            .field("release_on_timeout", &self.release_on_timeout)
+            .field("break_on_give_up", &self.break_on_give_up)
+            .field("backpressure", &self.backpressure)
+            .field("on_callback_panic", &self.on_callback_panic)
+            .field("leader_policy", &self.leader_policy)
+            .field("remove_lost_parties", &self.remove_lost_parties)
+            .field("max_generations", &self.max_generations)
+            .field("linked", &self.linked);
+        #[cfg(feature = "metrics")]
+        debug.field("cycle_history", &self.cycle_history);
+        #[cfg(feature = "event-log")]
+        debug.field("event_log_capacity", &self.event_log_capacity);
+        debug
+            .field("name", &self.name)
+            .field("action", &self.action.as_ref().map(|_| ".."))
+            .field("observer", &self.observer.as_ref().map(|_| ".."))
+            .field("clock", &self.clock.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+impl Default for CancellableBarrierBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CancellableBarrierBuilder {
+    /// Creates a builder for a barrier with a single party and no timeout
+    #[must_use]
+    pub fn new() -> Self {
+        CancellableBarrierBuilder {
+            parties: 1,
+            quorum: None,
+            default_timeout: None,
+            overall_timeout: None,
+            clock: None,
+            backoff: BackoffConfig::default(),
+            fair: false,
+            track_threads: false,
+            strict: false,
+            release_on_timeout: false,
+            break_on_give_up: false,
+            remove_lost_parties: false,
+            backpressure: false,
+            on_callback_panic: CallbackPanicPolicy::default(),
+            leader_policy: LeaderPolicy::default(),
+            max_generations: None,
+            linked: None,
+            #[cfg(feature = "metrics")]
+            cycle_history: 16,
+            #[cfg(feature = "event-log")]
+            event_log_capacity: 256,
+            name: None,
+            action: None,
+            observer: None,
+        }
+    }
+
+    /// Sets the number of threads that must arrive to release the barrier
+    #[must_use]
+    pub fn parties(mut self, n: usize) -> Self {
+        self.parties = n;
+        self
+    }
+
+    /// Releases each generation once `k` of the parties have arrived.
+    ///
+    /// See [`CancellableBarrier::quorum`] for what the remaining parties see.
+    #[must_use]
+    pub fn quorum(mut self, k: usize) -> Self {
+        self.quorum = Some(k);
+        self
+    }
+
+    /// Makes plain [`CancellableBarrier::wait`] calls time out after `timeout`
+    #[must_use]
+    pub fn default_timeout(mut self, timeout: Duration) -> Self {
+        self.default_timeout = Some(timeout);
+        self
+    }
+
+    /// Gives each generation `timeout` from its first arrival to complete, after which
+    /// all its waiters fail together.
+    ///
+    /// Unlike a per-call timeout, which only gives up the caller's own arrival, this
+    /// times out the whole generation: every thread and task waiting on it fails with
+    /// [`BarrierWaitError::TimedOut`](super::BarrierWaitError::TimedOut), and the
+    /// barrier moves on to a fresh generation as if it had been reset. The deadline is
+    /// enforced by the waiters themselves, so a generation only tasks are waiting on
+    /// times out the next time one of them is polled.
+    ///
+    /// A cancelled barrier doesn't time out, since everyone waiting on it has already
+    /// failed.
+    #[must_use]
+    pub fn overall_timeout(mut self, timeout: Duration) -> Self {
+        self.overall_timeout = Some(timeout);
+        self
+    }
+
+    /// Makes waiting threads spin and yield as configured before they park.
+    ///
+    /// While a thread is parked, the next ones to arrive are counted without taking the
+    /// barrier's lock, and those that see the release while spinning return without ever
+    /// taking it, which takes most of the contention off the lock when many threads
+    /// arrive together. That shortcut is off on barriers with a quorum, fairness, thread
+    /// tracking, strict mode, partial release on timeout, an overall timeout or an
+    /// observer.
+    #[must_use]
+    pub fn backoff(mut self, backoff: BackoffConfig) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Makes released threads return from their wait in the order they arrived.
+    ///
+    /// Off by default, since it hands the lock from one waiter to the next instead of
+    /// letting them all race for it. The order covers threads parked in a blocking
+    /// wait; the releasing thread and async waiters return without taking a turn.
+    #[must_use]
+    pub fn fair(mut self, fair: bool) -> Self {
+        self.fair = fair;
+        self
+    }
+
+    /// Keeps track of which threads have arrived in the current generation, so that a
+    /// second arrival from the same thread fails with
+    /// [`BarrierWaitError::AlreadyArrived`](super::BarrierWaitError::AlreadyArrived)
+    /// instead of counting twice.
+    ///
+    /// Meant as a debugging aid for barriers where every party is its own thread. Async
+    /// waits count as arrivals of the thread that first polls them, so tasks that share
+    /// an executor thread would be rejected.
+    ///
+    /// With the `diagnostics` feature, a wait that times out also lists the threads that
+    /// took part in the previous generation but haven't arrived in this one.
+    #[must_use]
+    pub fn track_threads(mut self, track_threads: bool) -> Self {
+        self.track_threads = track_threads;
+        self
+    }
+
+    /// Rejects arrivals with
+    /// [`BarrierWaitError::TooManyParties`](super::BarrierWaitError::TooManyParties)
+    /// when more threads are using the barrier than it has parties.
+    ///
+    /// Normally extra arrivals quietly count towards the next generation. In strict mode
+    /// an arrival fails if as many threads as there are parties are already inside the
+    /// barrier: parked, or released but not yet returned. That can only happen with too
+    /// many participants, so there are no false alarms, but a surplus thread that happens
+    /// to arrive while the others are outside won't be caught. Shrinking a barrier with
+    /// waiters inside can trip the check.
+    #[must_use]
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Makes a wait that times out release the generation with whoever has arrived,
+    /// instead of failing with
+    /// [`BarrierWaitError::TimedOut`](super::BarrierWaitError::TimedOut).
+    ///
+    /// Meant for best-effort work where going ahead without a laggard is acceptable. The
+    /// thread whose timeout released the generation is its leader, and every thread of
+    /// the generation can tell it was incomplete from
+    /// [`BarrierWaitResult::completed_partial`](super::BarrierWaitResult::completed_partial).
+    /// Off by default, so timeouts stay errors.
+    #[must_use]
+    pub fn release_on_timeout(mut self, release_on_timeout: bool) -> Self {
+        self.release_on_timeout = release_on_timeout;
+        self
+    }
+
+    /// Breaks the barrier when a waiting thread gives up, as Java's `CyclicBarrier` does.
+    ///
+    /// Once a wait times out or is interrupted, the barrier is cancelled with
+    /// [`CancelReason::Broken`](super::CancelReason::Broken): the thread that gave up gets
+    /// its own error, and every other wait of the generation, and every wait after it,
+    /// fails with the cancellation until the barrier is [`reset`](CancellableBarrier::reset).
+    /// Meant for all-or-nothing work where a partial arrival is a bug. Off by default, so
+    /// a thread that gives up only takes back its own arrival. A barrier that also
+    /// [releases on timeout](Self::release_on_timeout) releases instead of breaking when a
+    /// wait times out.
+    #[must_use]
+    pub fn break_on_give_up(mut self, break_on_give_up: bool) -> Self {
+        self.break_on_give_up = break_on_give_up;
+        self
+    }
+
+    /// Makes a participant lost by its
+    /// [`LivenessToken`](super::LivenessToken) leave the barrier, as with
+    /// [`CancellableBarrier::remove_party`], instead of cancelling it.
+    ///
+    /// The remaining parties are released if they are all waiting, and carry on without
+    /// the lost one from then on. A barrier down to its last party is cancelled all the
+    /// same. Off by default, since going on short-handed is rarely what the others expect.
+    #[must_use]
+    pub fn remove_lost_parties(mut self, remove_lost_parties: bool) -> Self {
+        self.remove_lost_parties = remove_lost_parties;
+        self
+    }
+
+    /// Keeps arrivals for the next generation waiting until the thread that released the
+    /// last one is done processing it, so that fast parties stay at most one generation
+    /// ahead of a slow leader.
+    ///
+    /// Without it a leader that processes each generation after its wait returns, outside
+    /// the barrier's lock, can fall ever further behind parties that come straight back.
+    /// With it, once a generation is released, arrivals at the next one block, without
+    /// being counted, until the releasing thread calls
+    /// [`CancellableBarrier::mark_processed`] or arrives again itself. Cancelling the
+    /// barrier fails the blocked arrivals, and resetting it drops the wait. Meant for a
+    /// barrier whose leader does the processing; off by default.
+    #[must_use]
+    pub fn backpressure(mut self, backpressure: bool) -> Self {
+        self.backpressure = backpressure;
+        self
+    }
+
+    /// Chooses what happens to a generation whose action or release listener panics. See
+    /// [`CallbackPanicPolicy`]; by default the generation is released all the same.
+    #[must_use]
+    pub fn on_callback_panic(mut self, policy: CallbackPanicPolicy) -> Self {
+        self.on_callback_panic = policy;
+        self
+    }
+
+    /// Chooses which party of each generation is its leader. See [`LeaderPolicy`]; by
+    /// default it is the last to arrive.
+    ///
+    /// Rotating the role spreads the work the leader does once its wait returns, which
+    /// would otherwise fall on whichever thread tends to be slowest. The action, and the
+    /// work passed to [`CancellableBarrier::wait_then`], still run on the thread that
+    /// releases the generation, since they run before anyone else is woken.
+    #[must_use]
+    pub fn leader_policy(mut self, policy: LeaderPolicy) -> Self {
+        self.leader_policy = policy;
+        self
+    }
+
+    /// Cancels the barrier once it has released `generations` generations, as a safety
+    /// valve against a loop that would otherwise go round the barrier forever.
+    ///
+    /// The first arrival after the last allowed release cancels the barrier with
+    /// [`CancelReason::MaxGenerationsReached`](super::CancelReason::MaxGenerationsReached)
+    /// instead of starting another generation, so it and every wait after it fail. Late
+    /// parties of the last generation still pass. Resetting the barrier doesn't lift the
+    /// cap, and generations released by force or by a timeout count towards it. By
+    /// default there is no cap.
+    #[must_use]
+    pub fn max_generations(mut self, generations: usize) -> Self {
+        self.max_generations = Some(generations);
+        self
+    }
+
+    /// Cancels the barrier with [`CancelReason::Shutdown`](super::CancelReason::Shutdown)
+    /// once `flag` is set. See [`CancellableBarrier::linked_to`].
+    #[must_use]
+    pub fn linked_to(mut self, flag: std::sync::Arc<AtomicBool>) -> Self {
+        self.linked = Some(flag);
+        self
+    }
+
+    /// Keeps the last `generations` released generations for
+    /// [`CancellableBarrier::recent_cycles`], instead of the default 16. With 0 none are
+    /// kept.
+    #[cfg(feature = "metrics")]
+    #[must_use]
+    pub fn cycle_history(mut self, generations: usize) -> Self {
+        self.cycle_history = generations;
+        self
+    }
+
+    /// Keeps the last `events` transitions for [`CancellableBarrier::event_log`], instead
+    /// of the default 256. With 0 none are kept.
+    #[cfg(feature = "event-log")]
+    #[must_use]
+    pub fn event_log_capacity(mut self, events: usize) -> Self {
+        self.event_log_capacity = events;
+        self
+    }
+
+    /// Attaches a name to the barrier for diagnostics
+    #[must_use]
+    pub fn name(mut self, name: impl Into<std::sync::Arc<str>>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Runs `action` with the generation once per generation, on the thread that releases
+    /// it, before any waiter is woken. See [`CancellableBarrier::with_action`].
+    #[must_use]
+    pub fn action(mut self, action: impl Fn(usize) + Send + Sync + 'static) -> Self {
+        self.action = Some(std::sync::Arc::new(action));
+        self
+    }
+
+    /// Attaches an observer that is told about every arrival, release and cancellation.
+    /// See [`BarrierObserver`].
+    #[must_use]
+    pub fn observer(mut self, observer: impl BarrierObserver + 'static) -> Self {
+        self.observer = Some(std::sync::Arc::new(observer));
+        self
+    }
+
+    /// Reads time off `clock` for every timeout, deadline and stuck check, instead of the
+    /// system clock. See [`Clock`].
+    #[must_use]
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(std::sync::Arc::new(clock));
+        self
+    }
+
+    /// Builds the barrier, failing if the configuration is invalid
+    pub fn build(self) -> Result<Arc<CancellableBarrier>, BarrierBuildError> {
+        self.build_with()
+    }
+
+    /// Builds the barrier on the lock and condition variable of `S` instead of the
+    /// default ones, failing if the configuration is invalid. See [`RawSync`].
+    pub fn build_with<S: RawSync>(self) -> Result<Arc<CancellableBarrier<S>>, BarrierBuildError> {
+        if self.parties == 0 {
+            return Err(BarrierBuildError::ZeroParties);
+        }
+        if self.quorum.is_some_and(|k| k == 0 || k > self.parties) {
+            return Err(BarrierBuildError::InvalidQuorum);
+        }
+        Ok(Arc::new(CancellableBarrier::from_builder(self)))
+    }
+}
@@ -0,0 +1,198 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use super::{CancelReason, CancellableBarrier, DefaultSync, RawSync};
+use crate::sync::Arc;
+
+/// A flat group of barriers cancelled and reset as a unit, such as the stages of one
+/// pipeline.
+///
+/// Unlike [`CancellableBarrier::link_child`], membership is explicit and there is no
+/// hierarchy: cancelling a member on its own leaves the others alone, and a barrier can
+/// belong to any number of groups. The group holds on to its members until they are
+/// unregistered.
+///
+/// [`Self::cancel_all`] cancels the members one after the other, not under a common lock,
+/// but a barrier registered at the same time can't slip through: once the group is
+/// cancelled, registering a barrier cancels it on the spot with the group's reason, until
+/// [`Self::reset_all`] restarts the group.
+#[derive(Debug)]
+pub struct CancelGroup<S: RawSync = DefaultSync> {
+    inner: Mutex<GroupState<S>>,
+}
+
+#[derive(Debug)]
+struct GroupState<S: RawSync> {
+    members: Vec<Arc<CancellableBarrier<S>>>,
+    /// Why the group was cancelled, until it is reset
+    cancelled: Option<CancelReason>,
+}
+
+impl<S: RawSync> Default for CancelGroup<S> {
+    fn default() -> Self {
+        CancelGroup {
+            inner: Mutex::new(GroupState {
+                members: Vec::new(),
+                cancelled: None,
+            }),
+        }
+    }
+}
+
+impl<S: RawSync> CancelGroup<S> {
+    /// Creates an empty group
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `barrier` to the group, cancelling it straight away if the group is
+    /// cancelled. Registering a barrier that is already a member does nothing.
+    pub fn register(&self, barrier: Arc<CancellableBarrier<S>>) {
+        let mut inner = self.lock();
+        if inner
+            .members
+            .iter()
+            .any(|member| Arc::ptr_eq(member, &barrier))
+        {
+            return;
+        }
+        let cancelled = inner.cancelled.clone();
+        inner.members.push(barrier.clone());
+        drop(inner);
+        if let Some(reason) = cancelled {
+            barrier.cancel_with(reason);
+        }
+    }
+
+    /// Removes `barrier` from the group, returning false if it wasn't a member. The
+    /// barrier itself is left as it is.
+    pub fn unregister(&self, barrier: &CancellableBarrier<S>) -> bool {
+        let mut inner = self.lock();
+        let before = inner.members.len();
+        inner
+            .members
+            .retain(|member| !std::ptr::eq(&**member, barrier));
+        inner.members.len() != before
+    }
+
+    /// Cancels every member with `reason`, and any barrier registered until the group
+    /// is reset
+    pub fn cancel_all(&self, reason: CancelReason) {
+        let members = {
+            let mut inner = self.lock();
+            inner.cancelled = Some(reason.clone());
+            inner.members.clone()
+        };
+        // without our lock, so cancel listeners can use the group
+        for member in members {
+            member.cancel_with(reason.clone());
+        }
+    }
+
+    /// Resets every member, and lets barriers registered from now on be
+    pub fn reset_all(&self) {
+        let members = {
+            let mut inner = self.lock();
+            inner.cancelled = None;
+            inner.members.clone()
+        };
+        for member in members {
+            member.reset();
+        }
+    }
+
+    /// Returns true if the group has been cancelled and not reset since
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled.is_some()
+    }
+
+    /// Returns the number of barriers in the group
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().members.len()
+    }
+
+    /// Returns true if no barriers are registered
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.lock().members.is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, GroupState<S>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_cancel_group() {
+        let first: CancelGroup = CancelGroup::new();
+        let second = CancelGroup::new();
+        let shared = CancellableBarrier::new(2);
+        let only_first = CancellableBarrier::new(2);
+        first.register(shared.clone());
+        first.register(shared.clone());
+        first.register(only_first.clone());
+        second.register(shared.clone());
+        assert_eq!((first.len(), second.len()), (2, 1));
+
+        let waiter = {
+            let only_first = only_first.clone();
+            thread::spawn(move || only_first.wait())
+        };
+        first.cancel_all(CancelReason::Shutdown);
+        assert_eq!(
+            waiter.join().unwrap().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Shutdown)
+        );
+        assert!(shared.is_cancelled());
+
+        // late members of a cancelled group are cancelled as they join
+        let late = CancellableBarrier::new(2);
+        first.register(late.clone());
+        assert_eq!(late.cancel_reason(), Some(CancelReason::Shutdown));
+
+        first.reset_all();
+        assert!(!first.is_cancelled());
+        assert!(!shared.is_cancelled() && !only_first.is_cancelled() && !late.is_cancelled());
+
+        // cancelling through the second group leaves the first one's other members alone
+        assert!(first.unregister(&shared));
+        assert!(!first.unregister(&shared));
+        second.cancel_all(CancelReason::Error);
+        assert!(shared.is_cancelled());
+        assert!(!only_first.is_cancelled());
+    }
+
+    #[test]
+    fn test_cancel_group_concurrent_registration() {
+        let group = std::sync::Arc::new(CancelGroup::new());
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let group = group.clone();
+                thread::spawn(move || {
+                    (0..16)
+                        .map(|_| {
+                            let barrier = CancellableBarrier::new(2);
+                            group.register(barrier.clone());
+                            barrier
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        group.cancel_all(CancelReason::Shutdown);
+        // registered before or after, every barrier ends up cancelled
+        for handle in handles {
+            for barrier in handle.join().unwrap() {
+                assert!(barrier.is_cancelled());
+            }
+        }
+        assert_eq!(group.len(), 8 * 16);
+    }
+}
@@ -0,0 +1,29 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// An external cancellation flag that a wait can be tied to with
+/// [`super::CancellableBarrier::wait_until`].
+///
+/// Implemented for `Fn() -> bool` closures and for [`AtomicBool`], directly or behind an
+/// `Arc`, so one shutdown flag can be shared by any number of barriers.
+pub trait CancelSignal {
+    /// Returns true once the waits tied to this signal should give up
+    fn is_cancelled(&self) -> bool;
+}
+
+impl<F: Fn() -> bool> CancelSignal for F {
+    fn is_cancelled(&self) -> bool {
+        self()
+    }
+}
+
+impl CancelSignal for AtomicBool {
+    fn is_cancelled(&self) -> bool {
+        self.load(Ordering::Acquire)
+    }
+}
+
+impl<T: CancelSignal + ?Sized> CancelSignal for std::sync::Arc<T> {
+    fn is_cancelled(&self) -> bool {
+        (**self).is_cancelled()
+    }
+}
@@ -0,0 +1,55 @@
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Weak;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{CancelReason, CancellableBarrier, RawSync};
+
+/// Handle to a cancellation scheduled with [`CancellableBarrier::cancel_after`].
+///
+/// Dropping the handle leaves the cancellation scheduled; call [`Self::abort`] to call it
+/// off.
+#[derive(Debug)]
+pub struct CancelTimer {
+    abort: mpsc::Sender<()>,
+}
+
+impl CancelTimer {
+    pub(super) fn spawn<S: RawSync>(
+        barrier: Weak<CancellableBarrier<S>>,
+        generation: usize,
+        timeout: Duration,
+    ) -> Self {
+        let (abort, aborted) = mpsc::channel();
+        // a timeout too large to represent never fires
+        let deadline = Instant::now().checked_add(timeout);
+
+        thread::Builder::new()
+            .name("barrier-cancel-timer".to_string())
+            .spawn(move || {
+                match aborted.recv_timeout(timeout) {
+                    Ok(()) => return,
+                    Err(RecvTimeoutError::Timeout) => {}
+                    // the handle was dropped, which doesn't abort the timer
+                    Err(RecvTimeoutError::Disconnected) => match deadline {
+                        Some(deadline) => {
+                            thread::sleep(deadline.saturating_duration_since(Instant::now()))
+                        }
+                        None => return,
+                    },
+                }
+                if let Some(barrier) = barrier.upgrade() {
+                    barrier.cancel_generation(generation, CancelReason::Timeout);
+                }
+            })
+            .expect("failed to spawn barrier cancel timer thread");
+
+        CancelTimer { abort }
+    }
+
+    /// Calls off the scheduled cancellation, if it hasn't fired yet
+    pub fn abort(self) {
+        // the timer thread is gone if it already fired
+        let _ = self.abort.send(());
+    }
+}
@@ -0,0 +1,79 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::{CancellableBarrier, DefaultSync, RawSync};
+
+/// Future returned by [`CancellableBarrier::cancelled`], resolving once the barrier is
+/// cancelled.
+///
+/// Like [`super::BarrierWait`], a named `Unpin` and `Send` type, for `select!` without
+/// boxing. It doesn't arrive at the barrier, so any number of tasks can await it at once
+/// without affecting who the barrier waits for. On the first poll it resolves straight
+/// away if the barrier is already cancelled; after that, it resolves on any cancellation
+/// from then on, even one undone by a reset before the task got to run again. Dropping
+/// it removes its waker from the barrier.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct BarrierCancelled<'a, S: RawSync = DefaultSync> {
+    barrier: &'a CancellableBarrier<S>,
+    /// Key of the waker kept in the barrier, with the barrier's count of cancellations
+    /// when it was first polled
+    registered: Option<(usize, u64)>,
+}
+
+impl<'a, S: RawSync> BarrierCancelled<'a, S> {
+    pub(super) fn new(barrier: &'a CancellableBarrier<S>) -> Self {
+        BarrierCancelled {
+            barrier,
+            registered: None,
+        }
+    }
+}
+
+impl<S: RawSync> Future for BarrierCancelled<'_, S> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        this.barrier.check_linked();
+        let mut state = this.barrier.lock();
+
+        let cancellations = state.cancellations;
+        let since = this
+            .registered
+            .map_or(cancellations, |(_, cancellations)| cancellations);
+        if state.cancelled.is_some() || cancellations != since {
+            if let Some((waker_key, _)) = this.registered.take() {
+                state.wakers.remove(&waker_key);
+            }
+            return Poll::Ready(());
+        }
+
+        match this.registered {
+            // woken by anything the barrier does, so the waker has to go back in
+            Some((waker_key, _)) => match state.wakers.get_mut(&waker_key) {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                Some(waker) => waker.clone_from(cx.waker()),
+                None => {
+                    state.wakers.insert(waker_key, cx.waker().clone());
+                }
+            },
+            None => {
+                let waker_key = state.next_waker_key;
+                state.next_waker_key = state.next_waker_key.wrapping_add(1);
+                state.wakers.insert(waker_key, cx.waker().clone());
+                this.registered = Some((waker_key, cancellations));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<S: RawSync> Drop for BarrierCancelled<'_, S> {
+    fn drop(&mut self) {
+        if let Some((waker_key, _)) = self.registered {
+            self.barrier.lock().wakers.remove(&waker_key);
+        }
+    }
+}
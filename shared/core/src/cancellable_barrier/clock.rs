@@ -0,0 +1,141 @@
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The source of time a barrier reads for its timeouts, deadlines and stuck checks,
+/// attached with [`super::CancellableBarrierBuilder::clock`].
+///
+/// [`SystemClock`] is used unless another is given. A clock that doesn't follow real
+/// time, such as [`MockClock`], makes every timeout path run at the pace the caller moves
+/// it, so tests can exercise them without sleeping. Metrics are timed by it too, all but
+/// the critical sections, which measure how long the lock was really held.
+///
+/// Also implemented for a clock behind an `Arc`, so the caller can keep a handle on it,
+/// to move it along.
+pub trait Clock: Send + Sync {
+    /// Returns the current time
+    fn now(&self) -> Instant;
+
+    /// Returns how long a blocked wait may sleep before reading the clock again, or
+    /// `None` to sleep until the next deadline.
+    ///
+    /// The default suits a clock that follows real time. One that jumps ahead on its own
+    /// terms should return a short interval, so that waiters notice the jump soon after it
+    /// happens.
+    fn poll_interval(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl<T: Clock + ?Sized> Clock for std::sync::Arc<T> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn poll_interval(&self) -> Option<Duration> {
+        (**self).poll_interval()
+    }
+}
+
+/// The default [`Clock`], reading [`Instant::now`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A [`Clock`] that only moves when told to, for deterministic tests of timeouts.
+///
+/// It starts at the real time it was created at and stays there until
+/// [`Self::advance`] moves it. A thread blocked on a barrier reading it wakes within a
+/// millisecond of the clock passing its deadline.
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<Instant>,
+}
+
+/// How often a wait on a [`MockClock`] looks at it again
+const MOCK_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+impl MockClock {
+    /// Creates a clock stopped at the current time
+    #[must_use]
+    pub fn new() -> Self {
+        MockClock {
+            now: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Moves the clock forward by `by`
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock().unwrap_or_else(PoisonError::into_inner);
+        *now += by;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn poll_interval(&self) -> Option<Duration> {
+        Some(MOCK_POLL_INTERVAL)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::cancellable_barrier::{BarrierWaitError, CancellableBarrier};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_mock_clock_drives_timeouts() {
+        let clock = Arc::new(MockClock::new());
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait_timeout(Duration::from_secs(3600)))
+        };
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        assert!(!barrier.is_stuck(Duration::from_secs(60)));
+
+        // an hour passes in no time at all
+        clock.advance(Duration::from_secs(60));
+        assert!(barrier.is_stuck(Duration::from_secs(60)));
+        clock.advance(Duration::from_secs(3540));
+        assert!(matches!(
+            waiter.join().unwrap(),
+            Err(BarrierWaitError::TimedOut {
+                arrived: 1,
+                missing: 1,
+                ..
+            })
+        ));
+        assert_eq!(barrier.state().arrived, 0);
+
+        // deadlines are read off the same clock
+        let deadline = clock.now() + Duration::from_secs(1);
+        clock.advance(Duration::from_secs(2));
+        assert!(matches!(
+            barrier.wait_deadline(deadline),
+            Err(BarrierWaitError::TimedOut { .. })
+        ));
+    }
+}
@@ -0,0 +1,172 @@
+use std::mem;
+use std::sync::PoisonError;
+
+use super::{BarrierWaitError, CancelReason, CancelledBarrier};
+use crate::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// A barrier where every thread contributes a value and, once all have arrived, only the
+/// last one to arrive gets them all.
+///
+/// This is a [`super::BarrierExchange`] for when a single coordinator continues with the
+/// values: the leader takes the generation's buffer and the other threads get nothing
+/// back, so no buffer is shared or cloned.
+#[derive(Debug)]
+pub struct BarrierCollect<T> {
+    mutex: Mutex<CollectState<T>>,
+    condvar: Condvar,
+}
+
+#[derive(Debug)]
+struct CollectState<T> {
+    total: usize,
+    generation: usize,
+    values: Vec<T>,
+    /// The generation released last, to tell a release from a reset
+    released: Option<usize>,
+    cancelled: Option<CancelReason>,
+}
+
+impl<T> BarrierCollect<T> {
+    /// Creates a new collection between `n` threads
+    #[must_use]
+    pub fn new(n: usize) -> Arc<Self> {
+        assert!(n > 0, "Barrier size must be greater than 0");
+        Arc::new(BarrierCollect {
+            mutex: Mutex::new(CollectState {
+                total: n,
+                generation: 0,
+                values: Vec::with_capacity(n),
+                released: None,
+                cancelled: None,
+            }),
+            condvar: Condvar::new(),
+        })
+    }
+
+    /// Contributes `value` and waits until all threads have contributed theirs or the
+    /// collection is cancelled.
+    ///
+    /// On release, the leader gets `Some` of the values of its generation in arrival
+    /// order, and every other thread gets `None`.
+    pub fn wait_collect(&self, value: T) -> Result<Option<Vec<T>>, BarrierWaitError> {
+        let mut state = self.lock();
+        check_cancelled(&state)?;
+
+        let generation = state.generation;
+        state.values.push(value);
+
+        if state.values.len() >= state.total {
+            // Last thread to arrive
+            let capacity = state.total;
+            let values = mem::replace(&mut state.values, Vec::with_capacity(capacity));
+            state.released = Some(generation);
+            state.generation = state.generation.wrapping_add(1);
+            self.condvar.notify_all();
+            return Ok(Some(values));
+        }
+
+        while state.generation == generation && state.cancelled.is_none() {
+            state = self
+                .condvar
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+
+        // a generation that was released stays released, even if the collection was
+        // cancelled before we got to run again
+        if state.released == Some(generation) {
+            return Ok(None);
+        }
+        check_cancelled(&state)?;
+        // our generation was thrown away by a reset
+        Err(CancelledBarrier::new(CancelReason::Unspecified).into())
+    }
+
+    /// Cancels the collection, causing all waiting threads to return with an error
+    pub fn cancel(&self) {
+        self.cancel_with(CancelReason::Unspecified);
+    }
+
+    /// Cancels the collection with a `reason` that waiting threads receive in their error
+    pub fn cancel_with(&self, reason: CancelReason) {
+        let mut state = self.lock();
+        state.cancelled = Some(reason);
+        self.condvar.notify_all();
+    }
+
+    /// Resets the collection to its initial state.
+    ///
+    /// Values contributed to the unfinished generation are dropped, so they can't leak
+    /// into the next one. Threads still waiting on it are woken with a cancelled error.
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.cancelled = None;
+        state.values.clear();
+        state.released = None;
+        state.generation = state.generation.wrapping_add(1);
+        self.condvar.notify_all();
+    }
+
+    /// Returns true if the collection is currently cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled.is_some()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CollectState<T>> {
+        self.mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn check_cancelled<T>(state: &CollectState<T>) -> Result<(), BarrierWaitError> {
+    match &state.cancelled {
+        Some(reason) => Err(CancelledBarrier::new(reason.clone()).into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_collect_only_leader_gets_values() {
+        let collect = BarrierCollect::new(3);
+
+        let handles: Vec<_> = (0..3)
+            .map(|i| {
+                let collect = collect.clone();
+                thread::spawn(move || collect.wait_collect(i).unwrap())
+            })
+            .collect();
+        let mut results: Vec<_> = handles
+            .into_iter()
+            .filter_map(|h| h.join().unwrap())
+            .collect();
+
+        assert_eq!(results.len(), 1);
+        results[0].sort();
+        assert_eq!(results[0], vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_collect_reset_drops_values() {
+        let collect = BarrierCollect::new(2);
+
+        let collect_clone = collect.clone();
+        let t1 = thread::spawn(move || collect_clone.wait_collect(1));
+        thread::sleep(Duration::from_millis(100));
+        collect.reset();
+        assert!(t1.join().unwrap().is_err());
+
+        let collect_clone = collect.clone();
+        let t1 = thread::spawn(move || collect_clone.wait_collect(2));
+        let leader = collect.wait_collect(3).unwrap();
+        let follower = t1.join().unwrap().unwrap();
+        let mut values = leader.or(follower).unwrap();
+        values.sort();
+        assert_eq!(values, vec![2, 3]);
+    }
+}
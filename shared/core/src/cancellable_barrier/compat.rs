@@ -0,0 +1,90 @@
+use super::{BarrierWaitResult, CancellableBarrier};
+use crate::sync::Arc;
+
+/// A barrier with the same API as [`std::sync::Barrier`], backed by a
+/// [`CancellableBarrier`].
+///
+/// This is meant for migrating code one step at a time: swap the type in, then reach for
+/// the underlying barrier through [`Self::cancellable`] where cancellation is wanted.
+///
+/// Differences from [`std::sync::Barrier`]:
+/// - [`Self::wait`] returns this crate's [`BarrierWaitResult`], which has the same
+///   `is_leader` method plus the released generation.
+/// - Cloning is cheap and clones share the same barrier, so there's no need to wrap it in
+///   an `Arc`, although that works too.
+/// - If the underlying barrier is cancelled, [`Self::wait`] panics, since the std API has
+///   no way to report it. Use [`CancellableBarrier::wait`] wherever cancellation can
+///   happen.
+#[derive(Debug, Clone)]
+pub struct CompatBarrier {
+    inner: Arc<CancellableBarrier>,
+}
+
+impl CompatBarrier {
+    /// Creates a new barrier that can block `n` threads.
+    ///
+    /// As with [`std::sync::Barrier::new`], a barrier of 0 behaves like a barrier of 1.
+    #[must_use]
+    pub fn new(n: usize) -> Self {
+        CompatBarrier {
+            inner: CancellableBarrier::new(n.max(1)),
+        }
+    }
+
+    /// Blocks the current thread until all threads have rendezvoused here.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the underlying barrier is cancelled.
+    pub fn wait(&self) -> BarrierWaitResult {
+        match self.inner.wait() {
+            Ok(result) => result,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    /// Returns the underlying barrier, for the code that wants to cancel or observe it
+    #[must_use]
+    pub fn cancellable(&self) -> &Arc<CancellableBarrier> {
+        &self.inner
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_compat_barrier_single_leader() {
+        let barrier = CompatBarrier::new(4);
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait().is_leader())
+            })
+            .collect();
+        let leaders = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|&is_leader| is_leader)
+            .count();
+        assert_eq!(leaders, 1);
+    }
+
+    #[test]
+    fn test_compat_barrier_of_zero() {
+        let barrier = CompatBarrier::new(0);
+        assert!(barrier.wait().is_leader());
+        assert!(barrier.wait().is_leader());
+    }
+
+    #[test]
+    #[should_panic(expected = "barrier cancelled")]
+    fn test_compat_barrier_panics_when_cancelled() {
+        let barrier = CompatBarrier::new(2);
+        barrier.cancellable().cancel();
+        barrier.wait();
+    }
+}
@@ -0,0 +1,230 @@
+use std::sync::PoisonError;
+use std::time::{Duration, Instant};
+
+use super::{BarrierWaitError, CancelReason, CancelledBarrier};
+use crate::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// A cyclic latch that releases its waiters once [`Self::signal`] has been called `n`
+/// times, by any thread.
+///
+/// Where [`super::CancellableBarrier`] counts the waiting threads themselves, here the
+/// parties that signal and the parties that wait are separate: signalling never blocks,
+/// and any number of threads can wait, or none at all. That fits events that must pile up
+/// before going on, such as `n` files finishing downloading.
+///
+/// Each `n`th signal completes a generation and wakes everyone waiting for it, and
+/// counting starts again from zero for the next one. Signals beyond the `n`th count
+/// towards the next generation, so none is lost when signals come in faster than the
+/// waiters can keep up. A thread that starts waiting after a generation completed waits
+/// for the following one.
+///
+/// Cancelling wakes the waiters with an error and fails every wait until
+/// [`Self::reset`]. Signals are still counted in the meantime.
+#[derive(Debug)]
+pub struct CountUpLatch {
+    mutex: Mutex<CountUpState>,
+    condvar: Condvar,
+}
+
+#[derive(Debug)]
+struct CountUpState {
+    target: usize,
+    signals: usize,
+    generation: usize,
+    cancelled: Option<CancelReason>,
+}
+
+impl CountUpLatch {
+    /// Creates a latch that releases its waiters every `n` calls to [`Self::signal`]
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is 0.
+    #[must_use]
+    pub fn new(n: usize) -> Arc<Self> {
+        assert!(n > 0, "a count-up latch needs at least one signal");
+        Arc::new(CountUpLatch {
+            mutex: Mutex::new(CountUpState {
+                target: n,
+                signals: 0,
+                generation: 0,
+                cancelled: None,
+            }),
+            condvar: Condvar::new(),
+        })
+    }
+
+    /// Counts one signal, completing the generation and waking its waiters if it is the
+    /// `n`th. Returns the generation the signal counted towards.
+    pub fn signal(&self) -> usize {
+        let mut state = self.lock();
+        let generation = state.generation;
+        state.signals += 1;
+        if state.signals == state.target {
+            state.signals = 0;
+            state.generation = state.generation.wrapping_add(1);
+            self.condvar.notify_all();
+        }
+        generation
+    }
+
+    /// Waits until the generation in progress completes, returning it, or until the latch
+    /// is cancelled
+    pub fn wait(&self) -> Result<usize, BarrierWaitError> {
+        self.wait_inner(None)
+    }
+
+    /// Like [`Self::wait`], but gives up once `timeout` has elapsed, returning
+    /// [`BarrierWaitError::TimedOut`]
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<usize, BarrierWaitError> {
+        self.wait_inner(Instant::now().checked_add(timeout))
+    }
+
+    fn wait_inner(&self, deadline: Option<Instant>) -> Result<usize, BarrierWaitError> {
+        let mut state = self.lock();
+        let generation = state.generation;
+        while state.generation == generation {
+            if let Some(reason) = &state.cancelled {
+                return Err(CancelledBarrier::new(reason.clone()).into());
+            }
+            // recompute what's left every time we wake, spurious or not
+            let now = Instant::now();
+            match deadline {
+                Some(deadline) if now >= deadline => {
+                    let missing = state.target - state.signals;
+                    return Err(BarrierWaitError::timed_out(state.signals, missing));
+                }
+                Some(deadline) => {
+                    state = self
+                        .condvar
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                }
+                None => {
+                    state = self
+                        .condvar
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner)
+                }
+            }
+        }
+        Ok(generation)
+    }
+
+    /// Cancels the latch, causing all waiting threads to return with an error
+    pub fn cancel(&self) {
+        self.cancel_with(CancelReason::Unspecified);
+    }
+
+    /// Cancels the latch with a `reason` that waiting threads receive in their error
+    pub fn cancel_with(&self, reason: CancelReason) {
+        let mut state = self.lock();
+        state.cancelled = Some(reason);
+        self.condvar.notify_all();
+    }
+
+    /// Clears a cancellation and throws away the signals counted towards the generation
+    /// in progress, which needs `n` fresh ones to complete. Threads still waiting on it
+    /// keep waiting.
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.cancelled = None;
+        state.signals = 0;
+    }
+
+    /// Returns the number of signals counted towards the generation in progress
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.lock().signals
+    }
+
+    /// Returns the number of generations completed so far
+    #[must_use]
+    pub fn generation(&self) -> usize {
+        self.lock().generation
+    }
+
+    /// Returns true if the latch is currently cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled.is_some()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CountUpState> {
+        self.mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_count_up_more_signals_than_waiters() {
+        let latch = CountUpLatch::new(4);
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let latch = latch.clone();
+                thread::spawn(move || latch.wait())
+            })
+            .collect();
+        thread::sleep(Duration::from_millis(50));
+
+        // ten signals from threads that never wait
+        let signallers: Vec<_> = (0..10)
+            .map(|_| {
+                let latch = latch.clone();
+                thread::spawn(move || latch.signal())
+            })
+            .collect();
+        for signaller in signallers {
+            signaller.join().unwrap();
+        }
+        for waiter in waiters {
+            assert_eq!(waiter.join().unwrap(), Ok(0));
+        }
+        // two generations completed, and the extra signals carried over
+        assert_eq!((latch.generation(), latch.count()), (2, 2));
+        assert_eq!(
+            latch.wait_timeout(Duration::from_millis(10)),
+            Err(BarrierWaitError::timed_out(2, 2))
+        );
+    }
+
+    #[test]
+    fn test_count_up_cancel_and_reset() {
+        let latch = CountUpLatch::new(2);
+        latch.signal();
+        latch.signal();
+        latch.signal();
+        let waiter = {
+            let latch = latch.clone();
+            thread::spawn(move || latch.wait())
+        };
+        thread::sleep(Duration::from_millis(50));
+        latch.cancel_with(CancelReason::Shutdown);
+        assert_eq!(
+            waiter.join().unwrap().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Shutdown)
+        );
+        assert!(latch.wait().is_err());
+
+        latch.reset();
+        assert!(!latch.is_cancelled());
+        assert_eq!((latch.generation(), latch.count()), (1, 0));
+        let waiter = {
+            let latch = latch.clone();
+            thread::spawn(move || latch.wait())
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(latch.signal(), 1);
+        latch.reset();
+        // the waiter needs two signals after the reset
+        assert_eq!(latch.signal(), 1);
+        assert!(!waiter.is_finished());
+        assert_eq!(latch.signal(), 1);
+        assert_eq!(waiter.join().unwrap(), Ok(1));
+    }
+}
@@ -0,0 +1,767 @@
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "diagnostics")]
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+#[cfg(feature = "metrics")]
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+#[cfg(feature = "metrics")]
+use std::sync::PoisonError;
+use std::thread::{self, ThreadId};
+
+#[cfg(feature = "diagnostics")]
+use super::BarrierThread;
+use super::{BarrierEvent, BarrierState, BarrierWaitError, CancellableBarrier, RawSync};
+#[cfg(feature = "metrics")]
+use super::{BarrierMetrics, CycleRecord};
+
+/// Point-in-time view of a barrier's state, captured under a single lock acquisition
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BarrierSnapshot {
+    /// Threads waiting in the current generation, or the sum of their weights if they
+    /// arrived with [`CancellableBarrier::wait_weighted`]
+    pub arrived: usize,
+    /// Threads needed to release the current generation
+    pub total: usize,
+    /// The current generation
+    pub generation: usize,
+    /// Whether the barrier is cancelled
+    pub cancelled: bool,
+}
+
+impl fmt::Debug for BarrierSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} waiting (gen {})",
+            self.arrived, self.total, self.generation
+        )?;
+        if self.cancelled {
+            write!(f, ", cancelled")?;
+        }
+        Ok(())
+    }
+}
+
+/// The threads that have arrived at a barrier that tracks them, as kept with its state
+#[derive(Debug, Default)]
+pub(super) struct ThreadTracking {
+    /// Threads that have arrived in the current generation
+    arrived: HashSet<ThreadId>,
+    /// Threads that arrived in the last released generation, to tell who is missing when
+    /// a wait times out
+    #[cfg(feature = "diagnostics")]
+    previous: HashSet<ThreadId>,
+    /// Names of the named threads among those tracked, to list them by name in the
+    /// diagnostics
+    #[cfg(feature = "diagnostics")]
+    names: HashMap<ThreadId, std::sync::Arc<str>>,
+}
+
+impl ThreadTracking {
+    /// Records the current thread's arrival, failing if it has already arrived in this
+    /// generation
+    fn arrive(&mut self) -> Result<ThreadId, BarrierWaitError> {
+        let current = thread::current();
+        let thread = current.id();
+        if !self.arrived.insert(thread) {
+            return Err(BarrierWaitError::AlreadyArrived);
+        }
+        #[cfg(feature = "diagnostics")]
+        if let Some(name) = current.name() {
+            self.names.entry(thread).or_insert_with(|| name.into());
+        }
+        Ok(thread)
+    }
+
+    /// Describes a tracked thread, by name if it has one
+    #[cfg(feature = "diagnostics")]
+    fn describe(&self, thread: ThreadId) -> BarrierThread {
+        BarrierThread::new(thread, self.names.get(&thread).cloned())
+    }
+
+    /// Describes the threads that have arrived in the current generation
+    #[cfg(feature = "diagnostics")]
+    pub(super) fn arrived(&self) -> Vec<BarrierThread> {
+        self.arrived
+            .iter()
+            .map(|&thread| self.describe(thread))
+            .collect()
+    }
+
+    /// Describes the threads that arrived in the last released generation but haven't in
+    /// this one
+    #[cfg(feature = "diagnostics")]
+    pub(super) fn missing(&self) -> Vec<BarrierThread> {
+        self.previous
+            .difference(&self.arrived)
+            .map(|&thread| self.describe(thread))
+            .collect()
+    }
+
+    /// Moves on to the next generation once the current one is released
+    pub(super) fn release(&mut self) {
+        #[cfg(feature = "diagnostics")]
+        {
+            // swapped rather than taken, so that neither set has to grow again
+            std::mem::swap(&mut self.previous, &mut self.arrived);
+            self.arrived.clear();
+            // only those who might be reported missing from the next generation
+            let previous = &self.previous;
+            self.names.retain(|thread, _| previous.contains(thread));
+        }
+        #[cfg(not(feature = "diagnostics"))]
+        self.arrived.clear();
+    }
+
+    /// Forgets the arrivals of a generation thrown away by a reset
+    pub(super) fn reset(&mut self) {
+        self.arrived.clear();
+    }
+}
+
+impl BarrierState {
+    /// Records the current thread's arrival if threads are tracked, returning its id so
+    /// the arrival can be forgotten again if it is rolled back
+    pub(super) fn track_arrival(&mut self) -> Result<Option<ThreadId>, BarrierWaitError> {
+        match &mut self.threads {
+            Some(threads) => threads.arrive().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub(super) fn forget_arrival(&mut self, thread: Option<ThreadId>) {
+        if let (Some(threads), Some(thread)) = (&mut self.threads, thread) {
+            threads.arrived.remove(&thread);
+        }
+    }
+}
+
+impl<S: RawSync> CancellableBarrier<S> {
+    /// Checks, in debug builds, what must hold whenever the barrier isn't locked, to catch
+    /// a method that leaves the state inconsistent. Called by the methods that change the
+    /// state once they are done with it.
+    pub(super) fn debug_check_invariants(&self, state: &BarrierState) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        assert_eq!(
+            state.parked,
+            state.park_tickets.len(),
+            "parked threads out of step with their tickets"
+        );
+        // arrivals beyond the size can only be threads whose condition holds the release
+        // back, parked on the generation in progress, unless the barrier is a manual one,
+        // whose size of 0 takes any number of arrivals
+        let held = state.park_tickets.range(state.released_below..).count();
+        assert!(
+            state.cancelled.is_some() || state.total == 0 || state.count <= state.total + held,
+            "{} arrivals left behind for {} parties",
+            state.count,
+            state.total
+        );
+        assert_eq!(
+            state.generation,
+            state.released.wrapping_add(state.resets),
+            "generation moved other than by a release or a reset"
+        );
+    }
+
+    /// Returns a consistent snapshot of the barrier's counters
+    #[must_use]
+    pub fn state(&self) -> BarrierSnapshot {
+        let state = self.lock();
+        BarrierSnapshot {
+            arrived: state.count,
+            total: state.total,
+            generation: state.generation,
+            cancelled: state.cancelled.is_some(),
+        }
+    }
+
+    /// Returns the barrier's cumulative counters.
+    ///
+    /// The counters are read without taking the barrier's lock, so they may be slightly
+    /// out of step with each other while threads are arriving.
+    #[cfg(feature = "metrics")]
+    #[must_use]
+    pub fn metrics(&self) -> BarrierMetrics {
+        self.metrics.snapshot()
+    }
+
+    /// Calls `f` with the barrier's metrics every time a generation is released, to push
+    /// them to a pipeline such as StatsD or OpenTelemetry instead of polling
+    /// [`Self::metrics`].
+    ///
+    /// The metrics are read once the release has been counted, so each call sees the
+    /// generation it follows. `f` runs on the releasing thread with the barrier locked,
+    /// as the release listeners do, so it must be quick and must not call back into the
+    /// barrier; hand the numbers to another thread for anything slow. A panic in it is
+    /// discarded, so a broken sink can't break the barrier. Setting a sink replaces the
+    /// one set before, and without one nothing is called.
+    #[cfg(feature = "metrics")]
+    pub fn set_metrics_sink(&self, f: impl Fn(&BarrierMetrics) + Send + Sync + 'static) {
+        *self
+            .metrics_sink
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(std::sync::Arc::new(f));
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(super) fn push_metrics(&self) {
+        let sink = self
+            .metrics_sink
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        if let Some(sink) = sink {
+            let metrics = self.metrics.snapshot();
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| sink(&metrics)));
+        }
+    }
+
+    /// Returns the last few released generations, oldest first, with when each was
+    /// released and how long it waited for its arrivals.
+    ///
+    /// Meant for post-mortem analysis of latency spikes without a metrics pipeline. The
+    /// barrier keeps as many generations as configured with
+    /// [`CancellableBarrierBuilder::cycle_history`], 16 by default.
+    #[cfg(feature = "metrics")]
+    #[must_use]
+    pub fn recent_cycles(&self) -> Vec<CycleRecord> {
+        self.metrics.recent_cycles()
+    }
+
+    /// Returns the barrier's last few transitions, oldest first: every arrival, release,
+    /// cancellation, reset and resize, with when it happened and in which generation.
+    ///
+    /// Finer grained than the per-generation records of the `metrics` feature, to
+    /// reconstruct what led up to a deadlock. The barrier keeps as many events as
+    /// configured with [`CancellableBarrierBuilder::event_log_capacity`], 256 by default,
+    /// and drops the oldest as new ones come in.
+    #[cfg(feature = "event-log")]
+    #[must_use]
+    pub fn event_log(&self) -> Vec<BarrierEvent> {
+        self.event_log.events()
+    }
+
+    /// Returns a receiver that is sent every transition of the barrier from now on: each
+    /// arrival, release, cancellation with its reason, reset and resize.
+    ///
+    /// For a monitoring thread that would rather read a channel than register an
+    /// observer. The events are sent under the barrier's lock without ever blocking it: a
+    /// receiver that falls more than 1024 events behind misses the newer ones until it
+    /// catches up, and one that is dropped is forgotten at the next event. Works with or
+    /// without the `event-log` feature.
+    pub fn events(&self) -> mpsc::Receiver<BarrierEvent> {
+        self.event_log.subscribe()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::cancellable_barrier::tests::{block_on, NoopWaker, TEST_SLEEP_DURATION};
+    use crate::cancellable_barrier::{BarrierEventKind, BarrierThread, CancelReason};
+    #[cfg(feature = "metrics")]
+    use crate::cancellable_barrier::{Clock, MockClock};
+    use crate::sync::Arc;
+    use std::collections::HashSet;
+    use std::future::Future;
+    use std::time::Duration;
+
+    #[test]
+    fn test_state_snapshot() {
+        let barrier = CancellableBarrier::new(2);
+        barrier.try_wait().unwrap();
+        assert_eq!(format!("{:?}", barrier.state()), "0/2 waiting (gen 0)");
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(
+            barrier.state(),
+            BarrierSnapshot {
+                arrived: 1,
+                total: 2,
+                generation: 0,
+                cancelled: false,
+            }
+        );
+
+        barrier.cancel();
+        t1.join().unwrap().unwrap_err();
+        assert_eq!(
+            format!("{:?}", barrier.state()),
+            "1/2 waiting (gen 0), cancelled"
+        );
+    }
+
+    #[test]
+    fn test_snapshot_serde() {
+        let barrier = CancellableBarrier::new(5);
+        barrier.wait_guarded().unwrap().wait().unwrap_err();
+
+        let json = serde_json::to_value(barrier.state()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "arrived": 0, "total": 5, "generation": 0, "cancelled": true })
+        );
+        let snapshot: BarrierSnapshot = serde_json::from_value(json).unwrap();
+        assert_eq!(snapshot, barrier.state());
+
+        let reason = CancelReason::Custom("scaling down".to_string());
+        let json = serde_json::to_string(&reason).unwrap();
+        assert_eq!(serde_json::from_str::<CancelReason>(&json).unwrap(), reason);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "arrivals left behind")]
+    fn test_invariant_check_catches_a_stray_count() {
+        let barrier = CancellableBarrier::new(2);
+        let mut state = barrier.lock();
+        state.count = 3;
+        barrier.debug_check_invariants(&state);
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[test]
+    fn test_diagnostics_name_threads() {
+        let barrier = CancellableBarrier::builder()
+            .parties(3)
+            .track_threads(true)
+            .build()
+            .unwrap();
+        let spawn_wait = |name: Option<&str>, timeout| {
+            let barrier = barrier.clone();
+            let builder = thread::Builder::new();
+            let builder = match name {
+                Some(name) => builder.name(name.to_string()),
+                None => builder,
+            };
+            builder
+                .spawn(move || barrier.wait_timeout(timeout))
+                .unwrap()
+        };
+
+        let named = spawn_wait(Some("io-worker-3"), Duration::from_secs(10));
+        let unnamed = spawn_wait(None, Duration::from_secs(10));
+        let unnamed_id = unnamed.thread().id();
+        barrier.wait().unwrap();
+        named.join().unwrap().unwrap();
+        unnamed.join().unwrap().unwrap();
+
+        // only the main thread comes back, so both are missing
+        let err = barrier.wait_timeout(TEST_SLEEP_DURATION).unwrap_err();
+        let BarrierWaitError::TimedOut {
+            missing_threads, ..
+        } = &err
+        else {
+            panic!("wait should time out, got {err:?}");
+        };
+        let mut names: Vec<_> = missing_threads.iter().map(BarrierThread::name).collect();
+        names.sort();
+        assert_eq!(names, vec![None, Some("io-worker-3")]);
+        let message = err.to_string();
+        assert!(message.contains("io-worker-3"), "{message}");
+        assert!(message.contains(&format!("{unnamed_id:?}")), "{message}");
+
+        // and a cancellation names who had arrived
+        let waiter = spawn_wait(Some("io-worker-7"), Duration::from_secs(10));
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        barrier.cancel();
+        let Err(BarrierWaitError::Cancelled(cancelled)) = waiter.join().unwrap() else {
+            panic!("expected a cancellation");
+        };
+        let arrived: Vec<_> = cancelled
+            .arrived_threads()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(arrived, vec!["io-worker-7"]);
+    }
+
+    #[test]
+    fn test_timeout_reports_missing_parties() {
+        let barrier = CancellableBarrier::builder()
+            .parties(3)
+            .track_threads(true)
+            .build()
+            .unwrap();
+        let spawn_wait = |timeout| {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait_timeout(timeout))
+        };
+
+        // everyone shows up for the first generation
+        let (t1, t2) = (
+            spawn_wait(Duration::from_secs(10)),
+            spawn_wait(Duration::from_secs(10)),
+        );
+        barrier.wait().unwrap();
+        let absentees = HashSet::from([t1.thread().id(), t2.thread().id()]);
+        t1.join().unwrap().unwrap();
+        t2.join().unwrap().unwrap();
+
+        // then a newcomer and the main thread arrive, but t1 and t2 don't
+        let t3 = spawn_wait(Duration::from_secs(10));
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        let Err(BarrierWaitError::TimedOut {
+            arrived,
+            missing,
+            missing_threads,
+        }) = barrier.wait_timeout(TEST_SLEEP_DURATION)
+        else {
+            panic!("wait should time out");
+        };
+        assert_eq!((arrived, missing), (2, 1));
+        assert!(BarrierWaitError::timed_out(2, 1)
+            .to_string()
+            .ends_with("with 2 arrived and 1 missing"));
+        if cfg!(feature = "diagnostics") {
+            let ids = missing_threads.iter().map(BarrierThread::id);
+            assert_eq!(HashSet::from_iter(ids), absentees);
+        } else {
+            assert!(missing_threads.is_empty());
+        }
+        barrier.cancel();
+        assert!(t3.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_track_threads_rejects_double_arrival() {
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .track_threads(true)
+            .build()
+            .unwrap();
+
+        let waker = Arc::new(NoopWaker).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut first = barrier.wait_async();
+        assert!(std::pin::Pin::new(&mut first).poll(&mut cx).is_pending());
+        assert_eq!(
+            block_on(barrier.wait_async()),
+            Err(BarrierWaitError::AlreadyArrived)
+        );
+        assert_eq!(barrier.state().arrived, 1);
+
+        // dropping the first wait forgets the arrival
+        drop(first);
+        assert!(barrier.wait_timeout(Duration::from_millis(10)).is_err());
+
+        // as does a release
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        barrier.wait().unwrap();
+        t1.join().unwrap().unwrap();
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        barrier.wait().unwrap();
+        t1.join().unwrap().unwrap();
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics() {
+        let clock = Arc::new(MockClock::new());
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        assert_eq!(barrier.metrics(), BarrierMetrics::default());
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        clock.advance(TEST_SLEEP_DURATION);
+        barrier.wait().unwrap();
+        t1.join().unwrap().unwrap();
+
+        assert!(barrier.wait_timeout(Duration::ZERO).is_err());
+        barrier.cancel();
+
+        let metrics = barrier.metrics();
+        assert_eq!(metrics.arrivals, 3);
+        assert_eq!(metrics.releases, 1);
+        assert_eq!(metrics.cancellations, 1);
+        assert_eq!(metrics.longest_wait, TEST_SLEEP_DURATION);
+        assert_eq!((metrics.resets, metrics.generation), (0, 1));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics_critical_sections() {
+        let barrier = CancellableBarrier::with_action(2, |_| thread::sleep(TEST_SLEEP_DURATION));
+        for _ in 0..2 {
+            let barrier_clone1 = barrier.clone();
+            let t1 = thread::spawn(move || barrier_clone1.wait());
+            while barrier.state().arrived < 1 {
+                thread::yield_now();
+            }
+            barrier.wait().unwrap();
+            t1.join().unwrap().unwrap();
+        }
+
+        // the releasing arrival held the lock through the action, the other only to park
+        let metrics = barrier.metrics();
+        assert!(metrics.max_release_critical_section >= TEST_SLEEP_DURATION);
+        assert!(metrics.max_critical_section < metrics.max_release_critical_section);
+    }
+
+    #[cfg(feature = "backtrace")]
+    #[test]
+    fn test_cancel_captures_backtrace() {
+        let barrier = CancellableBarrier::new(2);
+        barrier.cancel();
+        let BarrierWaitError::Cancelled(cancelled) = barrier.wait().unwrap_err() else {
+            panic!("expected a cancellation");
+        };
+        let backtrace = cancelled.backtrace().expect("captured on cancel");
+        assert_eq!(
+            backtrace.status(),
+            std::backtrace::BacktraceStatus::Captured
+        );
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics_sink() {
+        let barrier = CancellableBarrier::new(1);
+        barrier.wait().unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        barrier.set_metrics_sink(move |metrics| tx.send(metrics.releases).unwrap());
+        assert!(rx.try_recv().is_err());
+        barrier.wait().unwrap();
+        barrier.wait().unwrap();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![2, 3]);
+
+        // a panicking sink doesn't get in the way of the release
+        barrier.set_metrics_sink(|_| panic!("sink failed"));
+        assert!(barrier.wait().is_ok());
+        assert_eq!(barrier.metrics().releases, 4);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics_count_resets() {
+        let barrier = CancellableBarrier::new(1);
+        for _ in 0..3 {
+            barrier.cancel();
+            barrier.reset();
+        }
+        barrier.wait().unwrap();
+        barrier.reset_with(2).unwrap();
+
+        // read from another thread while one waits, holding nothing but the metrics
+        let barrier_clone = barrier.clone();
+        let waiter = thread::spawn(move || barrier_clone.wait());
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        let metrics = thread::scope(|scope| scope.spawn(|| barrier.metrics()).join().unwrap());
+        assert_eq!(metrics.cancellations, 3);
+        assert_eq!(metrics.resets, 4);
+        assert_eq!(metrics.generation, 5);
+        barrier.cancel();
+        assert!(waiter.join().unwrap().is_err());
+    }
+
+    #[cfg(feature = "event-log")]
+    #[test]
+    fn test_event_log() {
+        let barrier = CancellableBarrier::new(2);
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait())
+        };
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        barrier.wait().unwrap();
+        waiter.join().unwrap().unwrap();
+        barrier.cancel();
+        barrier.reset();
+        barrier.resize(3).unwrap();
+
+        let events: Vec<_> = barrier
+            .event_log()
+            .iter()
+            .map(|event| (event.generation, event.kind.clone()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (0, BarrierEventKind::Arrive { count: 1, total: 2 }),
+                (0, BarrierEventKind::Arrive { count: 2, total: 2 }),
+                (0, BarrierEventKind::Release),
+                (
+                    1,
+                    BarrierEventKind::Cancel {
+                        reason: CancelReason::Unspecified
+                    }
+                ),
+                (1, BarrierEventKind::Reset),
+                (2, BarrierEventKind::Resize { total: 3 }),
+            ]
+        );
+        let log = barrier.event_log();
+        assert!(log.windows(2).all(|pair| pair[0].at <= pair[1].at));
+        assert_eq!(log[5].to_string(), "generation 2: resized to 3");
+
+        // only the most recent are kept
+        let barrier = CancellableBarrier::builder()
+            .event_log_capacity(2)
+            .build()
+            .unwrap();
+        for _ in 0..3 {
+            barrier.wait().unwrap();
+        }
+        let events: Vec<_> = barrier
+            .event_log()
+            .iter()
+            .map(|event| (event.generation, event.kind.clone()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (2, BarrierEventKind::Arrive { count: 1, total: 1 }),
+                (2, BarrierEventKind::Release),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_events_channel() {
+        let barrier = CancellableBarrier::new(2);
+        let events = barrier.events();
+        drop(barrier.events());
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait())
+        };
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        barrier.wait().unwrap();
+        waiter.join().unwrap().unwrap();
+        barrier.cancel_with(CancelReason::Shutdown);
+        barrier.reset();
+        barrier.resize(3).unwrap();
+
+        let kinds: Vec<_> = events.try_iter().map(|event| event.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                BarrierEventKind::Arrive { count: 1, total: 2 },
+                BarrierEventKind::Arrive { count: 2, total: 2 },
+                BarrierEventKind::Release,
+                BarrierEventKind::Cancel {
+                    reason: CancelReason::Shutdown
+                },
+                BarrierEventKind::Reset,
+                BarrierEventKind::Resize { total: 3 },
+            ]
+        );
+
+        // a receiver that doesn't keep up misses events instead of blocking the barrier
+        let barrier = CancellableBarrier::new(1);
+        let events = barrier.events();
+        for _ in 0..1000 {
+            barrier.wait().unwrap();
+        }
+        assert_eq!(events.try_iter().count(), 1024);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_recent_cycles() {
+        let barrier = CancellableBarrier::builder()
+            .parties(1)
+            .cycle_history(3)
+            .build()
+            .unwrap();
+        assert!(barrier.recent_cycles().is_empty());
+        for _ in 0..5 {
+            barrier.wait().unwrap();
+        }
+        let cycles = barrier.recent_cycles();
+        let generations: Vec<_> = cycles.iter().map(|c| c.generation).collect();
+        assert_eq!(generations, vec![2, 3, 4]);
+        assert!(cycles
+            .windows(2)
+            .all(|w| w[0].completed_at <= w[1].completed_at));
+
+        let clock = Arc::new(MockClock::new());
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        clock.advance(TEST_SLEEP_DURATION);
+        barrier.wait().unwrap();
+        t1.join().unwrap().unwrap();
+        let cycles = barrier.recent_cycles();
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].wait, TEST_SLEEP_DURATION);
+        assert_eq!(cycles[0].completed_at, clock.now());
+
+        let barrier = CancellableBarrier::builder()
+            .parties(1)
+            .cycle_history(0)
+            .build()
+            .unwrap();
+        barrier.wait().unwrap();
+        assert!(barrier.recent_cycles().is_empty());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics_arrival_spread() {
+        let clock = Arc::new(MockClock::new());
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        // a straggler in the first generation, none in the second
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        clock.advance(TEST_SLEEP_DURATION);
+        barrier.wait().unwrap();
+        t1.join().unwrap().unwrap();
+        assert_eq!(barrier.metrics().last_arrival_spread, TEST_SLEEP_DURATION);
+
+        clock.advance(TEST_SLEEP_DURATION / 2);
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        barrier.wait().unwrap();
+        t1.join().unwrap().unwrap();
+
+        let metrics = barrier.metrics();
+        assert_eq!(metrics.last_arrival_spread, Duration::ZERO);
+        assert_eq!(metrics.longest_arrival_spread, TEST_SLEEP_DURATION);
+        assert_eq!(metrics.last_cycle, TEST_SLEEP_DURATION / 2);
+    }
+}
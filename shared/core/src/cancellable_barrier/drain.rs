@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use super::{BarrierState, BarrierWaitError, CancellableBarrier, RawSync, BLOCKING_SUPPORTED};
+
+/// A drain in progress, as kept with the barrier's state
+#[derive(Debug)]
+pub(super) struct Drain {
+    /// Tells the drain apart from one requested after it
+    id: u64,
+    /// Parties the barrier had when the drain was requested, each expected to leave
+    parties: usize,
+    /// Parties that have left so far
+    acknowledged: usize,
+}
+
+/// A request for every party of a [`CancellableBarrier`] to leave it, returned by
+/// [`CancellableBarrier::request_drain`].
+///
+/// While the token lives, waits on the barrier return [`BarrierWaitError::Draining`]
+/// instead of arriving, each of them acknowledging the drain for one party, and
+/// [`Self::wait`] tells when all of them have. Dropping the token ends the drain, and the
+/// barrier takes arrivals again, for example once it has been reconfigured.
+#[derive(Debug)]
+#[must_use = "dropping the token ends the drain"]
+pub struct DrainToken<'a, S: RawSync> {
+    barrier: &'a CancellableBarrier<S>,
+    id: u64,
+}
+
+impl<'a, S: RawSync> DrainToken<'a, S> {
+    pub(super) fn new(barrier: &'a CancellableBarrier<S>, state: &mut BarrierState) -> Self {
+        let id = state.next_drain;
+        state.next_drain += 1;
+        state.drain = Some(Drain {
+            id,
+            parties: state.total,
+            acknowledged: 0,
+        });
+        DrainToken { barrier, id }
+    }
+
+    /// Waits up to `timeout` for every party to have left, failing with
+    /// [`BarrierWaitError::TimedOut`] if some are still missing by then.
+    ///
+    /// The drain counts a party as soon as one of its waits returns
+    /// [`BarrierWaitError::Draining`], so a party that only waits now and then confirms
+    /// the drain the next time it does. Fails with the cancellation if the barrier is
+    /// or becomes cancelled, since a cancelled barrier's waits acknowledge nothing.
+    pub fn wait(&self, timeout: Duration) -> Result<(), BarrierWaitError> {
+        let deadline = self.barrier.clock.now().checked_add(timeout);
+        let mut state = self.barrier.lock();
+        loop {
+            state.check_cancelled()?;
+            let (acknowledged, parties) = state
+                .drain
+                .as_ref()
+                .filter(|drain| drain.id == self.id)
+                .map_or((0, 0), |drain| (drain.acknowledged, drain.parties));
+            if acknowledged >= parties {
+                return Ok(());
+            }
+            if !BLOCKING_SUPPORTED {
+                return Err(BarrierWaitError::WouldBlock);
+            }
+            let now = self.barrier.clock.now();
+            match deadline {
+                Some(deadline) if deadline <= now => {
+                    return Err(BarrierWaitError::timed_out(
+                        acknowledged,
+                        parties - acknowledged,
+                    ));
+                }
+                Some(deadline) => state = self.barrier.sleep_until(state, now, deadline),
+                None => state = self.barrier.sleep(state),
+            }
+        }
+    }
+
+    /// Returns how many parties have left the barrier since the drain was requested
+    #[must_use]
+    pub fn acknowledged(&self) -> usize {
+        let state = self.barrier.lock();
+        state
+            .drain
+            .as_ref()
+            .filter(|drain| drain.id == self.id)
+            .map_or(0, |drain| drain.acknowledged)
+    }
+}
+
+impl<S: RawSync> Drop for DrainToken<'_, S> {
+    fn drop(&mut self) {
+        let mut state = self.barrier.lock();
+        // a drain requested since then carries on
+        if state
+            .drain
+            .as_ref()
+            .is_some_and(|drain| drain.id == self.id)
+        {
+            state.drain = None;
+        }
+    }
+}
+
+impl BarrierState {
+    /// Returns true if the barrier is being drained, so waits must leave instead of
+    /// arriving
+    pub(super) fn draining(&self) -> bool {
+        self.drain.is_some()
+    }
+}
+
+impl<S: RawSync> CancellableBarrier<S> {
+    /// Counts a wait leaving for the drain in progress, waking its token, and returns
+    /// what the wait returns
+    pub(super) fn leave_for_drain(&self, state: &mut BarrierState) -> BarrierWaitError {
+        if let Some(drain) = &mut state.drain {
+            drain.acknowledged += 1;
+        }
+        S::notify_all(&self.condvar);
+        BarrierWaitError::Draining
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_drain_acknowledged_by_every_party() {
+        let barrier = CancellableBarrier::new(3);
+        let parked: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait())
+            })
+            .collect();
+        while barrier.state().arrived < 2 {
+            thread::yield_now();
+        }
+
+        let drain = barrier.request_drain();
+        // those already waiting leave too, giving their arrivals back
+        for waiter in parked {
+            assert_eq!(waiter.join().unwrap(), Err(BarrierWaitError::Draining));
+        }
+        assert_eq!(barrier.state().arrived, 0);
+        assert_eq!(drain.acknowledged(), 2);
+        assert_eq!(
+            drain.wait(Duration::from_millis(10)),
+            Err(BarrierWaitError::timed_out(2, 1))
+        );
+
+        let last = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait())
+        };
+        assert_eq!(drain.wait(Duration::from_secs(10)), Ok(()));
+        assert_eq!(last.join().unwrap(), Err(BarrierWaitError::Draining));
+
+        // done draining, the barrier goes on as before
+        drop(drain);
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait())
+            })
+            .collect();
+        assert!(barrier.wait().is_ok());
+        for waiter in waiters {
+            assert!(waiter.join().unwrap().is_ok());
+        }
+    }
+}
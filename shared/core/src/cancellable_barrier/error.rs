@@ -0,0 +1,481 @@
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+#[cfg(feature = "backtrace")]
+use std::backtrace::Backtrace;
+use std::fmt;
+use std::io;
+use std::thread::ThreadId;
+
+/// Error for a barrier that has been cancelled, carrying the reason it was cancelled with.
+///
+/// A [`super::CancellableBarrier`] also records who had arrived in the generation it was
+/// cancelled in, to help decide how to recover. That is diagnostic metadata, so two
+/// errors are equal if their reasons are, whatever was recorded alongside, and if they
+/// carry the same payload: none, or the one from the same cancellation.
+#[derive(Debug, Clone)]
+pub struct CancelledBarrier {
+    reason: CancelReason,
+    arrived: Option<usize>,
+    #[cfg(feature = "diagnostics")]
+    arrived_threads: Vec<BarrierThread>,
+    #[cfg(feature = "backtrace")]
+    backtrace: Option<std::sync::Arc<Backtrace>>,
+    payload: Option<std::sync::Arc<dyn Any + Send + Sync>>,
+}
+
+impl CancelledBarrier {
+    /// Creates the error for a barrier cancelled with `reason`
+    #[must_use]
+    pub fn new(reason: CancelReason) -> Self {
+        CancelledBarrier {
+            reason,
+            arrived: None,
+            #[cfg(feature = "diagnostics")]
+            arrived_threads: Vec::new(),
+            #[cfg(feature = "backtrace")]
+            backtrace: None,
+            payload: None,
+        }
+    }
+
+    /// Records that `arrived` parties, or that much weight, had arrived when the barrier
+    /// was cancelled
+    pub(super) fn with_arrived(mut self, arrived: usize) -> Self {
+        self.arrived = Some(arrived);
+        self
+    }
+
+    /// Records the threads that had arrived when the barrier was cancelled
+    #[cfg(feature = "diagnostics")]
+    pub(super) fn with_arrived_threads(mut self, threads: Vec<BarrierThread>) -> Self {
+        self.arrived_threads = threads;
+        self
+    }
+
+    /// Records where the barrier was cancelled from
+    #[cfg(feature = "backtrace")]
+    pub(super) fn with_backtrace(mut self, backtrace: Option<std::sync::Arc<Backtrace>>) -> Self {
+        self.backtrace = backtrace;
+        self
+    }
+
+    /// Attaches the payload the barrier was cancelled with
+    pub(super) fn with_payload(
+        mut self,
+        payload: Option<std::sync::Arc<dyn Any + Send + Sync>>,
+    ) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Returns why the barrier was cancelled
+    #[must_use]
+    pub fn reason(&self) -> &CancelReason {
+        &self.reason
+    }
+
+    /// Returns how many parties had arrived in the generation the barrier was cancelled
+    /// in, or their total weight, if the primitive keeps count
+    #[must_use]
+    pub fn arrived_count(&self) -> Option<usize> {
+        self.arrived
+    }
+
+    /// Returns the threads that had arrived in the generation the barrier was cancelled
+    /// in, on a barrier that tracks threads, and an empty slice otherwise
+    #[cfg(feature = "diagnostics")]
+    #[must_use]
+    pub fn arrived_threads(&self) -> &[BarrierThread] {
+        &self.arrived_threads
+    }
+
+    /// Returns the payload the barrier was cancelled with through
+    /// [`super::CancellableBarrier::cancel_with_payload`], if it is an `E`.
+    ///
+    /// `None` if the barrier was cancelled without a payload, or with one of another type;
+    /// [`Self::has_payload`] tells the two apart. Every waiter shares the one payload, so it
+    /// needn't be `Clone`.
+    #[must_use]
+    pub fn payload<E: Any>(&self) -> Option<&E> {
+        self.payload.as_deref()?.downcast_ref()
+    }
+
+    /// Returns true if the barrier was cancelled with a payload, whatever its type, so a
+    /// waiter can tell a payload it didn't expect from none at all
+    #[must_use]
+    pub fn has_payload(&self) -> bool {
+        self.payload.is_some()
+    }
+
+    /// Returns the backtrace of the call that cancelled the barrier, to find which part
+    /// of a program cancelled a shared barrier. Captured whatever `RUST_BACKTRACE` says,
+    /// but only by a [`super::CancellableBarrier`]; `None` for other primitives.
+    #[cfg(feature = "backtrace")]
+    #[must_use]
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_deref()
+    }
+}
+
+impl PartialEq for CancelledBarrier {
+    fn eq(&self, other: &Self) -> bool {
+        let same_payload = match (&self.payload, &other.payload) {
+            (None, None) => true,
+            (Some(a), Some(b)) => std::sync::Arc::ptr_eq(a, b),
+            _ => false,
+        };
+        self.reason == other.reason && same_payload
+    }
+}
+
+impl Eq for CancelledBarrier {}
+
+impl fmt::Display for CancelledBarrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            CancelReason::Unspecified => write!(f, "barrier cancelled"),
+            reason => write!(f, "barrier cancelled: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for CancelledBarrier {}
+
+/// A thread seen at a barrier that tracks threads, as listed in the diagnostics of
+/// [`CancelledBarrier`] and [`BarrierWaitError::TimedOut`].
+///
+/// Displays as the thread's name if it has one, so a timeout reads "missing threads
+/// [io-worker-3, io-worker-7]", and as its [`ThreadId`] otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BarrierThread {
+    id: ThreadId,
+    name: Option<std::sync::Arc<str>>,
+}
+
+impl BarrierThread {
+    #[cfg(feature = "diagnostics")]
+    pub(super) fn new(id: ThreadId, name: Option<std::sync::Arc<str>>) -> Self {
+        BarrierThread { id, name }
+    }
+
+    /// Returns the thread's id
+    #[must_use]
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    /// Returns the thread's name, if it was given one when it arrived
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+impl fmt::Display for BarrierThread {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{name}"),
+            None => write!(f, "{:?}", self.id),
+        }
+    }
+}
+
+/// Why a barrier was cancelled, as passed to [`super::CancellableBarrier::cancel_with`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum CancelReason {
+    /// Cancelled through [`super::CancellableBarrier::cancel`] without a reason
+    Unspecified,
+    /// The process or component is shutting down
+    Shutdown,
+    /// A participant hit an error
+    Error,
+    /// A participant or supervisor gave up waiting
+    Timeout,
+    /// A participant panicked before arriving
+    Panicked,
+    /// A participant registered with
+    /// [`super::CancellableBarrier::register_liveness`] stopped heartbeating, with the id
+    /// it was registered under
+    Lost(String),
+    /// A participant gave up waiting on a barrier built with
+    /// [`super::CancellableBarrierBuilder::break_on_give_up`]
+    Broken,
+    /// The barrier released as many generations as it was built to allow with
+    /// [`super::CancellableBarrierBuilder::max_generations`]
+    MaxGenerationsReached,
+    /// The barrier's action or a release listener panicked, on a barrier built with
+    /// [`super::CancellableBarrierBuilder::on_callback_panic`] set to
+    /// [`super::CallbackPanicPolicy::Cancel`] or [`super::CallbackPanicPolicy::Abort`]
+    CallbackPanicked,
+    /// Every remaining [`super::BarrierHandle`] was waiting on a generation that could
+    /// no longer complete, because nothing else held on to the barrier to arrive
+    Unreachable,
+    /// The process received `SIGINT`, on a barrier registered with
+    /// [`super::install_signal_cancel`]
+    Signal,
+    /// Any other reason
+    Custom(String),
+}
+
+impl fmt::Display for CancelReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CancelReason::Unspecified => write!(f, "unspecified"),
+            CancelReason::Shutdown => write!(f, "shutdown"),
+            CancelReason::Error => write!(f, "error"),
+            CancelReason::Timeout => write!(f, "timeout"),
+            CancelReason::Panicked => write!(f, "participant panicked"),
+            CancelReason::Lost(id) => write!(f, "participant {id} lost"),
+            CancelReason::Broken => write!(f, "broken by a participant that gave up"),
+            CancelReason::MaxGenerationsReached => {
+                write!(f, "maximum number of generations reached")
+            }
+            CancelReason::CallbackPanicked => write!(f, "release callback panicked"),
+            CancelReason::Unreachable => write!(f, "no parties left to complete the generation"),
+            CancelReason::Signal => write!(f, "interrupted by a signal"),
+            CancelReason::Custom(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+/// Error returned when a wait on the barrier does not complete
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BarrierWaitError {
+    /// The barrier was cancelled before it released
+    Cancelled(CancelledBarrier),
+    /// The barrier was aborted through [`super::CancellableBarrier::abort`], and can no
+    /// longer be used
+    Aborted,
+    /// The timeout elapsed before enough threads arrived
+    TimedOut {
+        /// Parties that had arrived when the wait gave up, the waiting one included, or
+        /// their total weight. Both counts are 0 for primitives without parties.
+        arrived: usize,
+        /// Parties still needed to release
+        missing: usize,
+        /// Threads that arrived in the previous generation but not in this one, on a
+        /// barrier that tracks threads with the `diagnostics` feature enabled, and empty
+        /// otherwise
+        missing_threads: Vec<BarrierThread>,
+    },
+    /// The [`super::CancelSignal`] the wait was tied to was tripped
+    Interrupted,
+    /// This thread has already arrived in the current generation, on a barrier built with
+    /// [`super::CancellableBarrierBuilder::track_threads`]
+    AlreadyArrived,
+    /// More threads are using the barrier than it has parties, on a barrier built with
+    /// [`super::CancellableBarrierBuilder::strict`]
+    TooManyParties,
+    /// The wait would have to block, on a target without threads to ever release it
+    /// (wasm32 without atomics). Async waits are unaffected.
+    WouldBlock,
+    /// The barrier is being drained through
+    /// [`super::CancellableBarrier::request_drain`], and the party is asked to leave
+    Draining,
+}
+
+impl fmt::Display for BarrierWaitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BarrierWaitError::Cancelled(cancelled) => cancelled.fmt(f),
+            BarrierWaitError::Aborted => write!(f, "barrier aborted"),
+            BarrierWaitError::TimedOut {
+                arrived,
+                missing,
+                missing_threads,
+            } => {
+                write!(f, "timed out waiting on barrier")?;
+                if arrived + missing > 0 {
+                    write!(f, " with {arrived} arrived and {missing} missing")?;
+                }
+                if let Some((first, rest)) = missing_threads.split_first() {
+                    write!(f, ", missing threads [{first}")?;
+                    for thread in rest {
+                        write!(f, ", {thread}")?;
+                    }
+                    write!(f, "]")?;
+                }
+                Ok(())
+            }
+            BarrierWaitError::Interrupted => write!(f, "wait on barrier interrupted"),
+            BarrierWaitError::AlreadyArrived => {
+                write!(f, "thread already arrived at barrier in this generation")
+            }
+            BarrierWaitError::TooManyParties => {
+                write!(f, "more threads arrived at barrier than it has parties")
+            }
+            BarrierWaitError::WouldBlock => {
+                write!(f, "waiting on barrier would block forever without threads")
+            }
+            BarrierWaitError::Draining => write!(f, "barrier draining"),
+        }
+    }
+}
+
+impl std::error::Error for BarrierWaitError {}
+
+impl BarrierWaitError {
+    /// Returns the cancellation reason if the wait failed because the barrier was cancelled
+    #[must_use]
+    pub fn cancel_reason(&self) -> Option<&CancelReason> {
+        match self {
+            BarrierWaitError::Cancelled(cancelled) => Some(cancelled.reason()),
+            _ => None,
+        }
+    }
+
+    /// Returns how many parties had arrived and how many were needed, as `(arrived,
+    /// total)`, if the wait timed out.
+    ///
+    /// Both are read under the barrier's lock as the wait gives up, so a caller that
+    /// retries can tell how close the generation came, as in "4/5 arrived, retrying".
+    #[must_use]
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        match self {
+            BarrierWaitError::TimedOut {
+                arrived, missing, ..
+            } => Some((*arrived, arrived + missing)),
+            _ => None,
+        }
+    }
+
+    /// Returns a timeout error with the given counts and no missing threads
+    pub(super) fn timed_out(arrived: usize, missing: usize) -> Self {
+        BarrierWaitError::TimedOut {
+            arrived,
+            missing,
+            missing_threads: Vec::new(),
+        }
+    }
+}
+
+impl From<CancelledBarrier> for BarrierWaitError {
+    fn from(cancelled: CancelledBarrier) -> Self {
+        BarrierWaitError::Cancelled(cancelled)
+    }
+}
+
+/// Maps to [`io::ErrorKind::TimedOut`] for a barrier cancelled with
+/// [`CancelReason::Timeout`], and to [`io::ErrorKind::Interrupted`] otherwise
+impl From<CancelledBarrier> for io::Error {
+    fn from(cancelled: CancelledBarrier) -> Self {
+        let kind = match cancelled.reason {
+            CancelReason::Timeout => io::ErrorKind::TimedOut,
+            _ => io::ErrorKind::Interrupted,
+        };
+        io::Error::new(kind, cancelled)
+    }
+}
+
+/// Maps each variant to the closest [`io::ErrorKind`], keeping the error as the source:
+/// cancellations as with [`CancelledBarrier`], aborts, interrupts and drains to
+/// [`io::ErrorKind::Interrupted`], timeouts to [`io::ErrorKind::TimedOut`], misuse to
+/// [`io::ErrorKind::InvalidInput`] and [`BarrierWaitError::WouldBlock`] to
+/// [`io::ErrorKind::WouldBlock`]
+impl From<BarrierWaitError> for io::Error {
+    fn from(err: BarrierWaitError) -> Self {
+        let kind = match &err {
+            BarrierWaitError::Cancelled(cancelled) => return cancelled.clone().into(),
+            BarrierWaitError::Aborted
+            | BarrierWaitError::Interrupted
+            | BarrierWaitError::Draining => io::ErrorKind::Interrupted,
+            BarrierWaitError::TimedOut { .. } => io::ErrorKind::TimedOut,
+            BarrierWaitError::AlreadyArrived | BarrierWaitError::TooManyParties => {
+                io::ErrorKind::InvalidInput
+            }
+            BarrierWaitError::WouldBlock => io::ErrorKind::WouldBlock,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// Error returned when a barrier is configured with invalid parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BarrierBuildError {
+    /// The barrier was configured with zero parties
+    ZeroParties,
+    /// The quorum was zero or larger than the number of parties
+    InvalidQuorum,
+    /// Linking the barrier as a child would have made it its own ancestor
+    LinkCycle,
+}
+
+impl fmt::Display for BarrierBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BarrierBuildError::ZeroParties => write!(f, "barrier size must be greater than 0"),
+            BarrierBuildError::InvalidQuorum => {
+                write!(
+                    f,
+                    "barrier quorum must be between 1 and the number of parties"
+                )
+            }
+            BarrierBuildError::LinkCycle => {
+                write!(
+                    f,
+                    "linking the barrier would make a cycle of linked barriers"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for BarrierBuildError {}
+
+impl From<BarrierBuildError> for io::Error {
+    fn from(err: BarrierBuildError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_error_kinds() {
+        let timeout: io::Error = CancelledBarrier::new(CancelReason::Timeout).into();
+        assert_eq!(timeout.kind(), io::ErrorKind::TimedOut);
+        let shutdown: io::Error = CancelledBarrier::new(CancelReason::Shutdown).into();
+        assert_eq!(shutdown.kind(), io::ErrorKind::Interrupted);
+        assert_eq!(shutdown.to_string(), "barrier cancelled: shutdown");
+
+        let cases = [
+            (
+                BarrierWaitError::Cancelled(CancelledBarrier::new(CancelReason::Timeout)),
+                io::ErrorKind::TimedOut,
+            ),
+            (BarrierWaitError::Aborted, io::ErrorKind::Interrupted),
+            (BarrierWaitError::timed_out(1, 2), io::ErrorKind::TimedOut),
+            (BarrierWaitError::Interrupted, io::ErrorKind::Interrupted),
+            (
+                BarrierWaitError::TooManyParties,
+                io::ErrorKind::InvalidInput,
+            ),
+            (BarrierWaitError::WouldBlock, io::ErrorKind::WouldBlock),
+            (BarrierWaitError::Draining, io::ErrorKind::Interrupted),
+        ];
+        for (err, kind) in cases {
+            let io_err: io::Error = err.clone().into();
+            assert_eq!(io_err.kind(), kind);
+            // the barrier error is kept as the source
+            let inner = io_err.into_inner().unwrap();
+            match inner.downcast::<BarrierWaitError>() {
+                Ok(inner) => assert_eq!(*inner, err),
+                Err(inner) => assert!(inner.is::<CancelledBarrier>()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_question_mark_into_io_result() {
+        fn build() -> io::Result<()> {
+            super::super::CancellableBarrier::try_new(0)?;
+            Ok(())
+        }
+        assert_eq!(build().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
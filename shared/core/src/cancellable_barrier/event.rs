@@ -0,0 +1,170 @@
+use std::sync::PoisonError;
+use std::time::{Duration, Instant};
+
+use super::{BarrierWaitError, CancelReason, CancelledBarrier};
+use crate::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// A manual-reset event, for signalling any number of waiting threads at once.
+///
+/// Once [set](Self::set), the event stays set, and every [`Self::wait`] returns straight
+/// away until it is [reset](Self::reset).
+#[derive(Debug)]
+pub struct CancellableEvent {
+    mutex: Mutex<EventState>,
+    condvar: Condvar,
+}
+
+#[derive(Debug)]
+struct EventState {
+    set: bool,
+    cancelled: Option<CancelReason>,
+}
+
+impl CancellableEvent {
+    /// Creates an event that isn't set
+    #[must_use]
+    pub fn new() -> Arc<Self> {
+        Arc::new(CancellableEvent {
+            mutex: Mutex::new(EventState {
+                set: false,
+                cancelled: None,
+            }),
+            condvar: Condvar::new(),
+        })
+    }
+
+    /// Sets the event, waking all waiting threads
+    pub fn set(&self) {
+        let mut state = self.lock();
+        state.set = true;
+        self.condvar.notify_all();
+    }
+
+    /// Resets the event to its initial state: not set and not cancelled
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.set = false;
+        state.cancelled = None;
+    }
+
+    /// Returns true if the event is currently set
+    #[must_use]
+    pub fn is_set(&self) -> bool {
+        self.lock().set
+    }
+
+    /// Waits until the event is set or cancelled.
+    ///
+    /// Returns straight away if the event is set, even if it is also cancelled.
+    pub fn wait(&self) -> Result<(), BarrierWaitError> {
+        self.wait_inner(None)
+    }
+
+    /// Like [`Self::wait`], but gives up once `timeout` has elapsed, returning
+    /// [`BarrierWaitError::TimedOut`]
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), BarrierWaitError> {
+        self.wait_inner(Instant::now().checked_add(timeout))
+    }
+
+    fn wait_inner(&self, deadline: Option<Instant>) -> Result<(), BarrierWaitError> {
+        let mut state = self.lock();
+        while !state.set {
+            if let Some(reason) = &state.cancelled {
+                return Err(CancelledBarrier::new(reason.clone()).into());
+            }
+            // recompute what's left every time we wake, spurious or not
+            let now = Instant::now();
+            match deadline {
+                Some(deadline) if now >= deadline => return Err(BarrierWaitError::timed_out(0, 0)),
+                Some(deadline) => {
+                    state = self
+                        .condvar
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                }
+                None => {
+                    state = self
+                        .condvar
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Cancels the event, causing all waiting threads to return with an error
+    pub fn cancel(&self) {
+        self.cancel_with(CancelReason::Unspecified);
+    }
+
+    /// Cancels the event with a `reason` that waiting threads receive in their error
+    pub fn cancel_with(&self, reason: CancelReason) {
+        let mut state = self.lock();
+        state.cancelled = Some(reason);
+        self.condvar.notify_all();
+    }
+
+    /// Returns true if the event is currently cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled.is_some()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, EventState> {
+        self.mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_event_set_wakes_all_and_stays_set() {
+        let event = CancellableEvent::new();
+
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let event = event.clone();
+                thread::spawn(move || event.wait())
+            })
+            .collect();
+        thread::sleep(Duration::from_millis(100));
+        event.set();
+        for waiter in waiters {
+            assert_eq!(waiter.join().unwrap(), Ok(()));
+        }
+        assert!(event.is_set());
+        assert_eq!(event.wait(), Ok(()));
+
+        event.reset();
+        assert!(!event.is_set());
+        assert_eq!(
+            event.wait_timeout(Duration::from_millis(50)),
+            Err(BarrierWaitError::timed_out(0, 0))
+        );
+    }
+
+    #[test]
+    fn test_event_cancel() {
+        let event = CancellableEvent::new();
+
+        let event_clone = event.clone();
+        let waiter = thread::spawn(move || event_clone.wait_timeout(Duration::from_secs(10)));
+        thread::sleep(Duration::from_millis(100));
+        event.cancel_with(CancelReason::Shutdown);
+        assert_eq!(
+            waiter.join().unwrap().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Shutdown)
+        );
+        assert!(event.is_cancelled());
+
+        event.reset();
+        assert!(!event.is_cancelled());
+        event.set();
+        assert_eq!(event.wait(), Ok(()));
+    }
+}
@@ -0,0 +1,179 @@
+//! Every transition of a barrier, streamed to the receivers of
+//! [`super::CancellableBarrier::events`] and, behind the `event-log` feature, kept in a
+//! bounded log.
+//!
+//! Without the feature the log is zero-sized, and every method only checks for
+//! receivers, so call sites don't need their own `cfg`s.
+
+#[cfg(feature = "event-log")]
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, TrySendError};
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+
+use super::CancelReason;
+
+/// One transition of a barrier, as kept by [`super::CancellableBarrier::event_log`] and
+/// sent by [`super::CancellableBarrier::events`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BarrierEvent {
+    /// When it happened
+    pub at: Instant,
+    /// The generation in progress when it happened
+    pub generation: usize,
+    /// What happened
+    pub kind: BarrierEventKind,
+}
+
+/// What a [`BarrierEvent`] records
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BarrierEventKind {
+    /// Someone arrived, bringing the arrivals, or their weight, to `count` of `total`.
+    /// Arrivals counted without the lock are logged together once it is next taken.
+    Arrive {
+        /// Arrivals counted so far
+        count: usize,
+        /// Arrivals needed to release
+        total: usize,
+    },
+    /// The generation was released
+    Release,
+    /// The barrier was cancelled
+    Cancel {
+        /// Why it was cancelled
+        reason: CancelReason,
+    },
+    /// The barrier was reset, throwing the generation away
+    Reset,
+    /// The number of parties changed to `total`
+    Resize {
+        /// The new number of parties
+        total: usize,
+    },
+}
+
+impl fmt::Display for BarrierEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "generation {}: ", self.generation)?;
+        match &self.kind {
+            BarrierEventKind::Arrive { count, total } => write!(f, "arrival, {count}/{total}"),
+            BarrierEventKind::Release => write!(f, "released"),
+            BarrierEventKind::Cancel { reason } => write!(f, "cancelled: {reason}"),
+            BarrierEventKind::Reset => write!(f, "reset"),
+            BarrierEventKind::Resize { total } => write!(f, "resized to {total}"),
+        }
+    }
+}
+
+/// Events a receiver of [`super::CancellableBarrier::events`] can fall behind by before
+/// newer ones are dropped
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Default)]
+pub(super) struct EventLog {
+    /// The last `capacity` events, oldest first. Only touched with the barrier locked, so
+    /// this lock is never contended.
+    #[cfg(feature = "event-log")]
+    events: Mutex<VecDeque<BarrierEvent>>,
+    #[cfg(feature = "event-log")]
+    capacity: usize,
+    /// Where [`super::CancellableBarrier::events`] receivers are sent the events
+    channels: Mutex<Vec<mpsc::SyncSender<BarrierEvent>>>,
+    /// Set while there are channels, so that recording without any doesn't take their lock
+    has_channels: AtomicBool,
+}
+
+impl EventLog {
+    /// Creates a log that keeps the last `capacity` events, allocated up front so that
+    /// logging never grows it
+    #[cfg(feature = "event-log")]
+    pub(super) fn new(capacity: usize) -> Self {
+        EventLog {
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            ..EventLog::default()
+        }
+    }
+
+    /// Records an arrival bringing `generation` to `count` of `total`. This and the
+    /// other `record_` methods are to be called with the barrier locked.
+    pub(super) fn record_arrival(&self, generation: usize, count: usize, total: usize) {
+        self.record(generation, || BarrierEventKind::Arrive { count, total });
+    }
+
+    pub(super) fn record_release(&self, generation: usize) {
+        self.record(generation, || BarrierEventKind::Release);
+    }
+
+    pub(super) fn record_cancel(&self, generation: usize, reason: &CancelReason) {
+        self.record(generation, || BarrierEventKind::Cancel {
+            reason: reason.clone(),
+        });
+    }
+
+    /// Records a reset of `generation`, the one thrown away
+    pub(super) fn record_reset(&self, generation: usize) {
+        self.record(generation, || BarrierEventKind::Reset);
+    }
+
+    pub(super) fn record_resize(&self, generation: usize, total: usize) {
+        self.record(generation, || BarrierEventKind::Resize { total });
+    }
+
+    /// Adds a channel for [`super::CancellableBarrier::events`]
+    pub(super) fn subscribe(&self) -> mpsc::Receiver<BarrierEvent> {
+        let (tx, rx) = mpsc::sync_channel(EVENT_CHANNEL_CAPACITY);
+        let mut channels = self.channels.lock().unwrap_or_else(PoisonError::into_inner);
+        channels.push(tx);
+        self.has_channels.store(true, Ordering::Relaxed);
+        rx
+    }
+
+    /// Takes only a timestamp and the event's few words, to keep the lock short: the
+    /// event is described when it is displayed, not when it is recorded. Nothing is
+    /// built if nothing would keep it. A channel that is full misses the event rather than
+    /// holding the barrier up, and one whose receiver is gone is dropped.
+    fn record(&self, generation: usize, kind: impl FnOnce() -> BarrierEventKind) {
+        #[cfg(feature = "event-log")]
+        let logged = self.capacity > 0;
+        #[cfg(not(feature = "event-log"))]
+        let logged = false;
+        let sent = self.has_channels.load(Ordering::Relaxed);
+        if !logged && !sent {
+            return;
+        }
+        let event = BarrierEvent {
+            at: Instant::now(),
+            generation,
+            kind: kind(),
+        };
+        if sent {
+            let mut channels = self.channels.lock().unwrap_or_else(PoisonError::into_inner);
+            channels.retain(|channel| {
+                !matches!(
+                    channel.try_send(event.clone()),
+                    Err(TrySendError::Disconnected(_))
+                )
+            });
+            self.has_channels
+                .store(!channels.is_empty(), Ordering::Relaxed);
+        }
+        #[cfg(feature = "event-log")]
+        if logged {
+            let mut events = self.events.lock().unwrap_or_else(PoisonError::into_inner);
+            if events.len() == self.capacity {
+                events.pop_front();
+            }
+            events.push_back(event);
+        }
+    }
+
+    #[cfg(feature = "event-log")]
+    pub(super) fn events(&self) -> Vec<BarrierEvent> {
+        let events = self.events.lock().unwrap_or_else(PoisonError::into_inner);
+        events.iter().cloned().collect()
+    }
+}
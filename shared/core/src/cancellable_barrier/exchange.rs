@@ -0,0 +1,299 @@
+use std::mem;
+use std::ops::Deref;
+use std::sync::PoisonError;
+
+use super::{BarrierWaitError, CancelReason, CancelledBarrier};
+use crate::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// A barrier where every thread contributes a value and, once all have arrived, each one
+/// gets the full set back.
+///
+/// Values are collected in arrival order into a buffer for the current generation. The
+/// last thread to arrive seals the buffer and every participant of that generation
+/// receives the same shared vector, so nothing is cloned per thread. With
+/// [`Self::wait_exchange_ref`] the buffers are recycled as well, so a steady exchange
+/// stops allocating once it has as many as are in use at a time.
+#[derive(Debug)]
+pub struct BarrierExchange<T> {
+    mutex: Mutex<ExchangeState<T>>,
+    condvar: Condvar,
+}
+
+#[derive(Debug)]
+struct ExchangeState<T> {
+    total: usize,
+    generation: usize,
+    values: Vec<T>,
+    /// The buffer sealed by the last release, with the generation it belongs to
+    released: Option<(usize, Arc<Vec<T>>)>,
+    /// An emptied buffer given back by the last [`ExchangeGuard`] of its generation, to
+    /// collect the next generation into
+    spare: Option<Vec<T>>,
+    cancelled: Option<CancelReason>,
+}
+
+impl<T> ExchangeState<T> {
+    /// Returns an empty buffer for the next generation, reusing the one released last if
+    /// nobody holds on to it any more
+    fn next_buffer(&mut self) -> Vec<T> {
+        if let Some((_, released)) = self.released.take() {
+            if let Ok(mut values) = Arc::try_unwrap(released) {
+                values.clear();
+                return values;
+            }
+        }
+        self.spare
+            .take()
+            .unwrap_or_else(|| Vec::with_capacity(self.total))
+    }
+}
+
+impl<T> BarrierExchange<T> {
+    /// Creates a new exchange between `n` threads
+    #[must_use]
+    pub fn new(n: usize) -> Arc<Self> {
+        assert!(n > 0, "Barrier size must be greater than 0");
+        Arc::new(BarrierExchange {
+            mutex: Mutex::new(ExchangeState {
+                total: n,
+                generation: 0,
+                values: Vec::with_capacity(n),
+                released: None,
+                spare: None,
+                cancelled: None,
+            }),
+            condvar: Condvar::new(),
+        })
+    }
+
+    /// Contributes `value` and waits until all threads have contributed theirs or the
+    /// exchange is cancelled.
+    ///
+    /// On release, every thread gets the values of its generation in arrival order.
+    pub fn wait_exchange(&self, value: T) -> Result<Arc<Vec<T>>, BarrierWaitError> {
+        let mut state = self.lock();
+        check_cancelled(&state)?;
+
+        let generation = state.generation;
+        state.values.push(value);
+
+        if state.values.len() >= state.total {
+            // Last thread to arrive
+            let next = state.next_buffer();
+            let values = Arc::new(mem::replace(&mut state.values, next));
+            state.released = Some((generation, values.clone()));
+            state.generation = state.generation.wrapping_add(1);
+            self.condvar.notify_all();
+            return Ok(values);
+        }
+
+        while state.generation == generation && state.cancelled.is_none() {
+            state = self
+                .condvar
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+
+        // a generation that was released stays released, even if the exchange was
+        // cancelled before we got to run again
+        match &state.released {
+            Some((released, values)) if *released == generation => Ok(values.clone()),
+            _ => {
+                check_cancelled(&state)?;
+                // our generation was thrown away by a reset
+                Err(CancelledBarrier::new(CancelReason::Unspecified).into())
+            }
+        }
+    }
+
+    /// Contributes `value` and waits as [`Self::wait_exchange`], but returns a guard that
+    /// reads the values in place.
+    ///
+    /// Every thread of the generation reads the same buffer through its guard, and the
+    /// buffer lives as long as any guard on it does: releasing the next generation
+    /// doesn't wait for the guards to be dropped, nor overwrite what they read, but
+    /// collects into another buffer. Once the last guard on a buffer is dropped the buffer
+    /// is emptied and kept for a later generation, so an exchange whose threads drop
+    /// their guards before waiting again goes back and forth between two buffers instead
+    /// of allocating for every generation.
+    pub fn wait_exchange_ref(&self, value: T) -> Result<ExchangeGuard<'_, T>, BarrierWaitError> {
+        let values = self.wait_exchange(value)?;
+        Ok(ExchangeGuard {
+            exchange: self,
+            values: Some(values),
+        })
+    }
+
+    /// Cancels the exchange, causing all waiting threads to return with an error
+    pub fn cancel(&self) {
+        self.cancel_with(CancelReason::Unspecified);
+    }
+
+    /// Cancels the exchange with a `reason` that waiting threads receive in their error
+    pub fn cancel_with(&self, reason: CancelReason) {
+        let mut state = self.lock();
+        state.cancelled = Some(reason);
+        self.condvar.notify_all();
+    }
+
+    /// Resets the exchange to its initial state.
+    ///
+    /// Values contributed to the unfinished generation are dropped, so they can't leak
+    /// into the next one. Threads still waiting on it are woken with a cancelled error.
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.cancelled = None;
+        state.values.clear();
+        state.released = None;
+        state.spare = None;
+        state.generation = state.generation.wrapping_add(1);
+        self.condvar.notify_all();
+    }
+
+    /// Returns true if the exchange is currently cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled.is_some()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ExchangeState<T>> {
+        self.mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The values of a generation of a [`BarrierExchange`], in arrival order, returned by
+/// [`BarrierExchange::wait_exchange_ref`].
+///
+/// Dereferences to the values. Dropping the last guard on them gives their buffer back to
+/// the exchange.
+#[derive(Debug)]
+pub struct ExchangeGuard<'a, T> {
+    exchange: &'a BarrierExchange<T>,
+    values: Option<Arc<Vec<T>>>,
+}
+
+impl<T> Deref for ExchangeGuard<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.values.as_ref().expect("only taken when dropped")
+    }
+}
+
+impl<T> Drop for ExchangeGuard<'_, T> {
+    fn drop(&mut self) {
+        let Some(values) = self.values.take() else {
+            return;
+        };
+        // under the lock, so either we see the exchange let go of the buffer or the next
+        // release sees us let go of it
+        let mut state = self.exchange.lock();
+        if let Ok(mut values) = Arc::try_unwrap(values) {
+            values.clear();
+            state.spare.get_or_insert(values);
+        }
+    }
+}
+
+fn check_cancelled<T>(state: &ExchangeState<T>) -> Result<(), BarrierWaitError> {
+    match &state.cancelled {
+        Some(reason) => Err(CancelledBarrier::new(reason.clone()).into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_exchange_collects_all_values() {
+        let exchange = BarrierExchange::new(3);
+
+        let handles: Vec<_> = (0..3)
+            .map(|i| {
+                let exchange = exchange.clone();
+                thread::spawn(move || exchange.wait_exchange(i).unwrap())
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        let mut values = results[0].as_ref().clone();
+        values.sort();
+        assert_eq!(values, vec![0, 1, 2]);
+        // everyone shares the same buffer
+        assert!(results.iter().all(|r| Arc::ptr_eq(r, &results[0])));
+    }
+
+    #[test]
+    fn test_exchange_generations_are_separate() {
+        let exchange = BarrierExchange::new(1);
+        assert_eq!(*exchange.wait_exchange("a").unwrap(), vec!["a"]);
+        assert_eq!(*exchange.wait_exchange("b").unwrap(), vec!["b"]);
+    }
+
+    #[test]
+    fn test_exchange_reset_drops_values() {
+        let exchange = BarrierExchange::new(2);
+
+        let exchange_clone = exchange.clone();
+        let t1 = thread::spawn(move || exchange_clone.wait_exchange(1));
+        thread::sleep(Duration::from_millis(100));
+        exchange.reset();
+        assert!(t1.join().unwrap().is_err());
+
+        let exchange_clone = exchange.clone();
+        let t1 = thread::spawn(move || exchange_clone.wait_exchange(2));
+        let mut values = exchange.wait_exchange(3).unwrap().as_ref().clone();
+        values.sort();
+        assert_eq!(values, vec![2, 3]);
+        t1.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_exchange_ref_recycles_buffers() {
+        let exchange = BarrierExchange::new(2);
+        let round = |values: [u32; 2]| {
+            let exchange_clone = exchange.clone();
+            let other = thread::spawn(move || exchange_clone.wait_exchange(values[0]).map(drop));
+            let guard = exchange.wait_exchange_ref(values[1]).unwrap();
+            other.join().unwrap().unwrap();
+            guard
+        };
+
+        // later generations don't touch a buffer still being read
+        let held = round([1, 2]);
+        let buffers: Vec<_> = (0..2).map(|i| round([i, i]).as_ptr()).collect();
+        assert!(!buffers.contains(&held.as_ptr()));
+        let mut values = held.to_vec();
+        values.sort();
+        assert_eq!(values, vec![1, 2]);
+        drop(held);
+
+        // and once the guards are dropped, the exchange goes back and forth between two
+        let mut buffers: Vec<_> = (0..8).map(|i| round([i, i + 1]).as_ptr()).collect();
+        buffers.sort();
+        buffers.dedup();
+        assert!(buffers.len() <= 2, "{buffers:?}");
+        let guard = round([7, 8]);
+        assert_eq!(guard.len(), 2);
+        assert!(guard.contains(&7) && guard.contains(&8));
+    }
+
+    #[test]
+    fn test_exchange_cancel() {
+        let exchange = BarrierExchange::new(2);
+
+        let exchange_clone = exchange.clone();
+        let t1 = thread::spawn(move || exchange_clone.wait_exchange(()));
+        exchange.cancel_with(CancelReason::Shutdown);
+        assert_eq!(
+            t1.join().unwrap().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Shutdown)
+        );
+        assert!(exchange.is_cancelled());
+        assert!(exchange.wait_exchange(()).is_err());
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::Ordering;
+
+use super::{
+    BarrierState, BarrierWaitError, BarrierWaitResult, CancellableBarrier, RawSync, WaitOptions,
+    BLOCKING_SUPPORTED,
+};
+
+/// The order released threads leave a barrier in, as kept with its state: the queue of a
+/// fair barrier, and the threads held back by a pause
+#[derive(Debug, Default)]
+pub(super) struct ReleaseOrder {
+    /// The threads parked on a fair barrier, in the order they leave: by generation, then
+    /// by priority, then in arrival order
+    queue: VecDeque<Queued>,
+    next_ticket: u64,
+    /// Whether released threads are held until the barrier is resumed
+    pub(super) paused: bool,
+    /// Park tickets of the held threads let go by [`CancellableBarrier::release_one`]
+    pub(super) let_go: HashSet<u64>,
+}
+
+/// A thread's place in a fair barrier's queue
+#[derive(Debug, Clone, Copy)]
+struct Queued {
+    ticket: u64,
+    /// The thread's park ticket
+    parked: u64,
+    generation: usize,
+    priority: u8,
+}
+
+impl BarrierState {
+    /// Queues the current thread to leave after those of earlier generations and of the
+    /// same generation with at least its priority
+    pub(super) fn join_queue(&mut self, parked: u64, priority: u8) -> u64 {
+        let ticket = self.order.next_ticket;
+        self.order.next_ticket += 1;
+        let generation = self.generation;
+        let position = self
+            .order
+            .queue
+            .iter()
+            .rposition(|queued| queued.generation != generation || queued.priority >= priority)
+            .map_or(0, |position| position + 1);
+        self.order.queue.insert(
+            position,
+            Queued {
+                ticket,
+                parked,
+                generation,
+                priority,
+            },
+        );
+        ticket
+    }
+
+    /// Returns true if the thread with queue ticket `ticket` is at the front of the fair
+    /// queue, with nobody left to leave before it
+    pub(super) fn is_next_to_leave(&self, ticket: u64) -> bool {
+        self.order.queue.front().map(|queued| queued.ticket) == Some(ticket)
+    }
+
+    /// Returns true if the thread with park ticket `ticket` is held back by a pause,
+    /// should its generation have been released
+    pub(super) fn is_held(&self, ticket: u64) -> bool {
+        self.order.paused && !self.order.let_go.contains(&ticket)
+    }
+}
+
+impl<S: RawSync> CancellableBarrier<S> {
+    /// Like [`Self::wait`], but on a fair barrier the thread returns before the threads
+    /// of its generation with a lower `priority`.
+    ///
+    /// A fair barrier lets its released threads go one at a time, and this orders them
+    /// by priority, highest first, and then in the order they arrived, so that
+    /// latency-critical threads are the first to get on with their work. Plain waits have
+    /// priority 0, the lowest. The order is only that of their returns: each thread can
+    /// only go once the one before it has left, but when they then get to run is up to the
+    /// OS scheduler, so this is best effort. Threads of an earlier generation still leave
+    /// first, and on a barrier that isn't fair the priority is ignored.
+    pub fn wait_with_priority(&self, priority: u8) -> Result<BarrierWaitResult, BarrierWaitError> {
+        self.wait_inner(WaitOptions {
+            deadline: self
+                .default_timeout
+                .and_then(|timeout| self.deadline_after(timeout)),
+            priority,
+            ..WaitOptions::default()
+        })
+    }
+
+    /// Keeps the thread that released a generation from returning while the barrier is
+    /// paused, as the other threads of that generation are kept
+    pub(super) fn hold_while_paused(&self, mut state: S::Guard<'_, BarrierState>) {
+        if !state.order.paused || !BLOCKING_SUPPORTED {
+            return;
+        }
+        // we've had the lock since releasing, so nobody has parked since
+        let ticket = state.park();
+        state.released_below = state.next_park_ticket;
+        while state.is_held(ticket) && state.cancelled.is_none() {
+            state = S::wait(&self.condvar, state);
+        }
+        self.unpark(&mut state, ticket);
+    }
+
+    /// Removes `ticket` from a fair barrier's queue, letting the next in line go if it was
+    /// at the front
+    pub(super) fn leave_queue(&self, state: &mut BarrierState, ticket: u64) {
+        if let Some(position) = state
+            .order
+            .queue
+            .iter()
+            .position(|queued| queued.ticket == ticket)
+        {
+            state.order.queue.remove(position);
+            if position == 0 {
+                S::notify_all(&self.condvar);
+            }
+        }
+    }
+
+    /// Pauses the barrier, holding back the threads of every generation released from now
+    /// on until [`Self::resume`] is called.
+    ///
+    /// This is a hold, not an abort: threads keep arriving and generations keep being
+    /// released as usual, but the released threads, leader included, don't return from
+    /// their wait until the barrier is resumed. A held thread has been released, so it
+    /// no longer times out or gives up on its signal, although cancelling the barrier lets
+    /// it go. Late parties of a quorum barrier and [`Self::try_wait`] don't wait for a
+    /// release, so they aren't held. [`Self::release_one`] lets the held threads go one at
+    /// a time.
+    pub fn pause(&self) {
+        let mut state = self.lock();
+        state.order.paused = true;
+        if let Some(window) = &self.window {
+            window.set_paused(true);
+        }
+    }
+
+    /// Resumes a paused barrier, letting every held thread return
+    pub fn resume(&self) {
+        let mut state = self.lock();
+        state.order.paused = false;
+        if let Some(window) = &self.window {
+            window.set_paused(false);
+        }
+        self.epoch.fetch_add(1, Ordering::Relaxed);
+        S::notify_all(&self.condvar);
+        state.wake_all();
+    }
+
+    /// Lets one thread held by a pause return, keeping the barrier paused, for stepping
+    /// through a release while debugging.
+    ///
+    /// Threads are let go in the order they parked, so the threads of an earlier
+    /// generation before those of a later one and the leader of a generation last, or on a
+    /// fair barrier in the order its queue lets them leave. Each thread has its own
+    /// ticket, so exactly the one let go returns, however many others are woken to check.
+    /// Tasks held in [`Self::wait_async`] take their turn as threads do. Returns false if
+    /// nothing was held, including when the barrier isn't paused.
+    pub fn release_one(&self) -> bool {
+        let mut state = self.lock();
+        if !state.order.paused {
+            return false;
+        }
+        let position = |ticket: u64| {
+            state
+                .order
+                .queue
+                .iter()
+                .position(|queued| queued.parked == ticket)
+                .unwrap_or(usize::MAX)
+        };
+        let next = state
+            .park_tickets
+            .range(..state.released_below)
+            .copied()
+            .filter(|ticket| !state.order.let_go.contains(ticket))
+            .min_by_key(|&ticket| position(ticket));
+        let Some(ticket) = next else {
+            return false;
+        };
+        state.order.let_go.insert(ticket);
+        S::notify_all(&self.condvar);
+        state.wake_all();
+        true
+    }
+
+    /// Returns true if the barrier is paused
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.lock().order.paused
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::cancellable_barrier::tests::{block_on, NoopWaker, TEST_SLEEP_DURATION};
+    use crate::cancellable_barrier::MockClock;
+    use crate::sync::Arc;
+    use std::future::Future;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_fair_release_order() {
+        const WAITERS: usize = 4;
+        let barrier = CancellableBarrier::builder()
+            .parties(WAITERS + 1)
+            .fair(true)
+            .build()
+            .unwrap();
+
+        let handles: Vec<_> = (0..WAITERS)
+            .map(|i| {
+                let barrier_clone = barrier.clone();
+                let handle = thread::spawn(move || {
+                    barrier_clone.wait().unwrap();
+                    // everyone who arrived before us has already left
+                    let state = barrier_clone.lock();
+                    assert!(state
+                        .order
+                        .queue
+                        .iter()
+                        .all(|queued| queued.ticket > i as u64));
+                });
+                // make sure the arrival order is the spawn order
+                while barrier.state().arrived <= i {
+                    thread::yield_now();
+                }
+                handle
+            })
+            .collect();
+
+        assert!(barrier.wait().unwrap().is_leader());
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(barrier.lock().order.queue.is_empty());
+    }
+
+    #[test]
+    fn test_fair_release_by_priority() {
+        const PRIORITIES: [u8; 5] = [0, 2, 1, 2, 0];
+        let barrier = CancellableBarrier::builder()
+            .parties(PRIORITIES.len() + 1)
+            .fair(true)
+            .build()
+            .unwrap();
+
+        let handles: Vec<_> = PRIORITIES
+            .iter()
+            .enumerate()
+            .map(|(i, &priority)| {
+                let barrier_clone = barrier.clone();
+                let handle = thread::spawn(move || {
+                    barrier_clone.wait_with_priority(priority).unwrap();
+                    // everyone with a higher priority, or the same and arrived before
+                    // us, has already left
+                    let state = barrier_clone.lock();
+                    assert!(state
+                        .order
+                        .queue
+                        .iter()
+                        .all(|queued| queued.priority < priority
+                            || (queued.priority == priority && queued.ticket > i as u64)));
+                });
+                // counted arrivals may still be spinning, so wait for the queue
+                while barrier.lock().order.queue.len() <= i {
+                    thread::yield_now();
+                }
+                handle
+            })
+            .collect();
+
+        assert!(barrier.wait().unwrap().is_leader());
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_fair_queue_skips_timed_out_waiters() {
+        let barrier = CancellableBarrier::builder()
+            .parties(3)
+            .fair(true)
+            .build()
+            .unwrap();
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait_timeout(Duration::from_millis(10)));
+        assert_eq!(t1.join().unwrap(), Err(BarrierWaitError::timed_out(1, 2)));
+
+        let barrier_clone1 = barrier.clone();
+        let barrier_clone2 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        let t2 = thread::spawn(move || barrier_clone2.wait());
+        barrier.wait_timeout(Duration::from_secs(10)).unwrap();
+        t1.join().unwrap().unwrap();
+        t2.join().unwrap().unwrap();
+        assert!(barrier.lock().order.queue.is_empty());
+    }
+
+    #[test]
+    fn test_pause_holds_released_threads() {
+        let clock = Arc::new(MockClock::new());
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        barrier.pause();
+        assert!(barrier.is_paused());
+
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait_timeout(TEST_SLEEP_DURATION))
+            })
+            .collect();
+        // released, but held past the timeout until resumed
+        while barrier.lock().parked < 2 {
+            thread::yield_now();
+        }
+        clock.advance(TEST_SLEEP_DURATION * 2);
+        assert_eq!(barrier.state().generation, 1);
+        assert!(handles.iter().all(|h| !h.is_finished()));
+
+        barrier.resume();
+        assert!(!barrier.is_paused());
+        let leaders = handles
+            .into_iter()
+            .map(|h| h.join().unwrap().unwrap())
+            .filter(|r| r.is_leader())
+            .count();
+        assert_eq!(leaders, 1);
+    }
+
+    #[test]
+    fn test_release_one_steps_through_held_threads() {
+        let barrier = CancellableBarrier::new(3);
+        barrier.pause();
+        assert!(!barrier.release_one());
+
+        let mut handles: VecDeque<_> = (0..3)
+            .map(|i| {
+                let handle = {
+                    let barrier = barrier.clone();
+                    thread::spawn(move || barrier.wait())
+                };
+                // park in a known order
+                while barrier.lock().parked <= i {
+                    thread::yield_now();
+                }
+                handle
+            })
+            .collect();
+        assert_eq!(barrier.state().generation, 1);
+
+        // one at a time, in the order they parked, the leader last
+        while let Some(handle) = handles.pop_front() {
+            assert!(barrier.release_one());
+            let result = handle.join().unwrap().unwrap();
+            assert_eq!(result.is_leader(), handles.is_empty());
+            assert_eq!(barrier.lock().parked, handles.len());
+            assert!(handles.iter().all(|h| !h.is_finished()));
+        }
+        assert!(!barrier.release_one());
+        assert!(barrier.is_paused());
+        barrier.resume();
+        assert_eq!(barrier.lock().parked, 0);
+    }
+
+    #[test]
+    fn test_pause_holds_async_leader() {
+        let barrier = CancellableBarrier::new(1);
+        barrier.pause();
+
+        let waker = Arc::new(NoopWaker).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut wait = barrier.wait_async();
+        assert!(std::pin::Pin::new(&mut wait).poll(&mut cx).is_pending());
+        assert_eq!(barrier.state().generation, 1);
+
+        barrier.resume();
+        let result = block_on(wait).unwrap();
+        assert!(result.is_leader());
+        assert_eq!(result.generation(), 0);
+        assert_eq!(barrier.lock().parked, 0);
+    }
+}
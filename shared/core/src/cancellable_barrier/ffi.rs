@@ -0,0 +1,238 @@
+//! C bindings for [`CancellableBarrier`], behind the `ffi` feature.
+//!
+//! The barrier is handed out as an opaque pointer from [`psyche_barrier_new`] that every
+//! participating thread, C or Rust, waits on, and that is released once with
+//! [`psyche_barrier_free`] after all of them are done with it. Waits return
+//! [`PSYCHE_BARRIER_LEADER`] or [`PSYCHE_BARRIER_OK`] on release, and one of the negative
+//! `PSYCHE_BARRIER_*` codes otherwise. A panic never unwinds into C: it is caught at the
+//! boundary and comes back as [`PSYCHE_BARRIER_PANICKED`], or as null from
+//! [`psyche_barrier_new`].
+//!
+//! The `psyche-barrier-ffi` crate builds these into a static and a shared library, with
+//! the matching C header.
+
+use std::ffi::c_int;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+
+use super::{BarrierWaitError, BarrierWaitResult, CancellableBarrier};
+use crate::sync::Arc;
+
+/// Released, and this thread was the leader of its generation
+pub const PSYCHE_BARRIER_LEADER: c_int = 1;
+/// Released, or the call succeeded
+pub const PSYCHE_BARRIER_OK: c_int = 0;
+/// The barrier was cancelled
+pub const PSYCHE_BARRIER_CANCELLED: c_int = -1;
+/// The timeout elapsed before the barrier released
+pub const PSYCHE_BARRIER_TIMED_OUT: c_int = -2;
+/// The wait was interrupted
+pub const PSYCHE_BARRIER_INTERRUPTED: c_int = -3;
+/// This thread had already arrived in the current generation
+pub const PSYCHE_BARRIER_ALREADY_ARRIVED: c_int = -4;
+/// More threads are using the barrier than it has parties
+pub const PSYCHE_BARRIER_TOO_MANY_PARTIES: c_int = -5;
+/// The wait would block forever on a target without threads
+pub const PSYCHE_BARRIER_WOULD_BLOCK: c_int = -6;
+/// The barrier was aborted
+pub const PSYCHE_BARRIER_ABORTED: c_int = -7;
+/// The barrier is being drained, and the thread is asked to leave
+pub const PSYCHE_BARRIER_DRAINING: c_int = -8;
+/// The call panicked, for instance in a release listener, and the panic was caught
+pub const PSYCHE_BARRIER_PANICKED: c_int = -9;
+/// The barrier pointer was null
+pub const PSYCHE_BARRIER_INVALID: c_int = -100;
+
+/// Opaque handle to a barrier, for C code
+pub struct PsycheBarrier {
+    inner: Arc<CancellableBarrier>,
+}
+
+fn wait_code(result: Result<BarrierWaitResult, BarrierWaitError>) -> c_int {
+    match result {
+        Ok(result) if result.is_leader() => PSYCHE_BARRIER_LEADER,
+        Ok(_) => PSYCHE_BARRIER_OK,
+        Err(BarrierWaitError::Cancelled(_)) => PSYCHE_BARRIER_CANCELLED,
+        Err(BarrierWaitError::TimedOut { .. }) => PSYCHE_BARRIER_TIMED_OUT,
+        Err(BarrierWaitError::Interrupted) => PSYCHE_BARRIER_INTERRUPTED,
+        Err(BarrierWaitError::AlreadyArrived) => PSYCHE_BARRIER_ALREADY_ARRIVED,
+        Err(BarrierWaitError::TooManyParties) => PSYCHE_BARRIER_TOO_MANY_PARTIES,
+        Err(BarrierWaitError::WouldBlock) => PSYCHE_BARRIER_WOULD_BLOCK,
+        Err(BarrierWaitError::Aborted) => PSYCHE_BARRIER_ABORTED,
+        Err(BarrierWaitError::Draining) => PSYCHE_BARRIER_DRAINING,
+    }
+}
+
+/// Runs `f` on the barrier behind `barrier`, or returns [`PSYCHE_BARRIER_INVALID`] if it
+/// is null, and [`PSYCHE_BARRIER_PANICKED`] if `f` panics.
+///
+/// # Safety
+///
+/// `barrier` must be null or a pointer from [`psyche_barrier_new`] that hasn't been freed.
+unsafe fn with_barrier(
+    barrier: *mut PsycheBarrier,
+    f: impl FnOnce(&CancellableBarrier) -> c_int,
+) -> c_int {
+    // SAFETY: non-null pointers come from `psyche_barrier_new` and are live, per the
+    // caller's contract, and the barrier is only ever used through shared references
+    match unsafe { barrier.as_ref() } {
+        // the barrier recovers from a panic while it was locked, so it stays usable
+        Some(barrier) => panic::catch_unwind(AssertUnwindSafe(|| f(&barrier.inner)))
+            .unwrap_or(PSYCHE_BARRIER_PANICKED),
+        None => PSYCHE_BARRIER_INVALID,
+    }
+}
+
+/// Creates a barrier for `n` threads, or returns null if `n` is 0 or creating it panicked
+#[no_mangle]
+pub extern "C" fn psyche_barrier_new(n: usize) -> *mut PsycheBarrier {
+    match panic::catch_unwind(|| CancellableBarrier::try_new(n)) {
+        Ok(Ok(inner)) => Box::into_raw(Box::new(PsycheBarrier { inner })),
+        Ok(Err(_)) | Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Waits until all threads have reached the barrier or the barrier is cancelled
+///
+/// # Safety
+///
+/// `barrier` must be null or a pointer from [`psyche_barrier_new`] that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn psyche_barrier_wait(barrier: *mut PsycheBarrier) -> c_int {
+    unsafe { with_barrier(barrier, |barrier| wait_code(barrier.wait())) }
+}
+
+/// Waits until all threads have reached the barrier, the barrier is cancelled, or
+/// `timeout_ms` milliseconds elapse
+///
+/// # Safety
+///
+/// `barrier` must be null or a pointer from [`psyche_barrier_new`] that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn psyche_barrier_wait_timeout_ms(
+    barrier: *mut PsycheBarrier,
+    timeout_ms: u64,
+) -> c_int {
+    unsafe {
+        with_barrier(barrier, |barrier| {
+            wait_code(barrier.wait_timeout(Duration::from_millis(timeout_ms)))
+        })
+    }
+}
+
+/// Cancels the barrier, causing all waiting threads to return
+/// [`PSYCHE_BARRIER_CANCELLED`]
+///
+/// # Safety
+///
+/// `barrier` must be null or a pointer from [`psyche_barrier_new`] that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn psyche_barrier_cancel(barrier: *mut PsycheBarrier) -> c_int {
+    unsafe {
+        with_barrier(barrier, |barrier| {
+            barrier.cancel();
+            PSYCHE_BARRIER_OK
+        })
+    }
+}
+
+/// Resets the barrier to its initial state, see [`CancellableBarrier::reset`]
+///
+/// # Safety
+///
+/// `barrier` must be null or a pointer from [`psyche_barrier_new`] that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn psyche_barrier_reset(barrier: *mut PsycheBarrier) -> c_int {
+    unsafe {
+        with_barrier(barrier, |barrier| {
+            barrier.reset();
+            PSYCHE_BARRIER_OK
+        })
+    }
+}
+
+/// Frees a barrier. Does nothing if `barrier` is null.
+///
+/// # Safety
+///
+/// `barrier` must be null or a pointer from [`psyche_barrier_new`] that hasn't been freed,
+/// and no thread may use it during or after this call.
+#[no_mangle]
+pub unsafe extern "C" fn psyche_barrier_free(barrier: *mut PsycheBarrier) {
+    if !barrier.is_null() {
+        // SAFETY: the pointer came from `Box::into_raw` and nothing uses it any more, per
+        // the caller's contract
+        let barrier = unsafe { Box::from_raw(barrier) };
+        // dropping the last reference drops the listeners, which may panic
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(barrier)));
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Lets the raw pointer cross to test threads, as C code would share it
+    #[derive(Clone, Copy)]
+    struct Shared(*mut PsycheBarrier);
+    unsafe impl Send for Shared {}
+
+    #[test]
+    fn test_ffi_wait_and_cancel() {
+        let barrier = psyche_barrier_new(2);
+        let shared = Shared(barrier);
+
+        let t1 = thread::spawn(move || {
+            let shared = shared;
+            unsafe { psyche_barrier_wait(shared.0) }
+        });
+        let ours = unsafe { psyche_barrier_wait(barrier) };
+        let mut codes = [ours, t1.join().unwrap()];
+        codes.sort();
+        assert_eq!(codes, [PSYCHE_BARRIER_OK, PSYCHE_BARRIER_LEADER]);
+
+        unsafe {
+            assert_eq!(
+                psyche_barrier_wait_timeout_ms(barrier, 10),
+                PSYCHE_BARRIER_TIMED_OUT
+            );
+            assert_eq!(psyche_barrier_cancel(barrier), PSYCHE_BARRIER_OK);
+            assert_eq!(psyche_barrier_wait(barrier), PSYCHE_BARRIER_CANCELLED);
+            assert_eq!(psyche_barrier_reset(barrier), PSYCHE_BARRIER_OK);
+            assert_eq!(
+                psyche_barrier_wait_timeout_ms(barrier, 10),
+                PSYCHE_BARRIER_TIMED_OUT
+            );
+            psyche_barrier_free(barrier);
+        }
+    }
+
+    #[test]
+    fn test_ffi_catches_panics() {
+        let barrier = psyche_barrier_new(1);
+        unsafe {
+            (*barrier)
+                .inner
+                .add_release_listener(|_| panic!("release listener panicked"));
+            assert_eq!(psyche_barrier_wait(barrier), PSYCHE_BARRIER_PANICKED);
+            // the barrier is still usable, and the listener panics again
+            assert_eq!(psyche_barrier_wait(barrier), PSYCHE_BARRIER_PANICKED);
+            assert_eq!(psyche_barrier_cancel(barrier), PSYCHE_BARRIER_OK);
+            assert_eq!(psyche_barrier_wait(barrier), PSYCHE_BARRIER_CANCELLED);
+            psyche_barrier_free(barrier);
+        }
+    }
+
+    #[test]
+    fn test_ffi_invalid_arguments() {
+        assert!(psyche_barrier_new(0).is_null());
+        unsafe {
+            assert_eq!(
+                psyche_barrier_wait(std::ptr::null_mut()),
+                PSYCHE_BARRIER_INVALID
+            );
+            psyche_barrier_free(std::ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use super::{
+    BarrierWaitError, BarrierWaitResult, CancelReason, CancellableBarrier, DefaultSync, RawSync,
+};
+use crate::sync::Arc;
+
+/// A pending arrival at a [`CancellableBarrier`], returned by
+/// [`CancellableBarrier::wait_guarded`].
+///
+/// Dropping the guard without calling [`ArrivalGuard::wait`] cancels the barrier, with
+/// [`CancelReason::Panicked`] if the thread is unwinding and [`CancelReason::Error`]
+/// otherwise.
+#[derive(Debug)]
+#[must_use = "dropping the guard without waiting cancels the barrier"]
+pub struct ArrivalGuard<'a, S: RawSync = DefaultSync> {
+    barrier: Option<&'a CancellableBarrier<S>>,
+}
+
+impl<'a, S: RawSync> ArrivalGuard<'a, S> {
+    pub(super) fn new(barrier: &'a CancellableBarrier<S>) -> Self {
+        ArrivalGuard {
+            barrier: Some(barrier),
+        }
+    }
+
+    /// Arrives at the barrier and waits, as [`CancellableBarrier::wait`]
+    pub fn wait(mut self) -> Result<BarrierWaitResult, BarrierWaitError> {
+        self.barrier
+            .take()
+            .expect("guard is only consumed once")
+            .wait()
+    }
+}
+
+impl<S: RawSync> Drop for ArrivalGuard<'_, S> {
+    fn drop(&mut self) {
+        if let Some(barrier) = self.barrier.take() {
+            let reason = if std::thread::panicking() {
+                CancelReason::Panicked
+            } else {
+                CancelReason::Error
+            };
+            barrier.cancel_with(reason);
+        }
+    }
+}
+
+/// A coordinator's promise to see a barrier through, returned by
+/// [`CancellableBarrier::cancel_on_drop`].
+///
+/// Dropping the guard cancels the barrier with the reason it was made with, unless
+/// [`CancelGuard::disarm`] was called first. Kept in the scope or task that drives the
+/// barrier, it makes sure that a panic, an early return or an aborted task fails the
+/// other parties' waits instead of leaving them waiting forever.
+#[derive(Debug)]
+#[must_use = "dropping the guard straight away cancels the barrier"]
+pub struct CancelGuard<S: RawSync = DefaultSync> {
+    barrier: Option<Arc<CancellableBarrier<S>>>,
+    reason: CancelReason,
+}
+
+impl<S: RawSync> CancelGuard<S> {
+    pub(super) fn new(barrier: Arc<CancellableBarrier<S>>, reason: CancelReason) -> Self {
+        CancelGuard {
+            barrier: Some(barrier),
+            reason,
+        }
+    }
+
+    /// Returns the guarded barrier
+    #[must_use]
+    pub fn barrier(&self) -> &Arc<CancellableBarrier<S>> {
+        self.barrier.as_ref().expect("guard is only consumed once")
+    }
+
+    /// Gives up the guard without cancelling the barrier, handing it back
+    pub fn disarm(mut self) -> Arc<CancellableBarrier<S>> {
+        self.barrier.take().expect("guard is only consumed once")
+    }
+}
+
+impl<S: RawSync> Drop for CancelGuard<S> {
+    fn drop(&mut self) {
+        if let Some(barrier) = self.barrier.take() {
+            barrier.cancel_with(self.reason.clone());
+        }
+    }
+}
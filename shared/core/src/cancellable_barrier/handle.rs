@@ -0,0 +1,177 @@
+use super::{BarrierWaitError, BarrierWaitResult, CancellableBarrier, DefaultSync, RawSync};
+use crate::sync::Arc;
+
+/// A party's membership of a [`CancellableBarrier`], returned by
+/// [`CancellableBarrier::join`].
+///
+/// Creating the handle adds a party to the barrier and dropping it removes that party
+/// again, so the barrier's size follows the handles that are alive and can't be
+/// miscounted. If every other party is already waiting when a handle is dropped, they are
+/// released, as with [`CancellableBarrier::remove_party`]. Cloning a handle joins another
+/// party.
+///
+/// Handles are counted on top of the parties the barrier was built with. A barrier never
+/// goes below one party, so dropping a handle that would leave it with none leaves that
+/// one party in place.
+///
+/// The barrier keeps track of its live handles apart from the arrivals. Should a party
+/// that isn't a handle go away, such as a built party whose thread dropped its `Arc`,
+/// the handles left waiting could wait forever. Handles notice instead: once every live
+/// handle is waiting on a generation that doesn't have all its arrivals, and the handles
+/// are all that holds on to the barrier, it is cancelled with
+/// [`CancelReason::Unreachable`](super::CancelReason::Unreachable). Anything else keeping
+/// an `Arc` to the barrier, a clone that never waits included, counts as someone who may
+/// still arrive.
+#[derive(Debug)]
+pub struct BarrierHandle<S: RawSync = DefaultSync> {
+    barrier: Arc<CancellableBarrier<S>>,
+    index: usize,
+}
+
+impl<S: RawSync> BarrierHandle<S> {
+    pub(super) fn new(barrier: Arc<CancellableBarrier<S>>) -> Self {
+        let index = barrier.add_handle();
+        BarrierHandle { barrier, index }
+    }
+
+    /// Waits until all parties have reached the barrier or the barrier is cancelled, as
+    /// [`CancellableBarrier::wait`], or until it notices nobody else is left to arrive.
+    /// Waiting through [`Self::barrier`] instead still counts the handle as waiting, but
+    /// doesn't look out for that.
+    pub fn wait(&self) -> Result<BarrierWaitResult, BarrierWaitError> {
+        self.barrier
+            .wait_as_handle(self.index, &|| Arc::strong_count(&self.barrier))
+    }
+
+    /// Returns the party's index, which stays the same for as long as the handle lives.
+    ///
+    /// Handles are numbered from 0 in the order they join the barrier, and an index isn't
+    /// handed out again once its handle is dropped. A round-robin
+    /// [`LeaderPolicy`](super::LeaderPolicy) takes turns in this order.
+    #[must_use]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the barrier this handle is a party of
+    #[must_use]
+    pub fn barrier(&self) -> &Arc<CancellableBarrier<S>> {
+        &self.barrier
+    }
+}
+
+impl<S: RawSync> Clone for BarrierHandle<S> {
+    fn clone(&self) -> Self {
+        BarrierHandle::new(self.barrier.clone())
+    }
+}
+
+impl<S: RawSync> Drop for BarrierHandle<S> {
+    fn drop(&mut self) {
+        // the parked handles see the reference go the next time they check
+        self.barrier.remove_handle(self.index);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_handle_counts_parties() {
+        let barrier = CancellableBarrier::new(1);
+        let handle = barrier.join();
+        let clone = handle.clone();
+        assert_eq!(barrier.state().total, 3);
+        drop(handle);
+        drop(clone);
+        assert_eq!(barrier.state().total, 1);
+    }
+
+    #[test]
+    fn test_dropping_handle_releases_waiters() {
+        let barrier = CancellableBarrier::new(1);
+        let handles: Vec<_> = (0..3).map(|_| barrier.join()).collect();
+        let mut handles = handles.into_iter();
+        let leaving = handles.next().unwrap();
+
+        let waiters: Vec<_> = handles
+            .map(|handle| thread::spawn(move || handle.wait()))
+            .collect();
+        // the barrier's own party
+        let barrier_clone = barrier.clone();
+        let own = thread::spawn(move || barrier_clone.wait());
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(barrier.state().arrived, 3);
+
+        drop(leaving);
+        for waiter in waiters {
+            assert_eq!(waiter.join().unwrap().map(|r| r.generation()), Ok(0));
+        }
+        assert_eq!(own.join().unwrap().map(|r| r.generation()), Ok(0));
+        // the waiting handles have been dropped too
+        assert_eq!(barrier.state().total, 1);
+    }
+
+    #[test]
+    fn test_round_robin_leader() {
+        const GENERATIONS: usize = 30;
+        let barrier = CancellableBarrier::builder()
+            .leader_policy(crate::cancellable_barrier::LeaderPolicy::RoundRobin)
+            .build()
+            .unwrap();
+        let handles: Vec<_> = (0..3).map(|_| barrier.join()).collect();
+        assert_eq!(
+            handles.iter().map(BarrierHandle::index).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+
+        let waiters: Vec<_> = handles
+            .into_iter()
+            .map(|handle| {
+                thread::spawn(move || {
+                    (0..GENERATIONS)
+                        .filter(|_| handle.wait().unwrap().is_leader())
+                        .count()
+                })
+            })
+            .collect();
+        // the built party waits without a handle, and always arrives last
+        for _ in 0..GENERATIONS {
+            while barrier.state().arrived < 3 {
+                thread::yield_now();
+            }
+            assert!(!barrier.wait().unwrap().is_leader());
+        }
+        for waiter in waiters {
+            assert_eq!(waiter.join().unwrap(), GENERATIONS / 3);
+        }
+    }
+
+    #[test]
+    fn test_unreachable_when_only_handles_are_left() {
+        let barrier = CancellableBarrier::new(1);
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let handle = barrier.join();
+                thread::spawn(move || handle.wait())
+            })
+            .collect();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(barrier.state().arrived, 2);
+        // someone could still arrive for the built party
+        for waiter in &waiters {
+            assert!(!waiter.is_finished());
+        }
+
+        drop(barrier);
+        for waiter in waiters {
+            assert_eq!(
+                waiter.join().unwrap().unwrap_err().cancel_reason(),
+                Some(&crate::cancellable_barrier::CancelReason::Unreachable)
+            );
+        }
+    }
+}
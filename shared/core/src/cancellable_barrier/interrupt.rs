@@ -0,0 +1,59 @@
+use std::sync::Weak;
+use std::thread::ThreadId;
+
+use super::{CancellableBarrier, DefaultSync, RawSync};
+
+/// Handle for interrupting the waits of one thread on a barrier, without cancelling the
+/// barrier for everyone else.
+///
+/// Created by [`CancellableBarrier::interrupt_handle`] and used with
+/// [`CancellableBarrier::wait_interruptible`]. It also interrupts the plain
+/// [`CancellableBarrier::wait`]s of the thread that made it. The handle can be cloned and
+/// sent to whichever thread decides when to interrupt, and doesn't keep the barrier alive.
+#[derive(Debug)]
+pub struct InterruptHandle<S: RawSync = DefaultSync> {
+    barrier: Weak<CancellableBarrier<S>>,
+    key: u64,
+    thread: ThreadId,
+}
+
+// derived, it would need `S: Clone`
+impl<S: RawSync> Clone for InterruptHandle<S> {
+    fn clone(&self) -> Self {
+        InterruptHandle {
+            barrier: self.barrier.clone(),
+            key: self.key,
+            thread: self.thread,
+        }
+    }
+}
+
+impl<S: RawSync> InterruptHandle<S> {
+    pub(super) fn new(barrier: Weak<CancellableBarrier<S>>, key: u64, thread: ThreadId) -> Self {
+        InterruptHandle {
+            barrier,
+            key,
+            thread,
+        }
+    }
+
+    pub(super) fn key(&self) -> u64 {
+        self.key
+    }
+
+    pub(super) fn belongs_to(&self, barrier: &CancellableBarrier<S>) -> bool {
+        std::ptr::eq(self.barrier.as_ptr(), barrier)
+    }
+
+    /// Interrupts the wait using this handle, or the plain wait of the thread that made it,
+    /// which returns with
+    /// [`BarrierWaitError::Interrupted`](super::BarrierWaitError::Interrupted). If no wait
+    /// is using it right now, the next one is interrupted as soon as it starts.
+    ///
+    /// Does nothing if the barrier is gone.
+    pub fn interrupt(&self) {
+        if let Some(barrier) = self.barrier.upgrade() {
+            barrier.interrupt(self.key, self.thread);
+        }
+    }
+}
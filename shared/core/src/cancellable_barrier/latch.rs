@@ -0,0 +1,206 @@
+use std::sync::PoisonError;
+use std::time::{Duration, Instant};
+
+use super::{BarrierWaitError, CancelReason, CancelledBarrier};
+use crate::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// A one-shot countdown latch: [`Self::wait`] blocks until [`Self::count_down`] has been
+/// called `n` times, or until the latch is cancelled.
+///
+/// Unlike [`super::CancellableBarrier`], the latch isn't cyclic and the threads counting
+/// down don't wait. Once the count reaches zero the latch stays open for good, and
+/// cancelling it afterwards has no effect on waiters.
+#[derive(Debug)]
+pub struct CancellableLatch {
+    mutex: Mutex<LatchState>,
+    condvar: Condvar,
+}
+
+#[derive(Debug)]
+struct LatchState {
+    total: usize,
+    remaining: usize,
+    cancelled: Option<CancelReason>,
+}
+
+impl CancellableLatch {
+    /// Creates a latch that opens after `n` calls to [`Self::count_down`].
+    ///
+    /// A latch of 0 starts out open.
+    #[must_use]
+    pub fn new(n: usize) -> Arc<Self> {
+        Arc::new(CancellableLatch {
+            mutex: Mutex::new(LatchState {
+                total: n,
+                remaining: n,
+                cancelled: None,
+            }),
+            condvar: Condvar::new(),
+        })
+    }
+
+    /// Decrements the count, opening the latch and waking all waiters when it reaches
+    /// zero. Counting down an open latch does nothing.
+    pub fn count_down(&self) {
+        let mut state = self.lock();
+        if state.remaining == 0 {
+            return;
+        }
+        state.remaining -= 1;
+        if state.remaining == 0 {
+            self.condvar.notify_all();
+        }
+    }
+
+    /// Waits until the latch is open or cancelled
+    pub fn wait(&self) -> Result<(), BarrierWaitError> {
+        self.wait_inner(None)
+    }
+
+    /// Like [`Self::wait`], but gives up once `timeout` has elapsed, returning
+    /// [`BarrierWaitError::TimedOut`]
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<(), BarrierWaitError> {
+        self.wait_inner(Instant::now().checked_add(timeout))
+    }
+
+    fn wait_inner(&self, deadline: Option<Instant>) -> Result<(), BarrierWaitError> {
+        let mut state = self.lock();
+        while state.remaining > 0 {
+            if let Some(reason) = &state.cancelled {
+                return Err(CancelledBarrier::new(reason.clone()).into());
+            }
+            // recompute what's left every time we wake, spurious or not
+            let now = Instant::now();
+            match deadline {
+                Some(deadline) if now >= deadline => {
+                    let arrived = state.total - state.remaining;
+                    return Err(BarrierWaitError::timed_out(arrived, state.remaining));
+                }
+                Some(deadline) => {
+                    state = self
+                        .condvar
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                }
+                None => {
+                    state = self
+                        .condvar
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Cancels the latch, causing all waiting threads to return with an error, unless it
+    /// has already opened
+    pub fn cancel(&self) {
+        self.cancel_with(CancelReason::Unspecified);
+    }
+
+    /// Cancels the latch with a `reason` that waiting threads receive in their error
+    pub fn cancel_with(&self, reason: CancelReason) {
+        let mut state = self.lock();
+        state.cancelled = Some(reason);
+        self.condvar.notify_all();
+    }
+
+    /// Returns the number of [`Self::count_down`] calls still needed to open the latch.
+    ///
+    /// Waiting never counts down, whether the wait succeeds, times out or is cancelled,
+    /// so this can be polled to follow progress.
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.lock().remaining
+    }
+
+    /// Returns true if the latch is currently cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled.is_some()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LatchState> {
+        self.mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_latch_opens_once() {
+        let latch = CancellableLatch::new(3);
+
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let latch = latch.clone();
+                thread::spawn(move || latch.wait())
+            })
+            .collect();
+        for _ in 0..3 {
+            let latch = latch.clone();
+            thread::spawn(move || latch.count_down()).join().unwrap();
+        }
+        for waiter in waiters {
+            assert_eq!(waiter.join().unwrap(), Ok(()));
+        }
+
+        // stays open, even when counted down further or cancelled
+        latch.count_down();
+        assert_eq!(latch.count(), 0);
+        latch.cancel();
+        assert_eq!(latch.wait(), Ok(()));
+        assert_eq!(CancellableLatch::new(0).wait(), Ok(()));
+    }
+
+    #[test]
+    fn test_latch_timeout_and_cancel() {
+        let latch = CancellableLatch::new(1);
+        assert_eq!(
+            latch.wait_timeout(Duration::from_millis(50)),
+            Err(BarrierWaitError::timed_out(0, 1))
+        );
+
+        let latch_clone = latch.clone();
+        let waiter = thread::spawn(move || latch_clone.wait());
+        thread::sleep(Duration::from_millis(100));
+        latch.cancel_with(CancelReason::Shutdown);
+        assert_eq!(
+            waiter.join().unwrap().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Shutdown)
+        );
+        assert!(latch.is_cancelled());
+        assert_eq!(latch.count(), 1);
+    }
+
+    #[test]
+    fn test_latch_cancel_mid_countdown() {
+        let latch = CancellableLatch::new(3);
+        latch.count_down();
+        // waiting, and timing out, doesn't count down
+        assert!(latch.wait_timeout(Duration::from_millis(10)).is_err());
+        assert_eq!(latch.count(), 2);
+
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let latch = latch.clone();
+                thread::spawn(move || latch.wait_timeout(Duration::from_secs(10)))
+            })
+            .collect();
+        latch.count_down();
+        thread::sleep(Duration::from_millis(50));
+        latch.cancel();
+        for waiter in waiters {
+            assert_eq!(
+                waiter.join().unwrap().unwrap_err().cancel_reason(),
+                Some(&CancelReason::Unspecified)
+            );
+        }
+        assert_eq!(latch.count(), 1);
+    }
+}
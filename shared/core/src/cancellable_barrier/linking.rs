@@ -0,0 +1,196 @@
+use std::sync::atomic::Ordering;
+use std::sync::PoisonError;
+use std::time::Duration;
+
+use super::{BarrierBuildError, CancelReason, CancellableBarrier, LivenessToken, RawSync};
+use crate::sync::Arc;
+
+impl<S: RawSync> CancellableBarrier<S> {
+    /// Links `child` to this barrier, so that cancelling this barrier cancels `child` as
+    /// well, with the same reason.
+    ///
+    /// Children can have children of their own, so cancelling the root of a hierarchy of
+    /// barriers tears all of it down. The cancellation reaches a child once this barrier's
+    /// cancel listeners have run, without any lock held, and passes over children that
+    /// are already cancelled. However this barrier is cancelled counts, including by a
+    /// timeout or an abort, but resetting it leaves its children as they are. The child
+    /// is kept alive for as long as this barrier is.
+    ///
+    /// Fails with [`BarrierBuildError::LinkCycle`], linking nothing, if `child` is this
+    /// barrier or one of its ancestors.
+    pub fn link_child(&self, child: Arc<CancellableBarrier<S>>) -> Result<(), BarrierBuildError> {
+        // one link at a time, so that two links can't each close half of a cycle
+        static LINKING: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        let _linking = LINKING.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let mut pending = vec![child.clone()];
+        while let Some(descendant) = pending.pop() {
+            if std::ptr::eq(&*descendant, self) {
+                return Err(BarrierBuildError::LinkCycle);
+            }
+            pending.extend(descendant.children());
+        }
+        self.children
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(child);
+        Ok(())
+    }
+
+    /// Snapshots the children, so that cancelling them doesn't hold our list locked
+    pub(super) fn children(&self) -> Vec<Arc<CancellableBarrier<S>>> {
+        self.children
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns true if the barrier is linked to a shutdown flag that has been set
+    pub(super) fn linked_flag_set(&self) -> bool {
+        self.linked
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Acquire))
+    }
+
+    /// Cancels the barrier with [`CancelReason::Shutdown`] if its linked shutdown flag is
+    /// set and it isn't cancelled yet
+    pub(super) fn check_linked(&self) {
+        if !self.linked_flag_set() {
+            return;
+        }
+        let mut state = self.lock();
+        if state.cancelled.is_none() {
+            self.cancel_locked(&mut state, CancelReason::Shutdown);
+            drop(state);
+            self.notify_cancel_listeners(CancelReason::Shutdown);
+        }
+    }
+
+    /// Cancels the barrier with `reason` if it is still on `generation` and not already
+    /// cancelled
+    #[cfg(not(loom))]
+    pub(super) fn cancel_generation(&self, generation: usize, reason: CancelReason) {
+        let mut state = self.lock();
+        if state.generation == generation && state.cancelled.is_none() {
+            self.cancel_locked(&mut state, reason.clone());
+            drop(state);
+            self.notify_cancel_listeners(reason);
+        }
+    }
+
+    /// Registers a participant that promises to heartbeat through the returned token at
+    /// least every `timeout`.
+    ///
+    /// This is a safety net against parties that are silently gone, such as a thread
+    /// that panicked elsewhere or a task that was dropped, and would otherwise leave
+    /// everyone else waiting for good. If the token misses a beat or is dropped without
+    /// being finished, the barrier is cancelled with [`CancelReason::Lost`] and `id`. See
+    /// [`LivenessToken`].
+    ///
+    /// The monitor runs on a background thread that doesn't keep the barrier alive.
+    #[cfg(not(loom))]
+    pub fn register_liveness(
+        self: &Arc<Self>,
+        id: impl Into<String>,
+        timeout: Duration,
+    ) -> LivenessToken {
+        LivenessToken::spawn(Arc::downgrade(self), id.into(), timeout)
+    }
+
+    /// Deals with a participant lost by its [`LivenessToken`]
+    #[cfg(not(loom))]
+    pub(super) fn lose_party(&self, id: String) {
+        if self.remove_lost_parties && self.remove_party().is_ok() {
+            return;
+        }
+        self.cancel_with(CancelReason::Lost(id));
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::cancellable_barrier::tests::cancelled;
+    use crate::sync::Mutex;
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+
+    #[test]
+    fn test_link_child_propagates_cancel() {
+        let root = CancellableBarrier::new(2);
+        let inner: Vec<_> = (0..2).map(|_| CancellableBarrier::new(2)).collect();
+        let leaf = CancellableBarrier::new(2);
+        for child in &inner {
+            root.link_child(child.clone()).unwrap();
+        }
+        inner[0].link_child(leaf.clone()).unwrap();
+        // a diamond is fine, a cycle isn't
+        inner[1].link_child(leaf.clone()).unwrap();
+        assert_eq!(
+            leaf.link_child(root.clone()),
+            Err(BarrierBuildError::LinkCycle)
+        );
+        assert_eq!(
+            root.link_child(root.clone()),
+            Err(BarrierBuildError::LinkCycle)
+        );
+
+        let leaf_clone = leaf.clone();
+        let waiter = thread::spawn(move || leaf_clone.wait());
+        while leaf.state().arrived == 0 {
+            thread::yield_now();
+        }
+        root.cancel_with(CancelReason::Shutdown);
+        assert_eq!(
+            waiter.join().unwrap(),
+            Err(cancelled(CancelReason::Shutdown))
+        );
+        for barrier in inner.iter().chain([&leaf]) {
+            assert_eq!(barrier.cancel_reason(), Some(CancelReason::Shutdown));
+        }
+
+        // cancelling a child leaves its parent alone
+        root.reset();
+        inner[0].reset();
+        inner[0].cancel();
+        assert!(!root.is_cancelled());
+    }
+
+    #[test]
+    fn test_linked_to_shutdown_flag() {
+        let shutdown = std::sync::Arc::new(AtomicBool::new(false));
+        let barrier = CancellableBarrier::linked_to(3, shutdown.clone());
+        let cancelled_with = std::sync::Arc::new(Mutex::new(None));
+        let cancelled_clone = cancelled_with.clone();
+        barrier.on_cancel(move |reason| *cancelled_clone.lock().unwrap() = Some(reason));
+
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait())
+            })
+            .collect();
+        while barrier.state().arrived < 2 {
+            thread::yield_now();
+        }
+        shutdown.store(true, Ordering::Release);
+        for handle in handles {
+            assert_eq!(
+                handle.join().unwrap(),
+                Err(cancelled(CancelReason::Shutdown))
+            );
+        }
+        assert!(barrier.is_cancelled());
+        assert_eq!(
+            *cancelled_with.lock().unwrap(),
+            Some(CancelReason::Shutdown)
+        );
+
+        // the flag cancels again after a reset for as long as it stays set
+        barrier.reset();
+        assert_eq!(barrier.try_wait(), Err(cancelled(CancelReason::Shutdown)));
+        shutdown.store(false, Ordering::Release);
+        barrier.reset();
+        assert_eq!(barrier.try_wait(), Ok(None));
+    }
+}
@@ -0,0 +1,143 @@
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Weak;
+use std::thread;
+use std::time::Duration;
+
+use super::{CancellableBarrier, RawSync};
+
+/// A participant's promise to keep heartbeating, returned by
+/// [`CancellableBarrier::register_liveness`].
+///
+/// A monitor watches the token from a background thread. If [`Self::beat`] isn't called
+/// within the timeout, or the token is dropped without [`Self::finish`], for example
+/// because its thread panicked or its task was dropped, the participant counts as lost:
+/// the barrier is cancelled with
+/// [`CancelReason::Lost`](super::CancelReason::Lost), or the party is removed if the
+/// barrier was built with
+/// [`CancellableBarrierBuilder::remove_lost_parties`](super::CancellableBarrierBuilder::remove_lost_parties).
+/// Once a participant is lost or finished, the token has no further effect.
+#[derive(Debug)]
+pub struct LivenessToken {
+    beats: mpsc::Sender<Beat>,
+}
+
+enum Beat {
+    Alive,
+    Finished,
+}
+
+impl LivenessToken {
+    pub(super) fn spawn<S: RawSync>(
+        barrier: Weak<CancellableBarrier<S>>,
+        id: String,
+        timeout: Duration,
+    ) -> Self {
+        let (beats, received) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("barrier-liveness".to_string())
+            .spawn(move || loop {
+                match received.recv_timeout(timeout) {
+                    Ok(Beat::Alive) => {}
+                    Ok(Beat::Finished) => return,
+                    Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => {
+                        if let Some(barrier) = barrier.upgrade() {
+                            barrier.lose_party(id);
+                        }
+                        return;
+                    }
+                }
+            })
+            .expect("failed to spawn barrier liveness thread");
+
+        LivenessToken { beats }
+    }
+
+    /// Tells the monitor the participant is still alive, restarting its timeout
+    pub fn beat(&self) {
+        // the monitor is gone if the participant already counts as lost
+        let _ = self.beats.send(Beat::Alive);
+    }
+
+    /// Stops monitoring the participant, which is then never counted as lost
+    pub fn finish(self) {
+        let _ = self.beats.send(Beat::Finished);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::cancellable_barrier::CancelReason;
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn test_missed_beat_cancels() {
+        let barrier = CancellableBarrier::new(2);
+        let token = barrier.register_liveness("worker", TIMEOUT);
+
+        let barrier_clone = barrier.clone();
+        let waiter = thread::spawn(move || barrier_clone.wait());
+        for _ in 0..4 {
+            thread::sleep(TIMEOUT / 2);
+            token.beat();
+        }
+        assert!(!barrier.is_cancelled());
+
+        // stop beating without finishing
+        assert_eq!(
+            waiter.join().unwrap().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Lost("worker".to_string()))
+        );
+        assert!(barrier.is_cancelled());
+        drop(token);
+    }
+
+    #[test]
+    fn test_dropped_token_cancels() {
+        let barrier = CancellableBarrier::new(2);
+        let token = barrier.register_liveness("worker", Duration::from_secs(60));
+        let panicked = thread::spawn(move || {
+            let _token = token;
+            panic!("worker died");
+        });
+        assert!(panicked.join().is_err());
+        assert_eq!(
+            barrier.wait().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Lost("worker".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_finished_token_does_nothing() {
+        let barrier = CancellableBarrier::new(1);
+        barrier.register_liveness("worker", TIMEOUT).finish();
+        thread::sleep(TIMEOUT * 2);
+        assert!(!barrier.is_cancelled());
+    }
+
+    #[test]
+    fn test_lost_party_is_removed() {
+        let barrier = CancellableBarrier::builder()
+            .parties(3)
+            .remove_lost_parties(true)
+            .build()
+            .unwrap();
+        let token = barrier.register_liveness("worker", Duration::from_secs(60));
+
+        let barrier_clone = barrier.clone();
+        let waiter = thread::spawn(move || barrier_clone.wait().map(|r| r.generation()));
+        thread::sleep(TIMEOUT);
+        let barrier_clone = barrier.clone();
+        let own = thread::spawn(move || barrier_clone.wait().map(|r| r.generation()));
+        thread::sleep(TIMEOUT);
+
+        // the two that are left are both waiting, so losing the third releases them
+        drop(token);
+        assert_eq!(waiter.join().unwrap(), Ok(0));
+        assert_eq!(own.join().unwrap(), Ok(0));
+        assert_eq!(barrier.state().total, 2);
+        assert!(!barrier.is_cancelled());
+    }
+}
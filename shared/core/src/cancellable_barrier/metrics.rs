@@ -0,0 +1,315 @@
+//! Cumulative counters behind the `metrics` feature.
+//!
+//! Without the feature the recorder is zero-sized and every method is a no-op, so call
+//! sites don't need their own `cfg`s.
+//!
+//! Times are read from the barrier's [`Clock`], so a [`super::MockClock`] drives them as
+//! it drives the timeouts, except for the critical sections: how long the lock was held
+//! is always real time.
+
+#[cfg(feature = "metrics")]
+use std::collections::VecDeque;
+#[cfg(feature = "metrics")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "metrics")]
+use std::sync::{Mutex, PoisonError};
+#[cfg(feature = "metrics")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "metrics")]
+use super::{Clock, SystemClock};
+
+/// Cumulative counters for a barrier, returned by
+/// [`super::CancellableBarrier::metrics`]
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BarrierMetrics {
+    /// Arrivals at the barrier, including ones later rolled back by a timeout or drop
+    pub arrivals: u64,
+    /// Generations released
+    pub releases: u64,
+    /// Calls to cancel the barrier
+    pub cancellations: u64,
+    /// Resets of the barrier, counting those that start a new generation when one runs
+    /// out of its overall timeout, but not those an aborted barrier ignores
+    pub resets: u64,
+    /// The generation in progress, which every release and reset moves on
+    pub generation: u64,
+    /// Longest time a thread spent parked before the barrier released or cancelled
+    pub longest_wait: Duration,
+    /// Time between the first and the last arrival of the last released generation,
+    /// which is how long the barrier waited for its stragglers
+    pub last_arrival_spread: Duration,
+    /// Longest time between the first and the last arrival of any released generation
+    pub longest_arrival_spread: Duration,
+    /// Time between the last two releases, which is how long the last cycle took
+    pub last_cycle: Duration,
+    /// Longest time a blocking or async wait held the barrier's lock to arrive without
+    /// releasing, from taking it to parking
+    pub max_critical_section: Duration,
+    /// Longest time the last arrival held the lock to release its generation, the action
+    /// and the releasing thread's own work included, which is the section every arrival
+    /// of the next generation can queue behind
+    pub max_release_critical_section: Duration,
+}
+
+/// One released generation, as kept by [`super::CancellableBarrier::recent_cycles`]
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CycleRecord {
+    /// The generation released
+    pub generation: usize,
+    /// When it was released
+    pub completed_at: Instant,
+    /// How long it took from its first arrival to its release, or zero if it was released
+    /// without any arrival
+    pub wait: Duration,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct MetricsRecorder {
+    #[cfg(feature = "metrics")]
+    arrivals: AtomicU64,
+    #[cfg(feature = "metrics")]
+    releases: AtomicU64,
+    #[cfg(feature = "metrics")]
+    cancellations: AtomicU64,
+    #[cfg(feature = "metrics")]
+    resets: AtomicU64,
+    /// Mirrors the barrier's generation, for reading without its lock
+    #[cfg(feature = "metrics")]
+    generation: AtomicU64,
+    #[cfg(feature = "metrics")]
+    longest_wait_nanos: AtomicU64,
+    /// When the generation in progress got its first arrival. Only touched with the
+    /// barrier locked, so this lock is never contended.
+    #[cfg(feature = "metrics")]
+    first_arrival: Mutex<Option<Instant>>,
+    #[cfg(feature = "metrics")]
+    last_spread_nanos: AtomicU64,
+    #[cfg(feature = "metrics")]
+    longest_spread_nanos: AtomicU64,
+    /// When the last generation was released, updated with the barrier locked
+    #[cfg(feature = "metrics")]
+    last_release: Mutex<Option<Instant>>,
+    #[cfg(feature = "metrics")]
+    last_cycle_nanos: AtomicU64,
+    #[cfg(feature = "metrics")]
+    max_section_nanos: AtomicU64,
+    #[cfg(feature = "metrics")]
+    max_release_section_nanos: AtomicU64,
+    /// The last `history_len` released generations, oldest first, updated with the
+    /// barrier locked
+    #[cfg(feature = "metrics")]
+    history: Mutex<VecDeque<CycleRecord>>,
+    #[cfg(feature = "metrics")]
+    history_len: usize,
+    #[cfg(feature = "metrics")]
+    clock: MetricsClock,
+}
+
+/// The barrier's clock, as kept by the recorder
+#[cfg(feature = "metrics")]
+struct MetricsClock(std::sync::Arc<dyn Clock>);
+
+#[cfg(feature = "metrics")]
+impl Default for MetricsClock {
+    fn default() -> Self {
+        MetricsClock(std::sync::Arc::new(SystemClock))
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl std::fmt::Debug for MetricsClock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MetricsClock").finish_non_exhaustive()
+    }
+}
+
+/// Measures how long a thread stays parked, or holds the barrier's lock
+#[derive(Debug)]
+pub(super) struct WaitTimer {
+    #[cfg(feature = "metrics")]
+    started: Instant,
+}
+
+impl WaitTimer {
+    /// Starts timing a critical section, in real time
+    pub(super) fn start() -> Self {
+        WaitTimer {
+            #[cfg(feature = "metrics")]
+            started: Instant::now(),
+        }
+    }
+}
+
+impl MetricsRecorder {
+    /// Creates a recorder that keeps the last `history_len` generations, timed by `clock`
+    #[cfg(feature = "metrics")]
+    pub(super) fn new(history_len: usize, clock: std::sync::Arc<dyn Clock>) -> Self {
+        MetricsRecorder {
+            history: Mutex::new(VecDeque::with_capacity(history_len)),
+            history_len,
+            clock: MetricsClock(clock),
+            ..Self::default()
+        }
+    }
+
+    /// Starts timing a wait, on the barrier's clock
+    pub(super) fn start_wait(&self) -> WaitTimer {
+        WaitTimer {
+            #[cfg(feature = "metrics")]
+            started: self.clock.0.now(),
+        }
+    }
+
+    pub(super) fn record_arrival(&self) {
+        #[cfg(feature = "metrics")]
+        self.arrivals.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records the arrival that starts a generation, to be called with the barrier locked
+    pub(super) fn record_first_arrival(&self) {
+        #[cfg(feature = "metrics")]
+        {
+            *self
+                .first_arrival
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = Some(self.clock.0.now());
+        }
+    }
+
+    pub(super) fn record_release(&self, generation: usize) {
+        #[cfg(feature = "metrics")]
+        {
+            self.releases.fetch_add(1, Ordering::Relaxed);
+            self.generation.store(
+                generation_u64(generation.wrapping_add(1)),
+                Ordering::Relaxed,
+            );
+            let now = self.clock.0.now();
+            let first_arrival = self
+                .first_arrival
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take();
+            let wait =
+                first_arrival.map_or(Duration::ZERO, |first| now.saturating_duration_since(first));
+            if first_arrival.is_some() {
+                let nanos = nanos(wait);
+                self.last_spread_nanos.store(nanos, Ordering::Relaxed);
+                self.longest_spread_nanos
+                    .fetch_max(nanos, Ordering::Relaxed);
+            }
+            if self.history_len > 0 {
+                let mut history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+                if history.len() == self.history_len {
+                    history.pop_front();
+                }
+                history.push_back(CycleRecord {
+                    generation,
+                    completed_at: now,
+                    wait,
+                });
+            }
+            let last_release = self
+                .last_release
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .replace(now);
+            if let Some(last_release) = last_release {
+                let nanos = nanos(now.saturating_duration_since(last_release));
+                self.last_cycle_nanos.store(nanos, Ordering::Relaxed);
+            }
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = generation;
+    }
+
+    pub(super) fn record_cancel(&self) {
+        #[cfg(feature = "metrics")]
+        self.cancellations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a reset that moved the barrier on to `generation`
+    pub(super) fn record_reset(&self, generation: usize) {
+        #[cfg(feature = "metrics")]
+        {
+            self.resets.fetch_add(1, Ordering::Relaxed);
+            self.generation
+                .store(generation_u64(generation), Ordering::Relaxed);
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = generation;
+    }
+
+    /// Records a wait timed since [`Self::start_wait`]
+    pub(super) fn record_wait(&self, timer: WaitTimer) {
+        #[cfg(feature = "metrics")]
+        {
+            let waited = self.clock.0.now().saturating_duration_since(timer.started);
+            let nanos = nanos(waited);
+            self.longest_wait_nanos.fetch_max(nanos, Ordering::Relaxed);
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = timer;
+    }
+
+    /// Records how long a wait held the lock since `timer` started, taking only a clock
+    /// read and an atomic maximum so that measuring barely lengthens the section
+    pub(super) fn record_critical_section(&self, timer: WaitTimer, released: bool) {
+        #[cfg(feature = "metrics")]
+        {
+            let nanos = nanos(timer.started.elapsed());
+            let max = if released {
+                &self.max_release_section_nanos
+            } else {
+                &self.max_section_nanos
+            };
+            max.fetch_max(nanos, Ordering::Relaxed);
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = (timer, released);
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(super) fn recent_cycles(&self) -> Vec<CycleRecord> {
+        let history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+        history.iter().copied().collect()
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(super) fn snapshot(&self) -> BarrierMetrics {
+        BarrierMetrics {
+            arrivals: self.arrivals.load(Ordering::Relaxed),
+            releases: self.releases.load(Ordering::Relaxed),
+            cancellations: self.cancellations.load(Ordering::Relaxed),
+            resets: self.resets.load(Ordering::Relaxed),
+            generation: self.generation.load(Ordering::Relaxed),
+            longest_wait: Duration::from_nanos(self.longest_wait_nanos.load(Ordering::Relaxed)),
+            last_arrival_spread: Duration::from_nanos(
+                self.last_spread_nanos.load(Ordering::Relaxed),
+            ),
+            longest_arrival_spread: Duration::from_nanos(
+                self.longest_spread_nanos.load(Ordering::Relaxed),
+            ),
+            last_cycle: Duration::from_nanos(self.last_cycle_nanos.load(Ordering::Relaxed)),
+            max_critical_section: Duration::from_nanos(
+                self.max_section_nanos.load(Ordering::Relaxed),
+            ),
+            max_release_critical_section: Duration::from_nanos(
+                self.max_release_section_nanos.load(Ordering::Relaxed),
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn generation_u64(generation: usize) -> u64 {
+    u64::try_from(generation).unwrap_or(u64::MAX)
+}
+
+#[cfg(feature = "metrics")]
+fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
@@ -0,0 +1,4817 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::hint;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, PoisonError};
+use std::task::Waker;
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use crate::sync::{Arc, AtomicUsize, Mutex};
+use arrival_window::ArrivalWindow;
+use diagnostics::ThreadTracking;
+use event_log::EventLog;
+use fairness::ReleaseOrder;
+use metrics::{MetricsRecorder, WaitTimer};
+use reentrancy::WaitMarker;
+use timeouts::GenerationTimes;
+
+mod arrival_window;
+#[cfg(not(loom))]
+mod async_barrier;
+mod broadcast;
+mod builder;
+mod cancel_group;
+mod cancel_signal;
+#[cfg(not(loom))]
+mod cancel_timer;
+mod cancelled_future;
+mod clock;
+mod collect;
+mod compat;
+mod count_up;
+mod diagnostics;
+mod drain;
+mod error;
+mod event;
+mod event_log;
+mod exchange;
+mod fairness;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod guard;
+#[cfg(not(loom))]
+mod handle;
+#[cfg(not(loom))]
+mod interrupt;
+mod latch;
+mod linking;
+#[cfg(not(loom))]
+mod liveness;
+mod metrics;
+#[cfg(all(test, not(loom)))]
+mod model_tests;
+mod observer;
+mod phaser;
+mod phases;
+#[cfg(not(loom))]
+mod pool;
+#[cfg(all(feature = "prometheus", not(loom)))]
+mod prometheus_metrics;
+mod quorum;
+mod raw_sync;
+mod reduce;
+mod reentrancy;
+mod rendezvous;
+#[cfg(not(loom))]
+mod retained;
+#[cfg(not(loom))]
+mod select;
+mod semaphore;
+mod set;
+#[cfg(all(unix, feature = "signal", not(loom)))]
+mod signal;
+mod static_barrier;
+mod thread_safety;
+mod timeouts;
+mod tree;
+mod wait_future;
+mod wait_group;
+
+#[cfg(not(loom))]
+pub use async_barrier::AsyncBarrier;
+pub use broadcast::BarrierBroadcast;
+pub use builder::{BackoffConfig, CallbackPanicPolicy, CancellableBarrierBuilder, LeaderPolicy};
+pub use cancel_group::CancelGroup;
+pub use cancel_signal::CancelSignal;
+#[cfg(not(loom))]
+pub use cancel_timer::CancelTimer;
+pub use cancelled_future::BarrierCancelled;
+pub use clock::{Clock, MockClock, SystemClock};
+pub use collect::BarrierCollect;
+pub use compat::CompatBarrier;
+pub use count_up::CountUpLatch;
+pub use diagnostics::BarrierSnapshot;
+pub use drain::DrainToken;
+pub use error::{
+    BarrierBuildError, BarrierThread, BarrierWaitError, CancelReason, CancelledBarrier,
+};
+pub use event::CancellableEvent;
+pub use event_log::{BarrierEvent, BarrierEventKind};
+pub use exchange::{BarrierExchange, ExchangeGuard};
+pub use guard::{ArrivalGuard, CancelGuard};
+#[cfg(not(loom))]
+pub use handle::BarrierHandle;
+#[cfg(not(loom))]
+pub use interrupt::InterruptHandle;
+pub use latch::CancellableLatch;
+#[cfg(not(loom))]
+pub use liveness::LivenessToken;
+#[cfg(feature = "metrics")]
+pub use metrics::{BarrierMetrics, CycleRecord};
+pub use observer::BarrierObserver;
+pub use phaser::{Phase, Phaser};
+pub use phases::BarrierPhases;
+#[cfg(not(loom))]
+pub use pool::{BarrierPool, PooledBarrier};
+pub use raw_sync::{DefaultSync, RawSync};
+pub use reduce::{BarrierReduce, ReduceOutcome};
+pub use rendezvous::Rendezvous;
+#[cfg(not(loom))]
+pub use retained::RetainedWait;
+#[cfg(not(loom))]
+pub use select::wait_any;
+pub use semaphore::CancellableSemaphore;
+pub use set::BarrierSet;
+#[cfg(all(unix, feature = "signal", not(loom)))]
+pub use signal::install_signal_cancel;
+pub use static_barrier::StaticBarrier;
+pub use tree::TreeBarrier;
+pub use wait_future::BarrierWait;
+pub use wait_group::CancellableWaitGroup;
+
+/// Result of a successful wait on a [`CancellableBarrier`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult {
+    generation: usize,
+    is_leader: bool,
+    is_late: bool,
+    is_first: bool,
+    partial: Option<CompletedPartial>,
+}
+
+impl BarrierWaitResult {
+    fn new(generation: usize, is_leader: bool) -> Self {
+        BarrierWaitResult {
+            generation,
+            is_leader,
+            is_late: false,
+            is_first: false,
+            partial: None,
+        }
+    }
+
+    /// Returns the generation that was released
+    #[must_use]
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Returns true for exactly one thread per generation: the one whose arrival
+    /// released the barrier
+    #[must_use]
+    pub fn is_leader(&self) -> bool {
+        self.is_leader
+    }
+
+    /// Returns true if this thread arrived at a quorum barrier after its generation had
+    /// already been released, and so passed through without waiting
+    #[must_use]
+    pub fn is_late(&self) -> bool {
+        self.is_late
+    }
+
+    /// Returns true if this thread was the first to arrive at its generation, and so
+    /// started it instead of joining one already in progress.
+    ///
+    /// A thread that comes in after the others have already been released, and finds the
+    /// next generation empty, is first to that one: logged together with
+    /// [`Self::generation`], it helps spot a thread that is routinely slow to come back.
+    /// Arrivals counted through the lock-free arrival window never started their
+    /// generation, as someone was already parked in it.
+    #[must_use]
+    pub fn is_first_arrival(&self) -> bool {
+        self.is_first
+    }
+
+    /// Returns how many parties had arrived if the generation was released incomplete,
+    /// because a wait timed out on a barrier built with
+    /// [`CancellableBarrierBuilder::release_on_timeout`], and `None` if everyone arrived
+    #[must_use]
+    pub fn completed_partial(&self) -> Option<CompletedPartial> {
+        self.partial
+    }
+
+    /// Returns whether this thread led the released generation, along with it
+    #[must_use]
+    pub fn role(&self) -> BarrierRole {
+        if self.is_leader {
+            BarrierRole::Leader(self.generation)
+        } else {
+            BarrierRole::Follower(self.generation)
+        }
+    }
+}
+
+/// How incomplete a generation released by a timeout was, see
+/// [`BarrierWaitResult::completed_partial`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompletedPartial {
+    /// Parties that had arrived when the generation was released, or their total weight
+    pub arrived: usize,
+    /// Parties the generation needed
+    pub total: usize,
+}
+
+/// The part a thread played in a released generation, as returned by
+/// [`CancellableBarrier::wait_role`], carrying the released generation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarrierRole {
+    /// The one thread per generation whose arrival released the barrier, as with
+    /// [`BarrierWaitResult::is_leader`]
+    Leader(usize),
+    /// Any other thread of the generation
+    Follower(usize),
+}
+
+impl BarrierRole {
+    /// Returns the generation that was released
+    #[must_use]
+    pub fn generation(&self) -> usize {
+        match *self {
+            BarrierRole::Leader(generation) | BarrierRole::Follower(generation) => generation,
+        }
+    }
+}
+
+/// Returns true if generation `current` is `target` or comes after it.
+///
+/// Generations wrap around once they pass `usize::MAX`, so "after" means at most half the
+/// counter's range ahead. Everywhere else generations are only compared for equality,
+/// which the wrap can't fool.
+fn generation_reached(current: usize, target: usize) -> bool {
+    current.wrapping_sub(target) <= usize::MAX / 2
+}
+
+/// Whether a wait can block until other threads arrive. On wasm32 without atomics there
+/// are no other threads, so a wait that can't release straight away fails instead.
+const BLOCKING_SUPPORTED: bool =
+    !cfg!(all(target_arch = "wasm32", not(target_feature = "atomics")));
+
+/// How often a parked thread checks the [`CancelSignal`] its wait is tied to, or the
+/// condition passed to [`CancellableBarrier::wait_while`]
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Closure run by the releasing thread of each generation
+type BarrierAction = dyn Fn(usize) + Send + Sync;
+
+/// Callback registered with [`CancellableBarrier::on_cancel`]
+type CancelListener = dyn Fn(CancelReason) + Send + Sync;
+
+/// Identifies a listener added with [`CancellableBarrier::add_release_listener`], for
+/// removing it again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReleaseListenerId(u64);
+
+/// Callback added with [`CancellableBarrier::add_arrival_listener`]
+type ArrivalListener = dyn Fn(usize, usize) + Send + Sync;
+
+#[cfg(feature = "metrics")]
+type MetricsSink = dyn Fn(&BarrierMetrics) + Send + Sync;
+
+/// Identifies a listener added with [`CancellableBarrier::add_arrival_listener`], for
+/// removing it again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ArrivalListenerId(u64);
+
+/// Called on every arrival, in registration order
+#[derive(Default)]
+struct ArrivalListeners {
+    listeners: Vec<(ArrivalListenerId, std::sync::Arc<ArrivalListener>)>,
+    next_id: u64,
+}
+
+impl fmt::Debug for ArrivalListeners {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArrivalListeners")
+            .field("listeners", &self.listeners.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Run after the action on every release, in registration order
+#[derive(Default)]
+struct ReleaseListeners {
+    listeners: Vec<(ReleaseListenerId, std::sync::Arc<BarrierAction>)>,
+    next_id: u64,
+    /// Added with [`CancellableBarrier::on_next_release`], run after the listeners on the
+    /// next release only
+    next_release: Vec<Box<dyn FnOnce(usize) + Send>>,
+}
+
+/// A synchronization primitive that allows multiple threads to wait at a point until
+/// enough threads have arrived or the barrier is cancelled
+pub struct CancellableBarrier<S: RawSync = DefaultSync> {
+    mutex: S::Mutex<BarrierState>,
+    condvar: S::Condvar,
+    /// Bumped under the lock on every release, cancel and reset, so spinning waiters can
+    /// notice a change without taking the lock
+    epoch: AtomicUsize,
+    /// Mirrors `total == 1`, for [`Self::wait`] to try its one-party path on. Only a
+    /// hint, checked again under the lock.
+    single_party: AtomicBool,
+    /// Set once an [`InterruptHandle`] has been handed out, from when [`Self::wait`] looks
+    /// for interrupts of its thread
+    interrupt_handles: AtomicBool,
+    name: Option<std::sync::Arc<str>>,
+    default_timeout: Option<Duration>,
+    overall_timeout: Option<Duration>,
+    /// Read for every timeout, deadline and stuck check
+    clock: std::sync::Arc<dyn Clock>,
+    backoff: BackoffConfig,
+    fair: bool,
+    strict: bool,
+    release_on_timeout: bool,
+    break_on_give_up: bool,
+    backpressure: bool,
+    on_callback_panic: CallbackPanicPolicy,
+    leader_policy: LeaderPolicy,
+    /// Generations the barrier releases before it makes the next arrival cancel it
+    max_generations: Option<usize>,
+    remove_lost_parties: bool,
+    /// Shutdown flag that cancels the barrier with [`CancelReason::Shutdown`] once set
+    linked: Option<std::sync::Arc<AtomicBool>>,
+    /// Lets threads that aren't the last to arrive be counted without the lock, on
+    /// barriers without the options that need every arrival to be seen under it
+    window: Option<ArrivalWindow>,
+    metrics: MetricsRecorder,
+    event_log: EventLog,
+    action: Option<std::sync::Arc<BarrierAction>>,
+    observer: Option<std::sync::Arc<dyn BarrierObserver>>,
+    cancel_listeners: Mutex<Vec<std::sync::Arc<CancelListener>>>,
+    /// Only locked on its own or with the state locked, in that order
+    release_listeners: Mutex<ReleaseListeners>,
+    /// Set with [`Self::set_metrics_sink`], pushed the metrics on every release
+    #[cfg(feature = "metrics")]
+    metrics_sink: Mutex<Option<std::sync::Arc<MetricsSink>>>,
+    /// Barriers added with [`Self::link_child`], cancelled along with this one
+    children: Mutex<Vec<Arc<CancellableBarrier<S>>>>,
+}
+
+#[derive(Debug)]
+struct BarrierState {
+    count: usize,
+    total: usize,
+    generation: usize,
+    /// Generations released since the barrier was built, unlike `generation` not bumped
+    /// by a reset
+    released: usize,
+    /// Generations thrown away by a reset since the barrier was built, which with
+    /// `released` accounts for every step of `generation`
+    resets: usize,
+    /// Parties per generation beyond the quorum, zero unless built with one
+    late_parties: usize,
+    /// Late parties still expected for the last released generation
+    late_pending: usize,
+    cancelled: Option<CancelReason>,
+    /// What was recorded about the cancellation alongside its reason
+    cancel_details: CancelDetails,
+    /// Times the barrier has been cancelled, for [`BarrierCancelled`] to notice a
+    /// cancellation that was reset before it could see it
+    cancellations: u64,
+    /// Whether the barrier was aborted, which keeps it cancelled for good
+    aborted: bool,
+    /// The last generation released by a timeout, with how incomplete it was, for the
+    /// threads of that generation that haven't returned yet
+    partial_release: Option<(usize, CompletedPartial)>,
+    /// When the current generation started, and when it runs out
+    times: GenerationTimes,
+    /// The last generation that ran out of its overall timeout, with the error its
+    /// threads that haven't returned yet get
+    expired: Option<(usize, BarrierWaitError)>,
+    /// The thread that released the last generation, until it is done processing it, on
+    /// a barrier with backpressure
+    processing: Option<ThreadId>,
+    wakers: HashMap<usize, Waker>,
+    next_waker_key: usize,
+    subscribers: Vec<mpsc::Sender<usize>>,
+    /// The order released threads leave in
+    order: ReleaseOrder,
+    /// Threads inside a blocking or async wait, from parking until they return, which
+    /// includes those of a released generation that haven't run yet
+    parked: usize,
+    /// Threads in [`CancellableBarrier::wait_idle`] or a shutdown's drain, to be woken
+    /// once nobody is parked
+    idle_waiters: usize,
+    /// A ticket for each parked thread or task, handed out in the order they parked
+    park_tickets: BTreeSet<u64>,
+    next_park_ticket: u64,
+    /// Park tickets below this belong to threads whose generation has been released
+    released_below: u64,
+    /// When the last few generations were released, oldest first: the last one, and any
+    /// whose threads are still parked, for [`CancellableBarrier::wait_timed`]
+    release_times: VecDeque<ReleaseTime>,
+    /// Arrivals counted since the barrier was built, which only ever goes up, so that an
+    /// idle wait can tell there were some even once a rollback or release has brought the
+    /// count back down
+    arrivals: u64,
+    /// The threads that have arrived, if tracking them
+    threads: Option<ThreadTracking>,
+    /// The indices of the live [`BarrierHandle`]s, each counted among the parties
+    handles: BTreeSet<usize>,
+    next_handle: usize,
+    /// The drain requested by the live [`DrainToken`], if any
+    drain: Option<drain::Drain>,
+    next_drain: u64,
+    /// Keys of the [`InterruptHandle`]s interrupted and not yet acted upon, with the
+    /// threads that made them
+    interrupts: HashMap<u64, ThreadId>,
+    #[cfg(not(loom))]
+    next_interrupt_key: u64,
+    /// Kept with the state, so that the arrival window stays shut while there are any
+    arrival_listeners: ArrivalListeners,
+}
+
+/// When a generation was released
+#[derive(Debug, Clone, Copy)]
+struct ReleaseTime {
+    generation: usize,
+    /// Only read if someone is to ask, to keep the clock off the release path
+    at: Option<Instant>,
+    /// The generation's threads all parked with tickets below this
+    parked_below: u64,
+    /// The index of the handle whose turn it was to lead, on a round-robin barrier with
+    /// handles
+    leader: Option<usize>,
+}
+
+/// What a barrier records about its cancellation, for the errors its waiters get back
+#[derive(Debug, Default)]
+struct CancelDetails {
+    /// Arrivals counted in the generation the barrier was cancelled in
+    arrived: usize,
+    /// Threads that had arrived when the barrier was cancelled, if tracking them
+    #[cfg(feature = "diagnostics")]
+    threads: Vec<BarrierThread>,
+    /// What the barrier was last cancelled with through
+    /// [`CancellableBarrier::cancel_with_payload`]
+    payload: Option<std::sync::Arc<dyn std::any::Any + Send + Sync>>,
+    /// Where the barrier was last cancelled from
+    #[cfg(feature = "backtrace")]
+    backtrace: Option<std::sync::Arc<std::backtrace::Backtrace>>,
+}
+
+impl CancelDetails {
+    /// Builds the error for a cancellation with `reason`
+    fn error(&self, reason: &CancelReason) -> CancelledBarrier {
+        let cancelled = CancelledBarrier::new(reason.clone()).with_arrived(self.arrived);
+        #[cfg(feature = "diagnostics")]
+        let cancelled = cancelled.with_arrived_threads(self.threads.clone());
+        #[cfg(feature = "backtrace")]
+        let cancelled = cancelled.with_backtrace(self.backtrace.clone());
+        cancelled.with_payload(self.payload.clone())
+    }
+}
+
+impl BarrierState {
+    /// Clears the cancellation along with everything recorded about it
+    fn clear_cancellation(&mut self) {
+        self.cancelled = None;
+        self.cancel_details = CancelDetails::default();
+    }
+
+    fn check_cancelled(&self) -> Result<(), BarrierWaitError> {
+        match &self.cancelled {
+            Some(_) if self.aborted => Err(BarrierWaitError::Aborted),
+            Some(reason) => Err(self.cancel_details.error(reason).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the index of the handle that leads `generation`, if that isn't up to the
+    /// order of arrival, and the generation is the last released one or one whose threads
+    /// haven't all left
+    fn release_leader(&self, generation: usize) -> Option<usize> {
+        self.release_times
+            .iter()
+            .rev()
+            .find(|released| released.generation == generation)
+            .and_then(|released| released.leader)
+    }
+
+    /// Returns true if the current thread's arrival has to wait for the last generation to
+    /// be processed, on a barrier with backpressure. The releasing thread itself never
+    /// does.
+    fn held_back(&self) -> bool {
+        self.processing
+            .is_some_and(|leader| leader != thread::current().id())
+    }
+
+    /// Rejects an arrival that would take more threads into the barrier than it has
+    /// parties, if the barrier is strict
+    fn check_capacity(&self, strict: bool) -> Result<(), BarrierWaitError> {
+        if strict && self.parked >= self.total {
+            return Err(BarrierWaitError::TooManyParties);
+        }
+        Ok(())
+    }
+
+    /// Uses up an interrupt pending on one of the [`InterruptHandle`]s made on `thread`,
+    /// if there is one
+    fn take_thread_interrupt(&mut self, thread: ThreadId) -> bool {
+        let key = self
+            .interrupts
+            .iter()
+            .find_map(|(&key, &owner)| (owner == thread).then_some(key));
+        key.is_some_and(|key| self.interrupts.remove(&key).is_some())
+    }
+
+    /// Counts the current thread as parked, returning its park ticket
+    fn park(&mut self) -> u64 {
+        let ticket = self.next_park_ticket;
+        self.next_park_ticket += 1;
+        self.parked += 1;
+        self.park_tickets.insert(ticket);
+        ticket
+    }
+
+    fn unpark(&mut self, ticket: u64) {
+        self.parked -= 1;
+        self.park_tickets.remove(&ticket);
+        self.order.let_go.remove(&ticket);
+    }
+
+    /// Returns true if the generation in progress can never complete: nothing but the
+    /// barrier's handles holds on to it, given the `references` to it, and every handle
+    /// is already parked on that generation, so nobody is left to arrive
+    fn unreachable(&self, references: usize) -> bool {
+        !self.handles.is_empty()
+            && references <= self.handles.len()
+            && self.park_tickets.range(self.released_below..).count() >= self.handles.len()
+            && !self.completed_by(self.count)
+    }
+
+    fn wake_all(&mut self) {
+        for (_, waker) in self.wakers.drain() {
+            waker.wake();
+        }
+    }
+}
+
+impl CancellableBarrier {
+    /// Creates a new barrier that can be used by `n` threads
+    #[must_use]
+    pub fn new(n: usize) -> Arc<Self> {
+        assert!(n > 0, "Barrier size must be greater than 0");
+        Arc::new(Self::from_builder(
+            CancellableBarrierBuilder::new().parties(n),
+        ))
+    }
+
+    /// Creates a manual barrier, which no number of arrivals releases, only
+    /// [`Self::trip`].
+    ///
+    /// It is a reusable gate: waiting threads block until another thread trips it, which
+    /// lets through everyone waiting at that moment, and threads that arrive after that
+    /// wait for the next trip. Its size is 0, so any number of threads can wait, and
+    /// none of them is leader. Cancelling and resetting work as on any barrier: a
+    /// cancelled gate fails every wait until it is reset, and a reset lets the waiting
+    /// threads go as a trip would but leaves the gate manual. Giving it a size with
+    /// [`Self::resize`] or [`Self::add_party`] makes it an ordinary barrier of that size.
+    #[must_use]
+    pub fn manual() -> Arc<Self> {
+        Arc::new(Self::from_builder(
+            CancellableBarrierBuilder::new().parties(0),
+        ))
+    }
+
+    /// Creates a new barrier that can be used by `n` threads, failing instead of panicking
+    /// if `n` is 0, for sizes that come from configuration
+    pub fn try_new(n: usize) -> Result<Arc<Self>, BarrierBuildError> {
+        Self::builder().parties(n).build()
+    }
+
+    /// Creates a new barrier for `n` threads, named for diagnostics.
+    ///
+    /// The name shows up in the barrier's `Debug` output and in tracing events.
+    #[must_use]
+    pub fn new_named(n: usize, name: impl Into<std::sync::Arc<str>>) -> Arc<Self> {
+        assert!(n > 0, "Barrier size must be greater than 0");
+        Arc::new(Self::from_builder(
+            CancellableBarrierBuilder::new().parties(n).name(name),
+        ))
+    }
+
+    /// Creates a new barrier for `n` threads that cancels itself with
+    /// [`CancelReason::Shutdown`] once `flag` is set, without anyone calling
+    /// [`Self::cancel`].
+    ///
+    /// This ties any number of barriers to one global shutdown flag. Blocking waits check
+    /// the flag when they arrive and poll it while parked, so a set flag is noticed within
+    /// a few milliseconds. Async waits and [`Self::try_wait`] check it whenever they are
+    /// polled or called, and parked tasks are woken along with everyone else once any
+    /// wait notices it. A reset uncancels the barrier as usual, but while the flag stays
+    /// set the next wait cancels it again.
+    #[must_use]
+    pub fn linked_to(n: usize, flag: std::sync::Arc<AtomicBool>) -> Arc<Self> {
+        assert!(n > 0, "Barrier size must be greater than 0");
+        Arc::new(Self::from_builder(
+            CancellableBarrierBuilder::new().parties(n).linked_to(flag),
+        ))
+    }
+
+    /// Creates a barrier for `n` threads that releases each generation as soon as `k` of
+    /// them have arrived.
+    ///
+    /// The remaining `n - k` threads of that generation don't block when they arrive:
+    /// their wait returns immediately with [`BarrierWaitResult::is_late`] set and the
+    /// generation they were late for. Only once all of them have passed through does the
+    /// next arrival count towards a new generation.
+    #[must_use]
+    pub fn quorum(n: usize, k: usize) -> Arc<Self> {
+        assert!(n > 0, "Barrier size must be greater than 0");
+        assert!(k > 0 && k <= n, "Barrier quorum must be between 1 and {n}");
+        Arc::new(Self::from_builder(
+            CancellableBarrierBuilder::new().parties(n).quorum(k),
+        ))
+    }
+
+    /// Creates a new barrier for `n` threads that runs `action` each time it releases,
+    /// with the generation being released.
+    ///
+    /// The action runs exactly once per generation, on the thread whose arrival released
+    /// it, while the barrier is still locked and before any waiter is woken, so the
+    /// waiters observe everything it did. It must not call back into the barrier.
+    ///
+    /// If the action panics, the generation is still released and the panic then
+    /// continues on the releasing thread, unless the barrier is built with another
+    /// [`CancellableBarrierBuilder::on_callback_panic`] policy.
+    #[must_use]
+    pub fn with_action(n: usize, action: impl Fn(usize) + Send + Sync + 'static) -> Arc<Self> {
+        assert!(n > 0, "Barrier size must be greater than 0");
+        Arc::new(Self::from_builder(
+            CancellableBarrierBuilder::new().parties(n).action(action),
+        ))
+    }
+
+    /// Creates a [`BarrierReduce`] between `n` threads that folds their values with
+    /// `combine`, starting from `identity`, configured once so every call site uses the
+    /// same reduction. See [`BarrierReduce::new`], which this is.
+    #[must_use]
+    pub fn reducing<T: Clone>(
+        n: usize,
+        combine: impl Fn(T, T) -> T + Send + Sync + 'static,
+        identity: T,
+    ) -> Arc<BarrierReduce<T>> {
+        BarrierReduce::new(n, identity, combine)
+    }
+
+    /// Returns a builder for configuring a barrier beyond its size
+    #[must_use]
+    pub fn builder() -> CancellableBarrierBuilder {
+        CancellableBarrierBuilder::new()
+    }
+
+    /// Spawns `n` scoped threads sharing a barrier for `n` threads, runs `f` on each with
+    /// its index and the barrier, and returns their results in index order once all of
+    /// them have finished.
+    ///
+    /// If a thread panics, the barrier is cancelled with [`CancelReason::Panicked`] so the
+    /// others don't wait for it forever, and the first panic is continued on the calling
+    /// thread after every thread has been joined.
+    #[cfg(not(loom))]
+    pub fn scope<F, R>(n: usize, f: F) -> Vec<R>
+    where
+        F: Fn(usize, &CancellableBarrier) -> R + Sync,
+        R: Send,
+    {
+        let barrier = Self::new(n);
+        let results: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..n)
+                .map(|i| {
+                    let (barrier, f) = (&barrier, &f);
+                    scope.spawn(move || {
+                        panic::catch_unwind(AssertUnwindSafe(|| f(i, barrier))).inspect_err(|_| {
+                            barrier.cancel_with(CancelReason::Panicked);
+                        })
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or_else(Err))
+                .collect()
+        });
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|payload| panic::resume_unwind(payload)))
+            .collect()
+    }
+}
+
+impl<S: RawSync> CancellableBarrier<S> {
+    fn from_builder(builder: CancellableBarrierBuilder) -> Self {
+        let total = builder.quorum.unwrap_or(builder.parties);
+        let clock = builder
+            .clock
+            .unwrap_or_else(|| std::sync::Arc::new(SystemClock));
+        CancellableBarrier {
+            mutex: S::new_mutex(BarrierState {
+                count: 0,
+                total,
+                generation: 0,
+                released: 0,
+                resets: 0,
+                late_parties: builder.parties - total,
+                late_pending: 0,
+                cancelled: None,
+                cancel_details: CancelDetails::default(),
+                cancellations: 0,
+                wakers: HashMap::new(),
+                next_waker_key: 0,
+                subscribers: Vec::new(),
+                order: ReleaseOrder::default(),
+                parked: 0,
+                idle_waiters: 0,
+                park_tickets: BTreeSet::new(),
+                next_park_ticket: 0,
+                released_below: 0,
+                release_times: VecDeque::new(),
+                arrivals: 0,
+                threads: builder.track_threads.then(ThreadTracking::default),
+                handles: BTreeSet::new(),
+                next_handle: 0,
+                drain: None,
+                next_drain: 0,
+                aborted: false,
+                partial_release: None,
+                times: GenerationTimes::default(),
+                expired: None,
+                processing: None,
+                interrupts: HashMap::new(),
+                #[cfg(not(loom))]
+                next_interrupt_key: 0,
+                arrival_listeners: ArrivalListeners::default(),
+            }),
+            condvar: S::new_condvar(),
+            epoch: AtomicUsize::new(0),
+            single_party: AtomicBool::new(total == 1),
+            interrupt_handles: AtomicBool::new(false),
+            name: builder.name,
+            default_timeout: builder.default_timeout,
+            overall_timeout: builder.overall_timeout,
+            backoff: builder.backoff,
+            fair: builder.fair,
+            strict: builder.strict,
+            release_on_timeout: builder.release_on_timeout,
+            break_on_give_up: builder.break_on_give_up,
+            backpressure: builder.backpressure,
+            on_callback_panic: builder.on_callback_panic,
+            leader_policy: builder.leader_policy,
+            max_generations: builder.max_generations,
+            remove_lost_parties: builder.remove_lost_parties,
+            linked: builder.linked,
+            window: (BLOCKING_SUPPORTED
+                && builder.quorum.is_none()
+                && !builder.fair
+                && !builder.track_threads
+                && !builder.strict
+                && !builder.release_on_timeout
+                && builder.overall_timeout.is_none()
+                && builder.observer.is_none())
+            .then(ArrivalWindow::new),
+            #[cfg(feature = "metrics")]
+            metrics: MetricsRecorder::new(builder.cycle_history, clock.clone()),
+            #[cfg(not(feature = "metrics"))]
+            metrics: MetricsRecorder::default(),
+            #[cfg(feature = "event-log")]
+            event_log: EventLog::new(builder.event_log_capacity),
+            #[cfg(not(feature = "event-log"))]
+            event_log: EventLog::default(),
+            action: builder.action,
+            observer: builder.observer,
+            cancel_listeners: Mutex::new(Vec::new()),
+            release_listeners: Mutex::new(ReleaseListeners::default()),
+            #[cfg(feature = "metrics")]
+            metrics_sink: Mutex::new(None),
+            children: Mutex::new(Vec::new()),
+            clock,
+        }
+    }
+
+    /// Waits until all threads have reached the barrier or the barrier is cancelled.
+    ///
+    /// If the barrier was built with a default timeout, this behaves like
+    /// [`Self::wait_timeout`] with that timeout. An [`InterruptHandle`] made on this
+    /// thread interrupts the wait as it would [`Self::wait_interruptible`] with it, and
+    /// [`Self::wait_uninterruptible`] is there for waits that must not be.
+    ///
+    /// On wasm32 without atomics there are no other threads to arrive, so this and the
+    /// other blocking waits return [`BarrierWaitError::WouldBlock`] instead of blocking
+    /// forever, unless the arrival releases the barrier, as it always does with one party.
+    pub fn wait(&self) -> Result<BarrierWaitResult, BarrierWaitError> {
+        // only once there are handles can this thread have an interrupt to look for, which
+        // the shortcuts don't
+        let interrupt_thread = self
+            .interrupt_handles
+            .load(Ordering::Relaxed)
+            .then(|| thread::current().id());
+        if interrupt_thread.is_none()
+            && self.default_timeout.is_none()
+            && self.single_party.load(Ordering::Relaxed)
+        {
+            if let Some(result) = self.wait_alone() {
+                return result;
+            }
+        }
+        self.wait_inner(WaitOptions {
+            deadline: self
+                .default_timeout
+                .and_then(|timeout| self.deadline_after(timeout)),
+            interrupt_thread,
+            ..WaitOptions::default()
+        })
+    }
+
+    /// Arrives at a barrier with a single party, which releases the generation and
+    /// leads it without ever waiting, as with a loop that only sometimes runs on more
+    /// than one thread.
+    ///
+    /// The lock is still taken, once, but none of the bookkeeping for threads that park is
+    /// done. Releasing without it would take more than an atomic generation: a `cancel`,
+    /// `reset` or `resize` racing the arrival has to see it either before or after the
+    /// generation it releases, never in between, and the action and the listeners have
+    /// to run once per generation, in order, which is what the lock is for. With nobody
+    /// to contend for it, taking it costs the one atomic swap each way that a lock-free
+    /// release would pay anyway. Returns `None`, having counted nothing, if the barrier
+    /// turns out to need the general path after all: it was resized, is paused or held
+    /// back, or is in the middle of something else.
+    fn wait_alone(&self) -> Option<Result<BarrierWaitResult, BarrierWaitError>> {
+        let _marker = WaitMarker::enter(self, self.name.as_deref());
+        self.check_linked();
+        let mut state = self.lock();
+        let section = WaitTimer::start();
+        if let Err(err) = state.check_cancelled() {
+            return Some(Err(err));
+        }
+        if state.draining() {
+            return Some(Err(self.leave_for_drain(&mut state)));
+        }
+        self.end_processing_if_leader(&mut state);
+        self.release_alone(state, section).ok().map(Ok)
+    }
+
+    /// Counts the arrival of [`Self::wait_alone`] and releases its generation, once the
+    /// barrier is known not to be cancelled or draining. Hands the lock and the timer back,
+    /// having counted nothing, if the barrier needs the general path.
+    fn release_alone<'a>(
+        &'a self,
+        mut state: S::Guard<'a, BarrierState>,
+        section: WaitTimer,
+    ) -> Result<BarrierWaitResult, (S::Guard<'a, BarrierState>, WaitTimer)> {
+        let plain = state.total == 1
+            && state.count == 0
+            && !state.order.paused
+            && !state.held_back()
+            && state.late_pending == 0
+            && state.times.expires_at.is_none()
+            && state.handles.is_empty()
+            && !self.generation_cap_reached(&state);
+        // with nobody counted the thread can't have arrived already, but if it somehow
+        // has, the general path says so
+        if !plain || state.track_arrival().is_err() {
+            return Err((state, section));
+        }
+
+        let generation = state.generation;
+        self.note_first_arrival(&mut state);
+        self.metrics.record_arrival();
+        state.count = 1;
+        self.note_arrival(&mut state);
+        self.observe_arrival(&state);
+        if let Err(panicked) = self.release(&mut state) {
+            panicked.resume(self, state);
+        }
+        self.metrics.record_critical_section(section, true);
+        Ok(BarrierWaitResult {
+            is_first: true,
+            ..BarrierWaitResult::new(generation, true)
+        })
+    }
+
+    /// Waits as [`Self::wait`], returning the thread's role in the released generation
+    /// for matching on
+    pub fn wait_role(&self) -> Result<BarrierRole, BarrierWaitError> {
+        self.wait().map(|result| result.role())
+    }
+
+    /// Waits as [`Self::wait`], and if this thread's arrival completes the generation,
+    /// runs `leader_work` before anyone else is let go.
+    ///
+    /// This is for work the leader has to finish before the others may go on, such as
+    /// setting up the next phase: the other threads of the generation only return once
+    /// `leader_work` has returned, and see everything it did. Only the leader's
+    /// `leader_work` runs; the others' are dropped. The work runs while the barrier is
+    /// locked, before the barrier's action, so like the action it must not call back into
+    /// the barrier. If it panics, the generation is still released and the panic then
+    /// continues on the leader.
+    pub fn wait_then(
+        &self,
+        leader_work: impl FnOnce(),
+    ) -> Result<BarrierWaitResult, BarrierWaitError> {
+        let mut leader_work = Some(leader_work);
+        let mut work = || {
+            if let Some(leader_work) = leader_work.take() {
+                leader_work();
+            }
+        };
+        self.wait_inner(WaitOptions {
+            deadline: self
+                .default_timeout
+                .and_then(|timeout| self.deadline_after(timeout)),
+            leader_work: Some(&mut work),
+            ..WaitOptions::default()
+        })
+    }
+
+    /// Like [`Self::wait`], but busy-spins up to `max_spins` times before parking the
+    /// thread.
+    ///
+    /// When the other threads are expected within microseconds, spinning avoids the
+    /// latency of being parked and woken by the OS. Cancellation is noticed on every spin.
+    /// With `max_spins` of 0 this is the same as a plain wait without the default timeout.
+    /// The spins come before any [`BackoffConfig`] the barrier was built with.
+    pub fn wait_spin(&self, max_spins: u32) -> Result<BarrierWaitResult, BarrierWaitError> {
+        self.wait_inner(WaitOptions {
+            spins: max_spins,
+            ..WaitOptions::default()
+        })
+    }
+
+    /// Like [`Self::wait`], but also gives up with [`BarrierWaitError::Interrupted`] once
+    /// `signal` is tripped, even though the barrier itself isn't cancelled.
+    ///
+    /// The signal is polled while the thread is parked, so it is noticed within a few
+    /// milliseconds. As with a timeout, the arrival is rolled back and the barrier carries
+    /// on without this thread.
+    pub fn wait_until(
+        &self,
+        signal: &impl CancelSignal,
+    ) -> Result<BarrierWaitResult, BarrierWaitError> {
+        self.wait_inner(WaitOptions {
+            deadline: self
+                .default_timeout
+                .and_then(|timeout| self.deadline_after(timeout)),
+            signal: Some(signal),
+            ..WaitOptions::default()
+        })
+    }
+
+    /// Waits until all threads have reached the barrier or the barrier is cancelled, and
+    /// for nothing else.
+    ///
+    /// Meant for critical sections where a thread must not be pulled away, for example
+    /// because it holds resources across the barrier. Only cancelling or aborting the
+    /// whole barrier ends the wait early: no timeout applies, not even the barrier's
+    /// default one, and interrupts and cancel signals can't reach it. An interrupt sent
+    /// through an [`InterruptHandle`] in the meantime is kept for the next wait it can
+    /// interrupt.
+    ///
+    /// This is the one to choose over [`Self::wait`], which a default timeout or an
+    /// interrupt through a handle made on the waiting thread ends.
+    pub fn wait_uninterruptible(&self) -> Result<BarrierWaitResult, BarrierWaitError> {
+        self.wait_inner(WaitOptions::default())
+    }
+
+    /// Like [`Self::wait`], but gives up with [`BarrierWaitError::Interrupted`] as soon as
+    /// `handle` is interrupted, while the other threads stay parked.
+    ///
+    /// As with a timeout, the arrival is rolled back and the barrier carries on without
+    /// this thread. An interrupt that comes while no wait is using the handle is kept for
+    /// the next one.
+    #[cfg(not(loom))]
+    pub fn wait_interruptible(
+        &self,
+        handle: &InterruptHandle<S>,
+    ) -> Result<BarrierWaitResult, BarrierWaitError> {
+        assert!(
+            handle.belongs_to(self),
+            "interrupt handle belongs to another barrier"
+        );
+        self.wait_inner(WaitOptions {
+            deadline: self
+                .default_timeout
+                .and_then(|timeout| self.deadline_after(timeout)),
+            interrupt: Some(handle.key()),
+            ..WaitOptions::default()
+        })
+    }
+
+    /// Returns a handle for interrupting the waits of a single thread, see
+    /// [`Self::wait_interruptible`], which also interrupts this thread's [`Self::wait`]s
+    #[cfg(not(loom))]
+    pub fn interrupt_handle(self: &Arc<Self>) -> InterruptHandle<S> {
+        let mut state = self.lock();
+        let key = state.next_interrupt_key;
+        state.next_interrupt_key += 1;
+        self.interrupt_handles.store(true, Ordering::Relaxed);
+        InterruptHandle::new(Arc::downgrade(self), key, thread::current().id())
+    }
+
+    /// Marks the waits using the handle with `key`, made on `thread`, as interrupted,
+    /// waking them up
+    #[cfg(not(loom))]
+    fn interrupt(&self, key: u64, thread: ThreadId) {
+        let mut state = self.lock();
+        state.interrupts.insert(key, thread);
+        self.epoch.fetch_add(1, Ordering::Relaxed);
+        S::notify_all(&self.condvar);
+    }
+
+    /// Like [`Self::wait`], but the barrier only releases once all threads have arrived
+    /// and `cond` returns true.
+    ///
+    /// Once the count is complete, `cond` is checked by the waiting threads that passed
+    /// one, each with its own, the first time it arrives and then every few milliseconds
+    /// until one of them sees it hold and releases the barrier, becoming the leader.
+    /// Every party should pass the same condition: a plain [`Self::wait`] that completes
+    /// the count releases straight away. The condition runs with the barrier locked, so
+    /// it must be quick and must not call back into the barrier. Cancellation and the
+    /// default timeout still end the wait while the condition doesn't hold.
+    pub fn wait_while(
+        &self,
+        cond: impl Fn() -> bool,
+    ) -> Result<BarrierWaitResult, BarrierWaitError> {
+        self.wait_inner(WaitOptions {
+            deadline: self
+                .default_timeout
+                .and_then(|timeout| self.deadline_after(timeout)),
+            condition: Some(&cond),
+            ..WaitOptions::default()
+        })
+    }
+
+    fn wait_inner(
+        &self,
+        mut options: WaitOptions<'_>,
+    ) -> Result<BarrierWaitResult, BarrierWaitError> {
+        let _marker = WaitMarker::enter(self, self.name.as_deref());
+        // declared before the lock guard so that, on unwind, it runs once the lock is released
+        let mut rollback = ArrivalRollback {
+            barrier: self,
+            weight: options.weight,
+            generation: None,
+            ticket: None,
+            thread: None,
+            parked: None,
+        };
+        self.check_linked();
+        let arrived = match self.arrive_through_window(&options, &mut rollback) {
+            Some(arrived) => arrived,
+            None => self.arrive_locked(&mut options, &mut rollback)?,
+        };
+        let (state, arrival) = match arrived {
+            Arrived::Counted(state, arrival) => (state, arrival),
+            Arrived::Done(result) => return Ok(result),
+        };
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "barrier_wait",
+            barrier = self.name.as_deref(),
+            generation = arrival.generation,
+            total = state.total,
+            parked_us = tracing::field::Empty,
+        )
+        .entered();
+        #[cfg(feature = "tracing")]
+        tracing::trace!(arrived = state.count, "arrived at barrier");
+
+        // an arrival through the window is never the last one
+        if arrival.spun.is_some() || !options.ready(&state) {
+            // Not all threads have arrived yet, or the condition doesn't hold
+            self.wait_for_release(state, arrival, options, &mut rollback)
+        } else {
+            // Last thread to arrive
+            rollback.generation = None;
+            Ok(self.release_on_arrival(state, arrival, options))
+        }
+    }
+
+    /// Counts a wait's arrival without the lock, if it can go through the arrival window,
+    /// and spins for its release before taking the lock to block.
+    ///
+    /// Returns `None`, having done nothing, if the wait has to arrive under the lock.
+    fn arrive_through_window(
+        &self,
+        options: &WaitOptions<'_>,
+        rollback: &mut ArrivalRollback<'_, S>,
+    ) -> Option<Arrived<'_, S>> {
+        let window = self.window.as_ref()?;
+        // arrivals through the window can leave before their release is looked up, and
+        // can't tell whose turn it is to lead
+        let ranked = options.party.is_some() && self.leader_policy == LeaderPolicy::RoundRobin;
+        if options.weight != 1
+            || options.signal.is_some()
+            || options.interrupt.is_some()
+            || options.interrupt_thread.is_some()
+            || options.condition.is_some()
+            || options.released_at.is_some()
+            || ranked
+        {
+            return None;
+        }
+        // read before arriving, so a release right after the arrival isn't missed
+        let epoch = self.epoch.load(Ordering::Relaxed);
+        let generation = window.arrive()?;
+
+        // counted without the lock, and not the last to arrive, so only take the lock if
+        // the generation isn't released by the time we're done spinning
+        rollback.generation = Some(generation);
+        self.metrics.record_arrival();
+        let timer = self.metrics.start_wait();
+        if options.spins > 0 || self.backoff != BackoffConfig::default() {
+            self.back_off(epoch, options.spins);
+        }
+        if window.released(generation) {
+            rollback.generation = None;
+            self.metrics.record_wait(timer);
+            return Some(Arrived::Done(BarrierWaitResult::new(generation, false)));
+        }
+        Some(Arrived::Counted(
+            self.lock(),
+            Arrival {
+                generation,
+                is_first: false,
+                thread: None,
+                spun: Some(timer),
+                section: None,
+            },
+        ))
+    }
+
+    /// Counts a wait's arrival under the lock, unless the barrier turns it away or has
+    /// a late arrival's result for it
+    fn arrive_locked(
+        &self,
+        options: &mut WaitOptions<'_>,
+        rollback: &mut ArrivalRollback<'_, S>,
+    ) -> Result<Arrived<'_, S>, BarrierWaitError> {
+        let mut state = self.wait_processed(self.lock(), options.deadline)?;
+        let section = WaitTimer::start();
+        state.check_cancelled()?;
+        if state.times.expires_at.is_some() {
+            // nobody was left to see the generation run out, so we start a new one
+            self.expire(&mut state, self.clock.now());
+        }
+        if options.interrupted(&mut state) {
+            return Err(BarrierWaitError::Interrupted);
+        }
+        if state.draining() {
+            return Err(self.leave_for_drain(&mut state));
+        }
+        if let Some(late) = state.take_late_arrival() {
+            self.metrics.record_arrival();
+            options.report_release(&state, late.generation);
+            return Ok(Arrived::Done(late));
+        }
+        if self.generation_cap_reached(&state) {
+            return Err(self.cancel_at_generation_cap(state));
+        }
+
+        state.check_capacity(self.strict)?;
+        let thread = state.track_arrival()?;
+        let generation = state.generation;
+        let is_first = state.count == 0;
+        if is_first {
+            self.note_first_arrival(&mut state);
+            state.times.expires_at = self
+                .overall_timeout
+                .and_then(|timeout| self.deadline_after(timeout));
+        }
+        state.count += options.weight;
+        self.note_arrival(&mut state);
+        state.times.timed_arrival |= options.released_at.is_some();
+        state.times.idle_wait |= options.idle.is_some();
+        // from here on a panicking condition must give the arrival back
+        rollback.generation = Some(generation);
+        rollback.thread = thread;
+        self.metrics.record_arrival();
+        self.observe_arrival(&state);
+        Ok(Arrived::Counted(
+            state,
+            Arrival {
+                generation,
+                is_first,
+                thread,
+                spun: None,
+                section: Some(section),
+            },
+        ))
+    }
+
+    /// Releases the generation that the wait's arrival completed
+    fn release_on_arrival(
+        &self,
+        mut state: S::Guard<'_, BarrierState>,
+        arrival: Arrival,
+        mut options: WaitOptions<'_>,
+    ) -> BarrierWaitResult {
+        let generation = arrival.generation;
+        if let Err(panicked) = self.release_after(&mut state, options.leader_work.take()) {
+            panicked.resume(self, state);
+        }
+        options.report_release(&state, generation);
+        let is_leader = options.leads(&state, generation, true);
+        if let Some(section) = arrival.section {
+            self.metrics.record_critical_section(section, true);
+        }
+        self.hold_while_paused(state);
+        BarrierWaitResult {
+            is_first: arrival.is_first,
+            ..BarrierWaitResult::new(generation, is_leader)
+        }
+    }
+
+    /// Parks a counted wait until its generation is released or the barrier cancelled,
+    /// releasing the generation itself if its condition comes true, or giving up its
+    /// arrival if it times out or is interrupted first
+    fn wait_for_release<'a>(
+        &'a self,
+        mut state: S::Guard<'a, BarrierState>,
+        arrival: Arrival,
+        mut options: WaitOptions<'_>,
+        rollback: &mut ArrivalRollback<'_, S>,
+    ) -> Result<BarrierWaitResult, BarrierWaitError> {
+        let generation = arrival.generation;
+        if !BLOCKING_SUPPORTED {
+            // nothing else can run to release us, so blocking would hang forever
+            state.count -= options.weight;
+            state.forget_arrival(arrival.thread);
+            rollback.generation = None;
+            return Err(BarrierWaitError::WouldBlock);
+        }
+        let park_ticket = state.park();
+        rollback.parked = Some(park_ticket);
+        self.debug_check_invariants(&state);
+        if let Some(section) = arrival.section {
+            self.metrics.record_critical_section(section, false);
+        }
+        rollback.ticket = self
+            .fair
+            .then(|| state.join_queue(park_ticket, options.priority));
+        #[cfg(feature = "tracing")]
+        let parked_at = Instant::now();
+        let spun = arrival.spun.is_some();
+        let parked = Parked {
+            generation,
+            is_first: arrival.is_first,
+            thread: arrival.thread,
+            timer: arrival.spun.unwrap_or_else(|| self.metrics.start_wait()),
+        };
+        let mut seen_arrivals = state.arrivals;
+        if !spun && (options.spins > 0 || self.backoff != BackoffConfig::default()) {
+            let epoch = self.epoch.load(Ordering::Relaxed);
+            drop(state);
+            self.back_off(epoch, options.spins);
+            state = self.lock();
+        }
+        // a paused barrier holds on to a released generation until it is resumed
+        while (state.generation == generation || state.is_held(park_ticket))
+            && state.cancelled.is_none()
+        {
+            if state.generation != generation {
+                if state.expired_error(generation).is_some() {
+                    // timed out, which a pause doesn't hold back
+                    break;
+                }
+                // released, so there's no arrival left to give up
+                state = S::wait(&self.condvar, state);
+                continue;
+            }
+            if options.condition.is_some() && options.ready(&state) {
+                // the count was complete and our condition came true
+                return Ok(self.release_parked(state, parked, None, &mut options, rollback));
+            }
+            // recompute what's left every time we wake, spurious or not
+            let now = self.clock.now();
+            if self.expire(&mut state, now) {
+                continue;
+            }
+            if let Some(idle) = options.idle {
+                if state.arrivals != seen_arrivals {
+                    // the stragglers are still coming, so give them another window from
+                    // the last of them, even if it has given up again since
+                    seen_arrivals = state.arrivals;
+                    options.deadline = state.times.last_arrival.and_then(|at| at.checked_add(idle));
+                }
+            }
+            let timed_out = options.deadline.is_some_and(|deadline| now >= deadline);
+            if timed_out && self.release_on_timeout {
+                // go ahead with whoever is here
+                let partial = CompletedPartial {
+                    arrived: state.count,
+                    total: state.total,
+                };
+                return Ok(self.release_parked(
+                    state,
+                    parked,
+                    Some(partial),
+                    &mut options,
+                    rollback,
+                ));
+            }
+            let gave_up = if timed_out {
+                Some(state.timed_out())
+            } else if options.interrupted(&mut state) {
+                Some(BarrierWaitError::Interrupted)
+            } else if state.draining() {
+                Some(BarrierWaitError::Draining)
+            } else {
+                None
+            };
+            if self.linked_flag_set() {
+                // cancelling takes the lock and calls the listeners without it
+                drop(state);
+                self.check_linked();
+                state = self.lock();
+                continue;
+            }
+            if options
+                .references
+                .is_some_and(|references| state.unreachable(references()))
+            {
+                self.cancel_locked(&mut state, CancelReason::Unreachable);
+                drop(state);
+                self.notify_cancel_listeners(CancelReason::Unreachable);
+                state = self.lock();
+                continue;
+            }
+            if let Some(err) = gave_up {
+                return Err(self.give_up(state, parked, err, rollback));
+            }
+
+            let poll_at = (options.signal.is_some()
+                || options.condition.is_some()
+                || self.linked.is_some()
+                || options.references.is_some())
+            .then(|| now + SIGNAL_POLL_INTERVAL);
+            self.open_window(&mut state);
+            match options
+                .deadline
+                .into_iter()
+                .chain(poll_at)
+                .chain(state.times.expires_at)
+                .min()
+            {
+                Some(wake_at) => state = self.sleep_until(state, now, wake_at),
+                None => state = self.sleep(state),
+            }
+            self.close_window(&mut state);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("parked_us", parked_at.elapsed().as_micros() as u64);
+        self.leave_released(state, parked, &mut options, rollback)
+    }
+
+    /// Releases the generation from a parked wait whose condition came true, or whose
+    /// deadline passed on a barrier that releases on timeout, with `partial` saying how
+    /// incomplete it was then
+    fn release_parked(
+        &self,
+        mut state: S::Guard<'_, BarrierState>,
+        parked: Parked,
+        partial: Option<CompletedPartial>,
+        options: &mut WaitOptions<'_>,
+        rollback: &mut ArrivalRollback<'_, S>,
+    ) -> BarrierWaitResult {
+        let generation = parked.generation;
+        rollback.leave(&mut state);
+        self.metrics.record_wait(parked.timer);
+        if let Some(partial) = partial {
+            state.partial_release = Some((generation, partial));
+        }
+        if let Err(panicked) = self.release_after(&mut state, options.leader_work.take()) {
+            panicked.resume(self, state);
+        }
+        options.report_release(&state, generation);
+        let is_leader = options.leads(&state, generation, true);
+        self.hold_while_paused(state);
+        BarrierWaitResult {
+            is_first: parked.is_first,
+            partial,
+            ..BarrierWaitResult::new(generation, is_leader)
+        }
+    }
+
+    /// Gives back a parked wait's arrival as it leaves with `err`, breaking the barrier if
+    /// it was built to
+    fn give_up(
+        &self,
+        mut state: S::Guard<'_, BarrierState>,
+        parked: Parked,
+        err: BarrierWaitError,
+        rollback: &mut ArrivalRollback<'_, S>,
+    ) -> BarrierWaitError {
+        state.count -= rollback.weight;
+        state.forget_arrival(parked.thread);
+        rollback.leave(&mut state);
+        self.metrics.record_wait(parked.timer);
+        if err == BarrierWaitError::Draining {
+            // leaving for a drain is expected, and breaks nothing
+            return self.leave_for_drain(&mut state);
+        }
+        if self.break_on_give_up {
+            // the rest of the generation fails with us
+            self.cancel_locked(&mut state, CancelReason::Broken);
+            drop(state);
+            self.notify_cancel_listeners(CancelReason::Broken);
+        }
+        err
+    }
+
+    /// Lets a parked wait go once its generation was released or the barrier cancelled,
+    /// after everyone ahead of it in a fair barrier's queue
+    fn leave_released(
+        &self,
+        mut state: S::Guard<'_, BarrierState>,
+        parked: Parked,
+        options: &mut WaitOptions<'_>,
+        rollback: &mut ArrivalRollback<'_, S>,
+    ) -> Result<BarrierWaitResult, BarrierWaitError> {
+        let generation = parked.generation;
+        rollback.generation = None;
+        self.metrics.record_wait(parked.timer);
+        // a generation that was released stays released, even if the barrier was
+        // cancelled before we got to run again
+        let outcome = if state.generation == generation {
+            state.check_cancelled()
+        } else {
+            state.expired_error(generation).map_or(Ok(()), Err)
+        };
+        if outcome.is_ok() {
+            if let Some(ticket) = rollback.ticket {
+                // wait for everyone who arrived before us to leave first
+                while !state.is_next_to_leave(ticket) {
+                    state = S::wait(&self.condvar, state);
+                }
+            }
+            // still parked, so our generation's release time is kept
+            options.report_release(&state, generation);
+        }
+        rollback.leave(&mut state);
+        outcome?;
+        Ok(BarrierWaitResult {
+            is_first: parked.is_first,
+            partial: state
+                .partial_release
+                .filter(|&(released, _)| released == generation)
+                .map(|(_, partial)| partial),
+            ..BarrierWaitResult::new(generation, options.leads(&state, generation, false))
+        })
+    }
+
+    /// Runs the releasing thread's `work`, if it has any, then releases the generation.
+    ///
+    /// As with the action, a panic in the work doesn't keep the generation from being
+    /// released, and continues once it is.
+    fn release_after(
+        &self,
+        state: &mut BarrierState,
+        work: Option<&mut dyn FnMut()>,
+    ) -> Result<(), PanickedRelease> {
+        let work_panic = work.and_then(|work| panic::catch_unwind(AssertUnwindSafe(work)).err());
+        self.release(state)?;
+        if let Some(payload) = work_panic {
+            panic::resume_unwind(payload);
+        }
+        Ok(())
+    }
+
+    /// Counts the thread or task with park ticket `ticket` as gone from its wait, waking
+    /// those waiting for the barrier to empty out if it was the last
+    fn unpark(&self, state: &mut BarrierState, ticket: u64) {
+        state.unpark(ticket);
+        if state.parked == 0 && state.idle_waiters > 0 {
+            S::notify_all(&self.condvar);
+        }
+    }
+
+    /// Spins and yields, as asked and as configured, until the epoch moves on from `epoch`
+    /// or we run out of patience.
+    ///
+    /// The epoch is only a hint: callers check the state again under the lock afterwards.
+    fn back_off(&self, epoch: usize, linear_spins: u32) {
+        // the longest a single spin round gets, as a power of two
+        const SPIN_LIMIT: u32 = 6;
+
+        let changed = || self.epoch.load(Ordering::Relaxed) != epoch;
+        for _ in 0..linear_spins {
+            if changed() {
+                return;
+            }
+            hint::spin_loop();
+        }
+        for round in 0..self.backoff.spins {
+            if changed() {
+                return;
+            }
+            for _ in 0..1u32 << round.min(SPIN_LIMIT) {
+                hint::spin_loop();
+            }
+        }
+        for _ in 0..self.backoff.yields {
+            if changed() {
+                return;
+            }
+            thread::yield_now();
+        }
+    }
+
+    /// Returns a guard standing for this thread's upcoming arrival.
+    ///
+    /// Call [`ArrivalGuard::wait`] once the thread is ready to arrive. If the guard is
+    /// dropped first, because of an early return or a panic, the barrier is cancelled so
+    /// the other parties get an error instead of waiting forever for an arrival that will
+    /// never come.
+    pub fn wait_guarded(&self) -> Result<ArrivalGuard<'_, S>, BarrierWaitError> {
+        self.lock().check_cancelled()?;
+        Ok(ArrivalGuard::new(self))
+    }
+
+    /// Returns a guard that cancels the barrier with `reason` when dropped, unless it is
+    /// disarmed first.
+    ///
+    /// Where [`Self::wait_guarded`] covers one party's arrival, this covers whoever
+    /// coordinates the barrier, such as a task that spawns the parties and must not go
+    /// away without seeing them through, on a panic or when it is aborted. Dropping the
+    /// guard wakes every waiting thread and task with the cancellation.
+    pub fn cancel_on_drop(self: Arc<Self>, reason: CancelReason) -> CancelGuard<S> {
+        CancelGuard::new(self, reason)
+    }
+
+    /// Arrives at the barrier without blocking.
+    ///
+    /// If this arrival completes the barrier, the other threads are released and the
+    /// generation is returned. Otherwise the arrival is not kept and `None` is returned.
+    /// A late party of a quorum barrier always passes, getting the released generation.
+    pub fn try_wait(&self) -> Result<Option<usize>, BarrierWaitError> {
+        let _marker = WaitMarker::enter(self, self.name.as_deref());
+        self.check_linked();
+        let mut state = self.lock();
+        state.check_cancelled()?;
+        self.end_processing_if_leader(&mut state);
+        if state.held_back() {
+            return Ok(None);
+        }
+        if let Some(late) = state.take_late_arrival() {
+            self.metrics.record_arrival();
+            return Ok(Some(late.generation));
+        }
+        if self.generation_cap_reached(&state) {
+            return Err(self.cancel_at_generation_cap(state));
+        }
+
+        if !state.completed_by(state.count + 1) {
+            return Ok(None);
+        }
+
+        let generation = state.generation;
+        if state.count == 0 {
+            self.note_first_arrival(&mut state);
+        }
+        self.metrics.record_arrival();
+        state.count += 1;
+        self.note_arrival(&mut state);
+        self.observe_arrival(&state);
+        if let Err(panicked) = self.release(&mut state) {
+            panicked.resume(self, state);
+        }
+        Ok(Some(generation))
+    }
+
+    /// Async version of [`Self::wait`] that parks the task instead of the thread.
+    ///
+    /// The returned future works on any executor. Dropping it before the barrier releases
+    /// rolls back this task's arrival, so an abandoned `.await` doesn't leave the other
+    /// parties waiting for it. A pending future leaves its task's waker with the barrier,
+    /// and the barrier wakes it on a release, cancel or reset, so it never asks to be
+    /// polled again for nothing. The default timeout, interrupts and a fair barrier's
+    /// release order only apply to blocking waits: see [`BarrierWait`].
+    pub fn wait_async(&self) -> BarrierWait<'_, S> {
+        BarrierWait::new(self)
+    }
+
+    /// Like [`Self::wait_async`], but the first poll yields back to the executor before
+    /// arriving, as a `yield_now().await` ahead of the wait would.
+    ///
+    /// For tight loops on a cooperative runtime, where a task that finds the barrier ready
+    /// every time would otherwise run on without giving others a turn. The first poll
+    /// wakes the task and returns pending without touching the barrier, and the arrival
+    /// is registered on the next one. Dropping the future in between does nothing.
+    pub fn wait_async_yielding(&self) -> BarrierWait<'_, S> {
+        BarrierWait::yielding(self)
+    }
+
+    /// Arrives at the barrier and returns a handle to check on the wait with timeouts,
+    /// that keeps the arrival counted when a check runs out of time.
+    ///
+    /// Unlike [`Self::wait_timeout`], which withdraws on a timeout and arrives afresh when
+    /// called again, the handle stays a single committed arrival: see [`RetainedWait`].
+    /// Dropping it before the barrier releases rolls the arrival back.
+    #[cfg(not(loom))]
+    pub fn arrive_retained(&self) -> RetainedWait<'_, S> {
+        RetainedWait::new(self)
+    }
+
+    /// Returns a future that resolves once the barrier is cancelled, straight away if it
+    /// already is.
+    ///
+    /// The async counterpart of [`Self::on_cancel`], for a supervisor task to `select!`
+    /// on so it notices the barrier being torn down from elsewhere. Awaiting it doesn't
+    /// arrive at the barrier, and any number of tasks can await it at once. The reason is
+    /// left to [`Self::cancel_reason`], since a reset may already have cleared it by the
+    /// time the future resolves.
+    pub fn cancelled(&self) -> BarrierCancelled<'_, S> {
+        BarrierCancelled::new(self)
+    }
+
+    /// Wraps the barrier in a handle for async code, sharing it rather than making
+    /// another.
+    ///
+    /// For a barrier set up synchronously and awaited later: the handle only offers the
+    /// async waits, and threads and tasks on either side are parties of the same
+    /// generations, each seeing the other's cancellations. Keep a clone of the `Arc` to
+    /// go on waiting from threads. See [`AsyncBarrier`].
+    #[cfg(not(loom))]
+    pub fn into_async(self: Arc<Self>) -> AsyncBarrier<S> {
+        AsyncBarrier::new(self)
+    }
+
+    /// Runs `f` in a crossbeam scope, handing it the scope and the barrier borrowed for
+    /// as long as the scope lives, behind the `crossbeam` feature.
+    ///
+    /// The crossbeam counterpart of [`CancellableBarrier::scope`], for code that spawns
+    /// its own threads: they can wait on the barrier, and borrow anything else that
+    /// outlives the scope, without cloning the `Arc` into each. Returns once every thread
+    /// of the scope has been joined, with an error if one that wasn't joined panicked, as
+    /// `crossbeam_utils::thread::scope` does.
+    ///
+    /// If `f` itself panics the barrier is cancelled with [`CancelReason::Panicked`]
+    /// before the scope joins its threads, so they don't wait for it forever, and the
+    /// panic continues once they have been joined. A spawned thread that might panic
+    /// should arrive through [`Self::wait_guarded`] for the same protection.
+    #[cfg(all(feature = "crossbeam", not(loom)))]
+    pub fn with_crossbeam_scope<'env, F, R>(&'env self, f: F) -> thread::Result<R>
+    where
+        F: FnOnce(&crossbeam_utils::thread::Scope<'env>, &'env Self) -> R,
+    {
+        crossbeam_utils::thread::scope(|scope| {
+            panic::catch_unwind(AssertUnwindSafe(|| f(scope, self))).inspect_err(|_| {
+                self.cancel_with(CancelReason::Panicked);
+            })
+        })
+        .map(|result| result.unwrap_or_else(|payload| panic::resume_unwind(payload)))
+    }
+
+    /// Returns an iterator that waits on the barrier at every step, yielding the
+    /// generation of each wait, for a worker that repeats its phases until the barrier is
+    /// cancelled.
+    ///
+    /// The iterator ends on the first wait that fails, as a loop over [`Self::wait`]
+    /// breaking on `Err` would, and keeps the error for [`BarrierPhases::error`]. Iterate
+    /// over `&mut phases` to still have it after the loop.
+    pub fn phases(&self) -> BarrierPhases<'_, S> {
+        BarrierPhases::new(self)
+    }
+
+    /// Waits, without arriving, until the barrier has released generations up to
+    /// `target`, so that its current generation is at least `target`, or the barrier is
+    /// cancelled.
+    ///
+    /// The waiting thread is an observer: it isn't counted towards any release. Returns
+    /// straight away if the barrier is already there, even if it was cancelled since.
+    /// Resetting the barrier also moves it to a new generation. Across the generation
+    /// counter wrapping around, `target` is reached if it is at most half the counter's
+    /// range behind the current generation.
+    pub fn wait_generation(&self, target: usize) -> Result<(), BarrierWaitError> {
+        let mut state = self.lock();
+        while !generation_reached(state.generation, target) {
+            state.check_cancelled()?;
+            if !BLOCKING_SUPPORTED {
+                return Err(BarrierWaitError::WouldBlock);
+            }
+            state = S::wait(&self.condvar, state);
+        }
+        Ok(())
+    }
+
+    /// Marks the last released generation as processed, letting arrivals at the next one
+    /// in, on a barrier built with
+    /// [`CancellableBarrierBuilder::backpressure`].
+    ///
+    /// Meant for the leader once it has dealt with its generation, although any thread may
+    /// call it. Does nothing if nothing is waiting to be processed.
+    pub fn mark_processed(&self) {
+        let mut state = self.lock();
+        if state.processing.take().is_some() {
+            S::notify_all(&self.condvar);
+            state.wake_all();
+        }
+    }
+
+    /// Ends the processing of the last generation if the current thread released it, as
+    /// its coming back to arrive again means it is done
+    fn end_processing_if_leader(&self, state: &mut BarrierState) {
+        if state.processing == Some(thread::current().id()) {
+            state.processing = None;
+            S::notify_all(&self.condvar);
+            state.wake_all();
+        }
+    }
+
+    /// Blocks an arrival, before it is counted, until the last generation is processed,
+    /// the barrier is cancelled or `deadline` passes
+    fn wait_processed<'a>(
+        &'a self,
+        mut state: S::Guard<'a, BarrierState>,
+        deadline: Option<Instant>,
+    ) -> Result<S::Guard<'a, BarrierState>, BarrierWaitError> {
+        self.end_processing_if_leader(&mut state);
+        while state.held_back() {
+            state.check_cancelled()?;
+            if !BLOCKING_SUPPORTED {
+                return Err(BarrierWaitError::WouldBlock);
+            }
+            let now = self.clock.now();
+            match deadline {
+                Some(deadline) if deadline <= now => return Err(state.timed_out()),
+                Some(deadline) => state = self.sleep_until(state, now, deadline),
+                None => state = self.sleep(state),
+            }
+        }
+        Ok(state)
+    }
+
+    /// Returns a receiver that yields each generation as it is released.
+    ///
+    /// Only generations released after subscribing are delivered; there is no backlog.
+    /// Cancelling the barrier closes the channel.
+    pub fn subscribe(&self) -> mpsc::Receiver<usize> {
+        let (tx, rx) = mpsc::channel();
+        let mut state = self.lock();
+        if state.cancelled.is_none() {
+            state.subscribers.push(tx);
+        }
+        rx
+    }
+
+    /// Tells the observer, if any, and the arrival listeners about the arrival that just
+    /// brought the count to where it is
+    fn observe_arrival(&self, state: &BarrierState) {
+        self.event_log
+            .record_arrival(state.generation, state.count, state.total);
+        if let Some(observer) = &self.observer {
+            observer.on_arrive(state.generation, state.count, state.total);
+        }
+        for (_, listener) in &state.arrival_listeners.listeners {
+            // the arrival is already counted, so a panic mustn't unwind past it
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| listener(state.count, state.total)));
+        }
+    }
+
+    /// Completes the current generation and wakes everyone waiting on it.
+    ///
+    /// Fails if a callback panicked and the barrier was cancelled instead, as its
+    /// [`CallbackPanicPolicy`] asks, for the caller to pass on once it has unlocked.
+    fn release(&self, state: &mut BarrierState) -> Result<(), PanickedRelease> {
+        let generation = state.generation;
+        // the action and listeners only see what they captured, so a panic can't leave
+        // our state torn, and the first one is passed on once everyone has run
+        let mut listeners = self
+            .release_listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let action_panic = self
+            .action
+            .iter()
+            .chain(listeners.listeners.iter().map(|(_, listener)| listener))
+            .fold(None, |first, f| {
+                let panicked = panic::catch_unwind(AssertUnwindSafe(|| f(generation))).err();
+                first.or(panicked)
+            });
+        let action_panic = std::mem::take(&mut listeners.next_release)
+            .into_iter()
+            .fold(action_panic, |first, f| {
+                let panicked = panic::catch_unwind(AssertUnwindSafe(|| f(generation))).err();
+                first.or(panicked)
+            });
+        drop(listeners);
+        let action_panic = match (action_panic, self.on_callback_panic) {
+            (Some(_), CallbackPanicPolicy::Ignore) => None,
+            (Some(payload), CallbackPanicPolicy::Cancel | CallbackPanicPolicy::Abort) => {
+                // the generation fails instead
+                state.aborted |= self.on_callback_panic == CallbackPanicPolicy::Abort;
+                self.cancel_locked(state, CancelReason::CallbackPanicked);
+                return Err(PanickedRelease(payload));
+            }
+            (action_panic, _) => action_panic,
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            barrier = self.name.as_deref(),
+            generation,
+            "barrier released"
+        );
+        if let Some(observer) = &self.observer {
+            observer.on_release(generation);
+        }
+        state
+            .subscribers
+            .retain(|subscriber| subscriber.send(generation).is_ok());
+        state.count = 0;
+        let leader = if self.leader_policy == LeaderPolicy::RoundRobin && !state.handles.is_empty()
+        {
+            let turn = state.released % state.handles.len();
+            state.handles.iter().nth(turn).copied()
+        } else {
+            None
+        };
+        state.released += 1;
+        state.generation = state.generation.wrapping_add(1);
+        if let Some(window) = &self.window {
+            window.set_current(state.generation);
+        }
+        state.late_pending = state.late_parties;
+        state.released_below = state.next_park_ticket;
+        if self.backpressure {
+            state.processing = Some(thread::current().id());
+        }
+        // forget the generations nobody parked is left to ask about
+        let oldest_parked = state.park_tickets.first().copied().unwrap_or(u64::MAX);
+        while state
+            .release_times
+            .front()
+            .is_some_and(|released| released.parked_below <= oldest_parked)
+        {
+            state.release_times.pop_front();
+        }
+        // late parties of a quorum barrier may ask once it is released, but can't wait
+        // for it on a target that can't block
+        let timed = state.times.timed_arrival || (state.late_parties > 0 && BLOCKING_SUPPORTED);
+        state.times.clear();
+        state.release_times.push_back(ReleaseTime {
+            generation,
+            at: timed.then(|| self.clock.now()),
+            parked_below: state.next_park_ticket,
+            leader,
+        });
+        if let Some(threads) = &mut state.threads {
+            threads.release();
+        }
+        self.epoch.fetch_add(1, Ordering::Relaxed);
+        self.metrics.record_release(generation);
+        self.event_log.record_release(generation);
+        #[cfg(feature = "metrics")]
+        self.push_metrics();
+        S::notify_all(&self.condvar);
+        state.wake_all();
+
+        self.debug_check_invariants(state);
+
+        if let Some(payload) = action_panic {
+            panic::resume_unwind(payload);
+        }
+        Ok(())
+    }
+
+    /// Asks every party to leave the barrier, returning the [`DrainToken`] that tells when
+    /// they all have.
+    ///
+    /// Unlike cancelling, draining is cooperative: until the token is dropped, each wait
+    /// returns [`BarrierWaitError::Draining`] instead of arriving, those already waiting
+    /// giving their arrivals back, and counts as that party's acknowledgement. Parties
+    /// can then finish their work and stop, or wait again once the drain is over. A drain
+    /// requested while another is in progress replaces it, and the older token then
+    /// reports nothing more.
+    pub fn request_drain(&self) -> DrainToken<'_, S> {
+        let mut state = self.lock();
+        let token = DrainToken::new(self, &mut state);
+        S::notify_all(&self.condvar);
+        state.wake_all();
+        token
+    }
+
+    /// Cancels the barrier, causing all waiting threads to return with an error.
+    ///
+    /// Returns how many threads had arrived at the current generation when it was
+    /// cancelled, telling a cancellation that cut short an almost complete generation
+    /// from one that interrupted nobody.
+    pub fn cancel(&self) -> usize {
+        self.cancel_with(CancelReason::Unspecified)
+    }
+
+    /// Cancels the barrier with a `reason` that waiting threads receive in their error,
+    /// returning how many threads had arrived as [`Self::cancel`] does.
+    ///
+    /// Cancelling an already cancelled barrier replaces the stored reason. With the
+    /// `backtrace` feature, every cancellation also captures a backtrace, which the
+    /// waiters get in their error from [`CancelledBarrier::backtrace`]; capturing is slow,
+    /// so the feature is off by default.
+    pub fn cancel_with(&self, reason: CancelReason) -> usize {
+        let mut state = self.lock();
+        let arrived = state.count;
+        self.cancel_locked(&mut state, reason.clone());
+        drop(state);
+        self.notify_cancel_listeners(reason);
+        arrived
+    }
+
+    /// Cancels the barrier with [`CancelReason::Error`] and a `payload` of the caller's
+    /// own type, such as the application error that made the generation pointless,
+    /// returning how many threads had arrived as [`Self::cancel`] does.
+    ///
+    /// Waiters get the payload back from [`CancelledBarrier::payload`] in their error,
+    /// asking for the type they expect, so they can react to exactly what went wrong. The
+    /// reason stays a plain [`CancelReason`], so observers, listeners and everything else
+    /// that looks at it are unaffected. Cancelling again replaces the payload, or drops it
+    /// if the new cancellation has none.
+    ///
+    /// The payload is type-erased rather than a type parameter of the barrier: a
+    /// `CancellableBarrier<E>` would have every primitive built on it, the builder and
+    /// [`BarrierWaitError`] carry `E`, for the sake of the one cancellation that has a
+    /// payload. A waiter asking for the wrong type gets `None` from
+    /// [`CancelledBarrier::payload`], and [`CancelledBarrier::has_payload`] tells it there
+    /// was one.
+    pub fn cancel_with_payload<E: std::any::Any + Send + Sync>(&self, payload: E) -> usize {
+        let mut state = self.lock();
+        let arrived = state.count;
+        self.cancel_locked(&mut state, CancelReason::Error);
+        state.cancel_details.payload = Some(std::sync::Arc::new(payload));
+        drop(state);
+        self.notify_cancel_listeners(CancelReason::Error);
+        arrived
+    }
+
+    /// Returns true if the barrier has released as many generations as it was built to
+    /// allow, so the next arrival must cancel it
+    fn generation_cap_reached(&self, state: &BarrierState) -> bool {
+        self.max_generations
+            .is_some_and(|max| state.released >= max)
+    }
+
+    /// Cancels the barrier for an arrival past its last allowed generation, returning
+    /// the error the arrival fails with. The barrier mustn't be cancelled already.
+    fn cancel_at_generation_cap(&self, mut state: S::Guard<'_, BarrierState>) -> BarrierWaitError {
+        self.cancel_locked(&mut state, CancelReason::MaxGenerationsReached);
+        let err = state.check_cancelled().err();
+        drop(state);
+        self.notify_cancel_listeners(CancelReason::MaxGenerationsReached);
+        err.expect("just cancelled")
+    }
+
+    /// Adds a listener that is called with the generation every time the barrier releases,
+    /// and returns an id to remove it with.
+    ///
+    /// Any number of listeners can be added, so independent components can each hook
+    /// into the release without sharing the single action. They run like the action, on
+    /// the releasing thread and before any waiter is woken, after the action and in the
+    /// order they were added, so they must not call back into the barrier. A panicking
+    /// listener doesn't keep the others from running; the first panic, the action's
+    /// included, continues on the releasing thread once all of them have.
+    pub fn add_release_listener(
+        &self,
+        f: impl Fn(usize) + Send + Sync + 'static,
+    ) -> ReleaseListenerId {
+        let mut listeners = self
+            .release_listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let id = ReleaseListenerId(listeners.next_id);
+        listeners.next_id += 1;
+        listeners.listeners.push((id, std::sync::Arc::new(f)));
+        id
+    }
+
+    /// Runs `f` with the generation once, on the next release, then forgets it.
+    ///
+    /// For work tied to whichever generation completes next, such as answering a caller
+    /// waiting on the current cycle, where [`Self::add_release_listener`] would fire on
+    /// every generation. It runs as a listener does, after the listeners and in the order
+    /// such callbacks were added, so it must not call back into the barrier either, and
+    /// a panic in it is passed on in the same way. A callback added while a generation is
+    /// being released runs on the one after, and one still pending when the barrier is
+    /// cancelled or reset waits for the next release all the same.
+    pub fn on_next_release(&self, f: impl FnOnce(usize) + Send + 'static) {
+        self.release_listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .next_release
+            .push(Box::new(f));
+    }
+
+    /// Adds a listener that is called with the count and the size every time a thread
+    /// arrives, such as with `3` and `5` for the third of five, and returns an id to
+    /// remove it with.
+    ///
+    /// Meant for progress reporting. Listeners run on the arriving thread, in the order
+    /// they were added, after the arrival has been counted and before it can release the
+    /// generation, so the last arrival of a generation reports the count equal to the
+    /// size. Threads arriving through [`Self::wait_weighted`] are reported once with the
+    /// count their weight brought it to, and late parties of a quorum barrier aren't
+    /// reported, since they don't count towards a generation. The barrier is locked while
+    /// they run, so a listener must not call back into the barrier, to wait on it, cancel
+    /// it or even read its state, or it deadlocks; hand the numbers off to another thread
+    /// if that's needed. A panicking listener is skipped for that arrival, so that it
+    /// can't cost the arriving thread its place.
+    ///
+    /// With any listener added, threads that aren't the last to arrive always take the
+    /// lock rather than being counted without it, which is slower under contention.
+    pub fn add_arrival_listener(
+        &self,
+        f: impl Fn(usize, usize) + Send + Sync + 'static,
+    ) -> ArrivalListenerId {
+        let mut state = self.lock();
+        let listeners = &mut state.arrival_listeners;
+        let id = ArrivalListenerId(listeners.next_id);
+        listeners.next_id += 1;
+        listeners.listeners.push((id, std::sync::Arc::new(f)));
+        id
+    }
+
+    /// Removes a listener added with [`Self::add_arrival_listener`], returning false if
+    /// it was already removed
+    pub fn remove_arrival_listener(&self, id: ArrivalListenerId) -> bool {
+        let mut state = self.lock();
+        let listeners = &mut state.arrival_listeners.listeners;
+        let before = listeners.len();
+        listeners.retain(|(listener, _)| *listener != id);
+        listeners.len() < before
+    }
+
+    /// Removes a listener added with [`Self::add_release_listener`], returning false if
+    /// it was already removed
+    pub fn remove_release_listener(&self, id: ReleaseListenerId) -> bool {
+        let mut listeners = self
+            .release_listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let before = listeners.listeners.len();
+        listeners.listeners.retain(|(listener, _)| *listener != id);
+        listeners.listeners.len() < before
+    }
+
+    /// Registers `f` to be called with the reason every time the barrier is cancelled.
+    ///
+    /// Listeners run on the cancelling thread, in registration order, after the barrier
+    /// has been marked cancelled and its waiters woken, and without any lock held, so
+    /// they may use the barrier themselves. Resetting the barrier doesn't call them; the
+    /// next cancellation does.
+    pub fn on_cancel(&self, f: impl Fn(CancelReason) + Send + Sync + 'static) {
+        self.cancel_listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(std::sync::Arc::new(f));
+    }
+
+    fn notify_cancel_listeners(&self, reason: CancelReason) {
+        // snapshot the list so a listener can register another without deadlocking
+        let listeners = self
+            .cancel_listeners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        for listener in listeners {
+            listener(reason.clone());
+        }
+        for child in self.children() {
+            if !child.is_cancelled() {
+                child.cancel_with(reason.clone());
+            }
+        }
+    }
+
+    fn cancel_locked(&self, state: &mut BarrierState, reason: CancelReason) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            barrier = self.name.as_deref(),
+            generation = state.generation,
+            arrived = state.count,
+            %reason,
+            "barrier cancelled"
+        );
+        if let Some(observer) = &self.observer {
+            observer.on_cancel(&reason);
+        }
+        self.event_log.record_cancel(state.generation, &reason);
+        state.cancelled = Some(reason);
+        state.cancel_details = CancelDetails {
+            arrived: state.count,
+            ..CancelDetails::default()
+        };
+        state.cancellations = state.cancellations.wrapping_add(1);
+        #[cfg(feature = "diagnostics")]
+        {
+            state.cancel_details.threads = state
+                .threads
+                .as_ref()
+                .map_or_else(Vec::new, ThreadTracking::arrived);
+        }
+        #[cfg(feature = "backtrace")]
+        {
+            state.cancel_details.backtrace = Some(std::sync::Arc::new(
+                std::backtrace::Backtrace::force_capture(),
+            ));
+        }
+        state.subscribers.clear();
+        self.epoch.fetch_add(1, Ordering::Relaxed);
+        self.metrics.record_cancel();
+        S::notify_all(&self.condvar);
+        state.wake_all();
+        self.debug_check_invariants(state);
+    }
+
+    /// Aborts the barrier, cancelling it for good after a failure it can't recover from.
+    ///
+    /// Waiting threads are woken, and every wait from now on fails with
+    /// [`BarrierWaitError::Aborted`]. Unlike a cancellation, an abort can't be undone:
+    /// [`Self::reset`] and [`Self::reset_with`] leave an aborted barrier as it is. The
+    /// barrier counts as cancelled too, and cancel listeners are called as they are for
+    /// [`Self::cancel`].
+    pub fn abort(&self) {
+        let mut state = self.lock();
+        state.aborted = true;
+        let reason = state.cancelled.clone().unwrap_or(CancelReason::Unspecified);
+        self.cancel_locked(&mut state, reason.clone());
+        drop(state);
+        self.notify_cancel_listeners(reason);
+    }
+
+    /// Shuts the barrier down for good: cancels it with [`CancelReason::Shutdown`], waits
+    /// up to `timeout` for every parked thread and task to leave its wait, then aborts it.
+    ///
+    /// Unlike [`Self::cancel`], which only wakes the waiters, this gives the canceller a
+    /// point after which no thread is inside the barrier any more. Waiters that leave in
+    /// time get the cancellation with its reason; from then on every wait fails with
+    /// [`BarrierWaitError::Aborted`]. A barrier that is already cancelled keeps its reason.
+    /// Async waiters only leave once their executor polls them again.
+    ///
+    /// Returns true if all waiters drained before the timeout. The barrier is aborted either
+    /// way.
+    pub fn shutdown(&self, timeout: Duration) -> bool {
+        let deadline = self.clock.now().checked_add(timeout);
+        let mut state = self.lock();
+        if state.cancelled.is_none() {
+            self.cancel_locked(&mut state, CancelReason::Shutdown);
+            drop(state);
+            self.notify_cancel_listeners(CancelReason::Shutdown);
+            state = self.lock();
+        }
+        // the last waiter to leave wakes us
+        while state.parked > 0 && BLOCKING_SUPPORTED {
+            let now = self.clock.now();
+            state.idle_waiters += 1;
+            state = match deadline {
+                Some(deadline) if deadline <= now => {
+                    state.idle_waiters -= 1;
+                    break;
+                }
+                Some(deadline) => self.sleep_until(state, now, deadline),
+                None => self.sleep(state),
+            };
+            state.idle_waiters -= 1;
+        }
+        let drained = state.parked == 0;
+        state.aborted = true;
+        if state.cancelled.is_none() {
+            // reset while we waited, but an aborted barrier has to stay cancelled
+            self.cancel_locked(&mut state, CancelReason::Shutdown);
+            drop(state);
+            self.notify_cancel_listeners(CancelReason::Shutdown);
+        }
+        drained
+    }
+
+    /// Resets the barrier to its initial state, unless it was aborted.
+    ///
+    /// Resetting starts a new generation and uncancels the barrier. Threads and tasks
+    /// still waiting on the generation in progress are woken and let go as if it had been
+    /// released, as with [`Self::force_release`]: their wait returns `Ok` with that
+    /// generation, and none of them is the leader. Threads that arrive after the reset
+    /// count towards the new generation. To have the waiters fail instead, cancel the
+    /// barrier before resetting it.
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        self.reset_locked(&mut state);
+    }
+
+    /// Uncancels the barrier in place, without starting a new generation as
+    /// [`Self::reset`] does.
+    ///
+    /// Meant for a barrier cancelled while nobody was waiting on it: the count and the
+    /// generation are left as they are and only the cancellation is cleared, with its
+    /// reason, payload and the rest of what was recorded about it. Threads that were waiting when the barrier was cancelled have already
+    /// returned with the cancellation, but their arrivals are still counted, so if any
+    /// thread had arrived this does nothing and returns false, and the barrier has to be
+    /// reset instead. An aborted barrier stays aborted, also returning false. Returns
+    /// true otherwise, including when the barrier wasn't cancelled.
+    pub fn uncancel(&self) -> bool {
+        let mut state = self.lock();
+        if state.aborted || state.count > 0 {
+            return state.cancelled.is_none() && !state.aborted;
+        }
+        state.clear_cancellation();
+        true
+    }
+
+    fn reset_locked(&self, state: &mut BarrierState) {
+        if state.aborted {
+            return;
+        }
+        state.clear_cancellation();
+        state.count = 0;
+        state.times.clear();
+        state.late_pending = 0;
+        state.processing = None;
+        if let Some(threads) = &mut state.threads {
+            threads.reset();
+        }
+        self.event_log.record_reset(state.generation);
+        state.generation = state.generation.wrapping_add(1);
+        state.resets = state.resets.wrapping_add(1);
+        state.released_below = state.next_park_ticket;
+        if let Some(window) = &self.window {
+            window.set_current(state.generation);
+        }
+        self.epoch.fetch_add(1, Ordering::Relaxed);
+        self.metrics.record_reset(state.generation);
+        // the waiters' generation is gone, so they must look again
+        S::notify_all(&self.condvar);
+        state.wake_all();
+        self.debug_check_invariants(state);
+    }
+
+    /// Releases the threads waiting in the current generation as if the barrier had
+    /// completed, however many have arrived.
+    ///
+    /// The waiting threads return `Ok` with the current generation, and none of them is
+    /// reported as leader since no arrival completed the barrier. The barrier's size is
+    /// unchanged: threads that arrive after the force belong to the next generation and
+    /// wait for a full count as usual. Does nothing if no thread is waiting or the
+    /// barrier is cancelled.
+    pub fn force_release(&self) {
+        let mut state = self.lock();
+        if state.count > 0 && state.cancelled.is_none() {
+            if let Err(panicked) = self.release(&mut state) {
+                panicked.resume(self, state);
+            }
+        }
+    }
+
+    /// Releases the threads waiting on the barrier, the way to open a
+    /// [manual](Self::manual) barrier.
+    ///
+    /// The same as [`Self::force_release`]: the threads waiting in the current generation
+    /// return `Ok` without a leader, and those that arrive afterwards wait for the next
+    /// trip. Does nothing if no thread is waiting or the barrier is cancelled.
+    pub fn trip(&self) {
+        self.force_release();
+    }
+
+    /// Adds a party to the barrier and returns a handle for it to wait with, which
+    /// removes the party again when dropped. See [`BarrierHandle`].
+    #[cfg(not(loom))]
+    pub fn join(self: &Arc<Self>) -> BarrierHandle<S> {
+        BarrierHandle::new(self.clone())
+    }
+
+    /// Adds the party of a new [`BarrierHandle`], returning its index
+    #[cfg(not(loom))]
+    fn add_handle(&self) -> usize {
+        let mut state = self.lock();
+        let index = state.next_handle;
+        state.next_handle += 1;
+        state.handles.insert(index);
+        let new_total = state.total + 1;
+        if let Err(panicked) = self.set_total(&mut state, new_total) {
+            panicked.resume(self, state);
+        }
+        index
+    }
+
+    /// Removes the party of the dropped [`BarrierHandle`] with `index`, unless it is the
+    /// last one
+    #[cfg(not(loom))]
+    fn remove_handle(&self, index: usize) {
+        let mut state = self.lock();
+        state.handles.remove(&index);
+        if state.total <= 1 {
+            return;
+        }
+        let new_total = state.total - 1;
+        if state.cancelled.is_some() {
+            // handles leaving a cancelled generation, as they do once it is unreachable,
+            // mustn't release it under the feet of those yet to see the cancellation
+            state.total = new_total;
+            self.single_party.store(new_total == 1, Ordering::Relaxed);
+            self.event_log.record_resize(state.generation, new_total);
+        } else if let Err(panicked) = self.set_total(&mut state, new_total) {
+            panicked.resume(self, state);
+        }
+    }
+
+    /// Waits as [`Self::wait`] on behalf of the [`BarrierHandle`] with `index`, which
+    /// counts the `references` to the barrier so that the wait can tell when nobody is
+    /// left to arrive
+    #[cfg(not(loom))]
+    fn wait_as_handle(
+        &self,
+        index: usize,
+        references: &dyn Fn() -> usize,
+    ) -> Result<BarrierWaitResult, BarrierWaitError> {
+        self.wait_inner(WaitOptions {
+            deadline: self
+                .default_timeout
+                .and_then(|timeout| self.deadline_after(timeout)),
+            references: Some(references),
+            party: Some(index),
+            ..WaitOptions::default()
+        })
+    }
+
+    /// Counts an arrival in the barrier's running total, noting when it came in if an idle
+    /// wait is there to re-arm its window from it
+    fn note_arrival(&self, state: &mut BarrierState) {
+        state.arrivals = state.arrivals.wrapping_add(1);
+        if state.times.idle_wait {
+            state.times.last_arrival = Some(self.clock.now());
+        }
+    }
+
+    /// Notes the first arrival of the generation in progress, for the metrics and
+    /// [`Self::is_stuck`]. Targets that can't block have no watchdog thread to ask, so as
+    /// with [`Self::deadline_after`] they never read a clock they may not have.
+    fn note_first_arrival(&self, state: &mut BarrierState) {
+        self.metrics.record_first_arrival();
+        if BLOCKING_SUPPORTED {
+            state.times.first_arrival = Some(self.clock.now());
+        }
+    }
+
+    /// Locks the state, recovering it if another thread panicked while holding the lock.
+    ///
+    /// The state is only counters and flags that are updated together, so a panic can't
+    /// leave it half-modified and there's no reason to spread the panic to every waiter.
+    ///
+    /// Closes the arrival window first, so the state holds every arrival.
+    fn lock(&self) -> S::Guard<'_, BarrierState> {
+        let mut state = S::lock(&self.mutex);
+        self.close_window(&mut state);
+        state
+    }
+
+    /// Opens the arrival window before parking, if the barrier has one and the generation
+    /// is still open to arrivals that don't complete it
+    fn open_window(&self, state: &mut BarrierState) {
+        if let Some(window) = &self.window {
+            self.close_window(state);
+            if state.cancelled.is_none()
+                && !state.order.paused
+                && state.arrival_listeners.listeners.is_empty()
+                && !state.times.idle_wait
+                && !state.completed_by(state.count + 1)
+            {
+                window.open(state.generation, state.count, state.total);
+            }
+        }
+    }
+
+    /// Closes the arrival window, counting the arrivals it let in
+    fn close_window(&self, state: &mut BarrierState) {
+        if let Some(window) = &self.window {
+            let arrived = window.close();
+            if arrived > 0 && state.count == 0 && BLOCKING_SUPPORTED {
+                // counted without the lock, so nobody has noted when the generation began
+                state.times.first_arrival = Some(self.clock.now());
+            }
+            state.count += arrived;
+            if arrived > 0 {
+                state.arrivals = state.arrivals.wrapping_add(arrived as u64);
+                self.event_log
+                    .record_arrival(state.generation, state.count, state.total);
+            }
+        }
+    }
+
+    /// Returns the number of parties the generation in progress needs, as last changed by
+    /// [`Self::resize`] or by parties joining and leaving.
+    ///
+    /// On a quorum barrier this is the quorum. It reads the size under the lock, so it is
+    /// up to date once any resize has returned, but another thread can change it right
+    /// after.
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.lock().total
+    }
+
+    /// Returns how many more arrivals the generation in progress needs to be released,
+    /// or how much more weight on a weighted barrier.
+    ///
+    /// On a quorum barrier this counts towards the quorum rather than the parties: with
+    /// `k` of `n`, it is `k` minus the arrivals so far. Both are read under one lock, so
+    /// the result stands for a single moment even while the barrier is being resized.
+    #[must_use]
+    pub fn remaining(&self) -> usize {
+        let state = self.lock();
+        state.total.saturating_sub(state.count)
+    }
+
+    /// Returns the name the barrier was built with, if any
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns true if the barrier is currently cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled.is_some()
+    }
+
+    /// Returns true if the barrier was aborted
+    #[must_use]
+    pub fn is_aborted(&self) -> bool {
+        self.lock().aborted
+    }
+
+    /// Returns the reason the barrier was last cancelled with, or `None` if it isn't
+    /// cancelled
+    #[must_use]
+    pub fn cancel_reason(&self) -> Option<CancelReason> {
+        self.lock().cancelled.clone()
+    }
+}
+
+impl<S: RawSync> fmt::Debug for CancellableBarrier<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellableBarrier")
+            .field("name", &self.name())
+            .field("state", &self.state())
+            .finish_non_exhaustive()
+    }
+}
+
+/// How much a blocking wait counts for, what else it waits on, and the ways it can be
+/// asked to give up early
+struct WaitOptions<'a> {
+    weight: usize,
+    deadline: Option<Instant>,
+    spins: u32,
+    signal: Option<&'a dyn CancelSignal>,
+    /// Key of the [`InterruptHandle`] the wait can be interrupted through
+    interrupt: Option<u64>,
+    /// The thread whose [`InterruptHandle`]s can all interrupt the wait
+    interrupt_thread: Option<ThreadId>,
+    /// What has to hold, besides everyone having arrived, for the wait to release
+    condition: Option<&'a dyn Fn() -> bool>,
+    /// Run if the wait releases the generation, before anyone else is let go
+    leader_work: Option<&'a mut dyn FnMut()>,
+    /// Where the thread goes in a fair barrier's queue, higher first
+    priority: u8,
+    /// Set to when the generation was released, if the wait succeeds
+    released_at: Option<&'a mut Option<Instant>>,
+    /// For a wait through a [`BarrierHandle`], counts the references to the barrier, to
+    /// tell when only parked handles are left to arrive
+    references: Option<&'a dyn Fn() -> usize>,
+    /// The index of the [`BarrierHandle`] waiting, if any
+    party: Option<usize>,
+    /// How long the count may go without growing before the wait gives up, re-arming the
+    /// deadline on every arrival
+    idle: Option<Duration>,
+}
+
+impl Default for WaitOptions<'_> {
+    fn default() -> Self {
+        WaitOptions {
+            weight: 1,
+            deadline: None,
+            spins: 0,
+            signal: None,
+            interrupt: None,
+            interrupt_thread: None,
+            condition: None,
+            leader_work: None,
+            priority: 0,
+            released_at: None,
+            references: None,
+            party: None,
+            idle: None,
+        }
+    }
+}
+
+impl WaitOptions<'_> {
+    /// Returns true if the generation can be released: everyone has arrived, and the
+    /// condition holds if the wait has one
+    fn ready(&self, state: &BarrierState) -> bool {
+        state.completed_by(state.count) && self.condition.is_none_or(|condition| condition())
+    }
+
+    /// Returns true if the wait has been interrupted, using up an interrupt through a
+    /// handle, since that interrupts only the wait it reaches
+    fn interrupted(&self, state: &mut BarrierState) -> bool {
+        self.signal.is_some_and(|signal| signal.is_cancelled())
+            || self
+                .interrupt
+                .is_some_and(|key| state.interrupts.remove(&key).is_some())
+            || self
+                .interrupt_thread
+                .is_some_and(|thread| state.take_thread_interrupt(thread))
+    }
+
+    /// Tells the waiter when `generation` was released, if it asked
+    fn report_release(&mut self, state: &BarrierState, generation: usize) {
+        if let Some(released_at) = self.released_at.as_deref_mut() {
+            *released_at = state.release_time(generation);
+        }
+    }
+
+    /// Returns true if the wait leads `generation`: on a round-robin barrier it is a
+    /// handle's turn that makes it the leader, otherwise whether `released_it` did
+    fn leads(&self, state: &BarrierState, generation: usize, released_it: bool) -> bool {
+        match state.release_leader(generation) {
+            Some(leader) => self.party == Some(leader),
+            None => released_it,
+        }
+    }
+}
+
+/// How a wait's arrival went
+enum Arrived<'a, S: RawSync + 'a> {
+    /// Counted in its generation, which the wait goes on to release or wait for, with the
+    /// barrier locked
+    Counted(S::Guard<'a, BarrierState>, Arrival),
+    /// Done without having to wait
+    Done(BarrierWaitResult),
+}
+
+/// A wait counted in its generation
+struct Arrival {
+    generation: usize,
+    /// Whether the wait was the first to arrive in its generation
+    is_first: bool,
+    /// The thread that arrived, if the barrier tracks them
+    thread: Option<ThreadId>,
+    /// Times the wait from its arrival through the window, after which it has spun
+    /// already
+    spun: Option<WaitTimer>,
+    /// Times the critical section of an arrival under the lock
+    section: Option<WaitTimer>,
+}
+
+/// A wait parked on its generation
+struct Parked {
+    generation: usize,
+    is_first: bool,
+    thread: Option<ThreadId>,
+    timer: WaitTimer,
+}
+
+/// The panic of a callback that cancelled the generation it was releasing, as the
+/// barrier's [`CallbackPanicPolicy`] asked
+struct PanickedRelease(Box<dyn std::any::Any + Send>);
+
+impl PanickedRelease {
+    /// Unlocks `state`, tells the cancel listeners, and continues the panic
+    fn resume<S: RawSync>(
+        self,
+        barrier: &CancellableBarrier<S>,
+        state: S::Guard<'_, BarrierState>,
+    ) -> ! {
+        drop(state);
+        barrier.notify_cancel_listeners(CancelReason::CallbackPanicked);
+        panic::resume_unwind(self.0)
+    }
+}
+
+/// Gives back a blocked thread's slot if it unwinds out of [`CancellableBarrier::wait`]
+struct ArrivalRollback<'a, S: RawSync> {
+    barrier: &'a CancellableBarrier<S>,
+    weight: usize,
+    generation: Option<usize>,
+    ticket: Option<u64>,
+    thread: Option<ThreadId>,
+    parked: Option<u64>,
+}
+
+impl<S: RawSync> ArrivalRollback<'_, S> {
+    /// Leaves the fair queue and counts the wait as gone, with nothing left to give back
+    fn leave(&mut self, state: &mut BarrierState) {
+        self.generation = None;
+        if let Some(ticket) = self.ticket.take() {
+            self.barrier.leave_queue(state, ticket);
+        }
+        if let Some(ticket) = self.parked.take() {
+            self.barrier.unpark(state, ticket);
+        }
+    }
+}
+
+impl<S: RawSync> Drop for ArrivalRollback<'_, S> {
+    fn drop(&mut self) {
+        if self.generation.is_none() && self.ticket.is_none() && self.parked.is_none() {
+            return;
+        }
+        // we only get here while unwinding, likely with the mutex poisoned
+        let mut state = self.barrier.lock();
+        if let Some(generation) = self.generation {
+            if state.generation == generation && state.cancelled.is_none() {
+                state.count -= self.weight;
+                state.forget_arrival(self.thread);
+            }
+        }
+        if let Some(ticket) = self.ticket {
+            self.barrier.leave_queue(&mut state, ticket);
+        }
+        if let Some(ticket) = self.parked {
+            self.barrier.unpark(&mut state, ticket);
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::future::Future;
+    use std::thread;
+
+    pub(super) const TEST_SLEEP_DURATION: Duration = Duration::from_millis(100);
+
+    pub(super) fn cancelled(reason: CancelReason) -> BarrierWaitError {
+        CancelledBarrier::new(reason).into()
+    }
+
+    #[test]
+    fn test_basic_barrier() {
+        let barrier = CancellableBarrier::new(3);
+        let barrier_clone1 = barrier.clone();
+        let barrier_clone2 = barrier.clone();
+
+        let t1 = thread::spawn(move || {
+            barrier.wait().unwrap();
+        });
+
+        let t2 = thread::spawn(move || {
+            barrier_clone1.wait().unwrap();
+        });
+
+        let t3 = thread::spawn(move || {
+            barrier_clone2.wait().unwrap();
+        });
+
+        t1.join().unwrap();
+        t2.join().unwrap();
+        t3.join().unwrap();
+    }
+
+    #[test]
+    fn test_cancel_barrier() {
+        let barrier = CancellableBarrier::new(3);
+        let barrier_clone1 = barrier.clone();
+        let barrier_clone2 = barrier.clone();
+
+        let t1 = thread::spawn(move || barrier.wait());
+        let t2 = thread::spawn(move || barrier_clone1.wait());
+
+        let t3 = thread::spawn(move || {
+            barrier_clone2.cancel();
+            barrier_clone2.wait()
+        });
+
+        assert!(t1.join().unwrap().is_err());
+        assert!(t2.join().unwrap().is_err());
+        assert!(t3.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_reset_barrier() {
+        let barrier = CancellableBarrier::new(2);
+        let barrier_clone1 = barrier.clone();
+
+        // First, cancel the barrier
+        barrier.cancel();
+        assert!(barrier.wait().is_err());
+
+        // Reset the barrier
+        barrier.reset();
+
+        // Now it should work again
+        let t1 = thread::spawn(move || {
+            barrier.wait().unwrap();
+        });
+
+        let t2 = thread::spawn(move || {
+            barrier_clone1.wait().unwrap();
+        });
+
+        t1.join().unwrap();
+        t2.join().unwrap();
+    }
+
+    #[test]
+    fn test_reset_wakes_waiters() {
+        let barrier = CancellableBarrier::new(3);
+        let barrier_clone1 = barrier.clone();
+
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        barrier.reset();
+        // without the wake-up this would wait for arrivals that never come
+        assert!(t1.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_reset_lets_waiters_go() {
+        let barrier = CancellableBarrier::new(4);
+
+        let waiters: Vec<_> = (0..3)
+            .map(|i| {
+                let barrier = barrier.clone();
+                thread::spawn(move || match i {
+                    0 => barrier.wait(),
+                    1 => barrier.wait_timeout(Duration::from_secs(60)),
+                    _ => block_on(barrier.wait_async()),
+                })
+            })
+            .collect();
+        while barrier.state().arrived < 3 {
+            thread::yield_now();
+        }
+        barrier.reset();
+        for waiter in waiters {
+            let result = waiter.join().unwrap().unwrap();
+            assert_eq!(result.generation(), 0);
+            assert!(!result.is_leader());
+        }
+
+        // a full generation is needed again from here on
+        let snapshot = barrier.state();
+        assert_eq!((snapshot.arrived, snapshot.generation), (0, 1));
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait())
+            })
+            .collect();
+        assert_eq!(barrier.wait().map(|r| r.generation()), Ok(1));
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().map(|r| r.generation()), Ok(1));
+        }
+    }
+
+    #[test]
+    fn test_wait_error() {
+        let barrier = CancellableBarrier::new(2);
+        barrier.cancel();
+
+        let err = barrier.wait().unwrap_err();
+        assert_eq!(err, cancelled(CancelReason::Unspecified));
+        assert_eq!(err.cancel_reason(), Some(&CancelReason::Unspecified));
+        assert_eq!(err.to_string(), "barrier cancelled");
+    }
+
+    #[test]
+    fn test_cancel_with_reason() {
+        let barrier = CancellableBarrier::new(2);
+        let barrier_clone1 = barrier.clone();
+
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        barrier.cancel_with(CancelReason::Shutdown);
+
+        let err = t1.join().unwrap().unwrap_err();
+        assert_eq!(err, cancelled(CancelReason::Shutdown));
+        assert_eq!(err.to_string(), "barrier cancelled: shutdown");
+        match err {
+            BarrierWaitError::Cancelled(err) => {
+                assert_eq!(err.reason(), &CancelReason::Shutdown);
+                assert_eq!(err.to_string(), "barrier cancelled: shutdown");
+            }
+            _ => panic!("expected a cancellation"),
+        }
+        assert_eq!(barrier.wait(), Err(cancelled(CancelReason::Shutdown)));
+    }
+
+    #[test]
+    fn test_cancel_reason() {
+        let barrier = CancellableBarrier::new(2);
+        assert_eq!(barrier.cancel_reason(), None);
+        barrier.cancel_with(CancelReason::Shutdown);
+        assert_eq!(barrier.cancel_reason(), Some(CancelReason::Shutdown));
+        barrier.cancel_with(CancelReason::Error);
+        assert_eq!(barrier.cancel_reason(), Some(CancelReason::Error));
+        barrier.reset();
+        assert_eq!(barrier.cancel_reason(), None);
+    }
+
+    #[test]
+    fn test_abort_survives_reset() {
+        let barrier = CancellableBarrier::new(2);
+        barrier.cancel();
+        barrier.reset();
+        assert!(!barrier.is_cancelled());
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        barrier.abort();
+        assert_eq!(t1.join().unwrap(), Err(BarrierWaitError::Aborted));
+        assert!(barrier.is_aborted());
+        assert!(barrier.is_cancelled());
+
+        barrier.reset();
+        assert_eq!(barrier.reset_with(1), Ok(()));
+        assert!(barrier.is_aborted());
+        assert_eq!(barrier.state().total, 2);
+        assert_eq!(barrier.wait(), Err(BarrierWaitError::Aborted));
+        assert_eq!(barrier.try_wait(), Err(BarrierWaitError::Aborted));
+    }
+
+    #[test]
+    fn test_try_new() {
+        assert_eq!(
+            CancellableBarrier::try_new(0).unwrap_err(),
+            BarrierBuildError::ZeroParties
+        );
+        assert_eq!(CancellableBarrier::try_new(3).unwrap().state().total, 3);
+    }
+
+    #[test]
+    fn test_builder_zero_parties() {
+        assert_eq!(
+            CancellableBarrier::builder()
+                .parties(0)
+                .build()
+                .unwrap_err(),
+            BarrierBuildError::ZeroParties
+        );
+    }
+
+    #[test]
+    fn test_try_wait() {
+        let barrier = CancellableBarrier::new(2);
+        let barrier_clone1 = barrier.clone();
+
+        // Neither call blocks, and neither leaves an arrival behind
+        let t1 = thread::spawn(move || barrier_clone1.try_wait());
+        assert_eq!(barrier.try_wait(), Ok(None));
+        assert_eq!(t1.join().unwrap(), Ok(None));
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.lock().count == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(barrier.try_wait(), Ok(Some(0)));
+        assert_eq!(t1.join().unwrap().map(|r| r.generation()), Ok(0));
+
+        barrier.cancel();
+        assert_eq!(
+            barrier.try_wait(),
+            Err(cancelled(CancelReason::Unspecified))
+        );
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let barrier = CancellableBarrier::new(1);
+        barrier.wait().unwrap();
+
+        let generations = barrier.subscribe();
+        barrier.wait().unwrap();
+        barrier.wait().unwrap();
+        barrier.cancel();
+
+        // generation 0 was released before subscribing, and cancelling ends the stream
+        assert_eq!(generations.iter().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    /// Minimal executor so the async tests don't depend on any particular runtime
+    struct ThreadWaker(thread::Thread);
+
+    impl std::task::Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    pub(super) fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn test_wait_async() {
+        let barrier = CancellableBarrier::new(3);
+
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || block_on(barrier.wait_async()))
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().map(|r| r.generation()), Ok(0));
+        }
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || block_on(barrier_clone1.wait_async()));
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        barrier.cancel();
+        assert_eq!(
+            t1.join().unwrap(),
+            Err(cancelled(CancelReason::Unspecified))
+        );
+    }
+
+    #[test]
+    fn test_cancel_on_drop() {
+        let barrier = CancellableBarrier::new(4);
+        let guard = barrier.clone().cancel_on_drop(CancelReason::Shutdown);
+        let tasks: Vec<_> = (0..3)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || block_on(barrier.wait_async()))
+            })
+            .collect();
+        while barrier.state().arrived < 3 {
+            thread::yield_now();
+        }
+        drop(guard);
+        for task in tasks {
+            assert_eq!(task.join().unwrap(), Err(cancelled(CancelReason::Shutdown)));
+        }
+
+        // a disarmed guard leaves the barrier alone
+        let barrier = CancellableBarrier::new(1);
+        let guard = barrier.clone().cancel_on_drop(CancelReason::Error);
+        assert!(Arc::ptr_eq(&guard.disarm(), &barrier));
+        assert!(!barrier.is_cancelled());
+    }
+
+    #[test]
+    fn test_wait_async_drop_rolls_back() {
+        let barrier = CancellableBarrier::new(2);
+
+        let waker = Arc::new(NoopWaker).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut wait = barrier.wait_async();
+        assert!(std::pin::Pin::new(&mut wait).poll(&mut cx).is_pending());
+        {
+            let state = barrier.lock();
+            assert_eq!(state.count, 1);
+            assert_eq!(state.wakers.len(), 1);
+        }
+
+        drop(wait);
+        let state = barrier.lock();
+        assert_eq!(state.count, 0);
+        assert!(state.wakers.is_empty());
+    }
+
+    /// Polls `future` until it completes or `timeout` elapses, dropping it in the latter
+    /// case, as a `select!` against a timer would
+    fn block_on_timeout<F: std::future::Future>(future: F, timeout: Duration) -> Option<F::Output> {
+        let deadline = Instant::now() + timeout;
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return Some(output);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            thread::park_timeout(deadline - now);
+        }
+    }
+
+    #[test]
+    fn test_wait_async_moves_between_threads() {
+        let barrier = CancellableBarrier::new(2);
+
+        // arrive on one thread and finish on another, as a task on a multi-threaded
+        // runtime may
+        let barrier_clone = barrier.clone();
+        let task = thread::spawn(move || {
+            let mut wait = Box::pin(async move { barrier_clone.wait_async().await });
+            let waker = Arc::new(NoopWaker).into();
+            let mut cx = std::task::Context::from_waker(&waker);
+            assert!(wait.as_mut().poll(&mut cx).is_pending());
+            wait
+        })
+        .join()
+        .unwrap();
+        assert_eq!(barrier.state().arrived, 1);
+
+        let finished = thread::spawn(move || block_on_timeout(task, Duration::from_secs(5)));
+        assert!(barrier.wait().is_ok());
+        assert!(finished.join().unwrap().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_wait_async_losing_a_race_leaves_no_trace() {
+        let barrier = CancellableBarrier::new(2);
+
+        // the timeout wins, several times over
+        for _ in 0..3 {
+            assert!(block_on_timeout(barrier.wait_async(), Duration::from_millis(20)).is_none());
+            let state = barrier.lock();
+            assert_eq!((state.count, state.parked), (0, 0));
+            assert!(state.wakers.is_empty());
+        }
+
+        // so the barrier still needs, and is released by, two fresh arrivals
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || block_on(barrier_clone1.wait_async()));
+        let ours = block_on_timeout(barrier.wait_async(), Duration::from_secs(10));
+        assert_eq!(ours.map(|r| r.map(|r| r.generation())), Some(Ok(0)));
+        assert_eq!(t1.join().unwrap().map(|r| r.generation()), Ok(0));
+    }
+
+    #[test]
+    fn test_wait_async_future_is_nameable() {
+        fn assert_storable<F: Future + Unpin + Send>(_: &F) {}
+
+        struct Task<'a> {
+            wait: BarrierWait<'a>,
+        }
+
+        let barrier = CancellableBarrier::new(1);
+        let mut task = Task {
+            wait: barrier.wait_async(),
+        };
+        assert_storable(&task.wait);
+        let waker = Arc::new(NoopWaker).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let result = std::pin::Pin::new(&mut task.wait).poll(&mut cx);
+        assert!(matches!(result, std::task::Poll::Ready(Ok(r)) if r.generation() == 0));
+    }
+
+    pub(super) struct NoopWaker;
+
+    impl std::task::Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// Counts its wakes, for an executor that only polls a task once it's woken
+    #[derive(Default)]
+    struct CountingWaker(std::sync::atomic::AtomicUsize);
+
+    impl std::task::Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    /// Polls `future` once, then once per wake until it completes, returning its output
+    /// with how many times it was polled. `event` runs after the first poll, to complete
+    /// the future from outside.
+    fn poll_on_wake<F: Future>(future: F, event: impl FnOnce()) -> (F::Output, usize) {
+        let counter = Arc::new(CountingWaker::default());
+        let waker = counter.clone().into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        let mut event = Some(event);
+        let mut polled = 0;
+        let mut woken = 0;
+        loop {
+            polled += 1;
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return (output, polled);
+            }
+            if let Some(event) = event.take() {
+                event();
+            }
+            // a future that asks to be polled again without being woken would hang here
+            let deadline = Instant::now() + TEST_SLEEP_DURATION * 10;
+            while counter.0.load(std::sync::atomic::Ordering::SeqCst) == woken {
+                assert!(Instant::now() < deadline, "pending without a wake to come");
+                thread::yield_now();
+            }
+            woken = counter.0.load(std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_wait_async_yielding() {
+        let barrier = CancellableBarrier::new(1);
+        let (result, polled) = poll_on_wake(barrier.wait_async(), || {});
+        assert_eq!(result.map(|r| r.generation()), Ok(0));
+        assert_eq!(polled, 1);
+
+        // the first poll only yields, without arriving
+        let (result, polled) = poll_on_wake(barrier.wait_async_yielding(), || {
+            assert_eq!(barrier.state().generation, 1);
+            assert_eq!(barrier.state().arrived, 0);
+        });
+        assert_eq!(result.map(|r| r.generation()), Ok(1));
+        assert_eq!(polled, 2);
+        drop(barrier.wait_async_yielding());
+    }
+
+    #[test]
+    fn test_async_wait_is_polled_only_when_woken() {
+        let barrier = CancellableBarrier::new(2);
+        let (result, polled) = poll_on_wake(barrier.wait_async(), || {
+            barrier.wait().unwrap();
+        });
+        assert_eq!(result.map(|r| r.generation()), Ok(0));
+        assert_eq!(polled, 2);
+
+        let (result, polled) = poll_on_wake(barrier.wait_async(), || {
+            barrier.cancel();
+        });
+        assert!(result.is_err());
+        assert_eq!(polled, 2);
+    }
+
+    #[test]
+    fn test_wait_async_leaves_blocking_only_behavior_out() {
+        let waker = Arc::new(NoopWaker).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+
+        // the default timeout running out doesn't end the wait
+        let clock = Arc::new(MockClock::new());
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .default_timeout(TEST_SLEEP_DURATION)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let mut wait = barrier.wait_async();
+        assert!(std::pin::Pin::new(&mut wait).poll(&mut cx).is_pending());
+        clock.advance(TEST_SLEEP_DURATION * 2);
+        assert!(std::pin::Pin::new(&mut wait).poll(&mut cx).is_pending());
+        barrier.wait().unwrap();
+        let result = std::pin::Pin::new(&mut wait).poll(&mut cx);
+        assert!(matches!(result, std::task::Poll::Ready(Ok(_))));
+
+        // nor does an interrupt, which is kept for the thread's next blocking wait
+        let barrier = CancellableBarrier::new(2);
+        let handle = barrier.interrupt_handle();
+        let mut wait = barrier.wait_async();
+        assert!(std::pin::Pin::new(&mut wait).poll(&mut cx).is_pending());
+        handle.interrupt();
+        assert!(std::pin::Pin::new(&mut wait).poll(&mut cx).is_pending());
+        assert_eq!(barrier.wait(), Err(BarrierWaitError::Interrupted));
+        let other = barrier.clone();
+        thread::spawn(move || other.wait().unwrap()).join().unwrap();
+        let result = std::pin::Pin::new(&mut wait).poll(&mut cx);
+        assert!(matches!(result, std::task::Poll::Ready(Ok(_))));
+
+        // and on a fair barrier a later arrival doesn't wait for an earlier one's turn
+        let barrier = CancellableBarrier::builder()
+            .parties(3)
+            .fair(true)
+            .build()
+            .unwrap();
+        let mut first = barrier.wait_async();
+        let mut second = barrier.wait_async();
+        assert!(std::pin::Pin::new(&mut first).poll(&mut cx).is_pending());
+        assert!(std::pin::Pin::new(&mut second).poll(&mut cx).is_pending());
+        barrier.wait().unwrap();
+        let result = std::pin::Pin::new(&mut second).poll(&mut cx);
+        assert!(matches!(result, std::task::Poll::Ready(Ok(_))));
+        let result = std::pin::Pin::new(&mut first).poll(&mut cx);
+        assert!(matches!(result, std::task::Poll::Ready(Ok(_))));
+    }
+
+    #[test]
+    fn test_into_async_shares_the_barrier() {
+        let barrier = CancellableBarrier::new(2);
+        let handle = barrier.clone().into_async();
+
+        // a thread and a task are parties of the same generation
+        let task = {
+            let handle = handle.clone();
+            thread::spawn(move || block_on(handle.wait()))
+        };
+        assert!(barrier.wait().is_ok());
+        assert_eq!(task.join().unwrap().map(|r| r.generation()), Ok(0));
+
+        // a cancel on the sync side fails the awaiter
+        let task = {
+            let handle = handle.clone();
+            thread::spawn(move || block_on(handle.wait()))
+        };
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        barrier.cancel_with(CancelReason::Shutdown);
+        assert_eq!(
+            task.join().unwrap().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Shutdown)
+        );
+
+        // and the other way round
+        barrier.reset();
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait())
+        };
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(handle.cancel(), 1);
+        assert!(waiter.join().unwrap().is_err());
+        assert!(Arc::ptr_eq(&handle.into_sync(), &barrier));
+    }
+
+    #[test]
+    fn test_cancelled_future() {
+        let barrier = CancellableBarrier::new(2);
+        let counter = Arc::new(CountingWaker::default());
+        let waker = counter.clone().into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut supervisors: Vec<_> = (0..3).map(|_| Box::pin(barrier.cancelled())).collect();
+        for supervisor in &mut supervisors {
+            assert!(supervisor.as_mut().poll(&mut cx).is_pending());
+        }
+
+        // releases wake the supervisors without resolving their futures
+        let barrier_clone = barrier.clone();
+        let waiter = thread::spawn(move || barrier_clone.wait());
+        assert!(barrier.wait().is_ok());
+        assert!(waiter.join().unwrap().is_ok());
+        assert_eq!(counter.0.load(Ordering::SeqCst), 3);
+        for supervisor in &mut supervisors {
+            assert!(supervisor.as_mut().poll(&mut cx).is_pending());
+        }
+
+        barrier.cancel();
+        assert_eq!(counter.0.load(Ordering::SeqCst), 6);
+        for supervisor in &mut supervisors {
+            assert!(supervisor.as_mut().poll(&mut cx).is_ready());
+        }
+        block_on(barrier.cancelled());
+    }
+
+    #[test]
+    fn test_cancelled_future_sees_reset_cancellation() {
+        let barrier = CancellableBarrier::new(2);
+        let waker = Arc::new(NoopWaker).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut cancelled = barrier.cancelled();
+        assert!(std::pin::Pin::new(&mut cancelled)
+            .poll(&mut cx)
+            .is_pending());
+        assert_eq!(barrier.lock().wakers.len(), 1);
+
+        barrier.cancel();
+        barrier.reset();
+        assert!(std::pin::Pin::new(&mut cancelled).poll(&mut cx).is_ready());
+
+        let mut dropped = barrier.cancelled();
+        assert!(std::pin::Pin::new(&mut dropped).poll(&mut cx).is_pending());
+        drop(dropped);
+        assert!(barrier.lock().wakers.is_empty());
+    }
+
+    #[test]
+    fn test_one_leader_per_generation() {
+        let results = CancellableBarrier::scope(5, |_, barrier| {
+            (0..10).map(|_| barrier.wait().unwrap()).collect::<Vec<_>>()
+        });
+
+        for generation in 0..10 {
+            let leaders = results
+                .iter()
+                .filter(|results| results[generation].is_leader())
+                .count();
+            assert_eq!(leaders, 1);
+            assert!(results
+                .iter()
+                .all(|results| results[generation].generation() == generation));
+        }
+    }
+
+    #[test]
+    fn test_scope_cancel_from_closure() {
+        let results = CancellableBarrier::scope(4, |i, barrier| {
+            if i == 0 {
+                barrier.cancel_with(CancelReason::Shutdown);
+            }
+            barrier.wait()
+        });
+        assert_eq!(results.len(), 4);
+        // everyone who hadn't been released yet sees the cancellation
+        assert!(results.iter().all(|r| r
+            .as_ref()
+            .is_err_and(|e| e.cancel_reason() == Some(&CancelReason::Shutdown))));
+    }
+
+    #[test]
+    #[should_panic(expected = "party 2 failed")]
+    fn test_scope_propagates_panic() {
+        CancellableBarrier::scope(3, |i, barrier| {
+            if i == 2 {
+                panic!("party {i} failed");
+            }
+            // released by the cancellation rather than hanging
+            assert_eq!(
+                barrier.wait().unwrap_err().cancel_reason(),
+                Some(&CancelReason::Panicked)
+            );
+        });
+    }
+
+    #[test]
+    fn test_wait_then_orders_leader_work() {
+        let setup = std::sync::Arc::new(AtomicUsize::new(0));
+        let results = CancellableBarrier::scope(4, |_, barrier| {
+            let setup = setup.clone();
+            let result = barrier
+                .wait_then(|| {
+                    // give the others every chance to run ahead of the work
+                    thread::sleep(Duration::from_millis(20));
+                    setup.fetch_add(1, Ordering::Relaxed);
+                })
+                .unwrap();
+            // the leader's work happened before anyone got here
+            (result.is_leader(), setup.load(Ordering::Relaxed))
+        });
+        assert_eq!(results.iter().filter(|(leader, _)| *leader).count(), 1);
+        assert!(results.iter().all(|&(_, setup)| setup == 1));
+    }
+
+    #[test]
+    fn test_cancel_returns_arrived() {
+        let barrier = CancellableBarrier::new(3);
+        assert_eq!(barrier.cancel(), 0);
+        barrier.reset();
+
+        let barrier_clone = barrier.clone();
+        let waiter = thread::spawn(move || barrier_clone.wait());
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(barrier.cancel_with(CancelReason::Shutdown), 1);
+        assert!(waiter.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_uncancel() {
+        let barrier = CancellableBarrier::new(2);
+        barrier.cancel();
+        assert!(barrier.uncancel());
+        assert_eq!(
+            barrier.state(),
+            BarrierSnapshot {
+                arrived: 0,
+                total: 2,
+                generation: 0,
+                cancelled: false
+            }
+        );
+
+        let barrier_clone = barrier.clone();
+        let waiter = thread::spawn(move || barrier_clone.wait());
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        barrier.cancel();
+        assert!(waiter.join().unwrap().is_err());
+        // the cancelled waiter is still counted
+        assert!(!barrier.uncancel());
+        assert!(barrier.is_cancelled());
+
+        barrier.reset();
+        barrier.cancel();
+        assert!(barrier.uncancel());
+
+        // nothing of a cancellation outlives it
+        let payload = Arc::new(());
+        barrier.cancel_with_payload(payload.clone());
+        assert!(barrier.uncancel());
+        assert_eq!(Arc::strong_count(&payload), 1);
+        barrier.cancel_with(CancelReason::Error);
+        let Err(BarrierWaitError::Cancelled(cancelled)) = barrier.try_wait() else {
+            panic!("expected a cancellation");
+        };
+        assert!(cancelled.payload::<Arc<()>>().is_none());
+        assert!(barrier.uncancel());
+        let barrier_clone = barrier.clone();
+        let waiter = thread::spawn(move || barrier_clone.wait());
+        assert!(barrier.wait().is_ok());
+        assert!(waiter.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_cancel_with_payload() {
+        #[derive(Debug, PartialEq)]
+        struct DiskFull {
+            device: String,
+        }
+
+        let barrier = CancellableBarrier::new(2);
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait())
+        };
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        let full = DiskFull {
+            device: "sda".to_string(),
+        };
+        assert_eq!(barrier.cancel_with_payload(full), 1);
+
+        let Err(BarrierWaitError::Cancelled(cancelled)) = waiter.join().unwrap() else {
+            panic!("expected a cancellation");
+        };
+        assert_eq!(cancelled.reason(), &CancelReason::Error);
+        assert_eq!(
+            cancelled
+                .payload::<DiskFull>()
+                .map(|full| full.device.as_str()),
+            Some("sda")
+        );
+        assert_eq!(cancelled.payload::<String>(), None);
+        assert!(cancelled.has_payload());
+
+        // errors from the same cancellation are equal, and one without the payload isn't
+        let Err(BarrierWaitError::Cancelled(again)) = barrier.wait() else {
+            panic!("expected a cancellation");
+        };
+        assert_eq!(again, cancelled);
+        assert_ne!(cancelled, CancelledBarrier::new(CancelReason::Error));
+
+        // a later cancellation without one drops it
+        barrier.cancel_with(CancelReason::Error);
+        let Err(BarrierWaitError::Cancelled(cancelled)) = barrier.wait() else {
+            panic!("expected a cancellation");
+        };
+        assert_eq!(cancelled.payload::<DiskFull>(), None);
+        assert!(!cancelled.has_payload());
+        assert_eq!(cancelled, CancelledBarrier::new(CancelReason::Error));
+    }
+
+    #[test]
+    fn test_cancel_reports_arrivals() {
+        let barrier = CancellableBarrier::builder()
+            .parties(3)
+            .track_threads(true)
+            .build()
+            .unwrap();
+
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || (thread::current().id(), barrier.wait()))
+            })
+            .collect();
+        while barrier.state().arrived < 2 {
+            thread::yield_now();
+        }
+        barrier.cancel();
+
+        for waiter in waiters {
+            let (id, result) = waiter.join().unwrap();
+            let Err(BarrierWaitError::Cancelled(cancelled)) = result else {
+                panic!("expected a cancellation, got {result:?}");
+            };
+            assert_eq!(cancelled.arrived_count(), Some(2));
+            #[cfg(feature = "diagnostics")]
+            {
+                assert_eq!(cancelled.arrived_threads().len(), 2);
+                assert!(cancelled.arrived_threads().iter().any(|t| t.id() == id));
+            }
+            #[cfg(not(feature = "diagnostics"))]
+            let _ = id;
+        }
+    }
+
+    #[test]
+    fn test_max_generations() {
+        let barrier = CancellableBarrier::builder()
+            .max_generations(2)
+            .build()
+            .unwrap();
+        assert_eq!(barrier.wait().map(|r| r.generation()), Ok(0));
+        assert_eq!(barrier.try_wait(), Ok(Some(1)));
+
+        // the next arrival cancels instead of starting a third generation
+        let reason = Some(&CancelReason::MaxGenerationsReached);
+        assert_eq!(barrier.wait().unwrap_err().cancel_reason(), reason);
+        assert_eq!(barrier.cancel_reason(), reason.cloned());
+
+        // and a reset doesn't lift the cap
+        barrier.reset();
+        assert_eq!(
+            block_on(barrier.wait_async()).unwrap_err().cancel_reason(),
+            reason
+        );
+        assert_eq!(barrier.state().generation, 3);
+    }
+
+    #[test]
+    fn test_break_on_give_up() {
+        let barrier = CancellableBarrier::builder()
+            .parties(3)
+            .break_on_give_up(true)
+            .build()
+            .unwrap();
+
+        let barrier_clone = barrier.clone();
+        let waiter = thread::spawn(move || barrier_clone.wait());
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(
+            barrier.wait_timeout(Duration::from_millis(10)),
+            Err(BarrierWaitError::timed_out(2, 1))
+        );
+
+        // the other waiter and everyone after fail until the barrier is reset
+        let broken = Err(cancelled(CancelReason::Broken));
+        assert_eq!(waiter.join().unwrap(), broken);
+        assert_eq!(barrier.wait(), broken);
+        barrier.reset();
+        barrier.resize(1).unwrap();
+        assert!(barrier.wait().unwrap().is_leader());
+    }
+
+    #[test]
+    fn test_custom_sync_backend() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+        static LOCKS: AtomicUsize = AtomicUsize::new(0);
+
+        /// std's lock, counting how often it's taken
+        enum Counting {}
+
+        impl RawSync for Counting {
+            type Mutex<T: Send + 'static> = Mutex<T>;
+            type Guard<'a, T: Send + 'static> = MutexGuard<'a, T>;
+            type Condvar = Condvar;
+
+            fn new_mutex<T: Send + 'static>(value: T) -> Mutex<T> {
+                Mutex::new(value)
+            }
+
+            fn lock<T: Send + 'static>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+                LOCKS.fetch_add(1, Ordering::Relaxed);
+                mutex.lock().unwrap_or_else(PoisonError::into_inner)
+            }
+
+            fn new_condvar() -> Condvar {
+                Condvar::new()
+            }
+
+            fn wait<'a, T: Send + 'static>(
+                condvar: &Condvar,
+                guard: Self::Guard<'a, T>,
+            ) -> Self::Guard<'a, T> {
+                condvar.wait(guard).unwrap_or_else(PoisonError::into_inner)
+            }
+
+            fn wait_timeout<'a, T: Send + 'static>(
+                condvar: &Condvar,
+                guard: Self::Guard<'a, T>,
+                timeout: Duration,
+            ) -> Self::Guard<'a, T> {
+                condvar
+                    .wait_timeout(guard, timeout)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0
+            }
+
+            fn notify_all(condvar: &Condvar) {
+                condvar.notify_all();
+            }
+        }
+
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .build_with::<Counting>()
+            .unwrap();
+        let barrier_clone = barrier.clone();
+        let waiter = thread::spawn(move || barrier_clone.wait());
+        let leaders = usize::from(barrier.wait().unwrap().is_leader())
+            + usize::from(waiter.join().unwrap().unwrap().is_leader());
+        assert_eq!(leaders, 1);
+        assert!(LOCKS.load(Ordering::Relaxed) >= 2);
+
+        barrier.cancel();
+        assert_eq!(barrier.wait(), Err(cancelled(CancelReason::Unspecified)));
+    }
+
+    #[test]
+    fn test_wait_role() {
+        let roles = CancellableBarrier::scope(3, |_, barrier| barrier.wait_role().unwrap());
+        let leaders = roles
+            .iter()
+            .filter(|role| matches!(role, BarrierRole::Leader(0)))
+            .count();
+        assert_eq!(leaders, 1);
+        assert!(roles.iter().all(|role| role.generation() == 0));
+    }
+
+    #[test]
+    fn test_wait_generation() {
+        let barrier = CancellableBarrier::new(2);
+        assert_eq!(barrier.wait_generation(0), Ok(()));
+
+        let barrier_clone1 = barrier.clone();
+        let observer = thread::spawn(move || barrier_clone1.wait_generation(2));
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || {
+            barrier_clone1.wait().unwrap();
+            barrier_clone1.wait().unwrap();
+        });
+        barrier.wait().unwrap();
+        // the observer didn't count, and one generation isn't enough
+        assert!(!observer.is_finished());
+        barrier.wait().unwrap();
+        t1.join().unwrap();
+        assert_eq!(observer.join().unwrap(), Ok(()));
+
+        let barrier_clone1 = barrier.clone();
+        let observer = thread::spawn(move || barrier_clone1.wait_generation(10));
+        barrier.cancel_with(CancelReason::Shutdown);
+        assert_eq!(
+            observer.join().unwrap(),
+            Err(cancelled(CancelReason::Shutdown))
+        );
+        assert_eq!(barrier.wait_generation(2), Ok(()));
+    }
+
+    #[test]
+    fn test_generation_wraps_around() {
+        let barrier = CancellableBarrier::new(2);
+        {
+            // as if reset that many times, which keeps the books straight
+            let mut state = barrier.lock();
+            state.generation = usize::MAX - 1;
+            state.resets = usize::MAX - 1;
+        }
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || {
+            (0..4)
+                .map(|_| barrier_clone1.wait().unwrap().generation())
+                .collect::<Vec<_>>()
+        });
+        let observer = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait_generation(1))
+        };
+        let ours: Vec<_> = (0..4)
+            .map(|_| barrier.wait().unwrap().generation())
+            .collect();
+        let expected = [usize::MAX - 1, usize::MAX, 0, 1];
+        assert_eq!(ours, expected);
+        assert_eq!(t1.join().unwrap(), expected);
+        assert_eq!(observer.join().unwrap(), Ok(()));
+        assert_eq!(barrier.state().generation, 2);
+        assert_eq!(barrier.wait_generation(usize::MAX), Ok(()));
+    }
+
+    #[test]
+    fn test_manual_barrier_trips() {
+        let gate = CancellableBarrier::manual();
+        let wait_all = |n: usize| -> Vec<_> {
+            let handles: Vec<_> = (0..n)
+                .map(|_| {
+                    let gate = gate.clone();
+                    thread::spawn(move || gate.wait())
+                })
+                .collect();
+            while gate.state().arrived < n {
+                thread::yield_now();
+            }
+            // however many arrive, nobody is let through until the trip
+            while gate.lock().parked < n {
+                thread::yield_now();
+            }
+            assert!(handles.iter().all(|h| !h.is_finished()));
+            gate.trip();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        };
+
+        for result in wait_all(4) {
+            let result = result.unwrap();
+            assert_eq!(result.generation(), 0);
+            assert!(!result.is_leader());
+        }
+        assert_eq!(gate.try_wait(), Ok(None));
+        assert_eq!(wait_all(2).len(), 2);
+
+        // a cancelled gate fails every wait until it is reset
+        gate.cancel();
+        assert!(gate.wait().is_err());
+        gate.reset();
+        assert!(wait_all(1)[0].is_ok());
+        assert_eq!(gate.state().total, 0);
+
+        // a reset lets the waiting threads go, and the gate stays manual
+        let generation = gate.state().generation;
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let gate = gate.clone();
+                thread::spawn(move || gate.wait())
+            })
+            .collect();
+        while gate.state().arrived < 2 {
+            thread::yield_now();
+        }
+        gate.reset();
+        for waiter in waiters {
+            let result = waiter.join().unwrap().unwrap();
+            assert_eq!(result.generation(), generation);
+            assert!(!result.is_leader());
+        }
+        assert_eq!(gate.state().total, 0);
+        assert_eq!(wait_all(1).len(), 1);
+    }
+
+    #[test]
+    fn test_force_release() {
+        let barrier = CancellableBarrier::new(3);
+        // nothing to release yet
+        barrier.force_release();
+        assert_eq!(barrier.state().generation, 0);
+
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait())
+            })
+            .collect();
+        while barrier.state().arrived < 2 {
+            thread::yield_now();
+        }
+        barrier.force_release();
+        for handle in handles {
+            let result = handle.join().unwrap().unwrap();
+            assert_eq!(result.generation(), 0);
+            assert!(!result.is_leader());
+        }
+
+        // the next generation still needs all three
+        let state = barrier.state();
+        assert_eq!((state.arrived, state.total, state.generation), (0, 3, 1));
+        assert_eq!(
+            barrier.wait_timeout(Duration::from_millis(20)),
+            Err(BarrierWaitError::timed_out(1, 2))
+        );
+    }
+
+    #[test]
+    fn test_guard_panic_cancels() {
+        let barrier = CancellableBarrier::new(3);
+
+        let survivors: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait())
+            })
+            .collect();
+        while barrier.state().arrived < 2 {
+            thread::yield_now();
+        }
+
+        let barrier_clone = barrier.clone();
+        let panicker = thread::spawn(move || {
+            let _guard = barrier_clone.wait_guarded().unwrap();
+            panic!("worker failed before arriving");
+        });
+        assert!(panicker.join().is_err());
+
+        for survivor in survivors {
+            assert_eq!(
+                survivor.join().unwrap(),
+                Err(cancelled(CancelReason::Panicked))
+            );
+        }
+    }
+
+    #[test]
+    fn test_guard_wait() {
+        let barrier = CancellableBarrier::new(2);
+        let barrier_clone1 = barrier.clone();
+
+        let t1 = thread::spawn(move || barrier_clone1.wait_guarded().unwrap().wait());
+        let guard = barrier.wait_guarded().unwrap();
+        assert_eq!(guard.wait().map(|r| r.generation()), Ok(0));
+        assert_eq!(t1.join().unwrap().map(|r| r.generation()), Ok(0));
+        assert!(!barrier.is_cancelled());
+    }
+
+    #[cfg(not(any(feature = "spin", feature = "parking_lot")))]
+    #[test]
+    fn test_survives_poisoned_lock() {
+        let barrier = CancellableBarrier::new(2);
+
+        let barrier_clone1 = barrier.clone();
+        let poisoner = thread::spawn(move || {
+            let _state = barrier_clone1.mutex.lock().unwrap();
+            panic!("panic while holding the barrier lock");
+        });
+        assert!(poisoner.join().is_err());
+        assert!(barrier.mutex.is_poisoned());
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        assert_eq!(barrier.wait().map(|r| r.generation()), Ok(0));
+        assert_eq!(t1.join().unwrap().map(|r| r.generation()), Ok(0));
+
+        barrier.cancel();
+        assert!(barrier.is_cancelled());
+        barrier.reset();
+        assert!(!barrier.is_cancelled());
+    }
+
+    #[test]
+    fn test_backpressure_one_generation_ahead() {
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .backpressure(true)
+            .build()
+            .unwrap();
+        let producer = {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                (0..3)
+                    .map(|_| barrier.wait().unwrap().generation())
+                    .collect::<Vec<_>>()
+            })
+        };
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        assert!(barrier.wait().unwrap().is_leader());
+
+        // while we process generation 0 the producer can't start counting generation 1
+        let state = barrier.state();
+        assert_eq!((state.arrived, state.generation), (0, 1));
+        assert_eq!(barrier.try_wait(), Ok(None));
+        barrier.mark_processed();
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        assert!(barrier.wait().unwrap().is_leader());
+
+        // coming back to wait again is as good as marking the generation processed
+        assert_eq!(barrier.state().arrived, 0);
+        assert_eq!(barrier.wait().unwrap().generation(), 2);
+        assert_eq!(producer.join().unwrap(), vec![0, 1, 2]);
+
+        // an async arrival is held back as well, here until the barrier is cancelled
+        let cancelled_wait = {
+            let barrier = barrier.clone();
+            thread::spawn(move || block_on(barrier.wait_async()))
+        };
+        assert_eq!(barrier.state().arrived, 0);
+        barrier.cancel();
+        assert_eq!(
+            cancelled_wait.join().unwrap(),
+            Err(cancelled(CancelReason::Unspecified))
+        );
+    }
+
+    #[test]
+    fn test_first_arrival() {
+        let barrier = CancellableBarrier::new(2);
+        for generation in 0..2 {
+            let first = {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait())
+            };
+            while barrier.state().arrived == 0 {
+                thread::yield_now();
+            }
+            let last = barrier.wait().unwrap();
+            let first = first.join().unwrap().unwrap();
+            assert_eq!(first.generation(), generation);
+            assert!(first.is_first_arrival() && !first.is_leader());
+            assert!(!last.is_first_arrival() && last.is_leader());
+        }
+
+        // a lone party both starts and completes its generation
+        let single = CancellableBarrier::new(1);
+        let result = single.wait().unwrap();
+        assert!(result.is_first_arrival() && result.is_leader());
+    }
+
+    #[test]
+    fn test_barrier_action() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let runs = std::sync::Arc::new(AtomicUsize::new(0));
+        let completed = std::sync::Arc::new(AtomicUsize::new(0));
+        let (runs_clone, completed_clone) = (runs.clone(), completed.clone());
+        let barrier = CancellableBarrier::with_action(2, move |generation| {
+            runs_clone.fetch_add(1, Ordering::SeqCst);
+            completed_clone.store(generation, Ordering::SeqCst);
+        });
+
+        for generation in 0..3 {
+            let barrier_clone1 = barrier.clone();
+            let runs_clone = runs.clone();
+            let t1 = thread::spawn(move || {
+                barrier_clone1.wait().unwrap();
+                runs_clone.load(Ordering::SeqCst)
+            });
+            barrier.wait().unwrap();
+            // the action has run by the time anyone is released
+            assert_eq!(t1.join().unwrap(), generation + 1);
+            assert_eq!(completed.load(Ordering::SeqCst), generation);
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_panicking_action_still_releases() {
+        let barrier = CancellableBarrier::with_action(2, |_| panic!("action failed"));
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        let barrier_clone2 = barrier.clone();
+        let leader = thread::spawn(move || barrier_clone2.wait());
+
+        assert!(leader.join().is_err());
+        assert_eq!(t1.join().unwrap().map(|r| r.generation()), Ok(0));
+        assert_eq!(barrier.state().generation, 1);
+        assert!(!barrier.is_cancelled());
+    }
+
+    #[test]
+    fn test_callback_panic_policies() {
+        let run = |policy| {
+            let barrier = CancellableBarrier::builder()
+                .parties(2)
+                .on_callback_panic(policy)
+                .build()
+                .unwrap();
+            barrier.add_release_listener(|_| panic!("listener failed"));
+            let cancelled = Arc::new(Mutex::new(None));
+            let cancelled_clone = cancelled.clone();
+            barrier.on_cancel(move |reason| *cancelled_clone.lock().unwrap() = Some(reason));
+
+            let barrier_clone = barrier.clone();
+            let waiter = thread::spawn(move || barrier_clone.wait());
+            while barrier.state().arrived < 1 {
+                thread::yield_now();
+            }
+            let leader = panic::catch_unwind(AssertUnwindSafe(|| barrier.wait()));
+            let cancelled = cancelled.lock().unwrap().clone();
+            (
+                barrier,
+                leader.map_err(|_| ()),
+                waiter.join().unwrap(),
+                cancelled,
+            )
+        };
+
+        // the panic is dropped and everyone is released
+        let (barrier, leader, waiter, cancelled) = run(CallbackPanicPolicy::Ignore);
+        assert!(leader.unwrap().unwrap().is_leader());
+        assert_eq!(waiter.map(|r| r.generation()), Ok(0));
+        assert_eq!((barrier.is_cancelled(), cancelled), (false, None));
+
+        let (barrier, leader, waiter, cancelled) = run(CallbackPanicPolicy::Cancel);
+        assert!(leader.is_err());
+        assert_eq!(
+            waiter.unwrap_err().cancel_reason(),
+            Some(&CancelReason::CallbackPanicked)
+        );
+        assert_eq!(cancelled, Some(CancelReason::CallbackPanicked));
+        assert_eq!(barrier.state().generation, 0);
+        barrier.reset();
+        assert!(!barrier.is_cancelled());
+
+        let (barrier, leader, waiter, cancelled) = run(CallbackPanicPolicy::Abort);
+        assert!(leader.is_err());
+        assert_eq!(waiter, Err(BarrierWaitError::Aborted));
+        assert_eq!(cancelled, Some(CancelReason::CallbackPanicked));
+        barrier.reset();
+        assert!(barrier.is_aborted());
+    }
+
+    #[test]
+    fn test_arrival_listeners() {
+        let barrier = CancellableBarrier::new(3);
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let listener = barrier.add_arrival_listener(move |count, total| {
+            tx.lock().unwrap().send((count, total)).unwrap();
+        });
+        barrier.add_arrival_listener(|_, _| panic!("listener failed"));
+
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait())
+            })
+            .collect();
+        // the panicking listener doesn't cost anyone their arrival
+        assert_eq!(rx.recv().unwrap(), (1, 3));
+        assert_eq!(rx.recv().unwrap(), (2, 3));
+        assert!(barrier.wait().is_ok());
+        assert_eq!(rx.recv().unwrap(), (3, 3));
+        for waiter in waiters {
+            assert!(waiter.join().unwrap().is_ok());
+        }
+
+        assert!(barrier.remove_arrival_listener(listener));
+        assert!(!barrier.remove_arrival_listener(listener));
+        assert_eq!(barrier.try_wait(), Ok(None));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, feature = "reentrancy"))]
+    fn test_reentrant_wait_panics() {
+        let barrier = CancellableBarrier::builder()
+            .parties(1)
+            .name("reentrant")
+            .build()
+            .unwrap();
+        let barrier_clone = barrier.clone();
+        let listener = barrier.add_release_listener(move |_| {
+            let _ = barrier_clone.wait();
+        });
+
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| barrier.wait())).unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(
+            message.contains("re-entered a wait on barrier reentrant"),
+            "{message}"
+        );
+
+        // leaving the wait by unwinding clears the mark
+        barrier.remove_release_listener(listener);
+        assert!(barrier.wait().unwrap().is_leader());
+    }
+
+    #[test]
+    fn test_release_listeners() {
+        let calls = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let calls_clone = calls.clone();
+        let barrier = CancellableBarrier::with_action(1, move |generation| {
+            calls_clone.lock().unwrap().push(("action", generation));
+        });
+        let listeners: Vec<_> = ["first", "second"]
+            .into_iter()
+            .map(|name| {
+                let calls = calls.clone();
+                barrier.add_release_listener(move |generation| {
+                    calls.lock().unwrap().push((name, generation));
+                })
+            })
+            .collect();
+
+        barrier.wait().unwrap();
+        assert!(barrier.remove_release_listener(listeners[0]));
+        assert!(!barrier.remove_release_listener(listeners[0]));
+        barrier.wait().unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                ("action", 0),
+                ("first", 0),
+                ("second", 0),
+                ("action", 1),
+                ("second", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_on_next_release_fires_once() {
+        let barrier = CancellableBarrier::new(1);
+        let (tx, rx) = mpsc::channel();
+        barrier.on_next_release(move |generation| tx.send(generation).unwrap());
+
+        barrier.wait().unwrap();
+        assert_eq!(rx.try_recv(), Ok(0));
+        barrier.wait().unwrap();
+        // forgotten once it has run, dropping the sender with it
+        assert_eq!(rx.try_recv(), Err(mpsc::TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn test_panicking_release_listener() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let barrier = CancellableBarrier::new(1);
+        let runs = std::sync::Arc::new(AtomicUsize::new(0));
+        barrier.add_release_listener(|_| panic!("listener failed"));
+        let runs_clone = runs.clone();
+        barrier.add_release_listener(move |_| {
+            runs_clone.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let barrier_clone = barrier.clone();
+        assert!(thread::spawn(move || barrier_clone.wait()).join().is_err());
+        // the later listener ran all the same, and the generation was released
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(barrier.state().generation, 1);
+    }
+
+    #[test]
+    fn test_wait_spin() {
+        let barrier = CancellableBarrier::new(2);
+
+        for spins in [0, 10, 1_000_000] {
+            let barrier_clone1 = barrier.clone();
+            let t1 = thread::spawn(move || barrier_clone1.wait_spin(spins));
+            barrier.wait_spin(spins).unwrap();
+            t1.join().unwrap().unwrap();
+        }
+
+        // a spinning thread sees the cancellation
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait_spin(u32::MAX));
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        barrier.cancel_with(CancelReason::Shutdown);
+        assert_eq!(t1.join().unwrap(), Err(cancelled(CancelReason::Shutdown)));
+    }
+
+    #[test]
+    fn test_arrival_without_the_lock() {
+        let barrier = CancellableBarrier::new(3);
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        // without closing the window as `lock` would
+        let mut state = barrier.mutex.lock().unwrap();
+        while state.parked < 1 {
+            drop(state);
+            thread::yield_now();
+            state = barrier.mutex.lock().unwrap();
+        }
+
+        // with a thread parked, the next arrival doesn't need the lock
+        let barrier_clone2 = barrier.clone();
+        let t2 = thread::spawn(move || barrier_clone2.wait_spin(u32::MAX));
+        let window = barrier.window.as_ref().unwrap();
+        while window.counted() == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(state.count, 1);
+        barrier.close_window(&mut state);
+        assert_eq!(state.count, 2);
+        drop(state);
+
+        // the last arrival takes the lock and releases both
+        assert!(barrier.wait().unwrap().is_leader());
+        assert_eq!(t1.join().unwrap().map(|r| r.generation()), Ok(0));
+        assert_eq!(t2.join().unwrap().map(|r| r.generation()), Ok(0));
+        assert_eq!(barrier.state().arrived, 0);
+    }
+
+    #[test]
+    fn test_arrival_without_the_lock_rolls_back() {
+        let clock = Arc::new(MockClock::new());
+        let barrier = CancellableBarrier::builder()
+            .parties(3)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait_timeout(TEST_SLEEP_DURATION * 2));
+        while barrier.lock().parked < 1 {
+            thread::yield_now();
+        }
+        let barrier_clone2 = barrier.clone();
+        let t2 = thread::spawn(move || barrier_clone2.wait_timeout(TEST_SLEEP_DURATION));
+        while barrier.state().arrived < 2 {
+            thread::yield_now();
+        }
+
+        // the second arrival gives its slot back when it times out, before the first
+        clock.advance(TEST_SLEEP_DURATION);
+        assert_eq!(t2.join().unwrap(), Err(BarrierWaitError::timed_out(2, 1)));
+        assert_eq!(barrier.state().arrived, 1);
+        clock.advance(TEST_SLEEP_DURATION);
+        assert_eq!(t1.join().unwrap(), Err(BarrierWaitError::timed_out(1, 2)));
+        assert_eq!(barrier.state().arrived, 0);
+    }
+
+    #[test]
+    fn test_arrivals_without_the_lock_under_contention() {
+        const THREADS: usize = 16;
+        const ROUNDS: usize = 100;
+        for backoff in [
+            BackoffConfig::default(),
+            BackoffConfig {
+                spins: 8,
+                yields: 4,
+            },
+        ] {
+            let barrier = CancellableBarrier::builder()
+                .parties(THREADS)
+                .backoff(backoff)
+                .build()
+                .unwrap();
+            let handles: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    let barrier = barrier.clone();
+                    thread::spawn(move || {
+                        (0..ROUNDS)
+                            .map(|round| {
+                                let result = barrier.wait().unwrap();
+                                assert_eq!(result.generation(), round);
+                                usize::from(result.is_leader())
+                            })
+                            .sum::<usize>()
+                    })
+                })
+                .collect();
+            let leaders: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+            assert_eq!(leaders, ROUNDS);
+            assert_eq!(barrier.state().arrived, 0);
+            assert_eq!(barrier.state().generation, ROUNDS);
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .backoff(BackoffConfig {
+                spins: 20,
+                yields: 100,
+            })
+            .build()
+            .unwrap();
+
+        for _ in 0..10 {
+            let barrier_clone1 = barrier.clone();
+            let t1 = thread::spawn(move || barrier_clone1.wait());
+            barrier.wait().unwrap();
+            t1.join().unwrap().unwrap();
+        }
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        barrier.cancel();
+        assert_eq!(
+            t1.join().unwrap(),
+            Err(cancelled(CancelReason::Unspecified))
+        );
+    }
+
+    #[test]
+    fn test_wait_until_signal() {
+        use std::sync::atomic::AtomicBool;
+
+        let barrier = CancellableBarrier::new(2);
+        let shutdown = std::sync::Arc::new(AtomicBool::new(false));
+
+        let barrier_clone1 = barrier.clone();
+        let shutdown_clone = shutdown.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait_until(&shutdown_clone));
+        thread::sleep(TEST_SLEEP_DURATION);
+        assert_eq!(barrier.state().arrived, 1);
+        shutdown.store(true, Ordering::Release);
+        assert_eq!(t1.join().unwrap(), Err(BarrierWaitError::Interrupted));
+
+        // only the waiter gave up, the barrier carries on
+        assert!(!barrier.is_cancelled());
+        assert_eq!(barrier.state().arrived, 0);
+        assert_eq!(
+            barrier.wait_until(&shutdown),
+            Err(BarrierWaitError::Interrupted)
+        );
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait_until(&|| false));
+        barrier.wait().unwrap();
+        t1.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_shutdown_drains_waiters() {
+        let barrier = CancellableBarrier::new(4);
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait())
+            })
+            .collect();
+        let mut pending = Box::pin(barrier.wait_async());
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        assert!(pending.as_mut().poll(&mut cx).is_pending());
+        while barrier.lock().parked < 3 {
+            thread::yield_now();
+        }
+
+        // the parked task never gets polled again, so it can't drain
+        assert!(!barrier.shutdown(TEST_SLEEP_DURATION));
+        for handle in handles {
+            assert_eq!(
+                handle.join().unwrap(),
+                Err(cancelled(CancelReason::Shutdown))
+            );
+        }
+        assert_eq!(barrier.wait(), Err(BarrierWaitError::Aborted));
+        assert_eq!(
+            pending.as_mut().poll(&mut cx),
+            std::task::Poll::Ready(Err(BarrierWaitError::Aborted))
+        );
+        assert!(barrier.shutdown(TEST_SLEEP_DURATION));
+    }
+
+    #[test]
+    fn test_reset_while_shutdown_drains() {
+        let barrier = CancellableBarrier::new(2);
+        let waker = Arc::new(NoopWaker).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut pending = Box::pin(barrier.wait_async());
+        assert!(pending.as_mut().poll(&mut cx).is_pending());
+        let shutdown = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.shutdown(Duration::from_secs(10)))
+        };
+        while !barrier.is_cancelled() {
+            thread::yield_now();
+        }
+
+        // the reset lets the task go, but doesn't get the barrier out of shutting down
+        barrier.reset();
+        assert!(pending.as_mut().poll(&mut cx).is_ready());
+        assert!(shutdown.join().unwrap());
+        assert!(barrier.is_aborted());
+        assert_eq!(barrier.cancel_reason(), Some(CancelReason::Shutdown));
+        assert_eq!(barrier.try_wait(), Err(BarrierWaitError::Aborted));
+    }
+
+    #[test]
+    fn test_shutdown_without_waiters() {
+        let barrier = CancellableBarrier::new(2);
+        barrier.cancel_with(CancelReason::Error);
+        assert!(barrier.shutdown(Duration::ZERO));
+        assert!(barrier.is_aborted());
+        assert_eq!(barrier.cancel_reason(), Some(CancelReason::Error));
+    }
+
+    #[test]
+    fn test_interrupt_handle_targets_one_waiter() {
+        let barrier = CancellableBarrier::new(3);
+        let handle = barrier.interrupt_handle();
+
+        let barrier_clone1 = barrier.clone();
+        let handle_clone = handle.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait_interruptible(&handle_clone));
+        let barrier_clone2 = barrier.clone();
+        let t2 = thread::spawn(move || barrier_clone2.wait());
+        while barrier.lock().parked < 2 {
+            thread::yield_now();
+        }
+        handle.interrupt();
+        assert_eq!(t1.join().unwrap(), Err(BarrierWaitError::Interrupted));
+
+        // the other waiter is still there, and the interrupted arrival was rolled back
+        assert_eq!(barrier.lock().parked, 1);
+        assert!(!t2.is_finished());
+        assert_eq!(barrier.state().arrived, 1);
+        assert!(!barrier.is_cancelled());
+
+        // an interrupt with nobody waiting hits the next wait, and only that one
+        handle.interrupt();
+        assert_eq!(
+            barrier.wait_interruptible(&handle),
+            Err(BarrierWaitError::Interrupted)
+        );
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait_interruptible(&handle));
+        barrier.wait().unwrap();
+        assert!(t1.join().unwrap().is_ok());
+        assert!(t2.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_wait_uninterruptible() {
+        let clock = Arc::new(MockClock::new());
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .default_timeout(Duration::from_millis(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let handle = barrier.interrupt_handle();
+        handle.interrupt();
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait_uninterruptible());
+        while barrier.lock().parked < 1 {
+            thread::yield_now();
+        }
+        // neither the default timeout nor the interrupt got to it
+        clock.advance(Duration::from_secs(1));
+        assert!(!t1.is_finished());
+        assert!(barrier.wait_uninterruptible().is_ok());
+        assert!(t1.join().unwrap().is_ok());
+
+        // the interrupt is still there for the handle's next wait
+        assert_eq!(
+            barrier.wait_interruptible(&handle),
+            Err(BarrierWaitError::Interrupted)
+        );
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait_uninterruptible());
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        barrier.cancel();
+        assert_eq!(
+            t1.join().unwrap(),
+            Err(cancelled(CancelReason::Unspecified))
+        );
+    }
+
+    #[test]
+    fn test_wait_honours_interrupts_of_its_thread() {
+        let barrier = CancellableBarrier::new(2);
+        let barrier_clone1 = barrier.clone();
+        let (sender, receiver) = mpsc::channel();
+        let t1 = thread::spawn(move || {
+            sender.send(barrier_clone1.interrupt_handle()).unwrap();
+            barrier_clone1.wait()
+        });
+        let handle = receiver.recv().unwrap();
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        handle.interrupt();
+        assert_eq!(t1.join().unwrap(), Err(BarrierWaitError::Interrupted));
+        assert_eq!(barrier.state().arrived, 0);
+
+        // the waits of other threads aren't the handle's to interrupt
+        handle.interrupt();
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        barrier.wait().unwrap();
+        assert!(t1.join().unwrap().is_ok());
+
+        // nor is a wait that must not be interrupted, even with one party
+        let barrier = CancellableBarrier::new(1);
+        let handle = barrier.interrupt_handle();
+        handle.interrupt();
+        assert!(barrier.wait_uninterruptible().is_ok());
+        assert_eq!(barrier.wait(), Err(BarrierWaitError::Interrupted));
+        assert_eq!(barrier.state().generation, 1);
+        assert!(barrier.wait().is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "another barrier")]
+    fn test_interrupt_handle_of_another_barrier() {
+        let handle = CancellableBarrier::new(1).interrupt_handle();
+        let _ = CancellableBarrier::new(1).wait_interruptible(&handle);
+    }
+
+    #[test]
+    fn test_wait_while() {
+        use std::sync::atomic::AtomicBool;
+
+        let barrier = CancellableBarrier::new(2);
+        let ready = std::sync::Arc::new(AtomicBool::new(false));
+
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                let ready = ready.clone();
+                thread::spawn(move || barrier.wait_while(|| ready.load(Ordering::SeqCst)))
+            })
+            .collect();
+        // everyone is here, but the condition holds the release back
+        while barrier.lock().parked < 2 {
+            thread::yield_now();
+        }
+        assert_eq!(barrier.state().arrived, 2);
+        assert_eq!(barrier.state().generation, 0);
+
+        ready.store(true, Ordering::SeqCst);
+        let leaders = handles
+            .into_iter()
+            .map(|h| h.join().unwrap().unwrap())
+            .filter(|r| r.is_leader())
+            .count();
+        assert_eq!(leaders, 1);
+        assert_eq!(barrier.state().generation, 1);
+
+        // cancellation still gets through
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait_while(|| false));
+        let barrier_clone2 = barrier.clone();
+        let t2 = thread::spawn(move || barrier_clone2.wait_while(|| false));
+        while barrier.state().arrived < 2 {
+            thread::yield_now();
+        }
+        barrier.cancel();
+        assert!(t1.join().unwrap().is_err());
+        assert!(t2.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_observer() {
+        #[derive(Default)]
+        struct Recorder(std::sync::Mutex<Vec<String>>);
+
+        impl BarrierObserver for Recorder {
+            fn on_arrive(&self, generation: usize, count: usize, total: usize) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("arrive {generation} {count}/{total}"));
+            }
+
+            fn on_release(&self, generation: usize) {
+                self.0.lock().unwrap().push(format!("release {generation}"));
+            }
+
+            fn on_cancel(&self, reason: &CancelReason) {
+                self.0.lock().unwrap().push(format!("cancel {reason}"));
+            }
+        }
+
+        let recorder = std::sync::Arc::new(Recorder::default());
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .observer(recorder.clone())
+            .build()
+            .unwrap();
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        barrier.wait().unwrap();
+        t1.join().unwrap().unwrap();
+        barrier.cancel_with(CancelReason::Shutdown);
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "arrive 0 1/2",
+                "arrive 0 2/2",
+                "release 0",
+                "cancel shutdown"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_on_cancel() {
+        use std::sync::mpsc;
+
+        let barrier = CancellableBarrier::new(2);
+        let (tx, rx) = mpsc::channel();
+        for id in 0..2 {
+            let tx = std::sync::Mutex::new(tx.clone());
+            barrier.on_cancel(move |reason| tx.lock().unwrap().send((id, reason)).unwrap());
+        }
+
+        barrier.cancel_with(CancelReason::Shutdown);
+        assert_eq!(rx.try_recv(), Ok((0, CancelReason::Shutdown)));
+        assert_eq!(rx.try_recv(), Ok((1, CancelReason::Shutdown)));
+
+        barrier.reset();
+        assert!(rx.try_recv().is_err());
+
+        // listeners may use the barrier
+        let barrier_clone1 = barrier.clone();
+        barrier.on_cancel(move |_| assert!(barrier_clone1.is_cancelled()));
+        barrier.cancel();
+        assert_eq!(rx.try_recv(), Ok((0, CancelReason::Unspecified)));
+        assert_eq!(rx.try_recv(), Ok((1, CancelReason::Unspecified)));
+    }
+
+    #[test]
+    fn test_strict_rejects_too_many_parties() {
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .strict(true)
+            .build()
+            .unwrap();
+
+        // two threads parked on a barrier of two can only mean a third party: one left
+        // over from the released generation and one waiting on the next
+        let waker = Arc::new(NoopWaker).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut first = barrier.wait_async();
+        assert!(std::pin::Pin::new(&mut first).poll(&mut cx).is_pending());
+        assert!(barrier.try_wait().unwrap().is_some());
+        let mut second = barrier.wait_async();
+        assert!(std::pin::Pin::new(&mut second).poll(&mut cx).is_pending());
+        assert_eq!(barrier.wait(), Err(BarrierWaitError::TooManyParties));
+
+        // once the released party leaves, there's room again
+        assert!(block_on(first).is_ok());
+        assert_eq!(barrier.wait().map(|r| r.is_leader()), Ok(true));
+        assert!(block_on(second).is_ok());
+        assert_eq!(barrier.lock().parked, 0);
+    }
+
+    #[test]
+    fn test_named_barrier_debug() {
+        let barrier = CancellableBarrier::new_named(2, "render-sync");
+        assert_eq!(barrier.name(), Some("render-sync"));
+        assert_eq!(
+            format!("{barrier:?}"),
+            r#"CancellableBarrier { name: Some("render-sync"), state: 0/2 waiting (gen 0), .. }"#
+        );
+
+        let unnamed = CancellableBarrier::new(1);
+        assert_eq!(unnamed.name(), None);
+    }
+
+    #[test]
+    fn test_single_party_wait() {
+        let released = std::sync::Arc::new(AtomicUsize::new(0));
+        let barrier = {
+            let released = released.clone();
+            CancellableBarrier::with_action(1, move |_| {
+                released.fetch_add(1, Ordering::Relaxed);
+            })
+        };
+        for generation in 0..3 {
+            let result = barrier.wait().unwrap();
+            assert!(result.is_leader());
+            assert_eq!(result.generation(), generation);
+        }
+        assert_eq!(released.load(Ordering::Relaxed), 3);
+        assert_eq!(barrier.state().generation, 3);
+
+        // a task alone at the barrier is released by its first poll
+        let waker = Arc::new(NoopWaker).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut wait = barrier.wait_async();
+        match std::pin::Pin::new(&mut wait).poll(&mut cx) {
+            std::task::Poll::Ready(result) => assert_eq!(result.unwrap().generation(), 3),
+            std::task::Poll::Pending => panic!("a single party waited"),
+        }
+        assert_eq!(released.load(Ordering::Relaxed), 4);
+
+        barrier.cancel_with(CancelReason::Shutdown);
+        assert_eq!(
+            barrier.wait().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Shutdown)
+        );
+        barrier.reset();
+
+        // grown past one party, waits park again, and shrunk back they don't
+        barrier.add_party();
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        let leader = barrier.wait().unwrap().is_leader();
+        assert_ne!(leader, t1.join().unwrap().unwrap().is_leader());
+        barrier.remove_party().unwrap();
+        assert!(barrier.wait().unwrap().is_leader());
+        assert_eq!(released.load(Ordering::Relaxed), 6);
+    }
+
+    #[cfg(feature = "crossbeam")]
+    #[test]
+    fn test_with_crossbeam_scope() {
+        let barrier = CancellableBarrier::new(4);
+        let offsets = [10, 20, 30, 40];
+        let generations = barrier
+            .with_crossbeam_scope(|scope, barrier| {
+                let handles: Vec<_> = offsets
+                    .iter()
+                    .map(|offset| {
+                        scope.spawn(move |_| barrier.wait().unwrap().generation() + offset)
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap();
+        assert_eq!(generations, offsets);
+
+        // a panicking scope cancels the barrier, so its threads can be joined
+        let waited = std::sync::Mutex::new(None);
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| {
+            barrier.with_crossbeam_scope(|scope, barrier| {
+                scope.spawn(|_| *waited.lock().unwrap() = Some(barrier.wait()));
+                panic!("coordinator failed");
+            })
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(
+            waited.into_inner().unwrap(),
+            Some(Err(cancelled(CancelReason::Panicked)))
+        );
+    }
+}
+
+// run with RUSTFLAGS="--cfg loom" cargo test -p psyche-core --release loom_
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    #[test]
+    fn loom_wait_and_cancel() {
+        loom::model(|| {
+            let barrier = CancellableBarrier::new(2);
+
+            let barrier_clone1 = barrier.clone();
+            let t1 = thread::spawn(move || barrier_clone1.wait());
+            let barrier_clone2 = barrier.clone();
+            let t2 = thread::spawn(move || barrier_clone2.cancel());
+
+            let r0 = barrier.wait();
+            let r1 = t1.join().unwrap();
+            t2.join().unwrap();
+
+            // both threads got out, and they agree on whether the generation released
+            match (r0, r1) {
+                (Ok(r0), Ok(r1)) => {
+                    assert_eq!((r0.generation(), r1.generation()), (0, 0));
+                    assert!(r0.is_leader() != r1.is_leader());
+                }
+                (Err(e0), Err(e1)) => {
+                    assert_eq!(e0.cancel_reason(), Some(&CancelReason::Unspecified));
+                    assert_eq!(e1.cancel_reason(), Some(&CancelReason::Unspecified));
+                }
+                (r0, r1) => panic!("inconsistent results {r0:?} and {r1:?}"),
+            }
+        });
+    }
+
+    #[test]
+    fn loom_three_parties() {
+        loom::model(|| {
+            let barrier = CancellableBarrier::new(3);
+
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    let barrier = barrier.clone();
+                    thread::spawn(move || barrier.wait().unwrap())
+                })
+                .collect();
+            let mut leaders = usize::from(barrier.wait().unwrap().is_leader());
+            for handle in handles {
+                leaders += usize::from(handle.join().unwrap().is_leader());
+            }
+
+            assert_eq!(leaders, 1);
+            assert_eq!(barrier.state().arrived, 0);
+            assert_eq!(barrier.state().generation, 1);
+        });
+    }
+
+    #[test]
+    fn loom_reset_lets_waiter_go() {
+        loom::model(|| {
+            let barrier = CancellableBarrier::new(2);
+
+            let barrier_clone = barrier.clone();
+            let waiter = thread::spawn(move || barrier_clone.wait());
+            barrier.reset();
+            barrier.cancel();
+
+            // a waiter that arrived before the reset is let go with its generation, one
+            // that arrived after it fails with the cancellation, and none is left waiting
+            match waiter.join().unwrap() {
+                Ok(result) => {
+                    assert_eq!(result.generation(), 0);
+                    assert!(!result.is_leader());
+                }
+                Err(err) => assert_eq!(err.cancel_reason(), Some(&CancelReason::Unspecified)),
+            }
+        });
+    }
+}
@@ -0,0 +1,291 @@
+//! Model-based tests: random sequences of operations are run against the barrier with a
+//! thread per arrival, and against a model that works out, without any threads, who
+//! should be released, with what, and when.
+//!
+//! After every operation the test waits for the barrier to settle, until its snapshot
+//! matches the model and every thread the model lets go has returned, so each run is
+//! deterministic and a failure can be replayed from its operations. On the way it checks
+//! that no thread is lost, that no generation has more than one leader, and that the
+//! count never goes past the size.
+
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use proptest::prelude::*;
+
+use super::{BarrierSnapshot, BarrierWaitError, BarrierWaitResult, CancellableBarrier};
+use crate::sync::Arc;
+
+/// How long a thread may take to do what the model says before it counts as lost
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    /// A new thread arrives with `wait`
+    Arrive,
+    Cancel,
+    Reset,
+    Resize(usize),
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        6 => Just(Op::Arrive),
+        1 => Just(Op::Cancel),
+        1 => Just(Op::Reset),
+        2 => (1..=4usize).prop_map(Op::Resize),
+    ]
+}
+
+/// What a thread's wait returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Released { generation: usize, leader: bool },
+    Cancelled,
+    Failed,
+}
+
+impl From<Result<BarrierWaitResult, BarrierWaitError>> for Outcome {
+    fn from(result: Result<BarrierWaitResult, BarrierWaitError>) -> Self {
+        match result {
+            Ok(result) => Outcome::Released {
+                generation: result.generation(),
+                leader: result.is_leader(),
+            },
+            Err(err) if err.cancel_reason().is_some() => Outcome::Cancelled,
+            Err(_) => Outcome::Failed,
+        }
+    }
+}
+
+/// The barrier as it should be
+#[derive(Debug)]
+struct Model {
+    total: usize,
+    count: usize,
+    generation: usize,
+    cancelled: bool,
+    /// Threads waiting on the current generation
+    waiting: Vec<usize>,
+}
+
+impl Model {
+    fn new(total: usize) -> Self {
+        Model {
+            total,
+            count: 0,
+            generation: 0,
+            cancelled: false,
+            waiting: Vec::new(),
+        }
+    }
+
+    /// Applies `op`, where `thread` is the thread an arrival would be, and returns the
+    /// threads it lets go with what they should get
+    fn apply(&mut self, op: Op, thread: usize) -> Vec<(usize, Outcome)> {
+        match op {
+            Op::Arrive if self.cancelled => vec![(thread, Outcome::Cancelled)],
+            Op::Arrive => {
+                self.count += 1;
+                self.waiting.push(thread);
+                if self.count >= self.total {
+                    self.release(Some(thread))
+                } else {
+                    Vec::new()
+                }
+            }
+            Op::Cancel => {
+                // the arrivals stay counted until the barrier is reset
+                self.cancelled = true;
+                self.waiting
+                    .drain(..)
+                    .map(|thread| (thread, Outcome::Cancelled))
+                    .collect()
+            }
+            Op::Reset => {
+                self.cancelled = false;
+                self.release(None)
+            }
+            Op::Resize(total) => {
+                self.total = total;
+                if self.count > 0 && self.count >= self.total {
+                    self.release(None)
+                } else {
+                    Vec::new()
+                }
+            }
+        }
+    }
+
+    fn release(&mut self, leader: Option<usize>) -> Vec<(usize, Outcome)> {
+        let generation = self.generation;
+        self.generation += 1;
+        self.count = 0;
+        self.waiting
+            .drain(..)
+            .map(|thread| {
+                let leader = Some(thread) == leader;
+                (thread, Outcome::Released { generation, leader })
+            })
+            .collect()
+    }
+
+    fn snapshot(&self) -> BarrierSnapshot {
+        BarrierSnapshot {
+            arrived: self.count,
+            total: self.total,
+            generation: self.generation,
+            cancelled: self.cancelled,
+        }
+    }
+}
+
+/// Cancels the barrier when a run ends, so a failed run doesn't leave threads waiting
+struct CancelOnDrop(Arc<CancellableBarrier>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+struct Run {
+    barrier: Arc<CancellableBarrier>,
+    model: Model,
+    threads: usize,
+    sender: mpsc::Sender<(usize, Outcome)>,
+    results: Receiver<(usize, Outcome)>,
+    outcomes: HashMap<usize, Outcome>,
+}
+
+impl Run {
+    fn new(total: usize) -> Self {
+        let (sender, results) = mpsc::channel();
+        Run {
+            barrier: CancellableBarrier::new(total),
+            model: Model::new(total),
+            threads: 0,
+            sender,
+            results,
+            outcomes: HashMap::new(),
+        }
+    }
+
+    fn apply(&mut self, op: Op) -> Result<(), TestCaseError> {
+        let thread = self.threads;
+        let expected = self.model.apply(op, thread);
+        match op {
+            Op::Arrive => {
+                self.threads += 1;
+                let barrier = self.barrier.clone();
+                let sender = self.sender.clone();
+                thread::spawn(move || {
+                    let outcome = barrier.wait().into();
+                    let _ = sender.send((thread, outcome));
+                });
+            }
+            Op::Cancel => {
+                self.barrier.cancel();
+            }
+            Op::Reset => self.barrier.reset(),
+            Op::Resize(total) => self.barrier.resize(total).unwrap(),
+        }
+        self.settle(op, &expected)
+    }
+
+    /// Waits until the barrier matches the model and the threads it let go have returned
+    fn settle(&mut self, op: Op, expected: &[(usize, Outcome)]) -> Result<(), TestCaseError> {
+        let deadline = Instant::now() + SETTLE_TIMEOUT;
+        loop {
+            let returned = expected
+                .iter()
+                .all(|(thread, _)| self.outcomes.contains_key(thread));
+            if returned && self.barrier.state() == self.model.snapshot() {
+                break;
+            }
+            match self.results.recv_timeout(Duration::from_millis(1)) {
+                Ok((thread, outcome)) => {
+                    self.outcomes.insert(thread, outcome);
+                }
+                Err(RecvTimeoutError::Timeout) if Instant::now() < deadline => {}
+                Err(_) => {
+                    return Err(TestCaseError::fail(format!(
+                        "lost a thread after {op:?}: barrier is {:?}, model is {:?}, \
+                         expected {expected:?}, got {:?}",
+                        self.barrier.state(),
+                        self.model,
+                        self.outcomes
+                    )))
+                }
+            }
+        }
+        // anything that returned early shows up now or in a later step
+        while let Ok((thread, outcome)) = self.results.try_recv() {
+            self.outcomes.insert(thread, outcome);
+        }
+
+        for &(thread, outcome) in expected {
+            prop_assert_eq!(
+                self.outcomes[&thread],
+                outcome,
+                "thread {} after {:?}",
+                thread,
+                op
+            );
+        }
+        for thread in &self.model.waiting {
+            prop_assert!(
+                !self.outcomes.contains_key(thread),
+                "thread {} returned while it should be waiting",
+                thread
+            );
+        }
+        let state = self.barrier.state();
+        prop_assert!(
+            state.arrived <= state.total,
+            "count past the size: {:?}",
+            state
+        );
+        Ok(())
+    }
+
+    fn check_leaders(&self) -> Result<(), TestCaseError> {
+        let mut leaders = HashMap::new();
+        for outcome in self.outcomes.values() {
+            if let Outcome::Released { generation, leader } = *outcome {
+                *leaders.entry(generation).or_insert(0) += usize::from(leader);
+            }
+        }
+        for (generation, leaders) in leaders {
+            prop_assert!(
+                leaders <= 1,
+                "generation {} has {} leaders",
+                generation,
+                leaders
+            );
+        }
+        Ok(())
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_barrier_matches_model(
+        total in 1..=4usize,
+        ops in prop::collection::vec(op(), 1..32),
+    ) {
+        let mut run = Run::new(total);
+        let _cancel = CancelOnDrop(run.barrier.clone());
+        for &op in &ops {
+            run.apply(op)?;
+        }
+        // cancel what's still waiting, so every thread has returned
+        run.apply(Op::Cancel)?;
+        prop_assert_eq!(run.outcomes.len(), run.threads);
+        run.check_leaders()?;
+    }
+}
@@ -0,0 +1,42 @@
+use super::CancelReason;
+
+/// Hooks into a barrier's lifecycle, for custom instrumentation, attached with
+/// [`super::CancellableBarrierBuilder::observer`].
+///
+/// Also implemented for an observer behind an `Arc`, so the caller can keep a handle on it,
+/// to read what it recorded. Every method does nothing by default, so an observer only
+/// implements the events it cares about. They are called with the barrier locked, in the
+/// order the events happen, so they must be quick and must not call back into the barrier.
+pub trait BarrierObserver: Send + Sync {
+    /// A thread arrived in `generation`, bringing the arrivals to `count` of `total`.
+    ///
+    /// Arrivals that are later rolled back, by a timeout for example, are reported too.
+    /// Late parties of a quorum barrier don't count towards any generation and aren't.
+    fn on_arrive(&self, generation: usize, count: usize, total: usize) {
+        let _ = (generation, count, total);
+    }
+
+    /// `generation` was released, before any of its threads is woken
+    fn on_release(&self, generation: usize) {
+        let _ = generation;
+    }
+
+    /// The barrier was cancelled with `reason`
+    fn on_cancel(&self, reason: &CancelReason) {
+        let _ = reason;
+    }
+}
+
+impl<T: BarrierObserver + ?Sized> BarrierObserver for std::sync::Arc<T> {
+    fn on_arrive(&self, generation: usize, count: usize, total: usize) {
+        (**self).on_arrive(generation, count, total);
+    }
+
+    fn on_release(&self, generation: usize) {
+        (**self).on_release(generation);
+    }
+
+    fn on_cancel(&self, reason: &CancelReason) {
+        (**self).on_cancel(reason);
+    }
+}
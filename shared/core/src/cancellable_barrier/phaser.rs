@@ -0,0 +1,193 @@
+use std::fmt;
+use std::sync::PoisonError;
+
+use super::{BarrierBuildError, BarrierWaitError, CancelReason, CancellableBarrier};
+use crate::sync::Arc;
+
+/// The number of a phase of a [`Phaser`], counting from 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Phase(pub usize);
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "phase {}", self.0)
+    }
+}
+
+/// A cyclic barrier for computations that run in numbered phases, where parties can
+/// register and deregister between phases.
+///
+/// Each phase completes once every registered party has arrived, and waiting returns the
+/// number of the phase that completed. This is a [`CancellableBarrier`] whose generations
+/// are the phases, so cancellation behaves the same way.
+#[derive(Debug)]
+pub struct Phaser {
+    barrier: Arc<CancellableBarrier>,
+}
+
+impl Phaser {
+    /// Creates a phaser for `parties` parties, starting at phase 0
+    #[must_use]
+    pub fn new(parties: usize) -> Arc<Self> {
+        Arc::new(Phaser {
+            barrier: CancellableBarrier::new(parties),
+        })
+    }
+
+    /// Arrives at the current phase and waits until every party has arrived or the
+    /// phaser is cancelled, returning the phase that completed
+    pub fn arrive_and_await(&self) -> Result<Phase, BarrierWaitError> {
+        self.barrier.wait().map(|result| Phase(result.generation()))
+    }
+
+    /// Waits, without arriving, until `phase` has completed or the phaser is cancelled,
+    /// returning the phase the phaser is at by then.
+    ///
+    /// Returns straight away if `phase` isn't the current phase.
+    pub fn await_advance(&self, phase: Phase) -> Result<Phase, BarrierWaitError> {
+        let mut state = self.barrier.lock();
+        while state.generation == phase.0 {
+            state.check_cancelled()?;
+            state = self
+                .barrier
+                .condvar
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        Ok(Phase(state.generation))
+    }
+
+    /// Registers one more party, which is needed from the current phase on, and returns
+    /// that phase
+    pub fn register(&self) -> Phase {
+        let mut state = self.barrier.lock();
+        let new_total = state.total + 1;
+        if let Err(panicked) = self.barrier.set_total(&mut state, new_total) {
+            panicked.resume(&self.barrier, state);
+        }
+        Phase(state.generation)
+    }
+
+    /// Deregisters a party that won't arrive at the current phase or any later one. If
+    /// every other party has already arrived, this completes the phase.
+    ///
+    /// The last party can't deregister, since a phaser needs at least one.
+    pub fn arrive_and_deregister(&self) -> Result<(), BarrierBuildError> {
+        self.barrier.remove_party()
+    }
+
+    /// Returns the phase in progress
+    #[must_use]
+    pub fn current_phase(&self) -> Phase {
+        Phase(self.barrier.lock().generation)
+    }
+
+    /// Returns the number of registered parties
+    #[must_use]
+    pub fn parties(&self) -> usize {
+        self.barrier.lock().total
+    }
+
+    /// Cancels the phaser, causing all waiting threads to return with an error
+    pub fn cancel(&self) {
+        self.barrier.cancel();
+    }
+
+    /// Cancels the phaser with a `reason` that waiting threads receive in their error
+    pub fn cancel_with(&self, reason: CancelReason) {
+        self.barrier.cancel_with(reason);
+    }
+
+    /// Returns true if the phaser is currently cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.barrier.is_cancelled()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_phaser_numbers_phases() {
+        let phaser = Phaser::new(3);
+        assert_eq!(phaser.current_phase(), Phase(0));
+
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let phaser = phaser.clone();
+                thread::spawn(move || {
+                    (0..4)
+                        .map(|_| phaser.arrive_and_await().unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(
+                handle.join().unwrap(),
+                vec![Phase(0), Phase(1), Phase(2), Phase(3)]
+            );
+        }
+        assert_eq!(phaser.current_phase(), Phase(4));
+        assert_eq!(Phase(4).to_string(), "phase 4");
+    }
+
+    #[test]
+    fn test_phaser_deregister_completes_phase() {
+        let phaser = Phaser::new(3);
+
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let phaser = phaser.clone();
+                thread::spawn(move || phaser.arrive_and_await())
+            })
+            .collect();
+        thread::sleep(Duration::from_millis(100));
+        phaser.arrive_and_deregister().unwrap();
+        for waiter in waiters {
+            assert_eq!(waiter.join().unwrap(), Ok(Phase(0)));
+        }
+
+        // later phases need one fewer arrival, until someone registers again
+        assert_eq!(phaser.parties(), 2);
+        assert_eq!(phaser.register(), Phase(1));
+        assert_eq!(phaser.parties(), 3);
+
+        let phaser = Phaser::new(1);
+        assert!(phaser.arrive_and_deregister().is_err());
+    }
+
+    #[test]
+    fn test_phaser_await_advance_and_cancel() {
+        let phaser = Phaser::new(2);
+        // a phase that's already over doesn't block
+        assert_eq!(phaser.await_advance(Phase(7)), Ok(Phase(0)));
+
+        let observer = {
+            let phaser = phaser.clone();
+            thread::spawn(move || phaser.await_advance(Phase(0)))
+        };
+        let phaser_clone = phaser.clone();
+        let party = thread::spawn(move || phaser_clone.arrive_and_await());
+        assert_eq!(phaser.arrive_and_await(), Ok(Phase(0)));
+        party.join().unwrap().unwrap();
+        assert_eq!(observer.join().unwrap(), Ok(Phase(1)));
+
+        let observer = {
+            let phaser = phaser.clone();
+            thread::spawn(move || phaser.await_advance(Phase(1)))
+        };
+        thread::sleep(Duration::from_millis(100));
+        phaser.cancel_with(CancelReason::Shutdown);
+        assert_eq!(
+            observer.join().unwrap().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Shutdown)
+        );
+        assert!(phaser.is_cancelled());
+        assert!(phaser.arrive_and_await().is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use std::iter::FusedIterator;
+
+use super::{BarrierWaitError, CancellableBarrier, DefaultSync, RawSync};
+
+/// Iterator returned by [`CancellableBarrier::phases`], waiting on the barrier at every
+/// step and yielding the generation each wait completed.
+///
+/// The iterator ends at the first wait that fails, whether the barrier was cancelled or
+/// the wait timed out under the barrier's default timeout, and [`Self::error`] tells which
+/// it was. Once ended it stays ended, even if the barrier is reset.
+#[derive(Debug)]
+#[must_use = "iterators do nothing unless iterated"]
+pub struct BarrierPhases<'a, S: RawSync = DefaultSync> {
+    barrier: &'a CancellableBarrier<S>,
+    error: Option<BarrierWaitError>,
+}
+
+impl<'a, S: RawSync> BarrierPhases<'a, S> {
+    pub(super) fn new(barrier: &'a CancellableBarrier<S>) -> Self {
+        BarrierPhases {
+            barrier,
+            error: None,
+        }
+    }
+
+    /// Returns the error of the wait that ended the iterator, or `None` if it hasn't
+    /// ended
+    #[must_use]
+    pub fn error(&self) -> Option<&BarrierWaitError> {
+        self.error.as_ref()
+    }
+}
+
+impl<S: RawSync> Iterator for BarrierPhases<'_, S> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.error.is_some() {
+            return None;
+        }
+        match self.barrier.wait() {
+            Ok(result) => Some(result.generation()),
+            Err(err) => {
+                self.error = Some(err);
+                None
+            }
+        }
+    }
+}
+
+impl<S: RawSync> FusedIterator for BarrierPhases<'_, S> {}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::cancellable_barrier::CancelReason;
+    use std::thread;
+
+    #[test]
+    fn test_phases_end_on_cancel() {
+        let barrier = CancellableBarrier::new(2);
+
+        let worker = {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let mut phases = barrier.phases();
+                let generations: Vec<_> = (&mut phases).collect();
+                // ended for good, with the error kept
+                assert_eq!(phases.next(), None);
+                (generations, phases.error().cloned())
+            })
+        };
+        for _ in 0..3 {
+            barrier.wait().unwrap();
+        }
+        barrier.cancel_with(CancelReason::Shutdown);
+
+        let (generations, error) = worker.join().unwrap();
+        assert_eq!(generations, vec![0, 1, 2]);
+        assert_eq!(
+            error.as_ref().and_then(BarrierWaitError::cancel_reason),
+            Some(&CancelReason::Shutdown)
+        );
+    }
+}
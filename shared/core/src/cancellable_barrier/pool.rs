@@ -0,0 +1,180 @@
+use std::ops::Deref;
+use std::sync::PoisonError;
+
+use super::CancellableBarrier;
+use crate::sync::{Arc, Mutex};
+
+/// Recycles [`CancellableBarrier`]s, for code that goes through many short-lived barriers
+/// and would rather not allocate and set up a new one each time.
+///
+/// [`Self::acquire`] hands out a barrier reset to the size asked for, taken from the
+/// barriers given back to the pool if there are any. Dropping the [`PooledBarrier`] gives
+/// it back, unless something else still holds on to it.
+///
+/// Pooled barriers are plain barriers, as [`CancellableBarrier::new`] builds them. A
+/// listener added to one stays with it when it is recycled, so listeners are best left to
+/// barriers that aren't pooled.
+#[derive(Debug)]
+pub struct BarrierPool {
+    idle: Mutex<Vec<Arc<CancellableBarrier>>>,
+    max_idle: usize,
+}
+
+impl BarrierPool {
+    /// Creates an empty pool that keeps at most `max_idle` barriers around between uses,
+    /// dropping any given back beyond that
+    #[must_use]
+    pub fn new(max_idle: usize) -> Self {
+        BarrierPool {
+            idle: Mutex::new(Vec::new()),
+            max_idle,
+        }
+    }
+
+    /// Hands out a barrier for `n` threads, recycling one given back to the pool if there
+    /// is one.
+    ///
+    /// A recycled barrier is reset to `n` with [`CancellableBarrier::reset_with`] first,
+    /// so like a new one it isn't cancelled, nobody has arrived, and it is on a generation
+    /// of its own. Only the generation counter carries over from its last use.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is 0.
+    pub fn acquire(&self, n: usize) -> PooledBarrier<'_> {
+        assert!(n > 0, "Barrier size must be greater than 0");
+        let recycled = self
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+        let barrier = match recycled {
+            Some(barrier) => {
+                barrier.reset_with(n).expect("size checked above");
+                barrier
+            }
+            None => CancellableBarrier::new(n),
+        };
+        PooledBarrier {
+            pool: self,
+            barrier: Some(barrier),
+        }
+    }
+
+    /// Returns how many barriers are waiting in the pool to be handed out again
+    #[must_use]
+    pub fn idle(&self) -> usize {
+        self.idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Takes `barrier` back if it is fit to hand out again: nobody else holds on to it,
+    /// nothing reset can't undo has been done to it, and there is room for it
+    fn give_back(&self, barrier: Arc<CancellableBarrier>) {
+        if Arc::strong_count(&barrier) > 1 || barrier.is_aborted() || barrier.is_paused() {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        if idle.len() < self.max_idle {
+            idle.push(barrier);
+        }
+    }
+}
+
+/// A barrier handed out by [`BarrierPool::acquire`], given back to the pool when
+/// dropped.
+///
+/// Dereferences to the barrier, and [`Self::barrier`] gives the `Arc` for sharing it with
+/// other threads. A barrier whose `Arc` is still held elsewhere when this is dropped, such
+/// as by a thread still waiting on it, isn't given back, and is freed once the last
+/// holder lets go of it as any other barrier would be.
+#[derive(Debug)]
+pub struct PooledBarrier<'a> {
+    pool: &'a BarrierPool,
+    barrier: Option<Arc<CancellableBarrier>>,
+}
+
+impl PooledBarrier<'_> {
+    /// Returns the `Arc` the pool keeps the barrier in, to clone for other threads
+    #[must_use]
+    pub fn barrier(&self) -> &Arc<CancellableBarrier> {
+        self.barrier.as_ref().expect("only taken when dropped")
+    }
+}
+
+impl Deref for PooledBarrier<'_> {
+    type Target = CancellableBarrier;
+
+    fn deref(&self) -> &CancellableBarrier {
+        self.barrier()
+    }
+}
+
+impl Drop for PooledBarrier<'_> {
+    fn drop(&mut self) {
+        if let Some(barrier) = self.barrier.take() {
+            self.pool.give_back(barrier);
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_recycled_barrier_is_reset() {
+        let pool = BarrierPool::new(1);
+        let first = pool.acquire(2);
+        let address = Arc::as_ptr(first.barrier());
+
+        // leave it cancelled, with an arrival still counted
+        let barrier = first.barrier().clone();
+        let waiter = thread::spawn(move || barrier.wait());
+        while first.state().arrived == 0 {
+            thread::yield_now();
+        }
+        first.cancel();
+        assert!(waiter.join().unwrap().is_err());
+        drop(first);
+        assert_eq!(pool.idle(), 1);
+
+        let second = pool.acquire(3);
+        assert_eq!(Arc::as_ptr(second.barrier()), address);
+        let state = second.state();
+        assert!(!state.cancelled);
+        assert_eq!(state.arrived, 0);
+        assert_eq!(state.total, 3);
+        assert_eq!(pool.idle(), 0);
+
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = second.barrier().clone();
+                thread::spawn(move || barrier.wait())
+            })
+            .collect();
+        assert!(second.wait().is_ok());
+        for waiter in waiters {
+            assert!(waiter.join().unwrap().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_pool_skips_barriers_still_in_use() {
+        let pool = BarrierPool::new(1);
+        let pooled = pool.acquire(1);
+        let kept = pooled.barrier().clone();
+        drop(pooled);
+        assert_eq!(pool.idle(), 0);
+
+        // the pool only keeps as many as it was asked to
+        let (first, second) = (pool.acquire(1), pool.acquire(1));
+        drop(first);
+        drop(second);
+        assert_eq!(pool.idle(), 1);
+        assert!(kept.wait().is_ok());
+    }
+}
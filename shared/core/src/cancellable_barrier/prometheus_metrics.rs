@@ -0,0 +1,176 @@
+//! Prometheus collector for a barrier's metrics, behind the `prometheus` feature.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError, Weak};
+
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::{Gauge, IntCounter, IntGauge, Opts};
+
+use super::{CancellableBarrier, RawSync};
+use crate::sync::Arc;
+
+/// Reads a barrier's [`super::BarrierMetrics`] into prometheus metrics whenever the
+/// registry is gathered, registered by [`CancellableBarrier::register_metrics`].
+///
+/// Doesn't keep the barrier alive: once it is gone, the metrics keep their last values.
+pub(super) struct BarrierCollector<S: RawSync> {
+    barrier: Weak<CancellableBarrier<S>>,
+    arrivals: IntCounter,
+    releases: IntCounter,
+    cancellations: IntCounter,
+    resets: IntCounter,
+    generation: IntGauge,
+    waiting: IntGauge,
+    last_cycle: Gauge,
+    /// Keeps concurrent gathers from interleaving their updates
+    updating: Mutex<()>,
+}
+
+impl<S: RawSync> BarrierCollector<S> {
+    pub(super) fn new(
+        barrier: Weak<CancellableBarrier<S>>,
+        labels: HashMap<String, String>,
+    ) -> prometheus::Result<Self> {
+        let opts = |name: &str, help: &str| Opts::new(name, help).const_labels(labels.clone());
+        Ok(BarrierCollector {
+            barrier,
+            arrivals: IntCounter::with_opts(opts(
+                "psyche_barrier_arrivals_total",
+                "Arrivals at the barrier",
+            ))?,
+            releases: IntCounter::with_opts(opts(
+                "psyche_barrier_releases_total",
+                "Generations the barrier released",
+            ))?,
+            cancellations: IntCounter::with_opts(opts(
+                "psyche_barrier_cancellations_total",
+                "Times the barrier was cancelled",
+            ))?,
+            resets: IntCounter::with_opts(opts(
+                "psyche_barrier_resets_total",
+                "Times the barrier was reset",
+            ))?,
+            generation: IntGauge::with_opts(opts(
+                "psyche_barrier_generation",
+                "Generation the barrier is on",
+            ))?,
+            waiting: IntGauge::with_opts(opts(
+                "psyche_barrier_waiting",
+                "Parties waiting in the current generation",
+            ))?,
+            last_cycle: Gauge::with_opts(opts(
+                "psyche_barrier_last_cycle_seconds",
+                "Time between the barrier's last two releases",
+            ))?,
+            updating: Mutex::new(()),
+        })
+    }
+
+    fn update(&self) {
+        let Some(barrier) = self.barrier.upgrade() else {
+            return;
+        };
+        let metrics = barrier.metrics();
+        let _updating = self.updating.lock().unwrap_or_else(PoisonError::into_inner);
+        // counters can only go up, so bring them to the barrier's totals
+        for (counter, total) in [
+            (&self.arrivals, metrics.arrivals),
+            (&self.releases, metrics.releases),
+            (&self.cancellations, metrics.cancellations),
+            (&self.resets, metrics.resets),
+        ] {
+            counter.inc_by(total.saturating_sub(counter.get()));
+        }
+        self.generation
+            .set(i64::try_from(metrics.generation).unwrap_or(i64::MAX));
+        self.waiting
+            .set(i64::try_from(barrier.state().arrived).unwrap_or(i64::MAX));
+        self.last_cycle.set(metrics.last_cycle.as_secs_f64());
+    }
+}
+
+impl<S: RawSync> Collector for BarrierCollector<S> {
+    fn desc(&self) -> Vec<&Desc> {
+        [
+            self.arrivals.desc(),
+            self.releases.desc(),
+            self.cancellations.desc(),
+            self.resets.desc(),
+            self.generation.desc(),
+            self.waiting.desc(),
+            self.last_cycle.desc(),
+        ]
+        .concat()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        self.update();
+        [
+            self.arrivals.collect(),
+            self.releases.collect(),
+            self.cancellations.collect(),
+            self.resets.collect(),
+            self.generation.collect(),
+            self.waiting.collect(),
+            self.last_cycle.collect(),
+        ]
+        .concat()
+    }
+}
+
+impl<S: RawSync> CancellableBarrier<S> {
+    /// Registers the barrier's metrics with a prometheus `registry`, to be read from the
+    /// barrier every time the registry is gathered.
+    ///
+    /// The metrics are `psyche_barrier_arrivals_total`, `psyche_barrier_releases_total`,
+    /// `psyche_barrier_cancellations_total`, `psyche_barrier_waiting` and
+    /// `psyche_barrier_last_cycle_seconds`. They carry `labels`, plus a `barrier` label
+    /// with the barrier's name if it has one; barriers registered with the same registry
+    /// need labels that tell them apart. The registration doesn't keep the barrier alive.
+    pub fn register_metrics(
+        self: &Arc<Self>,
+        registry: &prometheus::Registry,
+        mut labels: HashMap<String, String>,
+    ) -> prometheus::Result<()> {
+        if let Some(name) = self.name() {
+            labels.insert("barrier".to_string(), name.to_string());
+        }
+        let collector = BarrierCollector::new(Arc::downgrade(self), labels)?;
+        registry.register(Box::new(collector))
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_collector_reads_barrier_metrics() {
+        let barrier = CancellableBarrier::new_named(2, "sync");
+        let collector = BarrierCollector::new(
+            std::sync::Arc::downgrade(&barrier),
+            HashMap::from([("barrier".to_string(), "sync".to_string())]),
+        )
+        .unwrap();
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        barrier.wait().unwrap();
+        t1.join().unwrap().unwrap();
+        barrier.cancel();
+
+        collector.collect();
+        assert_eq!(collector.arrivals.get(), 2);
+        assert_eq!(collector.releases.get(), 1);
+        assert_eq!(collector.cancellations.get(), 1);
+        assert_eq!(collector.resets.get(), 0);
+        assert_eq!(collector.generation.get(), 1);
+        assert_eq!(collector.waiting.get(), 0);
+
+        // gathering again doesn't count twice
+        collector.collect();
+        assert_eq!(collector.arrivals.get(), 2);
+    }
+}
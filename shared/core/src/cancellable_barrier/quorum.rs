@@ -0,0 +1,512 @@
+use std::sync::atomic::Ordering;
+
+use super::{
+    BarrierBuildError, BarrierState, BarrierWaitError, BarrierWaitResult, CancellableBarrier,
+    PanickedRelease, RawSync, WaitOptions,
+};
+
+impl BarrierState {
+    /// Returns true if `count` arrivals complete the current generation, which no number
+    /// does on a manual barrier
+    pub(super) fn completed_by(&self, count: usize) -> bool {
+        self.total > 0 && count >= self.total
+    }
+
+    /// Lets a party of an already released quorum generation straight through
+    pub(super) fn take_late_arrival(&mut self) -> Option<BarrierWaitResult> {
+        if self.late_pending == 0 {
+            return None;
+        }
+        self.late_pending -= 1;
+        Some(BarrierWaitResult {
+            generation: self.generation.wrapping_sub(1),
+            is_leader: false,
+            is_late: true,
+            is_first: false,
+            partial: None,
+        })
+    }
+}
+
+impl<S: RawSync> CancellableBarrier<S> {
+    /// Like [`Self::wait`], but the arrival counts as `weight` parties rather than one.
+    ///
+    /// The barrier releases once the weights of the arrived threads add up to its size,
+    /// so a thread standing for a batch of work can count for the whole batch. An arrival
+    /// that takes the sum past the size still releases the barrier, and the excess is
+    /// dropped rather than carried over into the next generation. Resetting the barrier
+    /// likewise drops the weight gathered by the generation in progress. Late parties of
+    /// a quorum barrier pass through whatever their weight.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weight` is 0.
+    pub fn wait_weighted(&self, weight: usize) -> Result<BarrierWaitResult, BarrierWaitError> {
+        assert!(weight > 0, "Arrival weight must be greater than 0");
+        self.wait_inner(WaitOptions {
+            weight,
+            deadline: self
+                .default_timeout
+                .and_then(|timeout| self.deadline_after(timeout)),
+            ..WaitOptions::default()
+        })
+    }
+
+    /// Arrives on behalf of `n` parties at once, without blocking, such as the workers a
+    /// driver thread owns that sit this generation out.
+    ///
+    /// Unlike [`Self::try_wait`], the arrivals are kept when they don't complete the
+    /// barrier, and `None` is returned: they stand until the generation is released, reset
+    /// or cancelled, and there is no wait to give them back. If they complete it, the
+    /// generation is released and returned. Arrivals that take the count past the size are
+    /// dropped rather than carried over into the next generation, as the excess weight of
+    /// [`Self::wait_weighted`] is. The arrivals count towards the generation in progress,
+    /// not the late parties of a quorum barrier, and aren't held back by backpressure,
+    /// since the parties they stand for aren't running ahead. Arriving for no parties does
+    /// nothing.
+    pub fn arrive_many(&self, n: usize) -> Result<Option<usize>, BarrierWaitError> {
+        self.check_linked();
+        let mut state = self.lock();
+        state.check_cancelled()?;
+        if n == 0 {
+            return Ok(None);
+        }
+        if self.generation_cap_reached(&state) {
+            return Err(self.cancel_at_generation_cap(state));
+        }
+
+        let generation = state.generation;
+        if state.count == 0 {
+            self.note_first_arrival(&mut state);
+            state.times.expires_at = self
+                .overall_timeout
+                .and_then(|timeout| self.deadline_after(timeout));
+        }
+        self.metrics.record_arrival();
+        // saturating, so that any number of parties completes the barrier
+        state.count = state.count.saturating_add(n);
+        self.note_arrival(&mut state);
+        self.observe_arrival(&state);
+        if !state.completed_by(state.count) {
+            self.debug_check_invariants(&state);
+            return Ok(None);
+        }
+        if let Err(panicked) = self.release(&mut state) {
+            panicked.resume(self, state);
+        }
+        Ok(Some(generation))
+    }
+
+    /// Changes the number of threads needed to release the barrier.
+    ///
+    /// The new size applies to the generation in progress. If at least `new_total`
+    /// threads are already waiting, they are released immediately; none of them is
+    /// reported as leader since no arrival completed the barrier. Growing the barrier
+    /// makes the waiting threads wait for the larger total. On a quorum barrier this
+    /// changes the quorum; the number of late parties stays as built.
+    ///
+    /// The size is swapped and checked against the arrivals in one step, so resizing
+    /// while threads are arriving is safe: no thread is ever left waiting on a generation
+    /// that already has all the arrivals it needs.
+    pub fn resize(&self, new_total: usize) -> Result<(), BarrierBuildError> {
+        if new_total == 0 {
+            return Err(BarrierBuildError::ZeroParties);
+        }
+
+        let mut state = self.lock();
+        if let Err(panicked) = self.set_total(&mut state, new_total) {
+            panicked.resume(self, state);
+        }
+        Ok(())
+    }
+
+    /// Adds one party to the barrier, as [`Self::resize`] to one more than the current size
+    pub fn add_party(&self) {
+        let mut state = self.lock();
+        let new_total = state.total + 1;
+        if let Err(panicked) = self.set_total(&mut state, new_total) {
+            panicked.resume(self, state);
+        }
+    }
+
+    /// Removes one party from the barrier, as [`Self::resize`] to one less than the
+    /// current size.
+    ///
+    /// A thread that decides not to participate can call this instead of waiting; if
+    /// everyone else is already waiting, they are released.
+    pub fn remove_party(&self) -> Result<(), BarrierBuildError> {
+        let mut state = self.lock();
+        if state.total <= 1 {
+            return Err(BarrierBuildError::ZeroParties);
+        }
+        let new_total = state.total - 1;
+        if let Err(panicked) = self.set_total(&mut state, new_total) {
+            panicked.resume(self, state);
+        }
+        Ok(())
+    }
+
+    /// Resets the barrier to its initial state, but for `new_total` threads from now on.
+    ///
+    /// This is one step, so no thread can arrive in between and be counted against the old
+    /// size. Threads still waiting on the generation in progress are let go, as with
+    /// [`Self::reset`]. On a quorum barrier this sets the quorum, as with [`Self::resize`].
+    /// An aborted barrier is left as it is.
+    pub fn reset_with(&self, new_total: usize) -> Result<(), BarrierBuildError> {
+        if new_total == 0 {
+            return Err(BarrierBuildError::ZeroParties);
+        }
+
+        let mut state = self.lock();
+        if state.aborted {
+            return Ok(());
+        }
+        self.reset_locked(&mut state);
+        state.total = new_total;
+        self.single_party.store(new_total == 1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub(super) fn set_total(
+        &self,
+        state: &mut BarrierState,
+        new_total: usize,
+    ) -> Result<(), PanickedRelease> {
+        state.total = new_total;
+        self.single_party.store(new_total == 1, Ordering::Relaxed);
+        self.event_log.record_resize(state.generation, new_total);
+        if state.count > 0 && state.completed_by(state.count) {
+            self.release(state)?;
+        }
+        self.debug_check_invariants(state);
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::cancellable_barrier::tests::{block_on, cancelled, TEST_SLEEP_DURATION};
+    use crate::cancellable_barrier::CancelReason;
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_arrive_many() {
+        let barrier = CancellableBarrier::new(4);
+        let barrier_clone = barrier.clone();
+        let waiter = thread::spawn(move || barrier_clone.wait());
+        while barrier.lock().count == 0 {
+            thread::yield_now();
+        }
+
+        // kept without completing the barrier
+        assert_eq!(barrier.arrive_many(2), Ok(None));
+        assert_eq!(barrier.arrive_many(0), Ok(None));
+        assert_eq!(barrier.state().arrived, 3);
+        assert!(!waiter.is_finished());
+
+        // going past the size releases, and the excess isn't carried over
+        assert_eq!(barrier.arrive_many(5), Ok(Some(0)));
+        assert_eq!(waiter.join().unwrap().map(|r| r.generation()), Ok(0));
+        assert_eq!(barrier.state().arrived, 0);
+        assert_eq!(barrier.arrive_many(usize::MAX), Ok(Some(1)));
+
+        assert_eq!(barrier.arrive_many(1), Ok(None));
+        barrier.cancel();
+        assert_eq!(
+            barrier.arrive_many(3),
+            Err(cancelled(CancelReason::Unspecified))
+        );
+        barrier.reset();
+        assert_eq!(barrier.state().arrived, 0);
+    }
+
+    #[test]
+    fn test_quorum_two_of_three() {
+        let barrier = CancellableBarrier::quorum(3, 2);
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        let leader = barrier.wait().unwrap();
+        let follower = t1.join().unwrap().unwrap();
+        assert!(leader.is_leader() && !leader.is_late());
+        assert!(!follower.is_leader() && !follower.is_late());
+        assert_eq!(follower.generation(), 0);
+
+        // the third party doesn't block, and doesn't start the next generation
+        let late = barrier.wait().unwrap();
+        assert!(late.is_late() && !late.is_leader());
+        assert_eq!(late.generation(), 0);
+        assert_eq!(barrier.state().arrived, 0);
+
+        // now the next generation needs its own quorum
+        assert_eq!(barrier.try_wait(), Ok(None));
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        assert_eq!(barrier.wait().map(|r| r.generation()), Ok(1));
+        assert_eq!(t1.join().unwrap().map(|r| r.generation()), Ok(1));
+        assert_eq!(barrier.try_wait(), Ok(Some(1)));
+    }
+
+    #[test]
+    fn test_quorum_validation() {
+        let builder = CancellableBarrier::builder().parties(3);
+        assert_eq!(
+            builder.clone().quorum(0).build().unwrap_err(),
+            BarrierBuildError::InvalidQuorum
+        );
+        assert_eq!(
+            builder.clone().quorum(4).build().unwrap_err(),
+            BarrierBuildError::InvalidQuorum
+        );
+        assert_eq!(builder.quorum(3).build().unwrap().state().total, 3);
+    }
+
+    #[test]
+    fn test_wait_weighted() {
+        let barrier = CancellableBarrier::new(5);
+
+        let batch = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait_weighted(3))
+        };
+        while barrier.state().arrived < 3 {
+            thread::yield_now();
+        }
+        assert_eq!(barrier.state().arrived, 3);
+        // a weight past what's missing still releases, without spilling over
+        assert!(barrier.wait_weighted(4).unwrap().is_leader());
+        assert_eq!(batch.join().unwrap().map(|r| r.generation()), Ok(0));
+        assert_eq!(barrier.state().arrived, 0);
+
+        // a timed out weighted arrival takes all of its weight back
+        let barrier = CancellableBarrier::builder()
+            .parties(3)
+            .default_timeout(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        assert_eq!(
+            barrier.wait_weighted(2),
+            Err(BarrierWaitError::timed_out(2, 1))
+        );
+        assert_eq!(barrier.state().arrived, 0);
+    }
+
+    #[test]
+    fn test_resize() {
+        let barrier = CancellableBarrier::new(2);
+        assert_eq!(barrier.resize(0), Err(BarrierBuildError::ZeroParties));
+
+        // Growing mid-generation makes the waiter wait for the larger total
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        barrier.resize(3).unwrap();
+        assert_eq!(barrier.try_wait(), Ok(None));
+        let barrier_clone2 = barrier.clone();
+        let t2 = thread::spawn(move || barrier_clone2.wait());
+        while barrier.state().arrived < 2 {
+            thread::yield_now();
+        }
+        assert!(!t1.is_finished());
+
+        // Shrinking to the number already waiting releases them
+        barrier.resize(2).unwrap();
+        let r1 = t1.join().unwrap().unwrap();
+        let r2 = t2.join().unwrap().unwrap();
+        assert_eq!((r1.generation(), r2.generation()), (0, 0));
+        assert!(!r1.is_leader() && !r2.is_leader());
+        assert_eq!(barrier.state().arrived, 0);
+        assert_eq!(barrier.state().total, 2);
+    }
+
+    #[test]
+    fn test_shrink_to_arrivals_releases() {
+        let barrier = CancellableBarrier::new(5);
+
+        // async waiters are let go along with blocking ones
+        let mut waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait())
+            })
+            .collect();
+        waiters.push({
+            let barrier = barrier.clone();
+            thread::spawn(move || block_on(barrier.wait_async()))
+        });
+        while barrier.state().arrived < 3 {
+            thread::yield_now();
+        }
+
+        // nobody else arrives: the resize itself trips the barrier
+        barrier.resize(3).unwrap();
+        for waiter in waiters {
+            assert_eq!(waiter.join().unwrap().map(|r| r.generation()), Ok(0));
+        }
+        assert_eq!(barrier.state().generation, 1);
+        assert_eq!(barrier.state().arrived, 0);
+    }
+
+    #[test]
+    fn test_resize_while_waiting_stress() {
+        const WAITERS: usize = 4;
+        let barrier = CancellableBarrier::new(WAITERS);
+        let stop = std::sync::Arc::new(AtomicBool::new(false));
+
+        let waiters: Vec<_> = (0..WAITERS)
+            .map(|_| {
+                let (barrier, stop) = (barrier.clone(), stop.clone());
+                thread::spawn(move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        // a waiter stuck on a generation that should have released times out
+                        barrier.wait_timeout(Duration::from_secs(5))?;
+                    }
+                    Ok::<_, BarrierWaitError>(())
+                })
+            })
+            .collect();
+
+        let resizer = {
+            let (barrier, stop) = (barrier.clone(), stop.clone());
+            thread::spawn(move || {
+                let mut sizes = (1..=WAITERS).cycle();
+                while !stop.load(Ordering::Relaxed) {
+                    let size = sizes.next().unwrap();
+                    if size == WAITERS {
+                        barrier.reset_with(size).unwrap();
+                    } else {
+                        barrier.resize(size).unwrap();
+                    }
+                    // every arrival that completes a generation releases it
+                    let state = barrier.state();
+                    assert!(
+                        state.arrived == 0 || state.arrived < state.total,
+                        "{state:?}"
+                    );
+                    thread::yield_now();
+                }
+            })
+        };
+
+        thread::sleep(TEST_SLEEP_DURATION * 2);
+        stop.store(true, Ordering::Relaxed);
+        resizer.join().unwrap();
+        // let whoever is still waiting go, and anyone arriving from now on straight through
+        barrier.resize(1).unwrap();
+        for waiter in waiters {
+            waiter.join().unwrap().unwrap();
+        }
+        assert!(barrier.state().generation > 0);
+    }
+
+    #[test]
+    fn test_add_remove_party() {
+        let barrier = CancellableBarrier::new(1);
+        assert_eq!(barrier.remove_party(), Err(BarrierBuildError::ZeroParties));
+
+        barrier.add_party();
+        barrier.add_party();
+        assert_eq!(barrier.state().total, 3);
+
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait())
+            })
+            .collect();
+        while barrier.state().arrived < 2 {
+            thread::yield_now();
+        }
+
+        // The third party drops out, so the two already waiting are released
+        barrier.remove_party().unwrap();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().map(|r| r.generation()), Ok(0));
+        }
+        assert_eq!(barrier.state().total, 2);
+    }
+
+    #[test]
+    fn test_remaining_across_resize() {
+        let barrier = CancellableBarrier::new(4);
+        assert_eq!(barrier.remaining(), 4);
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait())
+        };
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(barrier.remaining(), 3);
+
+        // never a total from one moment with a count from another, starting from 4
+        let resizer = {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                for round in 0..1000 {
+                    barrier.resize(if round % 2 == 0 { 6 } else { 3 }).unwrap();
+                }
+            })
+        };
+        while !resizer.is_finished() {
+            assert!(matches!(barrier.remaining(), 2 | 3 | 5));
+        }
+        resizer.join().unwrap();
+        assert_eq!(barrier.remaining(), 2);
+        barrier.resize(1).unwrap();
+        waiter.join().unwrap().unwrap();
+        assert_eq!(barrier.remaining(), 1);
+
+        // a quorum barrier counts towards its quorum
+        assert_eq!(CancellableBarrier::quorum(5, 3).remaining(), 3);
+    }
+
+    #[test]
+    fn test_total_follows_resize() {
+        let barrier = CancellableBarrier::new(2);
+        assert_eq!(barrier.total(), 2);
+        barrier.resize(5).unwrap();
+        barrier.add_party();
+        barrier.remove_party().unwrap();
+        assert_eq!(barrier.total(), 5);
+        let handle = barrier.join();
+        assert_eq!(barrier.total(), 6);
+        drop(handle);
+        assert_eq!(barrier.total(), 5);
+        assert_eq!(CancellableBarrier::quorum(5, 3).total(), 3);
+    }
+
+    #[test]
+    fn test_reset_with() {
+        let barrier = CancellableBarrier::new(3);
+        assert_eq!(barrier.reset_with(0), Err(BarrierBuildError::ZeroParties));
+
+        let barrier_clone1 = barrier.clone();
+        let stale = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        barrier.reset_with(2).unwrap();
+        // the stale waiter is woken and let go
+        assert!(stale.join().unwrap().is_ok());
+        let snapshot = barrier.state();
+        assert_eq!((snapshot.total, snapshot.arrived), (2, 0));
+
+        barrier.cancel();
+        barrier.reset_with(2).unwrap();
+        assert!(!barrier.is_cancelled());
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        assert!(barrier.wait().is_ok());
+        assert!(t1.join().unwrap().is_ok());
+    }
+}
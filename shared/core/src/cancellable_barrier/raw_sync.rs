@@ -0,0 +1,100 @@
+use std::ops::DerefMut;
+use std::sync::PoisonError;
+use std::time::Duration;
+
+use crate::sync::{Condvar, Mutex, MutexGuard};
+
+/// The lock and condition variable a [`CancellableBarrier`](super::CancellableBarrier)
+/// parks its threads with.
+///
+/// The barrier is generic over this, defaulting to [`DefaultSync`], so that another lock,
+/// such as a priority-inheriting or real-time one, can be plugged in through
+/// [`CancellableBarrierBuilder::build_with`](super::CancellableBarrierBuilder::build_with)
+/// without changing how the barrier is used. The other primitives in this module always
+/// use the default.
+///
+/// The barrier only ever locks its own state with this. [`Self::wait`] and
+/// [`Self::wait_timeout`] must give up the lock while blocked and take it again before
+/// returning, and may return early or spuriously, since the barrier checks its state
+/// again either way. A lock that can be poisoned should hand out the guard regardless:
+/// the state is only counters and flags updated together, so a panic can't leave it
+/// half-modified.
+pub trait RawSync: 'static {
+    /// Mutex holding a `T`
+    type Mutex<T: Send + 'static>: Send + Sync;
+    /// Proof of holding the lock, giving access to the `T`
+    type Guard<'a, T: Send + 'static>: DerefMut<Target = T>;
+    /// Condition variable to block on while holding a [`Self::Guard`]
+    type Condvar: Send + Sync;
+
+    /// Creates a mutex holding `value`
+    fn new_mutex<T: Send + 'static>(value: T) -> Self::Mutex<T>;
+
+    /// Blocks until the lock is held
+    fn lock<T: Send + 'static>(mutex: &Self::Mutex<T>) -> Self::Guard<'_, T>;
+
+    /// Creates a condition variable
+    fn new_condvar() -> Self::Condvar;
+
+    /// Gives up the lock until woken by [`Self::notify_all`], then takes it again
+    fn wait<'a, T: Send + 'static>(
+        condvar: &Self::Condvar,
+        guard: Self::Guard<'a, T>,
+    ) -> Self::Guard<'a, T>;
+
+    /// Like [`Self::wait`], but returns with the lock held again once `timeout` has
+    /// elapsed even if not woken
+    fn wait_timeout<'a, T: Send + 'static>(
+        condvar: &Self::Condvar,
+        guard: Self::Guard<'a, T>,
+        timeout: Duration,
+    ) -> Self::Guard<'a, T>;
+
+    /// Wakes every thread blocked in [`Self::wait`] or [`Self::wait_timeout`]
+    fn notify_all(condvar: &Self::Condvar);
+}
+
+/// The lock a barrier uses unless built with another: std's, or the one chosen by the
+/// `parking_lot` or `spin` feature, parking_lot's if both are, or loom's under `cfg(loom)`
+#[derive(Debug, Clone, Copy)]
+pub enum DefaultSync {}
+
+impl RawSync for DefaultSync {
+    type Mutex<T: Send + 'static> = Mutex<T>;
+    type Guard<'a, T: Send + 'static> = MutexGuard<'a, T>;
+    type Condvar = Condvar;
+
+    fn new_mutex<T: Send + 'static>(value: T) -> Self::Mutex<T> {
+        Mutex::new(value)
+    }
+
+    fn lock<T: Send + 'static>(mutex: &Self::Mutex<T>) -> Self::Guard<'_, T> {
+        mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn new_condvar() -> Self::Condvar {
+        Condvar::new()
+    }
+
+    fn wait<'a, T: Send + 'static>(
+        condvar: &Self::Condvar,
+        guard: Self::Guard<'a, T>,
+    ) -> Self::Guard<'a, T> {
+        condvar.wait(guard).unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait_timeout<'a, T: Send + 'static>(
+        condvar: &Self::Condvar,
+        guard: Self::Guard<'a, T>,
+        timeout: Duration,
+    ) -> Self::Guard<'a, T> {
+        condvar
+            .wait_timeout(guard, timeout)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+    }
+
+    fn notify_all(condvar: &Self::Condvar) {
+        condvar.notify_all();
+    }
+}
@@ -0,0 +1,401 @@
+use std::fmt;
+use std::mem;
+use std::sync::PoisonError;
+use std::time::{Duration, Instant};
+
+use super::{BarrierWaitError, CancelReason, CancelledBarrier};
+use crate::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+type Combine<T> = dyn Fn(T, T) -> T + Send + Sync;
+
+/// A barrier that folds a value from every thread into one result, which each thread
+/// receives once all have arrived.
+///
+/// The reduction is given once, when the barrier is built, with [`Self::new`] or
+/// [`super::CancellableBarrier::reducing`], so every thread's value goes through the same
+/// one. Contributions are folded one at a time in arrival order, starting from the
+/// identity: `combine(combine(identity, first), second)` and so on. Nothing is regrouped,
+/// so `combine` needn't be associative to get a well-defined result, and `identity` need
+/// only be where the fold starts. Arrival order isn't deterministic between runs, though,
+/// so use an associative and commutative `combine` (a sum, a max, ...) if the result
+/// must not depend on scheduling; an order-sensitive one such as concatenation gets the
+/// values in the order the threads happened to arrive.
+pub struct BarrierReduce<T> {
+    mutex: Mutex<ReduceState<T>>,
+    condvar: Condvar,
+    identity: T,
+    combine: Box<Combine<T>>,
+}
+
+#[derive(Debug)]
+struct ReduceState<T> {
+    total: usize,
+    count: usize,
+    generation: usize,
+    acc: T,
+    /// The result of the last release, with the generation it belongs to and how many
+    /// threads contributed to it
+    released: Option<(usize, T, usize)>,
+    cancelled: Option<CancelReason>,
+}
+
+impl<T: Clone> BarrierReduce<T> {
+    /// Creates a new reduction between `n` threads
+    #[must_use]
+    pub fn new(
+        n: usize,
+        identity: T,
+        combine: impl Fn(T, T) -> T + Send + Sync + 'static,
+    ) -> Arc<Self> {
+        assert!(n > 0, "Barrier size must be greater than 0");
+        Arc::new(BarrierReduce {
+            mutex: Mutex::new(ReduceState {
+                total: n,
+                count: 0,
+                generation: 0,
+                acc: identity.clone(),
+                released: None,
+                cancelled: None,
+            }),
+            condvar: Condvar::new(),
+            identity,
+            combine: Box::new(combine),
+        })
+    }
+
+    /// Folds `value` into the current generation and waits until all threads have
+    /// contributed or the reduction is cancelled.
+    ///
+    /// On release, every thread gets the reduced value of its generation. That includes
+    /// a generation released early by [`Self::wait_reduce_timeout`], whose value then
+    /// reduces only the contributions received by then.
+    pub fn wait_reduce(&self, value: T) -> Result<T, BarrierWaitError> {
+        self.wait_inner(value, None)
+            .map(|(result, _contributed)| result)
+    }
+
+    /// Like [`Self::wait_reduce`], but once `timeout` has elapsed without every thread
+    /// contributing, releases the generation with the values received so far instead of
+    /// failing.
+    ///
+    /// This suits approximate aggregation, where a late worker can be skipped: the
+    /// outcome tells a complete reduction from a partial one and how many threads it
+    /// covers, and every thread waiting with a timeout gets the same. A thread that
+    /// contributes after the partial release counts towards the next generation, which
+    /// starts again from the identity.
+    pub fn wait_reduce_timeout(
+        &self,
+        value: T,
+        timeout: Duration,
+    ) -> Result<ReduceOutcome<T>, BarrierWaitError> {
+        let total = self.lock().total;
+        let (value, contributed) = self.wait_inner(value, Instant::now().checked_add(timeout))?;
+        Ok(if contributed >= total {
+            ReduceOutcome::Complete(value)
+        } else {
+            ReduceOutcome::Partial {
+                value,
+                contributed,
+                total,
+            }
+        })
+    }
+
+    /// Waits for the generation to be released, by everyone contributing or, with a
+    /// `deadline`, by running out of time, returning its result and how many threads
+    /// contributed to it
+    fn wait_inner(
+        &self,
+        value: T,
+        deadline: Option<Instant>,
+    ) -> Result<(T, usize), BarrierWaitError> {
+        let mut state = self.lock();
+        check_cancelled(&state)?;
+
+        let generation = state.generation;
+        let acc = mem::replace(&mut state.acc, self.identity.clone());
+        state.acc = (self.combine)(acc, value);
+        state.count += 1;
+
+        if state.count >= state.total {
+            // Last thread to arrive
+            return Ok(self.release(&mut state));
+        }
+
+        while state.generation == generation && state.cancelled.is_none() {
+            let now = Instant::now();
+            match deadline {
+                Some(deadline) if now >= deadline => {
+                    // go ahead with whoever is here
+                    return Ok(self.release(&mut state));
+                }
+                Some(deadline) => {
+                    state = self
+                        .condvar
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                }
+                None => {
+                    state = self
+                        .condvar
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner)
+                }
+            }
+        }
+
+        // a generation that was released stays released, even if the reduction was
+        // cancelled before we got to run again
+        match &state.released {
+            Some((released, result, contributed)) if *released == generation => {
+                Ok((result.clone(), *contributed))
+            }
+            _ => {
+                check_cancelled(&state)?;
+                // our generation was thrown away by a reset
+                Err(CancelledBarrier::new(CancelReason::Unspecified).into())
+            }
+        }
+    }
+
+    /// Releases the generation in progress, with whatever it has folded so far, and
+    /// starts the next one from the identity
+    fn release(&self, state: &mut ReduceState<T>) -> (T, usize) {
+        let result = mem::replace(&mut state.acc, self.identity.clone());
+        let contributed = state.count;
+        state.released = Some((state.generation, result.clone(), contributed));
+        state.count = 0;
+        state.generation = state.generation.wrapping_add(1);
+        self.condvar.notify_all();
+        (result, contributed)
+    }
+
+    /// Cancels the reduction, causing all waiting threads to return with an error
+    pub fn cancel(&self) {
+        self.cancel_with(CancelReason::Unspecified);
+    }
+
+    /// Cancels the reduction with a `reason` that waiting threads receive in their error
+    pub fn cancel_with(&self, reason: CancelReason) {
+        let mut state = self.lock();
+        state.cancelled = Some(reason);
+        self.condvar.notify_all();
+    }
+
+    /// Resets the reduction to its initial state.
+    ///
+    /// The partial result of the unfinished generation is dropped. Threads still waiting
+    /// on it are woken with a cancelled error.
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.cancelled = None;
+        state.count = 0;
+        state.acc = self.identity.clone();
+        state.released = None;
+        state.generation = state.generation.wrapping_add(1);
+        self.condvar.notify_all();
+    }
+
+    /// Returns true if the reduction is currently cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled.is_some()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ReduceState<T>> {
+        self.mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// How a generation of a [`BarrierReduce`] was released, as returned by
+/// [`BarrierReduce::wait_reduce_timeout`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReduceOutcome<T> {
+    /// Every thread contributed to the value
+    Complete(T),
+    /// The timeout ran out first, and the value reduces only some of the contributions
+    Partial {
+        /// The reduction of the values received before the timeout
+        value: T,
+        /// Threads whose values it covers
+        contributed: usize,
+        /// Threads the generation needed
+        total: usize,
+    },
+}
+
+impl<T> ReduceOutcome<T> {
+    /// Returns the reduced value, complete or not
+    #[must_use]
+    pub fn into_value(self) -> T {
+        match self {
+            ReduceOutcome::Complete(value) | ReduceOutcome::Partial { value, .. } => value,
+        }
+    }
+
+    /// Returns true if the generation was released before every thread contributed
+    #[must_use]
+    pub fn is_partial(&self) -> bool {
+        matches!(self, ReduceOutcome::Partial { .. })
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for BarrierReduce<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BarrierReduce")
+            .field("mutex", &self.mutex)
+            .field("identity", &self.identity)
+            .finish_non_exhaustive()
+    }
+}
+
+fn check_cancelled<T>(state: &ReduceState<T>) -> Result<(), BarrierWaitError> {
+    match &state.cancelled {
+        Some(reason) => Err(CancelledBarrier::new(reason.clone()).into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_reduce_sum() {
+        let reduce = BarrierReduce::new(4, 0, |a, b| a + b);
+
+        let handles: Vec<_> = (1..=4)
+            .map(|i| {
+                let reduce = reduce.clone();
+                thread::spawn(move || reduce.wait_reduce(i).unwrap())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), 10);
+        }
+
+        // the next generation starts again from the identity
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let reduce = reduce.clone();
+                thread::spawn(move || reduce.wait_reduce(1).unwrap())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), 4);
+        }
+    }
+
+    #[test]
+    fn test_reduce_folds_in_arrival_order() {
+        let reduce = BarrierReduce::new(1, String::new(), |acc, s| acc + &s);
+        assert_eq!(reduce.wait_reduce("a".to_string()).unwrap(), "a");
+
+        let reduce = BarrierReduce::new(2, String::new(), |acc, s| acc + &s);
+        let reduce_clone = reduce.clone();
+        let t1 = thread::spawn(move || reduce_clone.wait_reduce("first ".to_string()));
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(
+            reduce.wait_reduce("second".to_string()).unwrap(),
+            "first second"
+        );
+        assert_eq!(t1.join().unwrap().unwrap(), "first second");
+    }
+
+    #[test]
+    fn test_reducing_configured_once() {
+        use crate::cancellable_barrier::CancellableBarrier;
+
+        let sum = CancellableBarrier::reducing(8, |a, b| a + b, 0u64);
+        let handles: Vec<_> = (1..=8)
+            .map(|i| {
+                let sum = sum.clone();
+                thread::spawn(move || sum.wait_reduce(i).unwrap())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), 36);
+        }
+
+        // concatenation sees the values in arrival order
+        let concat = CancellableBarrier::reducing(3, |acc: String, s| acc + &s, String::new());
+        let handles: Vec<_> = ["a", "b"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, s)| {
+                let handle = {
+                    let concat = concat.clone();
+                    thread::spawn(move || concat.wait_reduce(s.to_string()))
+                };
+                while concat.lock().count <= i {
+                    thread::yield_now();
+                }
+                handle
+            })
+            .collect();
+        assert_eq!(concat.wait_reduce("c".to_string()).unwrap(), "abc");
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().unwrap(), "abc");
+        }
+    }
+
+    #[test]
+    fn test_reduce_timeout_releases_partial() {
+        let reduce = BarrierReduce::new(3, 0, |a, b| a + b);
+        let reduce_clone = reduce.clone();
+        let t1 =
+            thread::spawn(move || reduce_clone.wait_reduce_timeout(1, Duration::from_secs(10)));
+        while reduce.lock().count < 1 {
+            thread::yield_now();
+        }
+        // the third worker is late, so the two others go ahead without it
+        let outcome = reduce.wait_reduce_timeout(2, Duration::from_millis(50));
+        let partial = ReduceOutcome::Partial {
+            value: 3,
+            contributed: 2,
+            total: 3,
+        };
+        assert_eq!(outcome, Ok(partial.clone()));
+        assert_eq!(t1.join().unwrap(), Ok(partial));
+
+        // the late value starts the next generation afresh
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let reduce = reduce.clone();
+                thread::spawn(move || reduce.wait_reduce(10))
+            })
+            .collect();
+        let outcome = reduce
+            .wait_reduce_timeout(10, Duration::from_secs(10))
+            .unwrap();
+        assert!(!outcome.is_partial());
+        assert_eq!(outcome.into_value(), 30);
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), Ok(30));
+        }
+    }
+
+    #[test]
+    fn test_reduce_reset_and_cancel() {
+        let reduce = BarrierReduce::new(2, 0, |a, b| a + b);
+
+        let reduce_clone = reduce.clone();
+        let t1 = thread::spawn(move || reduce_clone.wait_reduce(5));
+        thread::sleep(Duration::from_millis(100));
+        reduce.reset();
+        assert!(t1.join().unwrap().is_err());
+
+        // the dropped contribution doesn't show up in the next result
+        let reduce_clone = reduce.clone();
+        let t1 = thread::spawn(move || reduce_clone.wait_reduce(1));
+        assert_eq!(reduce.wait_reduce(2), Ok(3));
+        assert_eq!(t1.join().unwrap(), Ok(3));
+
+        reduce.cancel();
+        assert!(reduce.is_cancelled());
+        assert!(reduce.wait_reduce(1).is_err());
+    }
+}
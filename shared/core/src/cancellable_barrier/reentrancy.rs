@@ -0,0 +1,69 @@
+//! Catches a thread waiting again on a barrier it's already waiting on.
+//!
+//! That can only happen from code the barrier runs on the waiting thread, such as the
+//! action, a listener or an observer, which runs with the barrier locked, so it deadlocks
+//! or, with some locks, panics far from the bug. With the check on, each thread keeps
+//! the barriers it is inside a wait of, and waiting on one of them again panics straight
+//! away, naming the barrier.
+//!
+//! The check is on in debug builds and in any build with the `reentrancy` feature, and
+//! costs nothing otherwise. Loom runs its threads on one of its own, so it's off there.
+
+#[cfg(all(any(debug_assertions, feature = "reentrancy"), not(loom)))]
+mod imp {
+    use std::cell::RefCell;
+
+    thread_local! {
+        /// Addresses of the barriers the current thread is inside a wait of
+        static INSIDE: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Marks the current thread as inside a wait of a barrier until dropped
+    pub(in super::super) struct WaitMarker(usize);
+
+    impl WaitMarker {
+        /// Marks the current thread as inside a wait of the barrier at `barrier`.
+        ///
+        /// # Panics
+        ///
+        /// Panics if it already is, since waiting again can only deadlock.
+        pub(in super::super) fn enter<T>(barrier: &T, name: Option<&str>) -> Self {
+            let address = std::ptr::from_ref(barrier) as usize;
+            INSIDE.with_borrow_mut(|inside| {
+                if inside.contains(&address) {
+                    panic!(
+                        "re-entered a wait on barrier {} from a thread already waiting on it, \
+                         such as from its action or a listener",
+                        name.unwrap_or("<unnamed>")
+                    );
+                }
+                inside.push(address);
+            });
+            WaitMarker(address)
+        }
+    }
+
+    impl Drop for WaitMarker {
+        fn drop(&mut self) {
+            INSIDE.with_borrow_mut(|inside| {
+                if let Some(position) = inside.iter().rposition(|&address| address == self.0) {
+                    inside.swap_remove(position);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(not(all(any(debug_assertions, feature = "reentrancy"), not(loom))))]
+mod imp {
+    /// Does nothing with the check off
+    pub(in super::super) struct WaitMarker;
+
+    impl WaitMarker {
+        pub(in super::super) fn enter<T>(_barrier: &T, _name: Option<&str>) -> Self {
+            WaitMarker
+        }
+    }
+}
+
+pub(super) use imp::WaitMarker;
@@ -0,0 +1,174 @@
+use std::sync::PoisonError;
+
+use super::{BarrierWaitError, CancelReason, CancelledBarrier};
+use crate::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// A meeting point where two threads swap values, like Java's `Exchanger`.
+///
+/// The first thread to call [`Self::exchange`] parks with its value until a partner
+/// arrives, then each gets the other's value. Further threads pair up the same way, two at
+/// a time. This is what a [`super::BarrierExchange`] of two does, without the shared
+/// vector and the need to work out which entry is the partner's.
+#[derive(Debug)]
+pub struct Rendezvous<T> {
+    mutex: Mutex<RendezvousState<T>>,
+    condvar: Condvar,
+}
+
+#[derive(Debug)]
+struct RendezvousState<T> {
+    /// The value of the thread parked waiting for a partner
+    offer: Option<T>,
+    /// The partner's value, until the parked thread picks it up
+    reply: Option<T>,
+    cancelled: Option<CancelReason>,
+}
+
+impl<T> Rendezvous<T> {
+    /// Creates a rendezvous with nobody waiting
+    #[must_use]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Rendezvous {
+            mutex: Mutex::new(RendezvousState {
+                offer: None,
+                reply: None,
+                cancelled: None,
+            }),
+            condvar: Condvar::new(),
+        })
+    }
+
+    /// Hands `value` to the partner thread and returns the partner's value, waiting for
+    /// one to arrive if needed, or returns an error if the rendezvous is cancelled first.
+    ///
+    /// If the rendezvous is cancelled, the value isn't handed over and is dropped.
+    pub fn exchange(&self, value: T) -> Result<T, BarrierWaitError> {
+        let mut state = self.lock();
+        loop {
+            check_cancelled(&state)?;
+            if state.reply.is_none() {
+                break;
+            }
+            // the previous pair hasn't finished swapping yet
+            state = self
+                .condvar
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+
+        if let Some(offer) = state.offer.take() {
+            // Second thread to arrive
+            state.reply = Some(value);
+            self.condvar.notify_all();
+            return Ok(offer);
+        }
+
+        state.offer = Some(value);
+        while state.reply.is_none() && state.cancelled.is_none() {
+            state = self
+                .condvar
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        // a partner that made it in time completes the swap, even if the rendezvous was
+        // cancelled before we got to run again
+        match state.reply.take() {
+            Some(reply) => {
+                // let the next pair in
+                self.condvar.notify_all();
+                Ok(reply)
+            }
+            None => {
+                // we only stop waiting without a reply once cancelled
+                state.offer = None;
+                let reason = state.cancelled.clone().unwrap_or(CancelReason::Unspecified);
+                Err(CancelledBarrier::new(reason).into())
+            }
+        }
+    }
+
+    /// Cancels the rendezvous, causing a waiting thread to return with an error
+    pub fn cancel(&self) {
+        self.cancel_with(CancelReason::Unspecified);
+    }
+
+    /// Cancels the rendezvous with a `reason` that a waiting thread receives in its error
+    pub fn cancel_with(&self, reason: CancelReason) {
+        let mut state = self.lock();
+        state.cancelled = Some(reason);
+        self.condvar.notify_all();
+    }
+
+    /// Returns true if the rendezvous is currently cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled.is_some()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RendezvousState<T>> {
+        self.mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn check_cancelled<T>(state: &RendezvousState<T>) -> Result<(), BarrierWaitError> {
+    match &state.cancelled {
+        Some(reason) => Err(CancelledBarrier::new(reason.clone()).into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rendezvous_swaps_values() {
+        let rendezvous = Rendezvous::new();
+
+        let rendezvous_clone = rendezvous.clone();
+        let producer = thread::spawn(move || rendezvous_clone.exchange("from producer"));
+        assert_eq!(rendezvous.exchange("from consumer"), Ok("from producer"));
+        assert_eq!(producer.join().unwrap(), Ok("from consumer"));
+    }
+
+    #[test]
+    fn test_rendezvous_pairs_many_threads() {
+        let rendezvous = Rendezvous::new();
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let rendezvous = rendezvous.clone();
+                thread::spawn(move || (i, rendezvous.exchange(i).unwrap()))
+            })
+            .collect();
+        let mut partners = [0; 8];
+        for handle in handles {
+            let (i, partner) = handle.join().unwrap();
+            partners[i] = partner;
+        }
+        // everyone was paired with someone who was paired back with them
+        for (i, &partner) in partners.iter().enumerate() {
+            assert_ne!(partner, i);
+            assert_eq!(partners[partner], i);
+        }
+    }
+
+    #[test]
+    fn test_rendezvous_cancel_wakes_partner() {
+        let rendezvous = Rendezvous::new();
+
+        let rendezvous_clone = rendezvous.clone();
+        let waiter = thread::spawn(move || rendezvous_clone.exchange(1));
+        thread::sleep(Duration::from_millis(100));
+        rendezvous.cancel_with(CancelReason::Shutdown);
+        assert_eq!(
+            waiter.join().unwrap().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Shutdown)
+        );
+        assert!(rendezvous.is_cancelled());
+        assert!(rendezvous.exchange(2).is_err());
+        assert!(rendezvous.lock().offer.is_none());
+    }
+}
@@ -0,0 +1,152 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Duration;
+
+use super::select::ThreadWaker;
+use super::{
+    BarrierWait, BarrierWaitError, BarrierWaitResult, CancellableBarrier, RawSync,
+    BLOCKING_SUPPORTED,
+};
+
+/// An arrival at a [`CancellableBarrier`] that stays counted across timed checks, returned
+/// by [`CancellableBarrier::arrive_retained`].
+///
+/// The arrival is registered when the handle is created. Each [`Self::wait_timeout`] then
+/// waits up to its timeout for the generation to complete, and running out of time only
+/// ends that check: the thread stays a committed participant, and checking again doesn't
+/// count it a second time. That suits a thread that has to get on with other work, such
+/// as a UI asking every frame whether everyone has synced yet.
+///
+/// Dropping the handle before its generation is released rolls the arrival back, as
+/// dropping a [`BarrierWait`] does. Once released, the arrival stands.
+#[derive(Debug)]
+#[must_use = "dropping the handle rolls the arrival back"]
+pub struct RetainedWait<'a, S: RawSync> {
+    barrier: &'a CancellableBarrier<S>,
+    wait: BarrierWait<'a, S>,
+    /// The outcome, once the wait has completed
+    outcome: Option<Result<BarrierWaitResult, BarrierWaitError>>,
+}
+
+impl<'a, S: RawSync> RetainedWait<'a, S> {
+    pub(super) fn new(barrier: &'a CancellableBarrier<S>) -> Self {
+        let mut retained = RetainedWait {
+            barrier,
+            wait: barrier.wait_async(),
+            outcome: None,
+        };
+        retained.poll();
+        retained
+    }
+
+    /// Waits up to `timeout` for the generation to complete, returning `Ok(None)` if it
+    /// hasn't by then.
+    ///
+    /// With a zero timeout this only checks. Once the wait has completed, every later
+    /// call returns its outcome again straight away. On a target that can't block, a
+    /// check that would have to wait fails with [`BarrierWaitError::WouldBlock`], and the
+    /// arrival is kept.
+    pub fn wait_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<BarrierWaitResult>, BarrierWaitError> {
+        let deadline = self.barrier.clock.now().checked_add(timeout);
+        loop {
+            if let Some(outcome) = self.poll() {
+                return outcome.map(Some);
+            }
+            let now = self.barrier.clock.now();
+            let mut park_for = match deadline {
+                Some(deadline) if deadline <= now => return Ok(None),
+                Some(deadline) => deadline - now,
+                None => Duration::MAX,
+            };
+            if let Some(interval) = self.barrier.clock.poll_interval() {
+                park_for = park_for.min(interval);
+            }
+            if !BLOCKING_SUPPORTED {
+                return Err(BarrierWaitError::WouldBlock);
+            }
+            thread::park_timeout(park_for);
+        }
+    }
+
+    /// Returns true once the wait has completed, whether released or failed, without
+    /// waiting
+    #[must_use]
+    pub fn is_complete(&mut self) -> bool {
+        self.poll().is_some()
+    }
+
+    /// Polls the wait, on behalf of the current thread
+    fn poll(&mut self) -> Option<Result<BarrierWaitResult, BarrierWaitError>> {
+        if self.outcome.is_none() {
+            let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+            let mut cx = Context::from_waker(&waker);
+            if let Poll::Ready(outcome) = Pin::new(&mut self.wait).poll(&mut cx) {
+                self.outcome = Some(outcome);
+            }
+        }
+        self.outcome.clone()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::cancellable_barrier::CancelReason;
+
+    #[test]
+    fn test_retained_wait_keeps_the_arrival() {
+        let barrier = CancellableBarrier::new(3);
+        let mut retained = barrier.arrive_retained();
+        assert_eq!(barrier.state().arrived, 1);
+
+        // checking again and again counts the thread once
+        for _ in 0..3 {
+            assert_eq!(retained.wait_timeout(Duration::from_millis(10)), Ok(None));
+        }
+        assert!(!retained.is_complete());
+        assert_eq!(barrier.state().arrived, 1);
+
+        let other = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait())
+        };
+        while barrier.state().arrived < 2 {
+            thread::yield_now();
+        }
+        let leader = barrier.wait().unwrap();
+        assert!(leader.is_leader());
+        let result = retained
+            .wait_timeout(Duration::from_secs(10))
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.generation(), 0);
+        assert!(retained.is_complete());
+        assert_eq!(retained.wait_timeout(Duration::ZERO), Ok(Some(result)));
+        other.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_retained_wait_drop_rolls_back() {
+        let barrier = CancellableBarrier::new(2);
+        let retained = barrier.arrive_retained();
+        assert_eq!(barrier.state().arrived, 1);
+        drop(retained);
+        assert_eq!(barrier.state().arrived, 0);
+
+        let mut retained = barrier.arrive_retained();
+        barrier.cancel_with(CancelReason::Shutdown);
+        assert_eq!(
+            retained
+                .wait_timeout(Duration::from_secs(10))
+                .unwrap_err()
+                .cancel_reason(),
+            Some(&CancelReason::Shutdown)
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use super::{
+    BarrierWait, BarrierWaitError, BarrierWaitResult, CancellableBarrier, RawSync,
+    BLOCKING_SUPPORTED,
+};
+
+/// Waits on several barriers at once, returning as soon as any one of them releases or is
+/// cancelled.
+///
+/// The current thread arrives at every barrier in `barriers`, in order, and the first wait
+/// to complete wins: the function returns its index along with the generation it
+/// released, or the error it failed with. If arriving at a barrier completes it, the
+/// later ones aren't arrived at at all.
+///
+/// The thread then withdraws from the others. Each withdrawal takes that barrier's lock
+/// and either gives the arrival back, if its generation is still waiting, or finds it
+/// already released, so no barrier is left counting a thread that has gone. A barrier
+/// whose generation released before the thread could withdraw, because it completed at
+/// about the same time as the winner, keeps the arrival: its other parties were let go
+/// with this thread counted, as with the losing branches of a `select!` over
+/// [`CancellableBarrier::wait_async`] futures. Only the winner's result is reported.
+///
+/// On a target that can't block, a wait that would have to fails with
+/// [`BarrierWaitError::WouldBlock`] for the first barrier, after withdrawing from all.
+///
+/// # Panics
+///
+/// Panics if `barriers` is empty.
+pub fn wait_any<S: RawSync>(
+    barriers: &[&CancellableBarrier<S>],
+) -> (usize, Result<usize, BarrierWaitError>) {
+    assert!(!barriers.is_empty(), "wait_any needs at least one barrier");
+
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut waits: Vec<BarrierWait<'_, S>> = Vec::with_capacity(barriers.len());
+    loop {
+        // poll the waits already arrived, then arrive at the next barrier, so that a
+        // release seen on one stops us from arriving at those after it
+        for (index, wait) in waits.iter_mut().enumerate() {
+            if let Poll::Ready(result) = Pin::new(wait).poll(&mut cx) {
+                return finish(waits, index, result);
+            }
+        }
+        if let Some(barrier) = barriers.get(waits.len()) {
+            let mut wait = barrier.wait_async();
+            let index = waits.len();
+            if let Poll::Ready(result) = Pin::new(&mut wait).poll(&mut cx) {
+                return finish(waits, index, result);
+            }
+            waits.push(wait);
+            continue;
+        }
+        if !BLOCKING_SUPPORTED {
+            drop(waits);
+            return (0, Err(BarrierWaitError::WouldBlock));
+        }
+        thread::park();
+    }
+}
+
+/// Withdraws the losing waits, each under its barrier's lock as it is dropped, and
+/// returns the winner's result
+fn finish<S: RawSync>(
+    losers: Vec<BarrierWait<'_, S>>,
+    index: usize,
+    result: Result<BarrierWaitResult, BarrierWaitError>,
+) -> (usize, Result<usize, BarrierWaitError>) {
+    drop(losers);
+    (index, result.map(|result| result.generation()))
+}
+
+/// Wakes a thread blocked on barrier futures, in [`wait_any`] or a
+/// [`super::RetainedWait`]
+pub(super) struct ThreadWaker(pub(super) Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::cancellable_barrier::CancelReason;
+    use std::time::Duration;
+
+    #[test]
+    fn test_wait_any_withdraws_from_losers() {
+        let first = CancellableBarrier::new(2);
+        let second = CancellableBarrier::new(2);
+
+        let selecting = {
+            let (first, second) = (first.clone(), second.clone());
+            thread::spawn(move || wait_any(&[&*first, &*second]))
+        };
+        while first.state().arrived == 0 || second.state().arrived == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(second.wait().unwrap().generation(), 0);
+        assert_eq!(selecting.join().unwrap(), (1, Ok(0)));
+
+        // no phantom arrival left behind on the barrier that lost
+        assert_eq!(first.state().arrived, 0);
+        let other = {
+            let first = first.clone();
+            thread::spawn(move || first.wait_timeout(Duration::from_secs(10)))
+        };
+        assert_eq!(first.wait().unwrap().generation(), 0);
+        other.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_wait_any_cancel_and_immediate_release() {
+        let first = CancellableBarrier::new(2);
+        let second = CancellableBarrier::new(2);
+        let selecting = {
+            let (first, second) = (first.clone(), second.clone());
+            thread::spawn(move || wait_any(&[&*first, &*second]))
+        };
+        while second.state().arrived == 0 {
+            thread::yield_now();
+        }
+        first.cancel_with(CancelReason::Shutdown);
+        let (index, result) = selecting.join().unwrap();
+        assert_eq!(index, 0);
+        assert_eq!(
+            result.unwrap_err().cancel_reason(),
+            Some(&CancelReason::Shutdown)
+        );
+        assert_eq!(second.state().arrived, 0);
+
+        // arriving completes the single-party barrier, so the one after it is untouched
+        let single = CancellableBarrier::new(1);
+        assert_eq!(wait_any(&[&*single, &*second]), (0, Ok(0)));
+        assert_eq!(second.state().arrived, 0);
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::VecDeque;
+use std::sync::PoisonError;
+use std::time::{Duration, Instant};
+
+use super::{BarrierWaitError, CancelReason, CancelledBarrier};
+use crate::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// A counting semaphore whose blocked acquirers can be woken with an error by cancelling
+/// it, for bounding concurrency in code that also has to shut down cleanly.
+///
+/// Permits are handed out in the order acquirers arrive, so a steady stream of new
+/// acquirers can't starve one that has been waiting longer. [`Self::try_acquire`] doesn't
+/// jump the queue either: it fails while anyone is waiting.
+#[derive(Debug)]
+pub struct CancellableSemaphore {
+    mutex: Mutex<SemaphoreState>,
+    condvar: Condvar,
+}
+
+#[derive(Debug)]
+struct SemaphoreState {
+    permits: usize,
+    /// Tickets of the blocked acquirers, in arrival order
+    queue: VecDeque<u64>,
+    next_ticket: u64,
+    cancelled: Option<CancelReason>,
+}
+
+impl SemaphoreState {
+    fn check_cancelled(&self) -> Result<(), BarrierWaitError> {
+        match &self.cancelled {
+            Some(reason) => Err(CancelledBarrier::new(reason.clone()).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl CancellableSemaphore {
+    /// Creates a semaphore with `permits` permits available
+    #[must_use]
+    pub fn new(permits: usize) -> Arc<Self> {
+        Arc::new(CancellableSemaphore {
+            mutex: Mutex::new(SemaphoreState {
+                permits,
+                queue: VecDeque::new(),
+                next_ticket: 0,
+                cancelled: None,
+            }),
+            condvar: Condvar::new(),
+        })
+    }
+
+    /// Takes a permit, waiting for one to be released if none are available, or returns
+    /// an error if the semaphore is cancelled first
+    pub fn acquire(&self) -> Result<(), BarrierWaitError> {
+        self.acquire_inner(None)
+    }
+
+    /// Like [`Self::acquire`], but gives up once `timeout` has elapsed, returning
+    /// [`BarrierWaitError::TimedOut`]
+    pub fn acquire_timeout(&self, timeout: Duration) -> Result<(), BarrierWaitError> {
+        self.acquire_inner(Instant::now().checked_add(timeout))
+    }
+
+    /// Takes a permit if one is available right away and nobody is queued for it.
+    ///
+    /// Returns false if the semaphore is cancelled.
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.lock();
+        if state.cancelled.is_some() || state.permits == 0 || !state.queue.is_empty() {
+            return false;
+        }
+        state.permits -= 1;
+        true
+    }
+
+    fn acquire_inner(&self, deadline: Option<Instant>) -> Result<(), BarrierWaitError> {
+        let mut state = self.lock();
+        state.check_cancelled()?;
+        if state.permits > 0 && state.queue.is_empty() {
+            state.permits -= 1;
+            return Ok(());
+        }
+
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.queue.push_back(ticket);
+        loop {
+            if let Err(err) = state.check_cancelled() {
+                self.leave_queue(&mut state, ticket);
+                return Err(err);
+            }
+            if state.permits > 0 && state.queue.front() == Some(&ticket) {
+                state.permits -= 1;
+                state.queue.pop_front();
+                if state.permits > 0 {
+                    // let the next in line take the permit that's left
+                    self.condvar.notify_all();
+                }
+                return Ok(());
+            }
+
+            // recompute what's left every time we wake, spurious or not
+            let now = Instant::now();
+            match deadline {
+                Some(deadline) if now >= deadline => {
+                    self.leave_queue(&mut state, ticket);
+                    return Err(BarrierWaitError::timed_out(0, 0));
+                }
+                Some(deadline) => {
+                    state = self
+                        .condvar
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                }
+                None => {
+                    state = self
+                        .condvar
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner)
+                }
+            }
+        }
+    }
+
+    /// Takes `ticket` out of the queue, handing the turn on if it was at the front
+    fn leave_queue(&self, state: &mut SemaphoreState, ticket: u64) {
+        let was_front = state.queue.front() == Some(&ticket);
+        state.queue.retain(|&t| t != ticket);
+        if was_front {
+            self.condvar.notify_all();
+        }
+    }
+
+    /// Returns a permit, waking the longest waiting acquirer
+    pub fn release(&self) {
+        let mut state = self.lock();
+        state.permits += 1;
+        self.condvar.notify_all();
+    }
+
+    /// Cancels the semaphore, causing all blocked and future acquirers to return with an
+    /// error. Permits can still be released.
+    pub fn cancel(&self) {
+        self.cancel_with(CancelReason::Unspecified);
+    }
+
+    /// Cancels the semaphore with a `reason` that blocked acquirers receive in their error
+    pub fn cancel_with(&self, reason: CancelReason) {
+        let mut state = self.lock();
+        state.cancelled = Some(reason);
+        self.condvar.notify_all();
+    }
+
+    /// Returns the number of permits currently available
+    #[must_use]
+    pub fn available_permits(&self) -> usize {
+        self.lock().permits
+    }
+
+    /// Returns true if the semaphore is currently cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled.is_some()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SemaphoreState> {
+        self.mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_semaphore_bounds_concurrency() {
+        let semaphore = CancellableSemaphore::new(2);
+        let in_flight = std::sync::Arc::new(AtomicUsize::new(0));
+        let peak = std::sync::Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let semaphore = semaphore.clone();
+                let in_flight = in_flight.clone();
+                let peak = peak.clone();
+                thread::spawn(move || {
+                    semaphore.acquire().unwrap();
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    semaphore.release();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[test]
+    fn test_semaphore_is_fifo() {
+        let semaphore = CancellableSemaphore::new(0);
+
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let semaphore = semaphore.clone();
+                let handle = thread::spawn(move || semaphore.acquire());
+                // queue the acquirers up one at a time
+                thread::sleep(Duration::from_millis(50));
+                handle
+            })
+            .collect();
+        semaphore.release();
+        // the permit is reserved for the first acquirer in line
+        assert!(!semaphore.try_acquire());
+        let mut handles = handles.into_iter();
+        assert_eq!(handles.next().unwrap().join().unwrap(), Ok(()));
+        assert_eq!(semaphore.lock().queue.len(), 2);
+
+        semaphore.cancel();
+        for handle in handles {
+            assert!(handle.join().unwrap().is_err());
+        }
+    }
+
+    #[test]
+    fn test_semaphore_try_acquire_and_timeout() {
+        let semaphore = CancellableSemaphore::new(1);
+        assert!(semaphore.try_acquire());
+        assert!(!semaphore.try_acquire());
+        assert_eq!(
+            semaphore.acquire_timeout(Duration::from_millis(50)),
+            Err(BarrierWaitError::timed_out(0, 0))
+        );
+        // timing out gave up the place in the queue
+        semaphore.release();
+        assert!(semaphore.try_acquire());
+
+        semaphore.cancel_with(CancelReason::Shutdown);
+        assert_eq!(
+            semaphore.acquire().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Shutdown)
+        );
+        assert!(!semaphore.try_acquire());
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::sync::{PoisonError, RwLock, RwLockReadGuard};
+
+use super::{CancelReason, CancellableBarrier};
+use crate::sync::Arc;
+
+/// A registry of barriers looked up by name, so that independent parts of a program can
+/// rendezvous on a barrier without passing it around, and so that shutdown can cancel
+/// every barrier in one call.
+///
+/// Barriers are created on first use and live as long as the set, or until removed.
+#[derive(Debug, Default)]
+pub struct BarrierSet {
+    barriers: RwLock<HashMap<String, Arc<CancellableBarrier>>>,
+}
+
+impl BarrierSet {
+    /// Creates an empty set
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the barrier registered as `name`, creating one for `n` threads if there
+    /// isn't one yet.
+    ///
+    /// The barrier is named after its key. If it already exists, `n` is ignored and the
+    /// barrier keeps its current size.
+    pub fn get_or_create(&self, name: &str, n: usize) -> Arc<CancellableBarrier> {
+        if let Some(barrier) = self.get(name) {
+            return barrier;
+        }
+        let mut barriers = self
+            .barriers
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        // another thread may have registered it since we checked
+        barriers
+            .entry(name.to_owned())
+            .or_insert_with(|| CancellableBarrier::new_named(n, name))
+            .clone()
+    }
+
+    /// Returns the barrier registered as `name`, if any
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<Arc<CancellableBarrier>> {
+        self.read().get(name).cloned()
+    }
+
+    /// Removes the barrier registered as `name` from the set and returns it.
+    ///
+    /// Threads already holding the barrier can keep using it.
+    pub fn remove(&self, name: &str) -> Option<Arc<CancellableBarrier>> {
+        self.barriers
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(name)
+    }
+
+    /// Cancels every barrier in the set, causing all threads waiting on any of them to
+    /// return with an error
+    pub fn cancel_all(&self) {
+        self.cancel_all_with(CancelReason::Unspecified);
+    }
+
+    /// Cancels every barrier in the set with a `reason` that waiting threads receive in
+    /// their error
+    pub fn cancel_all_with(&self, reason: CancelReason) {
+        for barrier in self.snapshot() {
+            barrier.cancel_with(reason.clone());
+        }
+    }
+
+    /// Resets every barrier in the set to its initial state
+    pub fn reset_all(&self) {
+        for barrier in self.snapshot() {
+            barrier.reset();
+        }
+    }
+
+    /// Returns the number of barriers in the set
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    /// Returns true if no barriers are registered
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, Arc<CancellableBarrier>>> {
+        self.barriers.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Copies the barriers out, so cancel listeners registered on them can use the set
+    /// without deadlocking
+    fn snapshot(&self) -> Vec<Arc<CancellableBarrier>> {
+        self.read().values().cloned().collect()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_barrier_set_get_or_create() {
+        let set = BarrierSet::new();
+        assert!(set.is_empty());
+
+        let a = set.get_or_create("a", 2);
+        assert!(Arc::ptr_eq(&a, &set.get_or_create("a", 5)));
+        assert_eq!(a.state().total, 2);
+        assert_eq!(a.name(), Some("a"));
+        assert!(set.get("b").is_none());
+
+        set.get_or_create("b", 1);
+        assert_eq!(set.len(), 2);
+        assert!(set.remove("b").is_some());
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_barrier_set_registers_once_across_threads() {
+        let set = std::sync::Arc::new(BarrierSet::new());
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let set = set.clone();
+                thread::spawn(move || set.get_or_create("shared", 8).wait().unwrap())
+            })
+            .collect();
+        let leaders = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|r| r.is_leader())
+            .count();
+        assert_eq!(leaders, 1);
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_barrier_set_cancel_and_reset_all() {
+        let set = BarrierSet::new();
+        let a = set.get_or_create("a", 2);
+        let b = set.get_or_create("b", 2);
+
+        let waiter = thread::spawn(move || a.wait());
+        set.cancel_all_with(CancelReason::Shutdown);
+        assert_eq!(
+            waiter.join().unwrap().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Shutdown)
+        );
+        assert!(b.is_cancelled());
+
+        set.reset_all();
+        assert!(!b.is_cancelled());
+        assert!(!set.get("a").unwrap().is_cancelled());
+    }
+}
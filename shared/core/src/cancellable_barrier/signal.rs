@@ -0,0 +1,136 @@
+//! Cancelling barriers on Ctrl-C, behind the `signal` feature on unix.
+//!
+//! A signal handler may not take locks, so the handler only sets a flag, and a watcher
+//! thread started with the first registration cancels the barriers once it sees it set.
+
+use std::ffi::c_int;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Once, OnceLock, PoisonError, Weak};
+use std::thread;
+
+use super::{CancelReason, CancellableBarrier, RawSync, SIGNAL_POLL_INTERVAL};
+
+/// `SIGINT`, which has this number on every unix
+const SIGINT: c_int = 2;
+/// A handler as `signal` returns it, which is pointer-sized
+#[allow(non_camel_case_types)]
+type sighandler_t = usize;
+/// What `signal` returns when it fails
+const SIG_ERR: sighandler_t = -1isize as sighandler_t;
+
+extern "C" {
+    fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> sighandler_t;
+}
+
+/// Set by the handler, and cleared by the watcher once it has cancelled the barriers
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// The barriers to cancel, kept weakly so that registering doesn't keep them alive
+static BARRIERS: Mutex<Vec<Weak<dyn CancelOnSignal>>> = Mutex::new(Vec::new());
+
+/// A barrier of any [`RawSync`], as kept in [`BARRIERS`]
+trait CancelOnSignal: Send + Sync {
+    fn cancel_on_signal(&self);
+}
+
+impl<S: RawSync> CancelOnSignal for CancellableBarrier<S>
+where
+    CancellableBarrier<S>: Send + Sync,
+{
+    fn cancel_on_signal(&self) {
+        self.cancel_with(CancelReason::Signal);
+    }
+}
+
+/// Cancels `barrier` with [`CancelReason::Signal`] when the process receives `SIGINT`,
+/// such as from Ctrl-C, so that every thread waiting on it returns promptly instead of
+/// keeping a command-line tool from shutting down.
+///
+/// The first call replaces whatever `SIGINT` handler the process had, which ends the
+/// default of killing the process: once the barriers are cancelled, shutting down is up
+/// to their threads. Each signal cancels every barrier registered so far that is still
+/// alive, and registering doesn't keep a barrier alive. Fails with the OS error if the
+/// handler can't be installed, as does every call after that.
+pub fn install_signal_cancel<S: RawSync>(barrier: Arc<CancellableBarrier<S>>) -> io::Result<()>
+where
+    CancellableBarrier<S>: Send + Sync,
+{
+    // kept, so that every call after a failed first one fails the same way
+    static INSTALLED: OnceLock<Result<(), i32>> = OnceLock::new();
+    let installed = INSTALLED.get_or_init(|| {
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+        if unsafe { signal(SIGINT, on_sigint) } == SIG_ERR {
+            return Err(io::Error::last_os_error().raw_os_error().unwrap_or(0));
+        }
+        Ok(())
+    });
+    installed.map_err(io::Error::from_raw_os_error)?;
+    watch(barrier);
+    Ok(())
+}
+
+extern "C" fn on_sigint(_signum: c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Registers `barrier` with the watcher, starting it if this is the first
+fn watch<S: RawSync>(barrier: Arc<CancellableBarrier<S>>)
+where
+    CancellableBarrier<S>: Send + Sync,
+{
+    static WATCHER: Once = Once::new();
+    let barrier: Arc<dyn CancelOnSignal> = barrier;
+    let mut barriers = BARRIERS.lock().unwrap_or_else(PoisonError::into_inner);
+    barriers.retain(|barrier| barrier.strong_count() > 0);
+    barriers.push(Arc::downgrade(&barrier));
+    drop(barriers);
+    WATCHER.call_once(|| {
+        thread::Builder::new()
+            .name("barrier-signal-watcher".to_string())
+            .spawn(|| loop {
+                thread::sleep(SIGNAL_POLL_INTERVAL);
+                if INTERRUPTED.swap(false, Ordering::Relaxed) {
+                    let barriers: Vec<_> = BARRIERS
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .iter()
+                        .filter_map(Weak::upgrade)
+                        .collect();
+                    // cancelled without the lock, so a listener can register another
+                    for barrier in barriers {
+                        barrier.cancel_on_signal();
+                    }
+                }
+            })
+            .expect("failed to spawn barrier signal watcher thread");
+    });
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::cancellable_barrier::BarrierWaitError;
+
+    #[test]
+    fn test_signal_cancels_waiting_threads() {
+        let barrier = CancellableBarrier::new(3);
+        watch(barrier.clone());
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait())
+            })
+            .collect();
+        while barrier.state().arrived < 2 {
+            thread::yield_now();
+        }
+
+        // as the handler does when SIGINT comes in
+        INTERRUPTED.store(true, Ordering::Relaxed);
+        for waiter in waiters {
+            let err = waiter.join().unwrap().unwrap_err();
+            assert_eq!(err.cancel_reason(), Some(&CancelReason::Signal));
+            assert!(matches!(err, BarrierWaitError::Cancelled(_)));
+        }
+    }
+}
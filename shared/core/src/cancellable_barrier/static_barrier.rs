@@ -0,0 +1,154 @@
+use std::time::{Duration, Instant};
+
+use super::{
+    BarrierSnapshot, BarrierWait, BarrierWaitError, BarrierWaitResult, CancelReason,
+    CancellableBarrier, CancellableBarrierBuilder,
+};
+use crate::sync::Arc;
+
+/// A [`CancellableBarrier`] for exactly `N` threads, with the size fixed by the type.
+///
+/// The API mirrors the dynamic barrier minus everything that changes its size, such as
+/// [`CancellableBarrier::resize`] and [`CancellableBarrier::reset_with`], so a
+/// `StaticBarrier<4>` is guaranteed to release every 4 arrivals for as long as it lives.
+/// A barrier of size 0 fails to compile.
+///
+/// To hand the barrier to code that takes the dynamic type, convert it into an
+/// `Arc<CancellableBarrier>`, which gives up the guarantee.
+#[derive(Debug)]
+pub struct StaticBarrier<const N: usize> {
+    inner: CancellableBarrier,
+}
+
+impl<const N: usize> StaticBarrier<N> {
+    const NONZERO: () = assert!(N > 0, "Barrier size must be greater than 0");
+
+    /// Creates a new barrier for `N` threads
+    #[must_use]
+    pub fn new() -> Arc<Self> {
+        // evaluated here, so a size of 0 fails to compile
+        let () = Self::NONZERO;
+        Arc::new(StaticBarrier {
+            inner: CancellableBarrier::from_builder(CancellableBarrierBuilder::new().parties(N)),
+        })
+    }
+
+    /// Returns the number of threads the barrier is for
+    #[must_use]
+    pub const fn parties(&self) -> usize {
+        N
+    }
+
+    /// Waits until all threads have reached the barrier or the barrier is cancelled, as
+    /// [`CancellableBarrier::wait`]
+    pub fn wait(&self) -> Result<BarrierWaitResult, BarrierWaitError> {
+        self.inner.wait()
+    }
+
+    /// Like [`Self::wait`], but gives up after `timeout`, as
+    /// [`CancellableBarrier::wait_timeout`]
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<BarrierWaitResult, BarrierWaitError> {
+        self.inner.wait_timeout(timeout)
+    }
+
+    /// Like [`Self::wait`], but gives up at `deadline`, as
+    /// [`CancellableBarrier::wait_deadline`]
+    pub fn wait_deadline(&self, deadline: Instant) -> Result<BarrierWaitResult, BarrierWaitError> {
+        self.inner.wait_deadline(deadline)
+    }
+
+    /// Async version of [`Self::wait`], as [`CancellableBarrier::wait_async`]
+    pub fn wait_async(&self) -> BarrierWait<'_> {
+        self.inner.wait_async()
+    }
+
+    /// Arrives at the barrier without blocking, as [`CancellableBarrier::try_wait`]
+    pub fn try_wait(&self) -> Result<Option<usize>, BarrierWaitError> {
+        self.inner.try_wait()
+    }
+
+    /// Cancels the barrier, causing all waiting threads to return with an error, and
+    /// returns how many threads had arrived, as [`CancellableBarrier::cancel`]
+    pub fn cancel(&self) -> usize {
+        self.inner.cancel()
+    }
+
+    /// Cancels the barrier with a `reason` that waiting threads receive in their error,
+    /// and returns how many threads had arrived, as [`CancellableBarrier::cancel_with`]
+    pub fn cancel_with(&self, reason: CancelReason) -> usize {
+        self.inner.cancel_with(reason)
+    }
+
+    /// Resets the barrier to its initial state, as [`CancellableBarrier::reset`]
+    pub fn reset(&self) {
+        self.inner.reset();
+    }
+
+    /// Returns true if the barrier is currently cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+
+    /// Returns a consistent snapshot of the barrier's counters
+    #[must_use]
+    pub fn state(&self) -> BarrierSnapshot {
+        self.inner.state()
+    }
+}
+
+impl<const N: usize> From<StaticBarrier<N>> for Arc<CancellableBarrier> {
+    fn from(barrier: StaticBarrier<N>) -> Self {
+        Arc::new(barrier.inner)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_static_barrier() {
+        let barrier = StaticBarrier::<3>::new();
+        assert_eq!(barrier.parties(), 3);
+
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait().unwrap().is_leader())
+            })
+            .collect();
+        let leaders = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|&is_leader| is_leader)
+            .count();
+        assert_eq!(leaders, 1);
+        assert_eq!(barrier.state().generation, 1);
+
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait())
+        };
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(barrier.cancel_with(CancelReason::Shutdown), 1);
+        assert!(waiter.join().unwrap().is_err());
+        barrier.reset();
+        assert_eq!(barrier.cancel(), 0);
+    }
+
+    #[test]
+    fn test_static_barrier_into_dynamic() {
+        let barrier = StaticBarrier::<2>::new();
+        barrier.cancel();
+        let dynamic: Arc<CancellableBarrier> = Arc::into_inner(barrier).unwrap().into();
+        // the state carries over, and the dynamic barrier can be resized again
+        assert!(dynamic.is_cancelled());
+        dynamic.reset();
+        dynamic.resize(1).unwrap();
+        assert!(dynamic.wait().unwrap().is_leader());
+    }
+}
@@ -0,0 +1,42 @@
+//! Compile-time checks of the thread-safety the public types promise.
+//!
+//! Sharing a barrier between threads needs `Arc<CancellableBarrier>` to be `Send` and
+//! `Sync`, which holds only as long as everything it stores is: a callback, waker or
+//! generic added without the right bounds would quietly take that away from downstream
+//! code. These fail the build instead.
+
+#[cfg(not(loom))]
+use super::{AsyncBarrier, BarrierHandle, InterruptHandle, RetainedWait};
+use super::{
+    BarrierBuildError, BarrierCancelled, BarrierWait, BarrierWaitError, CancelReason,
+    CancellableBarrier, CancellableBarrierBuilder, CancelledBarrier, DrainToken,
+};
+
+const fn assert_send<T: Send>() {}
+const fn assert_send_sync<T: Send + Sync>() {}
+const fn assert_error<T: std::error::Error + Send + Sync + 'static>() {}
+
+const _: () = {
+    assert_send_sync::<CancellableBarrier>();
+    assert_send::<CancellableBarrierBuilder>();
+    assert_send_sync::<DrainToken<'static, super::DefaultSync>>();
+
+    // errors have to cross threads, and fit in a `Box<dyn Error + Send + Sync>`
+    assert_error::<BarrierWaitError>();
+    assert_error::<CancelledBarrier>();
+    assert_error::<BarrierBuildError>();
+    assert_send_sync::<CancelReason>();
+
+    // spawning a task that awaits a wait, as `tokio::spawn` does, needs the future to be
+    // `Send`
+    assert_send::<BarrierWait<'static>>();
+    assert_send::<BarrierCancelled<'static>>();
+};
+
+#[cfg(not(loom))]
+const _: () = {
+    assert_send_sync::<AsyncBarrier>();
+    assert_send_sync::<BarrierHandle>();
+    assert_send_sync::<InterruptHandle>();
+    assert_send::<RetainedWait<'static, super::DefaultSync>>();
+};
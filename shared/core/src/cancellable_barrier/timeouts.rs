@@ -0,0 +1,784 @@
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "diagnostics")]
+use super::ThreadTracking;
+use super::{
+    BarrierState, BarrierWaitError, BarrierWaitResult, CancelReason, CancelTimer,
+    CancellableBarrier, RawSync, WaitOptions, BLOCKING_SUPPORTED,
+};
+use crate::sync::Arc;
+
+/// When the generation in progress started and when it runs out, with what the waits
+/// keeping time in it need noted, as kept with the barrier's state
+#[derive(Debug, Default)]
+pub(super) struct GenerationTimes {
+    /// When the generation times out, if the barrier has an overall timeout and someone
+    /// has arrived
+    pub(super) expires_at: Option<Instant>,
+    /// When the generation got its first arrival, if anyone has arrived
+    pub(super) first_arrival: Option<Instant>,
+    /// Whether a [`CancellableBarrier::wait_timed`] caller has arrived, so that the
+    /// release time is read
+    pub(super) timed_arrival: bool,
+    /// Whether a [`CancellableBarrier::wait_idle_timeout`] caller has arrived, so that
+    /// arrivals note when they came in
+    pub(super) idle_wait: bool,
+    /// When the last arrival came in, kept only while `idle_wait` is set
+    pub(super) last_arrival: Option<Instant>,
+}
+
+impl GenerationTimes {
+    /// Forgets the generation that was released or reset, for the next one to start from
+    /// scratch
+    pub(super) fn clear(&mut self) {
+        self.expires_at = None;
+        self.first_arrival = None;
+        self.timed_arrival = false;
+        self.idle_wait = false;
+    }
+}
+
+impl BarrierState {
+    /// Returns when `generation` was released, if it's the last released one or one
+    /// whose threads haven't all left
+    pub(super) fn release_time(&self, generation: usize) -> Option<Instant> {
+        self.release_times
+            .iter()
+            .rev()
+            .find(|released| released.generation == generation)
+            .and_then(|released| released.at)
+    }
+
+    /// Describes who had arrived, and who hadn't, for a wait that timed out
+    pub(super) fn timed_out(&self) -> BarrierWaitError {
+        #[cfg(feature = "diagnostics")]
+        let missing_threads = self
+            .threads
+            .as_ref()
+            .map_or_else(Vec::new, ThreadTracking::missing);
+        #[cfg(not(feature = "diagnostics"))]
+        let missing_threads = Vec::new();
+        BarrierWaitError::TimedOut {
+            arrived: self.count,
+            missing: self.total.saturating_sub(self.count),
+            missing_threads,
+        }
+    }
+
+    /// Returns the error for the threads of `generation`, if it ran out of its overall
+    /// timeout
+    pub(super) fn expired_error(&self, generation: usize) -> Option<BarrierWaitError> {
+        self.expired
+            .as_ref()
+            .filter(|(expired, _)| *expired == generation)
+            .map(|(_, err)| err.clone())
+    }
+}
+
+impl<S: RawSync> CancellableBarrier<S> {
+    /// Waits until all threads have reached the barrier, the barrier is cancelled, or
+    /// `timeout` elapses.
+    ///
+    /// A thread that times out does not keep its slot: its arrival is rolled back so the
+    /// remaining threads still need the full number of parties to release the barrier.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<BarrierWaitResult, BarrierWaitError> {
+        // a timeout too large to represent is as good as no timeout at all
+        self.wait_inner(WaitOptions {
+            deadline: self.deadline_after(timeout),
+            ..WaitOptions::default()
+        })
+    }
+
+    /// Like [`Self::wait_timeout`], but only gives up once `idle` has passed without
+    /// anyone arriving, the window starting over with every arrival.
+    ///
+    /// This suits stragglers that are slow but still coming, which a fixed timeout would
+    /// give up on however steadily they trickle in: as long as each arrives within `idle`
+    /// of the last, the wait keeps going, and only a stall times it out. An arrival that
+    /// gives up again, such as on its own timeout, still counts as one.
+    ///
+    /// While one is in progress, arrivals take the lock and read the barrier's [`Clock`],
+    /// to tell it when they came in.
+    pub fn wait_idle_timeout(&self, idle: Duration) -> Result<BarrierWaitResult, BarrierWaitError> {
+        self.wait_inner(WaitOptions {
+            deadline: self.deadline_after(idle),
+            idle: Some(idle),
+            ..WaitOptions::default()
+        })
+    }
+
+    /// Like [`Self::wait_timeout`], but gives up once `deadline` has passed rather than
+    /// after a relative duration.
+    pub fn wait_deadline(&self, deadline: Instant) -> Result<BarrierWaitResult, BarrierWaitError> {
+        self.wait_inner(WaitOptions {
+            deadline: Some(deadline),
+            ..WaitOptions::default()
+        })
+    }
+
+    /// Like [`Self::wait`], but also returns when the barrier released, as the generation
+    /// and the [`Instant`] its releasing thread tripped it at.
+    ///
+    /// Every thread of a generation gets the same instant, read once off the barrier's
+    /// [`Clock`] as it is released, so timings measured from it line up across the whole
+    /// cohort. The barrier's default timeout applies. A late party of a quorum barrier
+    /// gets the instant its generation was released at, before it arrived.
+    pub fn wait_timed(&self) -> Result<(usize, Instant), BarrierWaitError> {
+        let mut released_at = None;
+        let result = self.wait_inner(WaitOptions {
+            deadline: self
+                .default_timeout
+                .and_then(|timeout| self.deadline_after(timeout)),
+            released_at: Some(&mut released_at),
+            ..WaitOptions::default()
+        })?;
+        // only a late party on a target that can't block finds its generation untimed
+        let released_at = released_at.unwrap_or_else(|| self.clock.now());
+        Ok((result.generation(), released_at))
+    }
+
+    /// Like [`Self::wait_while`], but gives up after `timeout` as [`Self::wait_timeout`]
+    /// does, whether the count isn't complete by then or `cond` hasn't come true.
+    ///
+    /// A thread that times out has its arrival rolled back and returns
+    /// [`BarrierWaitError::TimedOut`], even if the count was complete and only the
+    /// condition held the release back, so the generation then needs another arrival
+    /// before it can release.
+    pub fn wait_timeout_while(
+        &self,
+        timeout: Duration,
+        cond: impl Fn() -> bool,
+    ) -> Result<BarrierWaitResult, BarrierWaitError> {
+        self.wait_inner(WaitOptions {
+            deadline: self.deadline_after(timeout),
+            condition: Some(&cond),
+            ..WaitOptions::default()
+        })
+    }
+
+    /// Waits, without arriving, until the barrier is idle: nobody has arrived at the
+    /// generation in progress and every thread and task released from earlier ones has
+    /// left its wait.
+    ///
+    /// For a supervisor about to reconfigure the barrier, which shouldn't race with
+    /// waiters still on their way out. Returns straight away if the barrier is already
+    /// idle, fails with the cancellation if it is or becomes cancelled, and with
+    /// [`BarrierWaitError::TimedOut`] if it isn't idle within `timeout`. The wait doesn't
+    /// keep anyone from arriving in the meantime. Async waiters only leave once their
+    /// executor polls them again.
+    pub fn wait_idle(&self, timeout: Option<Duration>) -> Result<(), BarrierWaitError> {
+        let deadline = timeout.and_then(|timeout| self.clock.now().checked_add(timeout));
+        let mut state = self.lock();
+        loop {
+            state.check_cancelled()?;
+            if state.count == 0 && state.parked == 0 {
+                return Ok(());
+            }
+            if !BLOCKING_SUPPORTED {
+                return Err(BarrierWaitError::WouldBlock);
+            }
+            // the last waiter to leave, a release and a cancellation all wake us
+            let now = self.clock.now();
+            state.idle_waiters += 1;
+            state = match deadline {
+                Some(deadline) if deadline <= now => {
+                    state.idle_waiters -= 1;
+                    return Err(state.timed_out());
+                }
+                Some(deadline) => self.sleep_until(state, now, deadline),
+                None => self.sleep(state),
+            };
+            state.idle_waiters -= 1;
+        }
+    }
+
+    /// Gives the generation in progress up to `grace` to complete, then cancels the
+    /// barrier with [`CancelReason::Shutdown`] if it hasn't.
+    ///
+    /// For a shutdown that would rather let the current round finish than cut it short:
+    /// the calling thread blocks until the generation is released, in which case nothing
+    /// is cancelled and the barrier carries on as usual, or until `grace` runs out. Unlike
+    /// [`Self::cancel_after`], which schedules a timeout on a background thread, the
+    /// caller waits for the outcome. Returns true if the generation was released in time.
+    /// A barrier cancelled by someone else in the meantime keeps its reason, and returns
+    /// false.
+    pub fn cancel_graceful(&self, grace: Duration) -> bool {
+        let deadline = self.clock.now().checked_add(grace);
+        let mut state = self.lock();
+        // a reset starts another generation, which can still finish in time
+        let released = state.released;
+        while state.released == released && state.cancelled.is_none() && BLOCKING_SUPPORTED {
+            match deadline {
+                Some(deadline) => {
+                    let now = self.clock.now();
+                    if deadline <= now {
+                        break;
+                    }
+                    state = self.sleep_until(state, now, deadline);
+                }
+                None => state = self.sleep(state),
+            }
+        }
+        if state.released != released {
+            return true;
+        }
+        if state.cancelled.is_none() {
+            self.cancel_locked(&mut state, CancelReason::Shutdown);
+            drop(state);
+            self.notify_cancel_listeners(CancelReason::Shutdown);
+        }
+        false
+    }
+
+    /// Cancels the barrier with [`CancelReason::Timeout`] unless the current generation is
+    /// released within `timeout`.
+    ///
+    /// The timer runs on a background thread that doesn't keep the barrier alive. It only
+    /// ever targets the generation in progress when it was scheduled: if that generation
+    /// is released, or the barrier is cancelled or reset in the meantime, the timer does
+    /// nothing when it fires. Use the returned handle to call it off early.
+    #[cfg(not(loom))]
+    pub fn cancel_after(self: &Arc<Self>, timeout: Duration) -> CancelTimer {
+        let generation = self.lock().generation;
+        CancelTimer::spawn(Arc::downgrade(self), generation, timeout)
+    }
+
+    /// Returns true if the current generation has had arrivals for at least `threshold`
+    /// without being released, for a watchdog to alert on or [`Self::force_release`].
+    ///
+    /// The time is counted from the generation's first arrival, even if that thread has
+    /// since given up, as long as anyone is still counted. A barrier nobody is counted at
+    /// isn't stuck, however long since it was last released, and neither is a cancelled
+    /// one, whose waiters are on their way out. On a target that can't block, where
+    /// nobody ever waits, no barrier is.
+    #[must_use]
+    pub fn is_stuck(&self, threshold: Duration) -> bool {
+        let state = self.lock();
+        if state.count == 0 || state.cancelled.is_some() {
+            return false;
+        }
+        state
+            .times
+            .first_arrival
+            .is_some_and(|first| self.clock.now().saturating_duration_since(first) >= threshold)
+    }
+
+    /// Times out the current generation if it has run out of its overall timeout by
+    /// `now`, failing its waiters and starting a fresh generation as [`Self::reset`]
+    /// does. Returns true if it timed out.
+    pub(super) fn expire(&self, state: &mut BarrierState, now: Instant) -> bool {
+        let expired = state.cancelled.is_none()
+            && !state.aborted
+            && state
+                .times
+                .expires_at
+                .is_some_and(|expires_at| now >= expires_at);
+        if expired {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                barrier = self.name.as_deref(),
+                generation = state.generation,
+                arrived = state.count,
+                "barrier generation timed out"
+            );
+            state.expired = Some((state.generation, state.timed_out()));
+            self.reset_locked(state);
+        }
+        expired
+    }
+
+    /// Returns when a wait with `timeout` gives up, or `None` if that is too far off to
+    /// represent.
+    ///
+    /// Targets that can't block never need a deadline, which spares them from reading a
+    /// clock they may not have.
+    pub(super) fn deadline_after(&self, timeout: Duration) -> Option<Instant> {
+        if !BLOCKING_SUPPORTED {
+            return None;
+        }
+        self.clock.now().checked_add(timeout)
+    }
+
+    /// Blocks on the condition variable until woken or until the clock reads `wake_at`,
+    /// as far as can be told from `now`
+    pub(super) fn sleep_until<'a>(
+        &'a self,
+        state: S::Guard<'a, BarrierState>,
+        now: Instant,
+        wake_at: Instant,
+    ) -> S::Guard<'a, BarrierState> {
+        let mut timeout = wake_at.saturating_duration_since(now);
+        if let Some(interval) = self.clock.poll_interval() {
+            timeout = timeout.min(interval);
+        }
+        S::wait_timeout(&self.condvar, state, timeout)
+    }
+
+    /// Blocks on the condition variable until woken, checking the clock now and then if
+    /// it doesn't follow real time
+    pub(super) fn sleep<'a>(
+        &'a self,
+        state: S::Guard<'a, BarrierState>,
+    ) -> S::Guard<'a, BarrierState> {
+        match self.clock.poll_interval() {
+            Some(interval) => S::wait_timeout(&self.condvar, state, interval),
+            None => S::wait(&self.condvar, state),
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::cancellable_barrier::tests::{block_on, cancelled, NoopWaker, TEST_SLEEP_DURATION};
+    use crate::cancellable_barrier::{CompletedPartial, MockClock};
+    use std::future::Future;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+
+    #[test]
+    fn test_wait_timeout_releases_slot() {
+        let barrier = CancellableBarrier::new(2);
+        let barrier_clone1 = barrier.clone();
+
+        // Nobody else arrives, so this times out and gives its slot back
+        assert_eq!(
+            barrier.wait_timeout(TEST_SLEEP_DURATION),
+            Err(BarrierWaitError::timed_out(1, 1))
+        );
+
+        // If the slot had been kept, this would release on its own
+        assert_eq!(
+            barrier.wait_timeout(TEST_SLEEP_DURATION),
+            Err(BarrierWaitError::timed_out(1, 1))
+        );
+
+        let t1 = thread::spawn(move || barrier_clone1.wait_timeout(TEST_SLEEP_DURATION * 10));
+        assert_eq!(
+            barrier
+                .wait_timeout(TEST_SLEEP_DURATION * 10)
+                .map(|r| r.generation()),
+            Ok(0)
+        );
+        assert_eq!(t1.join().unwrap().map(|r| r.generation()), Ok(0));
+    }
+
+    #[test]
+    fn test_wait_timeout_cancelled() {
+        let barrier = CancellableBarrier::new(2);
+        let barrier_clone1 = barrier.clone();
+
+        let t1 = thread::spawn(move || barrier_clone1.wait_timeout(TEST_SLEEP_DURATION * 10));
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+        barrier.cancel();
+
+        assert_eq!(
+            t1.join().unwrap(),
+            Err(cancelled(CancelReason::Unspecified))
+        );
+    }
+
+    #[test]
+    fn test_wait_deadline() {
+        let barrier = CancellableBarrier::new(2);
+        let barrier_clone1 = barrier.clone();
+
+        let deadline = Instant::now() + TEST_SLEEP_DURATION;
+        assert_eq!(
+            barrier.wait_deadline(deadline),
+            Err(BarrierWaitError::timed_out(1, 1))
+        );
+        assert!(Instant::now() >= deadline);
+
+        // A deadline in the past times out straight away and still rolls back
+        assert_eq!(
+            barrier.wait_deadline(Instant::now()),
+            Err(BarrierWaitError::timed_out(1, 1))
+        );
+
+        let deadline = Instant::now() + TEST_SLEEP_DURATION * 10;
+        let t1 = thread::spawn(move || barrier_clone1.wait_deadline(deadline));
+        assert_eq!(
+            barrier.wait_deadline(deadline).map(|r| r.generation()),
+            Ok(0)
+        );
+        assert_eq!(t1.join().unwrap().map(|r| r.generation()), Ok(0));
+    }
+
+    #[test]
+    fn test_builder_default_timeout() {
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .default_timeout(TEST_SLEEP_DURATION)
+            .name("test")
+            .build()
+            .unwrap();
+
+        assert_eq!(barrier.name(), Some("test"));
+        assert_eq!(barrier.wait(), Err(BarrierWaitError::timed_out(1, 1)));
+    }
+
+    #[test]
+    fn test_release_on_timeout() {
+        let barrier = CancellableBarrier::builder()
+            .parties(3)
+            .release_on_timeout(true)
+            .build()
+            .unwrap();
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        let leader = barrier.wait_timeout(TEST_SLEEP_DURATION).unwrap();
+        let follower = t1.join().unwrap().unwrap();
+        let partial = CompletedPartial {
+            arrived: 2,
+            total: 3,
+        };
+        assert!(leader.is_leader());
+        assert_eq!(leader.completed_partial(), Some(partial));
+        assert_eq!(follower.generation(), 0);
+        assert_eq!(follower.completed_partial(), Some(partial));
+
+        // a full generation afterwards isn't partial
+        let results = (0..3)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait().unwrap())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|h| h.join().unwrap());
+        assert!(results.into_iter().all(|r| r.completed_partial().is_none()));
+    }
+
+    #[test]
+    fn test_timeout_reports_progress() {
+        let barrier = CancellableBarrier::new(3);
+        let barrier_clone = barrier.clone();
+        let waiter = thread::spawn(move || barrier_clone.wait());
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+
+        let err = barrier.wait_timeout(Duration::from_millis(10)).unwrap_err();
+        assert_eq!(err.progress(), Some((2, 3)));
+        barrier.cancel();
+        assert_eq!(waiter.join().unwrap().unwrap_err().progress(), None);
+    }
+
+    #[test]
+    fn test_overall_timeout() {
+        let barrier = CancellableBarrier::builder()
+            .parties(3)
+            .overall_timeout(TEST_SLEEP_DURATION)
+            .build()
+            .unwrap();
+
+        let barrier_clone = barrier.clone();
+        let blocking = thread::spawn(move || barrier_clone.wait());
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        let barrier_clone = barrier.clone();
+        let task = thread::spawn(move || block_on(barrier_clone.wait_async()));
+
+        // the blocking waiter times out the generation for the task too
+        let timed_out = BarrierWaitError::TimedOut {
+            arrived: 2,
+            missing: 1,
+            missing_threads: Vec::new(),
+        };
+        assert_eq!(blocking.join().unwrap(), Err(timed_out.clone()));
+        assert_eq!(task.join().unwrap(), Err(timed_out));
+        assert_eq!(barrier.state().arrived, 0);
+        assert_eq!(barrier.state().generation, 1);
+        assert!(!barrier.is_cancelled());
+
+        // the next generation gets a deadline of its own
+        let generations = (0..3)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait().map(|r| r.generation()))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|h| h.join().unwrap());
+        assert!(generations.into_iter().all(|r| r == Ok(1)));
+    }
+
+    #[test]
+    fn test_wait_timed_shares_the_release_instant() {
+        let barrier = CancellableBarrier::new(4);
+        let before = Instant::now();
+        for generation in 0..2 {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    let barrier = barrier.clone();
+                    thread::spawn(move || barrier.wait_timed().unwrap())
+                })
+                .collect();
+            let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+            let (released, at) = results[0];
+            assert_eq!(released, generation);
+            assert!(before <= at && at <= Instant::now());
+            assert!(results.iter().all(|&result| result == (released, at)));
+        }
+
+        // plain waits in the same generation are fine too
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait_timed())
+        };
+        let others: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || barrier.wait())
+            })
+            .collect();
+        let (released, _) = barrier.wait_timed().unwrap();
+        assert_eq!(waiter.join().unwrap().unwrap().0, released);
+        for other in others {
+            other.join().unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_is_stuck() {
+        let clock = Arc::new(MockClock::new());
+        let barrier = CancellableBarrier::builder()
+            .parties(2)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        // nobody has arrived, so nothing is stuck however long it's been
+        assert!(!barrier.is_stuck(Duration::ZERO));
+
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait())
+        };
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        assert!(!barrier.is_stuck(Duration::from_secs(60)));
+        clock.advance(TEST_SLEEP_DURATION);
+        assert!(!barrier.is_stuck(TEST_SLEEP_DURATION + Duration::from_millis(1)));
+        assert!(barrier.is_stuck(TEST_SLEEP_DURATION));
+
+        barrier.force_release();
+        assert!(waiter.join().unwrap().is_ok());
+        assert!(!barrier.is_stuck(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_wait_idle() {
+        let barrier = CancellableBarrier::new(2);
+        assert_eq!(barrier.wait_idle(None), Ok(()));
+
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait())
+        };
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        assert!(matches!(
+            barrier.wait_idle(Some(Duration::from_millis(20))),
+            Err(BarrierWaitError::TimedOut { arrived: 1, .. })
+        ));
+
+        let idle = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait_idle(Some(Duration::from_secs(10))))
+        };
+        while barrier.lock().idle_waiters == 0 {
+            thread::yield_now();
+        }
+        assert!(!idle.is_finished());
+        barrier.wait().unwrap();
+        assert_eq!(idle.join().unwrap(), Ok(()));
+        waiter.join().unwrap().unwrap();
+
+        barrier.cancel();
+        assert_eq!(
+            barrier.wait_idle(None).unwrap_err().cancel_reason(),
+            Some(&CancelReason::Unspecified)
+        );
+    }
+
+    #[test]
+    fn test_wait_idle_woken_by_last_waiter() {
+        let barrier = CancellableBarrier::new(2);
+        let waker = Arc::new(NoopWaker).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut pending = Box::pin(barrier.wait_async());
+        assert!(pending.as_mut().poll(&mut cx).is_pending());
+        // released, but the task hasn't been polled to leave yet
+        barrier.reset();
+        let idle = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait_idle(None))
+        };
+        while barrier.lock().idle_waiters == 0 {
+            thread::yield_now();
+        }
+
+        assert!(pending.as_mut().poll(&mut cx).is_ready());
+        assert_eq!(idle.join().unwrap(), Ok(()));
+        assert_eq!(barrier.lock().idle_waiters, 0);
+    }
+
+    #[test]
+    fn test_cancel_after() {
+        let barrier = CancellableBarrier::new(2);
+
+        let _timer = barrier.cancel_after(TEST_SLEEP_DURATION);
+        assert_eq!(barrier.wait(), Err(cancelled(CancelReason::Timeout)));
+    }
+
+    #[test]
+    fn test_cancel_after_spares_next_generation() {
+        let barrier = CancellableBarrier::new(2);
+
+        let timer = barrier.cancel_after(TEST_SLEEP_DURATION);
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        barrier.wait().unwrap();
+        t1.join().unwrap().unwrap();
+
+        // the timer fires while generation 1 is in progress and must leave it alone
+        thread::sleep(TEST_SLEEP_DURATION * 2);
+        assert!(!barrier.is_cancelled());
+        timer.abort();
+
+        let timer = barrier.cancel_after(TEST_SLEEP_DURATION);
+        timer.abort();
+        thread::sleep(TEST_SLEEP_DURATION * 2);
+        assert!(!barrier.is_cancelled());
+    }
+
+    #[test]
+    fn test_cancel_graceful() {
+        let barrier = CancellableBarrier::new(2);
+
+        // the generation completes within the grace period, so nothing is cancelled
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait())
+        };
+        let latecomer = {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                thread::sleep(TEST_SLEEP_DURATION);
+                barrier.wait()
+            })
+        };
+        assert!(barrier.cancel_graceful(TEST_SLEEP_DURATION * 20));
+        assert!(waiter.join().unwrap().is_ok());
+        assert!(latecomer.join().unwrap().is_ok());
+        assert!(!barrier.is_cancelled());
+
+        // nobody comes to complete it, so it is cancelled once the grace period is over
+        let waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait())
+        };
+        let started = Instant::now();
+        assert!(!barrier.cancel_graceful(TEST_SLEEP_DURATION));
+        assert!(started.elapsed() >= TEST_SLEEP_DURATION);
+        assert_eq!(
+            waiter.join().unwrap().unwrap_err().cancel_reason(),
+            Some(&CancelReason::Shutdown)
+        );
+    }
+
+    #[test]
+    fn test_wait_idle_timeout() {
+        let clock = Arc::new(MockClock::new());
+        let barrier = CancellableBarrier::builder()
+            .parties(3)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let idle_waiter = {
+            let barrier = barrier.clone();
+            thread::spawn(move || barrier.wait_idle_timeout(Duration::from_secs(60)))
+        };
+        while barrier.state().arrived < 1 {
+            thread::yield_now();
+        }
+
+        // an arrival that gives up straight away still starts the window over
+        clock.advance(Duration::from_secs(45));
+        assert!(matches!(
+            barrier.wait_timeout(Duration::ZERO),
+            Err(BarrierWaitError::TimedOut { .. })
+        ));
+        clock.advance(Duration::from_secs(45));
+        // which kept it waiting past a fixed timeout of the same length
+        assert!(!idle_waiter.is_finished());
+
+        let barrier_clone1 = barrier.clone();
+        let t1 = thread::spawn(move || barrier_clone1.wait());
+        while barrier.state().arrived < 2 {
+            thread::yield_now();
+        }
+        clock.advance(Duration::from_secs(59));
+        assert!(!idle_waiter.is_finished());
+
+        // a stall times out, one idle window after the last arrival
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(
+            idle_waiter.join().unwrap(),
+            Err(BarrierWaitError::timed_out(2, 1))
+        );
+        barrier.cancel();
+        assert!(t1.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_wait_timeout_while() {
+        use std::sync::atomic::AtomicBool;
+
+        let barrier = CancellableBarrier::new(2);
+        let ready = std::sync::Arc::new(AtomicBool::new(false));
+        let wait = |barrier: &Arc<CancellableBarrier>| {
+            let (barrier, ready) = (barrier.clone(), ready.clone());
+            thread::spawn(move || {
+                barrier.wait_timeout_while(TEST_SLEEP_DURATION, || ready.load(Ordering::SeqCst))
+            })
+        };
+
+        // everyone arrives but the condition never holds, so both give up
+        let (t1, t2) = (wait(&barrier), wait(&barrier));
+        for waiter in [t1, t2] {
+            assert!(matches!(
+                waiter.join().unwrap(),
+                Err(BarrierWaitError::TimedOut { .. })
+            ));
+        }
+        assert_eq!(barrier.state().arrived, 0);
+        assert_eq!(barrier.state().generation, 0);
+
+        ready.store(true, Ordering::SeqCst);
+        let (t1, t2) = (wait(&barrier), wait(&barrier));
+        let results = [t1.join().unwrap().unwrap(), t2.join().unwrap().unwrap()];
+        assert!(results.iter().all(|result| result.generation() == 0));
+
+        ready.store(false, Ordering::SeqCst);
+        let t1 = wait(&barrier);
+        while barrier.state().arrived == 0 {
+            thread::yield_now();
+        }
+        barrier.cancel();
+        assert_eq!(
+            t1.join().unwrap(),
+            Err(cancelled(CancelReason::Unspecified))
+        );
+    }
+}
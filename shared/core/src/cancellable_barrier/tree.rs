@@ -0,0 +1,291 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::PoisonError;
+
+use super::{
+    BarrierWaitError, BarrierWaitResult, CancelReason, CancellableBarrier, CancelledBarrier,
+};
+use crate::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// A barrier for many threads, built as a tree of [`CancellableBarrier`]s so that no
+/// single lock is contended by every thread.
+///
+/// Arrivals are spread over leaf nodes of at most `fan_in` threads each. The last thread
+/// to arrive at a node carries the arrival up to its parent, while the others park at
+/// the node until the root completes and the news comes back down. Each generation, the
+/// thread that completes the root is the leader.
+///
+/// Cancelling the tree cancels its root, which cancels every node below it, so all
+/// waiting threads get the error wherever they are parked.
+#[derive(Debug)]
+pub struct TreeBarrier {
+    nodes: Vec<TreeNode>,
+    /// Index into `nodes` of the leaf each party slot belongs to
+    leaves: Vec<usize>,
+    tickets: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct TreeNode {
+    /// Collects the arrivals from this node's children
+    up: Arc<CancellableBarrier>,
+    /// Holds the children until the generation completes, except at the root
+    gate: Option<Arc<Gate>>,
+    parent: Option<usize>,
+}
+
+/// Where the threads of a node wait for their generation to complete.
+///
+/// A thread's leaf can change from one generation to the next, so a gate is opened for a
+/// given generation rather than counting the threads that pass through it.
+#[derive(Debug)]
+struct Gate {
+    mutex: Mutex<GateState>,
+    condvar: Condvar,
+}
+
+#[derive(Debug)]
+struct GateState {
+    /// Generations completed so far
+    opened: usize,
+    cancelled: Option<CancelReason>,
+}
+
+impl Gate {
+    fn new() -> Self {
+        Gate {
+            mutex: Mutex::new(GateState {
+                opened: 0,
+                cancelled: None,
+            }),
+            condvar: Condvar::new(),
+        }
+    }
+
+    fn wait(&self, generation: usize) -> Result<(), BarrierWaitError> {
+        let mut state = self.lock();
+        while state.opened <= generation && state.cancelled.is_none() {
+            state = self
+                .condvar
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        match &state.cancelled {
+            // a completed generation stays completed, as with the nodes themselves
+            Some(reason) if state.opened <= generation => {
+                Err(CancelledBarrier::new(reason.clone()).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn open(&self, generation: usize) {
+        let mut state = self.lock();
+        state.opened = state.opened.max(generation + 1);
+        self.condvar.notify_all();
+    }
+
+    fn cancel(&self, reason: CancelReason) {
+        let mut state = self.lock();
+        state.cancelled = Some(reason);
+        self.condvar.notify_all();
+    }
+
+    fn reset(&self) {
+        let mut state = self.lock();
+        state.opened = 0;
+        state.cancelled = None;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, GateState> {
+        self.mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl TreeBarrier {
+    /// Creates a tree barrier for `n` threads, where each node synchronizes at most
+    /// `fan_in` threads or child nodes
+    #[must_use]
+    pub fn new(n: usize, fan_in: usize) -> Arc<Self> {
+        assert!(n > 0, "Barrier size must be greater than 0");
+        assert!(fan_in > 1, "Tree barrier fan-in must be at least 2");
+
+        // lay out the levels from the leaves up, with parents filled in afterwards
+        let mut nodes = Vec::new();
+        let mut level: Vec<usize> = (0..n.div_ceil(fan_in))
+            .map(|i| {
+                nodes.push((fan_in.min(n - i * fan_in), None));
+                nodes.len() - 1
+            })
+            .collect();
+        let leaves = (0..n).map(|slot| level[slot / fan_in]).collect();
+        while level.len() > 1 {
+            level = level
+                .chunks(fan_in)
+                .map(|children| {
+                    nodes.push((children.len(), None));
+                    let parent = nodes.len() - 1;
+                    for &child in children {
+                        nodes[child].1 = Some(parent);
+                    }
+                    parent
+                })
+                .collect();
+        }
+
+        let nodes: Vec<_> = nodes
+            .into_iter()
+            .map(|(size, parent)| TreeNode {
+                up: CancellableBarrier::new(size),
+                gate: parent.map(|_| Arc::new(Gate::new())),
+                parent,
+            })
+            .collect();
+
+        // cancelling a node cancels everything below it
+        for node in &nodes {
+            if let (Some(parent), Some(gate)) = (node.parent, &node.gate) {
+                let up = node.up.clone();
+                let gate = gate.clone();
+                nodes[parent].up.on_cancel(move |reason| {
+                    up.cancel_with(reason.clone());
+                    gate.cancel(reason);
+                });
+            }
+        }
+
+        Arc::new(TreeBarrier {
+            nodes,
+            leaves,
+            tickets: AtomicUsize::new(0),
+        })
+    }
+
+    /// Waits until all threads have reached the barrier or the barrier is cancelled
+    pub fn wait(&self) -> Result<BarrierWaitResult, BarrierWaitError> {
+        let ticket = self.tickets.fetch_add(1, Ordering::Relaxed);
+        let generation = ticket / self.leaves.len();
+        let leaf = self.leaves[ticket % self.leaves.len()];
+        let is_leader = self.wait_at(leaf, generation)?;
+        Ok(BarrierWaitResult::new(generation, is_leader))
+    }
+
+    /// Arrives at `node` and returns once `generation` is complete, with whether this
+    /// thread was the one to complete the root
+    fn wait_at(&self, node: usize, generation: usize) -> Result<bool, BarrierWaitError> {
+        let node = &self.nodes[node];
+        let arrived = node.up.wait()?;
+        let (Some(parent), Some(gate)) = (node.parent, &node.gate) else {
+            // releasing the root completes the generation
+            return Ok(arrived.is_leader());
+        };
+        if arrived.is_leader() {
+            let is_leader = self.wait_at(parent, generation)?;
+            gate.open(generation);
+            Ok(is_leader)
+        } else {
+            gate.wait(generation)?;
+            Ok(false)
+        }
+    }
+
+    fn root(&self) -> &CancellableBarrier {
+        // the root is always laid out last
+        &self.nodes.last().expect("tree has at least one node").up
+    }
+
+    /// Cancels the barrier, causing all waiting threads to return with an error
+    pub fn cancel(&self) {
+        self.cancel_with(CancelReason::Unspecified);
+    }
+
+    /// Cancels the barrier with a `reason` that waiting threads receive in their error
+    pub fn cancel_with(&self, reason: CancelReason) {
+        self.root().cancel_with(reason);
+    }
+
+    /// Resets every node of the barrier to its initial state
+    pub fn reset(&self) {
+        for node in &self.nodes {
+            node.up.reset();
+            if let Some(gate) = &node.gate {
+                gate.reset();
+            }
+        }
+        self.tickets.store(0, Ordering::Relaxed);
+    }
+
+    /// Returns true if the barrier is currently cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.root().is_cancelled()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_tree_barrier_layout() {
+        let tree = TreeBarrier::new(10, 4);
+        // leaves of 4, 4 and 2, under a root of 3
+        let sizes: Vec<_> = tree.nodes.iter().map(|n| n.up.state().total).collect();
+        assert_eq!(sizes, vec![4, 4, 2, 3]);
+        assert_eq!(tree.leaves.len(), 10);
+        assert!(tree.nodes[..3].iter().all(|n| n.parent == Some(3)));
+        assert!(tree.nodes[3].parent.is_none());
+
+        let single = TreeBarrier::new(3, 4);
+        assert_eq!(single.nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_tree_barrier_generations() {
+        const THREADS: usize = 21;
+        let tree = TreeBarrier::new(THREADS, 3);
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let tree = tree.clone();
+                thread::spawn(move || {
+                    (0..5)
+                        .map(|_| tree.wait().unwrap())
+                        .filter(|r| r.is_leader())
+                        .map(|r| r.generation())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut led: Vec<_> = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect();
+        led.sort();
+        // one leader per generation
+        assert_eq!(led, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_tree_barrier_cancel_reaches_leaves() {
+        let tree = TreeBarrier::new(9, 3);
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let tree = tree.clone();
+                thread::spawn(move || tree.wait())
+            })
+            .collect();
+        thread::sleep(Duration::from_millis(100));
+        tree.cancel_with(CancelReason::Shutdown);
+        for handle in handles {
+            assert_eq!(
+                handle.join().unwrap().unwrap_err().cancel_reason(),
+                Some(&CancelReason::Shutdown)
+            );
+        }
+        assert!(tree.nodes.iter().all(|n| n.up.is_cancelled()));
+        assert!(tree.is_cancelled());
+    }
+}
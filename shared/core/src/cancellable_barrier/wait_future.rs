@@ -0,0 +1,306 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::task::{Context, Poll};
+use std::thread::ThreadId;
+
+use super::metrics::WaitTimer;
+use super::reentrancy::WaitMarker;
+use super::{BarrierWaitError, BarrierWaitResult, CancellableBarrier, DefaultSync, RawSync};
+
+/// Future returned by [`CancellableBarrier::wait_async`].
+///
+/// A named, `Unpin` and `Send` type, so it can be stored in a struct, polled through
+/// `Pin::new(&mut wait)`, and used in `select!` or `join!` without boxing.
+///
+/// It resolves to the same results as [`CancellableBarrier::wait`], save for what only a
+/// thread parked in a blocking wait does:
+///
+/// - The barrier's default timeout doesn't apply, as nothing would wake the task when it
+///   runs out. Wrap the future in the runtime's timeout instead, which drops it and so
+///   withdraws the arrival.
+/// - An [`InterruptHandle`](super::InterruptHandle) doesn't interrupt it, and an
+///   interrupt sent while it is pending is kept for the thread's next blocking wait.
+///   Dropping the future is how a task is interrupted.
+/// - On a [fair](super::CancellableBarrierBuilder::fair) barrier it doesn't take a turn
+///   in the release order, and resolves on the first poll after the release.
+///
+/// The arrival is registered on the first poll. From then until the barrier releases, the
+/// task's waker is kept in the barrier and woken on release or cancellation.
+///
+/// # Dropping
+///
+/// Dropping the future before it completes, as a `select!` does with the branches that
+/// lose, removes the waker and rolls back the arrival, so the other parties don't wait for
+/// a task that is gone. Once its generation has been released the arrival stands, and
+/// dropping the future only loses the result. A future that was never polled never
+/// arrived, so dropping it does nothing.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct BarrierWait<'a, S: RawSync = DefaultSync> {
+    barrier: &'a CancellableBarrier<S>,
+    stage: Stage,
+    timer: Option<WaitTimer>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Stage {
+    /// Yields to the executor once before arriving, for
+    /// [`CancellableBarrier::wait_async_yielding`]
+    Yielding,
+    NotArrived,
+    /// Waiting to arrive until the last generation is processed, on a barrier with
+    /// backpressure
+    HeldBack {
+        waker_key: usize,
+    },
+    Arrived {
+        generation: usize,
+        waker_key: usize,
+        park_ticket: u64,
+        thread: Option<ThreadId>,
+        /// Whether this task released its generation and is only held by a pause
+        is_leader: bool,
+        is_first: bool,
+    },
+    Done,
+}
+
+impl<'a, S: RawSync> BarrierWait<'a, S> {
+    pub(super) fn new(barrier: &'a CancellableBarrier<S>) -> Self {
+        BarrierWait {
+            barrier,
+            stage: Stage::NotArrived,
+            timer: None,
+        }
+    }
+
+    pub(super) fn yielding(barrier: &'a CancellableBarrier<S>) -> Self {
+        let mut wait = BarrierWait::new(barrier);
+        wait.stage = Stage::Yielding;
+        wait
+    }
+
+    fn record_wait(&mut self) {
+        if let Some(timer) = self.timer.take() {
+            self.barrier.metrics.record_wait(timer);
+        }
+    }
+}
+
+impl<S: RawSync> Future for BarrierWait<'_, S> {
+    type Output = Result<BarrierWaitResult, BarrierWaitError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if let Stage::Yielding = this.stage {
+            this.stage = Stage::NotArrived;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let _marker = WaitMarker::enter(this.barrier, this.barrier.name.as_deref());
+        this.barrier.check_linked();
+        let mut state = this.barrier.lock();
+        if let Stage::HeldBack { waker_key } = this.stage {
+            state.wakers.remove(&waker_key);
+            this.stage = Stage::NotArrived;
+        }
+
+        match this.stage {
+            Stage::NotArrived => {
+                let mut section = WaitTimer::start();
+                if let Err(err) = state.check_cancelled() {
+                    this.stage = Stage::Done;
+                    return Poll::Ready(Err(err));
+                }
+                if state.draining() {
+                    this.stage = Stage::Done;
+                    return Poll::Ready(Err(this.barrier.leave_for_drain(&mut state)));
+                }
+                this.barrier.end_processing_if_leader(&mut state);
+                if this.barrier.single_party.load(Ordering::Relaxed) {
+                    // a task alone at the barrier releases it on the spot, as a thread does
+                    (state, section) = match this.barrier.release_alone(state, section) {
+                        Ok(result) => {
+                            this.stage = Stage::Done;
+                            return Poll::Ready(Ok(result));
+                        }
+                        Err(general) => general,
+                    };
+                }
+                if state.held_back() {
+                    let waker_key = state.next_waker_key;
+                    state.next_waker_key = state.next_waker_key.wrapping_add(1);
+                    state.wakers.insert(waker_key, cx.waker().clone());
+                    this.stage = Stage::HeldBack { waker_key };
+                    return Poll::Pending;
+                }
+                if state.times.expires_at.is_some() {
+                    this.barrier.expire(&mut state, this.barrier.clock.now());
+                }
+                if let Some(late) = state.take_late_arrival() {
+                    this.barrier.metrics.record_arrival();
+                    this.stage = Stage::Done;
+                    return Poll::Ready(Ok(late));
+                }
+                if this.barrier.generation_cap_reached(&state) {
+                    this.stage = Stage::Done;
+                    return Poll::Ready(Err(this.barrier.cancel_at_generation_cap(state)));
+                }
+
+                if let Err(err) = state.check_capacity(this.barrier.strict) {
+                    this.stage = Stage::Done;
+                    return Poll::Ready(Err(err));
+                }
+                let thread = match state.track_arrival() {
+                    Ok(thread) => thread,
+                    Err(err) => {
+                        this.stage = Stage::Done;
+                        return Poll::Ready(Err(err));
+                    }
+                };
+                let generation = state.generation;
+                let is_first = state.count == 0;
+                if is_first {
+                    this.barrier.note_first_arrival(&mut state);
+                    state.times.expires_at = this
+                        .barrier
+                        .overall_timeout
+                        .and_then(|timeout| this.barrier.deadline_after(timeout));
+                }
+                state.count += 1;
+                this.barrier.note_arrival(&mut state);
+                this.barrier.metrics.record_arrival();
+                this.barrier.observe_arrival(&state);
+
+                let is_leader = state.completed_by(state.count);
+                if is_leader {
+                    // Last task to arrive
+                    if let Err(panicked) = this.barrier.release(&mut state) {
+                        panicked.resume(this.barrier, state);
+                    }
+                    if !state.order.paused {
+                        this.barrier.metrics.record_critical_section(section, true);
+                        this.stage = Stage::Done;
+                        return Poll::Ready(Ok(BarrierWaitResult {
+                            is_first,
+                            ..BarrierWaitResult::new(generation, true)
+                        }));
+                    }
+                }
+
+                let waker_key = state.next_waker_key;
+                state.next_waker_key = state.next_waker_key.wrapping_add(1);
+                state.wakers.insert(waker_key, cx.waker().clone());
+                let park_ticket = state.park();
+                if is_leader {
+                    // still under the lock we released with, so it's our generation's
+                    state.released_below = state.next_park_ticket;
+                }
+                this.barrier.debug_check_invariants(&state);
+                this.barrier
+                    .metrics
+                    .record_critical_section(section, is_leader);
+                this.timer = Some(this.barrier.metrics.start_wait());
+                this.stage = Stage::Arrived {
+                    generation,
+                    waker_key,
+                    park_ticket,
+                    thread,
+                    is_leader,
+                    is_first,
+                };
+                Poll::Pending
+            }
+            Stage::Arrived {
+                generation,
+                waker_key,
+                park_ticket,
+                thread,
+                is_leader,
+                is_first,
+            } => {
+                if state.generation == generation {
+                    this.barrier.expire(&mut state, this.barrier.clock.now());
+                }
+                if let Some(err) = state.expired_error(generation) {
+                    state.wakers.remove(&waker_key);
+                    this.barrier.unpark(&mut state, park_ticket);
+                    this.stage = Stage::Done;
+                    this.record_wait();
+                    return Poll::Ready(Err(err));
+                }
+
+                // a released generation wins over a later cancellation, as in `wait`, but
+                // is held back while the barrier is paused
+                let held = state.is_held(park_ticket) && state.cancelled.is_none();
+                if state.generation != generation && !held {
+                    state.wakers.remove(&waker_key);
+                    this.barrier.unpark(&mut state, park_ticket);
+                    this.stage = Stage::Done;
+                    this.record_wait();
+                    return Poll::Ready(Ok(BarrierWaitResult {
+                        is_first,
+                        ..BarrierWaitResult::new(generation, is_leader)
+                    }));
+                }
+
+                if let Err(err) = state.check_cancelled() {
+                    state.wakers.remove(&waker_key);
+                    this.barrier.unpark(&mut state, park_ticket);
+                    this.stage = Stage::Done;
+                    this.record_wait();
+                    return Poll::Ready(Err(err));
+                }
+                if state.draining() && state.generation == generation {
+                    // give our slot back, as dropping the wait would
+                    state.count -= 1;
+                    state.forget_arrival(thread);
+                    state.wakers.remove(&waker_key);
+                    this.barrier.unpark(&mut state, park_ticket);
+                    this.stage = Stage::Done;
+                    this.record_wait();
+                    return Poll::Ready(Err(this.barrier.leave_for_drain(&mut state)));
+                }
+
+                // we may have been moved to another task since the last poll
+                match state.wakers.get_mut(&waker_key) {
+                    Some(waker) if waker.will_wake(cx.waker()) => {}
+                    Some(waker) => waker.clone_from(cx.waker()),
+                    None => {
+                        state.wakers.insert(waker_key, cx.waker().clone());
+                    }
+                }
+                Poll::Pending
+            }
+            Stage::Yielding => unreachable!("yielded before locking"),
+            Stage::HeldBack { .. } => unreachable!("held back arrivals are retried above"),
+            Stage::Done => panic!("BarrierWait polled after completion"),
+        }
+    }
+}
+
+impl<S: RawSync> Drop for BarrierWait<'_, S> {
+    fn drop(&mut self) {
+        if let Stage::HeldBack { waker_key } = self.stage {
+            self.barrier.lock().wakers.remove(&waker_key);
+        }
+        if let Stage::Arrived {
+            generation,
+            waker_key,
+            park_ticket,
+            thread,
+            ..
+        } = self.stage
+        {
+            let mut state = self.barrier.lock();
+            state.wakers.remove(&waker_key);
+            if state.generation == generation && state.cancelled.is_none() {
+                // Never released, give our slot back
+                state.count -= 1;
+                state.forget_arrival(thread);
+            }
+            self.barrier.unpark(&mut state, park_ticket);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::sync::PoisonError;
+
+use super::{BarrierWaitError, CancelReason, CancelledBarrier};
+use crate::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// A wait group in the style of Go's `sync.WaitGroup`, with cancellation: workers are
+/// registered with [`Self::add`] and finish with [`Self::done`], and [`Self::wait`] blocks
+/// until there are none left.
+///
+/// Unlike [`super::CancellableBarrier`], the count isn't fixed up front, and the group can
+/// be reused once it has drained by adding more work.
+#[derive(Debug)]
+pub struct CancellableWaitGroup {
+    mutex: Mutex<WaitGroupState>,
+    condvar: Condvar,
+}
+
+#[derive(Debug)]
+struct WaitGroupState {
+    pending: usize,
+    cancelled: Option<CancelReason>,
+}
+
+impl CancellableWaitGroup {
+    /// Creates a wait group with nothing pending
+    #[must_use]
+    pub fn new() -> Arc<Self> {
+        Arc::new(CancellableWaitGroup {
+            mutex: Mutex::new(WaitGroupState {
+                pending: 0,
+                cancelled: None,
+            }),
+            condvar: Condvar::new(),
+        })
+    }
+
+    /// Adds `n` to the number of pending workers
+    pub fn add(&self, n: usize) {
+        self.lock().pending += n;
+    }
+
+    /// Marks one pending worker as done, waking all waiters when it was the last one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if nothing is pending, as that means `done` was called more often than
+    /// workers were added.
+    pub fn done(&self) {
+        let mut state = self.lock();
+        assert!(
+            state.pending > 0,
+            "done called on a wait group with nothing pending"
+        );
+        state.pending -= 1;
+        if state.pending == 0 {
+            self.condvar.notify_all();
+        }
+    }
+
+    /// Waits until nothing is pending or the wait group is cancelled.
+    ///
+    /// Returns straight away if nothing is pending, even if the group is cancelled.
+    pub fn wait(&self) -> Result<(), BarrierWaitError> {
+        let mut state = self.lock();
+        while state.pending > 0 {
+            if let Some(reason) = &state.cancelled {
+                return Err(CancelledBarrier::new(reason.clone()).into());
+            }
+            state = self
+                .condvar
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        Ok(())
+    }
+
+    /// Cancels the wait group, causing all waiting threads to return with an error
+    pub fn cancel(&self) {
+        self.cancel_with(CancelReason::Unspecified);
+    }
+
+    /// Cancels the wait group with a `reason` that waiting threads receive in their error
+    pub fn cancel_with(&self, reason: CancelReason) {
+        let mut state = self.lock();
+        state.cancelled = Some(reason);
+        self.condvar.notify_all();
+    }
+
+    /// Returns the number of pending workers
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.lock().pending
+    }
+
+    /// Returns true if the wait group is currently cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.lock().cancelled.is_some()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, WaitGroupState> {
+        self.mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_wait_group_waits_for_all_workers() {
+        let group = CancellableWaitGroup::new();
+        assert_eq!(group.wait(), Ok(()));
+
+        group.add(4);
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let group = group.clone();
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(20));
+                    group.done();
+                })
+            })
+            .collect();
+        assert_eq!(group.wait(), Ok(()));
+        assert_eq!(group.pending(), 0);
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        // the drained group can be reused
+        group.add(1);
+        group.done();
+        assert_eq!(group.wait(), Ok(()));
+    }
+
+    #[test]
+    fn test_wait_group_cancel_while_pending() {
+        let group = CancellableWaitGroup::new();
+        group.add(3);
+        group.done();
+
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let group = group.clone();
+                thread::spawn(move || group.wait())
+            })
+            .collect();
+        thread::sleep(Duration::from_millis(100));
+        group.cancel_with(CancelReason::Shutdown);
+        for waiter in waiters {
+            assert_eq!(
+                waiter.join().unwrap().unwrap_err().cancel_reason(),
+                Some(&CancelReason::Shutdown)
+            );
+        }
+        assert!(group.is_cancelled());
+        assert_eq!(group.pending(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "nothing pending")]
+    fn test_wait_group_done_without_add() {
+        CancellableWaitGroup::new().done();
+    }
+}
@@ -1,31 +1,87 @@
 #![allow(unexpected_cfgs)]
+// tests build on std either way, for the harness and the threads they spawn
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
+#[cfg(not(feature = "std"))]
+mod alloc_barrier;
+#[cfg(feature = "std")]
 mod batch_id;
+#[cfg(feature = "std")]
 mod bounded_queue;
+#[cfg(feature = "std")]
 mod cancellable_barrier;
+#[cfg(feature = "std")]
 mod data_shuffle;
+#[cfg(feature = "std")]
 mod deterministic_shuffle;
+#[cfg(feature = "std")]
 mod interval_tree;
+#[cfg(feature = "std")]
 mod lcg;
+#[cfg(feature = "std")]
 mod lr_scheduler;
+#[cfg(feature = "std")]
 mod running_average;
+#[cfg(feature = "std")]
 mod similarity;
+#[cfg(feature = "std")]
 mod sized_iterator;
+mod sync;
+#[cfg(feature = "std")]
 mod token_size;
-
+#[cfg(not(feature = "std"))]
+pub use alloc_barrier::{
+    BarrierWaitError, BarrierWaitResult, CancelReason, CancellableBarrier, CancelledBarrier,
+};
+#[cfg(feature = "std")]
 pub use batch_id::BatchId;
+#[cfg(feature = "std")]
 pub use bounded_queue::BoundedQueue;
-pub use cancellable_barrier::{CancellableBarrier, CancelledBarrier};
+#[cfg(all(feature = "std", feature = "ffi"))]
+pub use cancellable_barrier::ffi;
+#[cfg(all(feature = "std", unix, feature = "signal", not(loom)))]
+pub use cancellable_barrier::install_signal_cancel;
+#[cfg(all(feature = "std", not(loom)))]
+pub use cancellable_barrier::{
+    wait_any, AsyncBarrier, BarrierHandle, BarrierPool, CancelTimer, InterruptHandle,
+    LivenessToken, PooledBarrier, RetainedWait,
+};
+#[cfg(feature = "std")]
+pub use cancellable_barrier::{
+    ArrivalGuard, ArrivalListenerId, BackoffConfig, BarrierBroadcast, BarrierBuildError,
+    BarrierCancelled, BarrierCollect, BarrierEvent, BarrierEventKind, BarrierExchange,
+    BarrierObserver, BarrierPhases, BarrierReduce, BarrierRole, BarrierSet, BarrierSnapshot,
+    BarrierThread, BarrierWait, BarrierWaitError, BarrierWaitResult, CallbackPanicPolicy,
+    CancelGroup, CancelGuard, CancelReason, CancelSignal, CancellableBarrier,
+    CancellableBarrierBuilder, CancellableEvent, CancellableLatch, CancellableSemaphore,
+    CancellableWaitGroup, CancelledBarrier, Clock, CompatBarrier, CompletedPartial, CountUpLatch,
+    DefaultSync, DrainToken, ExchangeGuard, LeaderPolicy, MockClock, Phase, Phaser, RawSync,
+    ReduceOutcome, ReleaseListenerId, Rendezvous, StaticBarrier, SystemClock, TreeBarrier,
+};
+#[cfg(all(feature = "std", feature = "metrics"))]
+pub use cancellable_barrier::{BarrierMetrics, CycleRecord};
+#[cfg(feature = "std")]
 pub use data_shuffle::Shuffle;
+#[cfg(feature = "std")]
 pub use deterministic_shuffle::deterministic_shuffle;
+#[cfg(feature = "std")]
 pub use interval_tree::{ClosedInterval, IntervalTree};
+#[cfg(feature = "std")]
 pub use lcg::LCG;
+#[cfg(feature = "std")]
 pub use lr_scheduler::*;
+#[cfg(feature = "std")]
 pub use running_average::RunningAverage;
+#[cfg(feature = "std")]
 pub use similarity::{
     hamming_distance, is_similar, jaccard_distance, manhattan_distance, DistanceThresholds,
 };
+#[cfg(feature = "std")]
 pub use sized_iterator::SizedIterator;
+#[cfg(feature = "std")]
 pub use token_size::TokenSize;
 
 #[cfg(test)]
@@ -0,0 +1,44 @@
+//! Locking primitives for the synchronization types in this crate, swapped for loom's
+//! model-checked versions when built with `--cfg loom`, for busy-waiting versions that
+//! need no OS thread parking when the `spin` feature is enabled, or for parking_lot's when
+//! the `parking_lot` feature is enabled. None of them make the crate build without std.
+//!
+//! Loom is a `cfg` set through `RUSTFLAGS` rather than a feature because it takes away
+//! the parts of the API it can't model, which a feature, unified across the dependency
+//! graph, mustn't do.
+//!
+//! The features compose, so that crates which each pick a backend still build together:
+//! loom wins over parking_lot, which wins over spin, which wins over std.
+//!
+//! Built without the `std` feature, only the spin mutex is left, for the alloc-only
+//! barrier.
+
+#[cfg(all(feature = "parking_lot", not(loom)))]
+mod parking_lot;
+#[cfg(any(
+    not(feature = "std"),
+    all(feature = "spin", not(any(loom, feature = "parking_lot")))
+))]
+mod spin;
+
+#[cfg(all(feature = "parking_lot", not(loom)))]
+pub(crate) use self::parking_lot::{Condvar, Mutex, MutexGuard};
+#[cfg(loom)]
+pub(crate) use loom::sync::{
+    atomic::{AtomicBool, AtomicUsize},
+    Arc, Condvar, Mutex, MutexGuard,
+};
+#[cfg(not(feature = "std"))]
+pub(crate) use spin::Mutex;
+#[cfg(all(feature = "spin", not(any(loom, feature = "parking_lot"))))]
+pub(crate) use spin::{Condvar, Mutex, MutexGuard};
+#[cfg(all(feature = "std", not(loom)))]
+pub(crate) use std::sync::{
+    atomic::{AtomicBool, AtomicUsize},
+    Arc,
+};
+#[cfg(all(
+    feature = "std",
+    not(any(loom, feature = "spin", feature = "parking_lot"))
+))]
+pub(crate) use std::sync::{Condvar, Mutex, MutexGuard};
@@ -0,0 +1,70 @@
+//! Thin wrappers around `parking_lot::{Mutex, Condvar}`.
+//!
+//! parking_lot's locks are smaller and usually faster under contention than std's, but its
+//! condvar takes the guard by reference and nothing poisons. These wrappers give it the
+//! std-shaped API the crate is written against, with every `LockResult` being `Ok`.
+
+use std::sync::LockResult;
+use std::time::Duration;
+
+pub(crate) use ::parking_lot::MutexGuard;
+
+#[derive(Debug)]
+// `pub` only because `DefaultSync` exposes them; the module is private, so they stay unnameable
+pub struct Mutex<T>(::parking_lot::Mutex<T>);
+
+impl<T> Mutex<T> {
+    pub(crate) fn new(data: T) -> Self {
+        Mutex(::parking_lot::Mutex::new(data))
+    }
+
+    pub(crate) fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        Ok(self.0.lock())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Condvar(::parking_lot::Condvar);
+
+/// Counterpart of `std::sync::WaitTimeoutResult`, which can't be constructed outside std
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct WaitTimeoutResult(bool);
+
+impl WaitTimeoutResult {
+    #[allow(dead_code)]
+    pub(crate) fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+impl Condvar {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn wait<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+    ) -> LockResult<MutexGuard<'a, T>> {
+        self.0.wait(&mut guard);
+        Ok(guard)
+    }
+
+    pub(crate) fn wait_timeout<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> LockResult<(MutexGuard<'a, T>, WaitTimeoutResult)> {
+        let result = self.0.wait_for(&mut guard, timeout);
+        Ok((guard, WaitTimeoutResult(result.timed_out())))
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn notify_one(&self) {
+        self.0.notify_one();
+    }
+
+    pub(crate) fn notify_all(&self) {
+        self.0.notify_all();
+    }
+}
@@ -0,0 +1,163 @@
+//! Spin-based stand-ins for `std::sync::{Mutex, Condvar}`.
+//!
+//! These only rely on atomics, for targets where blocking the thread on the OS isn't an
+//! option. Waiting burns CPU instead of parking, and the mutex never poisons. The API
+//! mirrors the parts of std's that the crate uses, `LockResult` included, so callers don't
+//! need to know which backend they're on.
+//!
+//! Built without the `std` feature, only the mutex is left, locked through
+//! [`Mutex::acquire`], for the alloc-only barrier.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::hint::spin_loop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::AtomicBool;
+#[cfg(feature = "std")]
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+#[cfg(feature = "std")]
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::sync::LockResult;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+// `pub` only because `DefaultSync` exposes them; the module is private, so they stay unnameable
+pub struct Mutex<T> {
+    locked: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+// SAFETY: access to `data` is serialized by `locked`, as with std's mutex
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub(crate) fn new(data: T) -> Self {
+        Mutex {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        // a panicking holder just releases the lock, so there's nothing to poison
+        Ok(self.acquire())
+    }
+
+    pub(crate) fn acquire(&self) -> MutexGuard<'_, T> {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.locked.load(Ordering::Relaxed) {
+                spin_loop();
+            }
+        }
+        MutexGuard { mutex: self }
+    }
+}
+
+impl<T> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mutex").finish_non_exhaustive()
+    }
+}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: we hold the lock
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: we hold the lock
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+    }
+}
+
+/// Condition variable that wakes waiters by bumping an epoch they spin on
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub struct Condvar {
+    epoch: AtomicUsize,
+}
+
+/// Counterpart of `std::sync::WaitTimeoutResult`, which can't be constructed outside std
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct WaitTimeoutResult(bool);
+
+#[cfg(feature = "std")]
+impl WaitTimeoutResult {
+    #[allow(dead_code)]
+    pub(crate) fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl Condvar {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> LockResult<MutexGuard<'a, T>> {
+        // read the epoch while still holding the lock, so a notify that happens after we
+        // release it can't be missed
+        let epoch = self.epoch.load(Ordering::Acquire);
+        let mutex = guard.mutex;
+        drop(guard);
+        while self.epoch.load(Ordering::Acquire) == epoch {
+            spin_loop();
+        }
+        Ok(mutex.acquire())
+    }
+
+    pub(crate) fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> LockResult<(MutexGuard<'a, T>, WaitTimeoutResult)> {
+        let deadline = Instant::now().checked_add(timeout);
+        let epoch = self.epoch.load(Ordering::Acquire);
+        let mutex = guard.mutex;
+        drop(guard);
+        let mut timed_out = false;
+        while self.epoch.load(Ordering::Acquire) == epoch {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                timed_out = true;
+                break;
+            }
+            spin_loop();
+        }
+        Ok((mutex.acquire(), WaitTimeoutResult(timed_out)))
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn notify_one(&self) {
+        // waking everyone is allowed, since waiters must tolerate spurious wakeups
+        self.notify_all();
+    }
+
+    pub(crate) fn notify_all(&self) {
+        self.epoch.fetch_add(1, Ordering::Release);
+    }
+}